@@ -1,3 +1,5 @@
+use rand::seq::SliceRandom;
+
 pub use theme::Theme;
 
 pub mod theme;
@@ -17,6 +19,27 @@ impl Default for Appearance {
     }
 }
 
+impl Appearance {
+    /// Installed theme named `name`, ignoring case
+    pub fn find(&self, name: &str) -> Option<&Theme> {
+        self.all
+            .iter()
+            .find(|theme| theme.name.eq_ignore_ascii_case(name))
+    }
+
+    /// Random installed theme other than `current`, `None` if there's no
+    /// other theme
+    pub fn random(&self, current: &str) -> Option<&Theme> {
+        let others = self
+            .all
+            .iter()
+            .filter(|theme| theme.name != current)
+            .collect::<Vec<_>>();
+
+        others.choose(&mut rand::thread_rng()).copied()
+    }
+}
+
 #[derive(Debug, Clone)]
 pub enum Selected {
     Static(Theme),
@@ -3,8 +3,10 @@ use core::fmt;
 use serde::{Deserialize, Serialize};
 
 pub use self::away::Away;
+pub use self::bot::Bot;
 
 pub mod away;
+pub mod bot;
 
 use crate::user::Nick;
 use crate::{channel, config, message, Server};
@@ -29,6 +31,8 @@ pub enum Internal {
     FileTransfers,
     Logs,
     Highlights,
+    #[strum(serialize = "Raw Log")]
+    RawLog,
 }
 
 impl Buffer {
@@ -90,7 +94,12 @@ impl Upstream {
 }
 
 impl Internal {
-    pub const ALL: &'static [Self] = &[Self::FileTransfers, Self::Logs, Self::Highlights];
+    pub const ALL: &'static [Self] = &[
+        Self::FileTransfers,
+        Self::Logs,
+        Self::Highlights,
+        Self::RawLog,
+    ];
 }
 
 #[derive(Debug, Clone, Default, Deserialize, Serialize)]
@@ -112,6 +121,8 @@ pub struct TextInput {
     pub visibility: TextInputVisibility,
     #[serde(default)]
     pub auto_format: AutoFormat,
+    #[serde(default)]
+    pub empty_messages: EmptyMessages,
 }
 
 #[derive(Debug, Clone, Copy, Default, Deserialize)]
@@ -131,6 +142,16 @@ pub enum AutoFormat {
     All,
 }
 
+/// What to do with messages which render empty, e.g. only whitespace or
+/// formatting codes
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum EmptyMessages {
+    #[default]
+    Drop,
+    Confirm,
+}
+
 #[derive(Debug, Clone, Deserialize)]
 pub struct Timestamp {
     #[serde(default = "default_timestamp")]
@@ -158,6 +179,8 @@ pub struct Nickname {
     pub alignment: Alignment,
     #[serde(default = "default_bool_true")]
     pub show_access_levels: bool,
+    #[serde(default)]
+    pub show_previous_nickname: bool,
 }
 
 impl Default for Nickname {
@@ -167,6 +190,7 @@ impl Default for Nickname {
             brackets: Default::default(),
             alignment: Default::default(),
             show_access_levels: default_bool_true(),
+            show_previous_nickname: false,
         }
     }
 }
@@ -4,6 +4,8 @@ use serde::Deserialize;
 pub struct Away {
     #[serde(default)]
     pub appearance: Appearance,
+    #[serde(default)]
+    pub show_back: bool,
 }
 
 impl Away {
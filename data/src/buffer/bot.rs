@@ -0,0 +1,48 @@
+use serde::Deserialize;
+
+use super::away::Appearance;
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct Bot {
+    #[serde(default = "default_appearance")]
+    pub appearance: Appearance,
+    #[serde(default = "default_bool_true")]
+    pub show_badge: bool,
+    #[serde(default = "default_bool_true")]
+    pub highlight: bool,
+    #[serde(default = "default_bool_true")]
+    pub show_join_part: bool,
+}
+
+impl Default for Bot {
+    fn default() -> Self {
+        Self {
+            appearance: default_appearance(),
+            show_badge: true,
+            highlight: true,
+            show_join_part: true,
+        }
+    }
+}
+
+impl Bot {
+    pub fn should_dim(&self, is_bot: bool) -> bool {
+        is_bot && matches!(self.appearance, Appearance::Dimmed)
+    }
+
+    pub fn should_show_badge(&self, is_bot: bool) -> bool {
+        is_bot && self.show_badge
+    }
+
+    pub fn should_highlight(&self, is_bot: bool) -> bool {
+        !is_bot || self.highlight
+    }
+}
+
+fn default_appearance() -> Appearance {
+    Appearance::Solid
+}
+
+fn default_bool_true() -> bool {
+    true
+}
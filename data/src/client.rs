@@ -3,22 +3,23 @@ use futures::{channel::mpsc, Future, FutureExt};
 use irc::proto::{self, command, Command};
 use itertools::{Either, Itertools};
 use std::cmp::Ordering;
-use std::collections::{BTreeMap, HashMap, HashSet};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
 use std::path::PathBuf;
 use std::time::{Duration, Instant};
-use std::{fmt, io};
+use std::{fmt, io, iter};
 
 use tokio::fs;
 
 use anyhow::{anyhow, bail, Result};
 
-use crate::history::ReadMarker;
+use crate::history::{self, ReadMarker};
 use crate::isupport::{ChatHistoryState, ChatHistorySubcommand, MessageReference};
 use crate::message::{message_id, server_time, source};
 use crate::time::Posix;
-use crate::user::{Nick, NickRef};
+use crate::user::{AccessLevel, Nick, NickRef};
 use crate::{
-    buffer, compression, config, ctcp, dcc, environment, isupport, message, mode, Server, User,
+    buffer, compression, config, ctcp, dcc, environment, isupport, message, mode, whois, Server,
+    User,
 };
 use crate::{file_transfer, server};
 
@@ -26,6 +27,8 @@ const HIGHLIGHT_BLACKOUT_INTERVAL: Duration = Duration::from_secs(5);
 
 const CLIENT_CHATHISTORY_LIMIT: u16 = 500;
 const CHATHISTORY_REQUEST_TIMEOUT: Duration = Duration::from_secs(120);
+const WHOIS_LOOKUP_TIMEOUT: Duration = Duration::from_secs(30);
+const NICK_RECOVERY_INTERVAL: Duration = Duration::from_secs(60);
 
 #[derive(Debug, Clone, Copy)]
 pub enum Status {
@@ -87,6 +90,31 @@ pub enum Broadcast {
         channels: Vec<String>,
         sent_time: DateTime<Utc>,
     },
+    Back {
+        user: User,
+        sent_time: DateTime<Utc>,
+    },
+    /// Summary of the replies to us joining a channel
+    Joined {
+        channel: String,
+        users: usize,
+        topic: Option<String>,
+        topic_who: Option<String>,
+        topic_time: Option<DateTime<Utc>>,
+        sent_time: DateTime<Utc>,
+    },
+    /// Users who quit in a `netsplit` batch, with their channels
+    Netsplit {
+        servers: Vec<String>,
+        users: Vec<(User, Vec<String>)>,
+        sent_time: DateTime<Utc>,
+    },
+    /// Users who joined in a `netjoin` batch, with their channels
+    Netjoin {
+        servers: Vec<String>,
+        users: Vec<(User, Vec<String>)>,
+        sent_time: DateTime<Utc>,
+    },
 }
 
 #[derive(Debug)]
@@ -109,21 +137,46 @@ pub enum Event {
     ChatHistoryAcknowledged(DateTime<Utc>),
     ChatHistoryTargetReceived(String, DateTime<Utc>),
     ChatHistoryTargetsReceived(DateTime<Utc>),
+    /// Joining the channel failed for a missing or wrong key
+    ChannelKeyRequired(String),
 }
 
 struct ChatHistoryRequest {
     subcommand: ChatHistorySubcommand,
     requested_at: Instant,
+    /// Reference of the batch the server opened in response, once seen
+    batch: Option<String>,
+}
+
+struct AwayReply {
+    message: String,
+    received_at: Instant,
+}
+
+/// `WHOWAS` follow-up of a user who doesn't exist, and where to show it
+#[derive(Default)]
+struct WhowasLookup {
+    whowas: whois::Whowas,
+    /// Buffers `/whois` was run from
+    buffers: Vec<buffer::Upstream>,
+    /// Whether the user's query shows it in place of a [`whois::Summary`]
+    summary: bool,
 }
 
 pub struct Client {
     server: Server,
     config: config::Server,
     handle: server::Handle,
-    alt_nick: Option<usize>,
+    /// # fallback nicknames tried while registering
+    fallback_nicks: usize,
     resolved_nick: Option<String>,
+    /// Set while connected with a fallback nickname and trying to change
+    /// back to the configured one, holding the time of the last attempt
+    nick_recovery: Option<Option<Instant>>,
+    user_modes: BTreeSet<char>,
     chanmap: BTreeMap<String, Channel>,
     channels: Vec<String>,
+    kicks: HashMap<String, Kick>,
     users: HashMap<String, Vec<User>>,
     labels: HashMap<String, Context>,
     batches: HashMap<String, Batch>,
@@ -137,11 +190,34 @@ pub struct Client {
     supports_read_marker: bool,
     supports_chathistory: bool,
     chathistory_requests: HashMap<String, ChatHistoryRequest>,
+    /// Requests waiting on an in-flight request for the same target
+    chathistory_queue: HashMap<String, VecDeque<ChatHistorySubcommand>>,
     chathistory_exhausted: HashMap<String, bool>,
     chathistory_targets_request: Option<ChatHistoryRequest>,
     highlight_blackout: HighlightBlackout,
+    /// Keyed by nickname folded with the server's `CASEMAPPING`
+    away_replies: HashMap<String, AwayReply>,
+    previous_nicknames: HashMap<Nick, Nick>,
+    account_seeds: HashMap<String, Nick>,
     registration_required_channels: Vec<String>,
     isupport: HashMap<isupport::Kind, isupport::Parameter>,
+    /// Set once the MOTD ends after registering
+    registered: bool,
+    logged_in: bool,
+    after_identify_sent: bool,
+    scheduled_commands: VecDeque<(Instant, proto::Message)>,
+    whois_lookups: HashMap<Nick, whois::Lookup>,
+    whois_requested_at: Option<Instant>,
+    /// Follow up failed [`whois::Summary`] lookups with `WHOWAS`
+    whowas_summaries: bool,
+    /// `/whois` requests awaiting a reply, by the buffer they were run from
+    whois_requests: HashMap<Nick, buffer::Upstream>,
+    whowas_lookups: HashMap<Nick, WhowasLookup>,
+    /// Keys used to join channels this session, tried before configured keys
+    session_channel_keys: HashMap<String, String>,
+    /// Latest read markers of queries relayed by the server, e.g. from our
+    /// other clients
+    peer_read_markers: HashMap<Nick, ReadMarker>,
 }
 
 impl fmt::Debug for Client {
@@ -161,9 +237,12 @@ impl Client {
             config,
             handle: sender,
             resolved_nick: None,
-            alt_nick: None,
+            nick_recovery: None,
+            user_modes: BTreeSet::new(),
+            fallback_nicks: 0,
             chanmap: BTreeMap::default(),
             channels: vec![],
+            kicks: HashMap::new(),
             users: HashMap::new(),
             labels: HashMap::new(),
             batches: HashMap::new(),
@@ -177,11 +256,26 @@ impl Client {
             supports_read_marker: false,
             supports_chathistory: false,
             chathistory_requests: HashMap::new(),
+            chathistory_queue: HashMap::new(),
             chathistory_exhausted: HashMap::new(),
             chathistory_targets_request: None,
             highlight_blackout: HighlightBlackout::Blackout(Instant::now()),
+            away_replies: HashMap::new(),
+            previous_nicknames: HashMap::new(),
+            account_seeds: HashMap::new(),
             registration_required_channels: vec![],
             isupport: HashMap::new(),
+            registered: false,
+            logged_in: false,
+            after_identify_sent: false,
+            scheduled_commands: VecDeque::new(),
+            whois_lookups: HashMap::new(),
+            whois_requested_at: None,
+            whowas_summaries: false,
+            whois_requests: HashMap::new(),
+            whowas_lookups: HashMap::new(),
+            session_channel_keys: HashMap::new(),
+            peer_read_markers: HashMap::new(),
         }
     }
 
@@ -213,10 +307,71 @@ impl Client {
         }
     }
 
-    fn join(&mut self, channels: &[String]) {
-        let keys = HashMap::new();
+    /// Send configured commands through the command parser, holding back
+    /// delayed commands until `tick`. Commands which can't be sent are
+    /// reported to the server buffer.
+    fn send_commands(&mut self, commands: &[config::server::Command]) -> Result<Vec<Event>> {
+        let now = Instant::now();
+        let mut send_at = self
+            .scheduled_commands
+            .back()
+            .map_or(now, |(at, _)| (*at).max(now));
+        let mut events = vec![];
+
+        for command in commands {
+            send_at += command.delay();
+
+            let parsed = crate::command::parse(command.command(), None, Some(self.nickname()))
+                .map_err(|error| error.to_string())
+                .and_then(|command| {
+                    proto::Command::try_from(command)
+                        .map_err(|_| "not a server command".to_string())
+                });
 
-        let messages = group_joins(channels, &keys);
+            match parsed {
+                Ok(parsed) if send_at <= now => self.handle.try_send(parsed.into())?,
+                Ok(parsed) => self.scheduled_commands.push_back((send_at, parsed.into())),
+                Err(error) => {
+                    // Only name the command, its arguments may hold credentials
+                    let name = command
+                        .command()
+                        .split_ascii_whitespace()
+                        .next()
+                        .unwrap_or_default();
+
+                    events.push(Event::WithTarget(
+                        message::Encoded::from(command!(
+                            "NOTICE",
+                            "*",
+                            format!("Failed to run {name}: {error}")
+                        )),
+                        self.nickname().to_owned(),
+                        message::Target::Server {
+                            source: message::Source::Internal(source::Internal::Status(
+                                source::Status::Error,
+                            )),
+                        },
+                    ));
+                }
+            }
+        }
+
+        Ok(events)
+    }
+
+    fn send_after_identify(&mut self) -> Result<Vec<Event>> {
+        if self.after_identify_sent {
+            return Ok(vec![]);
+        }
+
+        self.after_identify_sent = true;
+
+        let after_identify = self.config.after_identify.clone();
+        self.send_commands(&after_identify)
+    }
+
+    fn join(&mut self, channels: &[String]) {
+        let messages = group_joins(channels, &self.config.channel_keys);
 
         for message in messages {
             if let Err(e) = self.handle.try_send(message) {
@@ -225,6 +380,53 @@ impl Client {
         }
     }
 
+    /// Use `identity` from now on. Our nickname is changed right away if
+    /// we're using the configured one, the rest applies on next connect.
+    fn update_identity(&mut self, identity: &config::Identity) {
+        let previous = self.config.nickname.clone();
+
+        self.config = self.config.clone().with_identity(identity);
+
+        if self.nickname().as_ref() == previous && self.config.nickname != previous {
+            if let Err(e) = self
+                .handle
+                .try_send(command!("NICK", &self.config.nickname))
+            {
+                log::warn!("Error sending nick: {e}");
+            }
+        }
+    }
+
+    /// Notice with `text` in the server buffer
+    fn status_notice(&self, status: source::Status, text: String) -> Event {
+        Event::WithTarget(
+            message::Encoded::from(command!("NOTICE", "*", text)),
+            self.nickname().to_owned(),
+            message::Target::Server {
+                source: message::Source::Internal(source::Internal::Status(status)),
+            },
+        )
+    }
+
+    /// Notices in the server buffer for configured nicknames longer than the
+    /// server's `NICKLEN`, which the server truncates
+    fn nicklen_notices(&self, nicklen: u16) -> Vec<Event> {
+        iter::once(&self.config.nickname)
+            .chain(&self.config.alt_nicks)
+            .filter(|nickname| nickname.chars().count() > nicklen as usize)
+            .map(|nickname| {
+                let truncated = nickname.chars().take(nicklen as usize).collect::<String>();
+
+                self.status_notice(
+                    source::Status::Error,
+                    format!(
+                        "Nickname {nickname} is longer than the server's NICKLEN of {nicklen} and is truncated to {truncated}"
+                    ),
+                )
+            })
+            .collect()
+    }
+
     fn start_reroute(&self, command: &Command) -> bool {
         use Command::*;
 
@@ -236,6 +438,9 @@ impl Client {
     }
 
     fn send(&mut self, buffer: &buffer::Upstream, mut message: message::Encoded) {
+        message.command = self.route_through_channel(message.command.clone());
+        message.command = self.with_channel_keys(message.command.clone());
+
         if self.supports_labels {
             use proto::Tag;
 
@@ -253,23 +458,218 @@ impl Client {
 
         self.reroute_responses_to = self.start_reroute(&message.command).then(|| buffer.clone());
 
+        if let Command::WHOIS(_, nicks) = &message.command {
+            for nick in nicks.split(',').filter(|nick| !nick.is_empty()) {
+                self.whois_requests.insert(Nick::from(nick), buffer.clone());
+            }
+        }
+
         if let Err(e) = self.handle.try_send(message.into()) {
             log::warn!("Error sending message: {e}");
         }
     }
 
-    fn receive(&mut self, message: message::Encoded) -> Result<Vec<Event>> {
+    /// Send PRIVMSG/NOTICE to a user as CPRIVMSG/CNOTICE through a channel
+    /// we share with them, which bypasses target change limits
+    fn route_through_channel(&self, command: Command) -> Command {
+        let cmessage_channel = |target: &str, kind: isupport::Kind| {
+            if !self.isupport.contains_key(&kind)
+                || target.contains(',')
+                || proto::parse_channel_from_target(target, self.chantypes(), self.statusmsg())
+                    .is_some()
+            {
+                return None;
+            }
+
+            shared_voiced_channel(&self.chanmap, self.nickname(), target).map(str::to_string)
+        };
+
+        match command {
+            Command::PRIVMSG(target, text) => {
+                match cmessage_channel(&target, isupport::Kind::CPRIVMSG) {
+                    Some(channel) => Command::CPRIVMSG(target, channel, text),
+                    None => Command::PRIVMSG(target, text),
+                }
+            }
+            Command::NOTICE(target, text) => {
+                match cmessage_channel(&target, isupport::Kind::CNOTICE) {
+                    Some(channel) => Command::CNOTICE(target, channel, text),
+                    None => Command::NOTICE(target, text),
+                }
+            }
+            command => command,
+        }
+    }
+
+    /// Add known keys to a JOIN without keys, and remember the keys of a
+    /// JOIN with keys for the session
+    fn with_channel_keys(&mut self, command: Command) -> Command {
+        match command {
+            Command::JOIN(chanlist, None) => join_with_keys(&chanlist, |channel| {
+                self.session_channel_keys
+                    .get(channel)
+                    .or_else(|| self.config.channel_keys.get(channel))
+                    .map(String::as_str)
+            }),
+            Command::JOIN(chanlist, Some(keys)) => {
+                for (channel, key) in chanlist.split(',').zip(keys.split(',')) {
+                    if !key.is_empty() {
+                        self.session_channel_keys
+                            .insert(channel.to_string(), key.to_string());
+                    }
+                }
+
+                Command::JOIN(chanlist, Some(keys))
+            }
+            command => command,
+        }
+    }
+
+    /// Join `channel` with `key`, e.g. entered after joining failed for
+    /// the key. The key is remembered for the session.
+    pub fn join_with_key(&mut self, channel: &str, key: &str) -> Result<()> {
+        self.session_channel_keys
+            .insert(channel.to_string(), key.to_string());
+
+        self.handle.try_send(command!("JOIN", channel, key))?;
+
+        Ok(())
+    }
+
+    fn receive(&mut self, mut message: message::Encoded) -> Result<Vec<Event>> {
         log::trace!("Message received => {:?}", *message);
 
+        self.track_bots(&mut message);
+
+        if let Some(events) = self.record_whowas_lookup(&message) {
+            return Ok(events);
+        }
+
+        self.follow_up_whois(&message);
+
+        if self.record_whois_lookup(&message) {
+            return Ok(vec![]);
+        }
+
         let stop_reroute = stop_reroute(&message.command);
 
+        let back = self.clear_away_reply(&message);
+
         let events = self.handle(message, None)?;
 
         if stop_reroute {
             self.reroute_responses_to = None;
         }
 
-        Ok(events)
+        Ok(back.into_iter().chain(events).collect())
+    }
+
+    /// Track users flagged as bots by the bot mode tag or WHOIS, and tag
+    /// messages from users known to be bots so they're handled as such
+    /// even when the server doesn't tag them (e.g. JOIN / PART)
+    fn track_bots(&mut self, message: &mut message::Encoded) {
+        if let Command::Numeric(proto::command::Numeric::RPL_WHOISBOT, args) = &message.command {
+            if let Some(nick) = args.get(1) {
+                self.update_user_bot(NickRef::from(nick.as_str()), true);
+            }
+            return;
+        }
+
+        let Some(user) = message.user() else {
+            return;
+        };
+
+        if message::is_bot(message) {
+            self.update_user_bot(user.nickname(), true);
+        } else if self.is_bot(user.nickname()) {
+            message.tags.push(proto::Tag {
+                key: "bot".to_string(),
+                value: None,
+            });
+        }
+    }
+
+    fn is_bot(&self, nick: NickRef) -> bool {
+        let user = User::from(nick.to_owned());
+
+        self.chanmap
+            .values()
+            .any(|channel| channel.users.get(&user).is_some_and(|user| user.is_bot()))
+    }
+
+    fn update_user_bot(&mut self, nick: NickRef, bot: bool) {
+        let user = User::from(nick.to_owned());
+
+        for channel in self.chanmap.values_mut() {
+            if let Some(mut user) = channel.users.take(&user) {
+                user.update_bot(bot);
+                channel.users.insert(user);
+            }
+        }
+    }
+
+    /// The user mode flagging bots, shown in WHO replies
+    fn bot_mode(&self) -> Option<char> {
+        match self.isupport.get(&isupport::Kind::BOT)? {
+            isupport::Parameter::BOT(mode) => Some(*mode),
+            _ => None,
+        }
+    }
+
+    /// Forget the away reply of a user once they're seen to be back,
+    /// either through away-notify or by them speaking
+    fn clear_away_reply(&mut self, message: &message::Encoded) -> Option<Event> {
+        // Ignore playback from chathistory
+        if message.tags.iter().any(|tag| tag.key == "batch") {
+            return None;
+        }
+
+        let user = message.user()?;
+
+        if !matches!(
+            message.command,
+            Command::AWAY(None) | Command::PRIVMSG(..) | Command::NOTICE(..)
+        ) {
+            return None;
+        }
+
+        let key = self.casemapping().fold(user.nickname().as_ref());
+
+        self.away_replies.remove(&key).map(|_| {
+            Event::Broadcast(Broadcast::Back {
+                user,
+                sent_time: server_time(message),
+            })
+        })
+    }
+
+    /// Take the nickname a user had before their most recent nick change,
+    /// the first time they speak under their new nickname
+    fn take_previous_nickname(&mut self, message: &message::Encoded) -> Option<Nick> {
+        // Ignore playback from chathistory
+        if message.tags.iter().any(|tag| tag.key == "batch") {
+            return None;
+        }
+
+        if !matches!(message.command, Command::PRIVMSG(..) | Command::NOTICE(..)) {
+            return None;
+        }
+
+        let user = message.user()?;
+
+        self.previous_nicknames.remove(&user.nickname().to_owned())
+    }
+
+    /// Carry over the color of a user who was seen under another nickname
+    /// with the same account
+    fn with_account_seed(&self, user: User) -> User {
+        match user
+            .accountname()
+            .and_then(|accountname| self.account_seeds.get(accountname))
+        {
+            Some(seed) => user.with_color_seed(seed.clone()),
+            None => user,
+        }
     }
 
     fn handle(
@@ -313,12 +713,27 @@ impl Client {
                         let mut batch = Batch::new(context);
 
                         batch.chathistory = match params.first().map(|x| x.as_str()) {
-                            Some("chathistory") => params
-                                .get(1)
-                                .map(|target| ChatHistoryBatch::Target(target.clone())),
+                            // Only claim the batch for a request awaiting one, so
+                            // concurrent requests each get their own batch
+                            Some("chathistory") => params.get(1).and_then(|target| {
+                                let request = self.chathistory_requests.get_mut(target)?;
+
+                                if request.batch.is_some() {
+                                    return None;
+                                }
+
+                                request.batch = Some(reference.clone());
+
+                                Some(ChatHistoryBatch::Target(target.clone()))
+                            }),
                             Some("draft/chathistory-targets") => Some(ChatHistoryBatch::Targets),
                             _ => None,
                         };
+                        batch.net = match params.first().map(|x| x.as_str()) {
+                            Some("netsplit") => Some(NetBatch::Split(params[1..].to_vec())),
+                            Some("netjoin") => Some(NetBatch::Join(params[1..].to_vec())),
+                            _ => None,
+                        };
 
                         self.batches.insert(reference, batch);
                     }
@@ -335,9 +750,12 @@ impl Client {
                                     Some(ChatHistoryBatch::Target(batch_target)) => {
                                         let continuation_subcommand = if let Some(
                                             ChatHistoryRequest { subcommand, .. },
-                                        ) =
-                                            self.chathistory_requests.get(batch_target)
-                                        {
+                                        ) = self
+                                            .chathistory_requests
+                                            .get(batch_target)
+                                            .filter(|request| {
+                                                request.batch.as_deref() == Some(reference.as_str())
+                                            }) {
                                             if let ChatHistorySubcommand::Before(_, _, limit) =
                                                 subcommand
                                             {
@@ -430,15 +848,26 @@ impl Client {
                                                 }
                                             }
                                         } else {
+                                            // The request timed out and was retried,
+                                            // leave the retry in flight
                                             None
                                         };
 
-                                        self.clear_chathistory_request(Some(batch_target));
+                                        if self.chathistory_requests.get(batch_target).is_some_and(
+                                            |request| {
+                                                request.batch.as_deref() == Some(reference.as_str())
+                                            },
+                                        ) {
+                                            if let Some(continuation_subcommand) =
+                                                continuation_subcommand
+                                            {
+                                                self.chathistory_queue
+                                                    .entry(batch_target.to_string())
+                                                    .or_default()
+                                                    .push_front(continuation_subcommand);
+                                            }
 
-                                        if let Some(continuation_subcommand) =
-                                            continuation_subcommand
-                                        {
-                                            self.send_chathistory_request(continuation_subcommand);
+                                            self.clear_chathistory_request(Some(batch_target));
                                         }
                                     }
                                     Some(ChatHistoryBatch::Targets) => {
@@ -472,6 +901,14 @@ impl Client {
                                     _ => (),
                                 }
 
+                                if let Some(net) = finished.net {
+                                    return Ok(collapse_net_batch(
+                                        net,
+                                        finished.events,
+                                        server_time(&message),
+                                    ));
+                                }
+
                                 return Ok(finished.events);
                             }
                         }
@@ -490,13 +927,6 @@ impl Client {
                             .chathistory
                             .as_ref()
                             .and_then(|chathistory| chathistory.target())
-                    })
-                    .and_then(|target| {
-                        if self.chathistory_requests.contains_key(&target) {
-                            Some(target)
-                        } else {
-                            None
-                        }
                     }) {
                     if Some(User::from(Nick::from("HistServ"))) == message.user() {
                         // HistServ provides event-playback without event-playback
@@ -505,6 +935,8 @@ impl Client {
                         // via event-playback.
                         vec![]
                     } else {
+                        let message = message.backfilled();
+
                         match &message.command {
                             Command::NICK(_) => {
                                 let target = message::Target::Channel {
@@ -559,6 +991,16 @@ impl Client {
                     return Ok(events);
                 }
             }
+            // Shown beside our user modes, wherever /umode was sent from
+            Command::Numeric(ERR_UMODEUNKNOWNFLAG | ERR_USERSDONTMATCH, _) => {
+                return Ok(vec![Event::WithTarget(
+                    message,
+                    self.nickname().to_owned(),
+                    message::Target::Server {
+                        source: source::Source::Server(None),
+                    },
+                )]);
+            }
             // Label context whois
             _ if context.as_ref().map(Context::is_whois).unwrap_or_default() => {
                 if let Some(source) = context
@@ -846,6 +1288,8 @@ impl Client {
             Command::Numeric(RPL_LOGGEDIN, args) => {
                 log::info!("[{}] logged in", self.server);
 
+                self.logged_in = true;
+
                 if !self.registration_required_channels.is_empty() {
                     for message in group_joins(
                         &self.registration_required_channels,
@@ -868,6 +1312,17 @@ impl Client {
                         }
                     });
                 }
+
+                // SASL logins complete before registration, in which case
+                // after identify commands are sent once RPL_WELCOME arrives
+                if self.resolved_nick.is_some() {
+                    let events = self.send_after_identify()?;
+
+                    return Ok(vec![Event::Single(message, self.nickname().to_owned())]
+                        .into_iter()
+                        .chain(events)
+                        .collect());
+                }
             }
             Command::Numeric(RPL_LOGGEDOUT, _) => {
                 log::info!("[{}] logged out", self.server);
@@ -883,7 +1338,9 @@ impl Client {
                 }
             }
             Command::PRIVMSG(channel, text) | Command::NOTICE(channel, text) => {
-                if let Some(user) = message.user() {
+                if let Some(mut user) = message.user() {
+                    user.update_bot(message::is_bot(&message));
+
                     if let Some(command) = dcc::decode(text) {
                         match command {
                             dcc::Command::Send(request) => {
@@ -1005,16 +1462,41 @@ impl Client {
 
                 if ourself {
                     self.resolved_nick = Some(nick.clone());
+                    // Recovered, or changed by hand
+                    self.nick_recovery = None;
                 }
 
                 let new_nick = Nick::from(nick.as_str());
 
+                // The away reply may not hold for whoever has the nickname next
+                let key = self.casemapping().fold(old_user.nickname().as_ref());
+                self.away_replies.remove(&key);
+
                 self.chanmap.values_mut().for_each(|channel| {
                     if let Some(user) = channel.users.take(&old_user) {
-                        channel.users.insert(user.with_nickname(new_nick.clone()));
+                        let user = user.with_nickname(new_nick.clone());
+
+                        if let Some(accountname) = user.accountname() {
+                            self.account_seeds
+                                .insert(accountname.to_string(), Nick::from(user.seed()));
+                        }
+
+                        channel.users.insert(user);
                     }
                 });
 
+                if !ourself {
+                    let old_nick = old_user.nickname().to_owned();
+                    let previous = self
+                        .previous_nicknames
+                        .remove(&old_nick)
+                        .unwrap_or(old_nick);
+
+                    if previous != new_nick {
+                        self.previous_nicknames.insert(new_nick.clone(), previous);
+                    }
+                }
+
                 let channels = self.user_channels(old_user.nickname());
 
                 return Ok(vec![Event::Broadcast(Broadcast::Nickname {
@@ -1025,31 +1507,55 @@ impl Client {
                     sent_time: server_time(&message),
                 })]);
             }
-            Command::Numeric(ERR_NICKNAMEINUSE | ERR_ERRONEUSNICKNAME, _)
+            Command::Numeric(ERR_NICKNAMEINUSE | ERR_ERRONEUSNICKNAME, args)
                 if self.resolved_nick.is_none() =>
             {
-                // Try alt nicks
-                match &mut self.alt_nick {
-                    Some(index) => {
-                        if *index == self.config.alt_nicks.len() - 1 {
-                            self.alt_nick = None;
-                        } else {
-                            *index += 1;
-                        }
-                    }
-                    None if !self.config.alt_nicks.is_empty() => self.alt_nick = Some(0),
-                    None => {}
-                }
-
-                if let Some(nick) = self.alt_nick.and_then(|i| self.config.alt_nicks.get(i)) {
+                if let Some(nick) = self.config.fallback_nickname(self.fallback_nicks) {
+                    self.fallback_nicks += 1;
                     self.handle.try_send(command!("NICK", nick))?;
+                } else {
+                    let nick = args.get(1).map(String::as_str).unwrap_or_default();
+                    let notice = self.status_notice(
+                        source::Status::Error,
+                        format!("Nickname {nick} is unavailable and there are no other nicknames to try, disconnecting"),
+                    );
+
+                    self.quit(None);
+
+                    return Ok(vec![notice]);
                 }
             }
+            // Failed attempt to recover the configured nickname, retried on tick
+            Command::Numeric(ERR_NICKNAMEINUSE, args)
+                if self.nick_recovery.is_some() && args.get(1) == Some(&self.config.nickname) =>
+            {
+                return Ok(vec![]);
+            }
             Command::Numeric(RPL_WELCOME, args) => {
                 // Updated actual nick
                 let nick = ok!(args.first());
                 self.resolved_nick = Some(nick.to_string());
 
+                let mut events = vec![];
+
+                if nick != &self.config.nickname {
+                    let recovery = if self.config.recover_nick {
+                        self.nick_recovery = Some(None);
+
+                        ", trying to change to it periodically"
+                    } else {
+                        ""
+                    };
+
+                    events.push(self.status_notice(
+                        source::Status::Error,
+                        format!(
+                            "Connected as {nick} since {} is unavailable{recovery}",
+                            self.config.nickname
+                        ),
+                    ));
+                }
+
                 // Send nick password & ghost
                 if let Some(nick_pass) = self.config.nick_password.as_ref() {
                     // Try ghost recovery if we couldn't claim our nick
@@ -1104,18 +1610,22 @@ impl Client {
                 }
 
                 // Loop on connect commands
-                for command in self.config.on_connect.iter() {
-                    if let Ok(cmd) = crate::command::parse(command, None) {
-                        if let Ok(command) = proto::Command::try_from(cmd) {
-                            self.handle.try_send(command.into())?;
-                        };
-                    };
+                let on_connect = self.config.on_connect.clone();
+                events.extend(self.send_commands(&on_connect)?);
+
+                if self.logged_in {
+                    events.extend(self.send_after_identify()?);
                 }
 
                 // Send JOIN
                 for message in group_joins(&self.config.channels, &self.config.channel_keys) {
                     self.handle.try_send(message)?;
                 }
+
+                return Ok(vec![Event::Single(message, self.nickname().to_owned())]
+                    .into_iter()
+                    .chain(events)
+                    .collect());
             }
             // QUIT
             Command::QUIT(comment) => {
@@ -1125,6 +1635,11 @@ impl Client {
                     channel.users.remove(&user);
                 });
 
+                self.previous_nicknames.remove(&user.nickname().to_owned());
+
+                let key = self.casemapping().fold(user.nickname().as_ref());
+                self.away_replies.remove(&key);
+
                 let channels = self.user_channels(user.nickname());
 
                 return Ok(vec![Event::Broadcast(Broadcast::Quit {
@@ -1139,6 +1654,7 @@ impl Client {
 
                 if user.nickname() == self.nickname() {
                     self.chanmap.remove(channel);
+                    self.kicks.remove(channel);
                 } else if let Some(channel) = self.chanmap.get_mut(channel) {
                     channel.users.remove(&user);
                 }
@@ -1147,7 +1663,17 @@ impl Client {
                 let user = ok!(message.user());
 
                 if user.nickname() == self.nickname() {
-                    self.chanmap.insert(channel.clone(), Channel::default());
+                    self.chanmap.insert(
+                        channel.clone(),
+                        Channel {
+                            joining: Some(Joining {
+                                sent_time: server_time(&message),
+                                topic: None,
+                            }),
+                            ..Channel::default()
+                        },
+                    );
+                    self.kicks.remove(channel);
 
                     // Sends WHO to get away state on users if WHO poll is enabled.
                     if self.config.who_poll_enabled {
@@ -1182,21 +1708,30 @@ impl Client {
                         channel.clone(),
                         server_time(&message),
                     )]);
-                } else if let Some(channel) = self.chanmap.get_mut(channel) {
+                } else {
                     let user = if self.supports_extended_join {
                         accountname.as_ref().map_or(user.clone(), |accountname| {
-                            user.with_accountname(accountname)
+                            self.with_account_seed(user.with_accountname(accountname))
                         })
                     } else {
                         user
                     };
 
-                    channel.users.insert(user);
+                    if let Some(channel) = self.chanmap.get_mut(channel) {
+                        channel.users.insert(user);
+                    }
                 }
             }
-            Command::KICK(channel, victim, _) => {
+            Command::KICK(channel, victim, comment) => {
                 if victim == self.nickname().as_ref() {
                     self.chanmap.remove(channel);
+                    self.kicks.insert(
+                        channel.clone(),
+                        Kick {
+                            by: message.user().map(|user| user.nickname().to_owned()),
+                            reason: comment.clone(),
+                        },
+                    );
                 } else if let Some(channel) = self.chanmap.get_mut(channel) {
                     channel
                         .users
@@ -1205,11 +1740,16 @@ impl Client {
             }
             Command::Numeric(RPL_WHOREPLY, args) => {
                 let target = ok!(args.get(1));
+                let bot_mode = self.bot_mode();
 
                 if self.is_channel(target) {
                     if let Some(channel) = self.chanmap.get_mut(target) {
                         channel.update_user_away(ok!(args.get(5)), ok!(args.get(6)));
 
+                        if let Some(bot_mode) = bot_mode {
+                            channel.update_user_bot(ok!(args.get(5)), ok!(args.get(6)), bot_mode);
+                        }
+
                         if matches!(channel.last_who, Some(WhoStatus::Requested(_, None)) | None) {
                             channel.last_who = Some(WhoStatus::Receiving(None));
                             log::debug!("[{}] {target} - WHO receiving...", self.server);
@@ -1224,11 +1764,16 @@ impl Client {
             }
             Command::Numeric(RPL_WHOSPCRPL, args) => {
                 let target = ok!(args.get(2));
+                let bot_mode = self.bot_mode();
 
                 if self.is_channel(target) {
                     if let Some(channel) = self.chanmap.get_mut(target) {
                         channel.update_user_away(ok!(args.get(3)), ok!(args.get(4)));
 
+                        if let Some(bot_mode) = bot_mode {
+                            channel.update_user_bot(ok!(args.get(3)), ok!(args.get(4)), bot_mode);
+                        }
+
                         if self.supports_account_notify {
                             if let (Some(user), Some(accountname)) = (args.get(3), args.get(5)) {
                                 channel.update_user_accountname(user, accountname);
@@ -1322,10 +1867,16 @@ impl Client {
                         }
                     }
                 } else {
+                    let ourself = self.is_own_nickname(target);
+
+                    if ourself {
+                        self.update_user_modes(modes);
+                    }
+
                     // Only check for being logged in via mode if account-notify is not available,
                     // since it is not standardized across networks.
 
-                    if target == self.nickname().as_ref()
+                    if ourself
                         && !self.supports_account_notify
                         && !self.registration_required_channels.is_empty()
                     {
@@ -1346,6 +1897,33 @@ impl Client {
                     }
                 }
             }
+            Command::Numeric(RPL_AWAY, args) => {
+                let key = self.casemapping().fold(ok!(args.get(1)));
+                let away_message = args.get(2).cloned().unwrap_or_default();
+                let now = Instant::now();
+
+                // Only show the same away reply again once the interval has passed
+                if self.away_replies.get(&key).is_some_and(|reply| {
+                    reply.message == away_message
+                        && now.duration_since(reply.received_at) < self.config.away_reply_interval
+                }) {
+                    return Ok(vec![]);
+                }
+
+                self.away_replies.insert(
+                    key,
+                    AwayReply {
+                        message: away_message,
+                        received_at: now,
+                    },
+                );
+            }
+            Command::Numeric(RPL_UMODEIS, args) => {
+                let modes = ok!(args.get(1));
+
+                self.user_modes.clear();
+                self.update_user_modes(modes);
+            }
             Command::Numeric(RPL_NAMREPLY, args) if args.len() > 3 => {
                 if let Some(channel) = self.chanmap.get_mut(&args[2]) {
                     for user in args[3].split(' ') {
@@ -1368,6 +1946,18 @@ impl Client {
                         if !channel.names_init {
                             channel.names_init = true;
 
+                            // The names list ends the replies to joining
+                            if let Some(joining) = channel.joining.take() {
+                                return Ok(vec![Event::Broadcast(Broadcast::Joined {
+                                    channel: target.clone(),
+                                    users: channel.users.len(),
+                                    topic: joining.topic,
+                                    topic_who: channel.topic.who.clone(),
+                                    topic_time: channel.topic.time,
+                                    sent_time: joining.sent_time,
+                                })]);
+                            }
+
                             return Ok(vec![]);
                         }
                     }
@@ -1385,8 +1975,16 @@ impl Client {
             }
             Command::Numeric(RPL_TOPIC, args) => {
                 if let Some(channel) = self.chanmap.get_mut(&args[1]) {
-                    channel.topic.content =
-                        Some(message::parse_fragments(ok!(args.get(2)).to_owned(), &[]));
+                    let topic = ok!(args.get(2));
+
+                    channel.topic.content = Some(message::parse_fragments(topic.to_owned(), &[]));
+
+                    // Summarized once joined
+                    if let Some(joining) = &mut channel.joining {
+                        joining.topic = Some(topic.clone());
+
+                        return Ok(vec![]);
+                    }
                 }
                 // Exclude topic message from history to prevent spam during dev
                 #[cfg(feature = "dev")]
@@ -1400,11 +1998,27 @@ impl Client {
                         Some(timestamp.datetime().ok_or_else(|| {
                             anyhow!("Unable to parse timestamp: {:?}", timestamp)
                         })?);
+
+                    // Summarized once joined
+                    if channel.joining.is_some() {
+                        return Ok(vec![]);
+                    }
                 }
                 // Exclude topic message from history to prevent spam during dev
                 #[cfg(feature = "dev")]
                 return Ok(vec![]);
             }
+            Command::Numeric(ERR_BADCHANNELKEY, args) => {
+                let channel = ok!(args.get(1)).clone();
+
+                // Don't retry a remembered key which is no longer valid
+                self.session_channel_keys.remove(&channel);
+
+                return Ok(vec![
+                    Event::Single(message, self.nickname().to_owned()),
+                    Event::ChannelKeyRequired(channel),
+                ]);
+            }
             Command::Numeric(ERR_NOCHANMODES, args) => {
                 let channel = ok!(args.get(1));
 
@@ -1423,6 +2037,10 @@ impl Client {
             }
             Command::Numeric(RPL_ISUPPORT, args) => {
                 let args_len = args.len();
+                let mut events = vec![];
+                // Tokens whose value changed, e.g. after oper-up or a rehash
+                let mut changed = vec![];
+
                 for (index, arg) in args.iter().enumerate().skip(1) {
                     let operation = arg.parse::<isupport::Operation>();
 
@@ -1437,7 +2055,18 @@ impl Client {
                                             parameter
                                         );
 
-                                        self.isupport.insert(kind.clone(), parameter.clone());
+                                        let previous =
+                                            self.isupport.insert(kind.clone(), parameter.clone());
+
+                                        if previous.as_ref() == Some(&parameter) {
+                                            continue;
+                                        }
+
+                                        changed.push(arg.as_str());
+
+                                        if let isupport::Parameter::NICKLEN(nicklen) = parameter {
+                                            events.extend(self.nicklen_notices(nicklen));
+                                        }
 
                                         if let isupport::Parameter::MONITOR(target_limit) =
                                             parameter
@@ -1464,7 +2093,9 @@ impl Client {
                                             self.server,
                                             kind
                                         );
-                                        self.isupport.remove(&kind);
+                                        if self.isupport.remove(&kind).is_some() {
+                                            changed.push(arg.as_str());
+                                        }
                                     }
                                 }
                             };
@@ -1482,7 +2113,18 @@ impl Client {
                     }
                 }
 
-                return Ok(vec![]);
+                if self.registered && !changed.is_empty() {
+                    events.push(self.status_notice(
+                        source::Status::Success,
+                        format!("Server changed ISUPPORT: {}", changed.join(" ")),
+                    ));
+                }
+
+                return Ok(events);
+            }
+            Command::Numeric(RPL_ENDOFMOTD | ERR_NOMOTD, _) => {
+                // ISUPPORT is sent before the MOTD, anything after is a change
+                self.registered = true;
             }
             Command::TAGMSG(_) => {
                 return Ok(vec![]);
@@ -1490,9 +2132,16 @@ impl Client {
             Command::ACCOUNT(accountname) => {
                 let old_user = ok!(message.user());
 
+                let account_seed = self.account_seeds.get(accountname).cloned();
+
                 self.chanmap.values_mut().for_each(|channel| {
                     if let Some(user) = channel.users.take(&old_user) {
-                        channel.users.insert(user.with_accountname(accountname));
+                        let user = user.with_accountname(accountname);
+
+                        channel.users.insert(match &account_seed {
+                            Some(seed) => user.with_color_seed(seed.clone()),
+                            None => user,
+                        });
                     }
                 });
 
@@ -1567,6 +2216,14 @@ impl Client {
                     .strip_prefix("timestamp=")
                     .and_then(|timestamp| timestamp.parse::<ReadMarker>().ok())
                 {
+                    if !self.is_channel(target) {
+                        let peer_read_marker = self
+                            .peer_read_markers
+                            .entry(Nick::from(target.as_str()))
+                            .or_insert(read_marker);
+                        *peer_read_marker = read_marker.max(*peer_read_marker);
+                    }
+
                     return Ok(vec![Event::UpdateReadMarker(target.clone(), read_marker)]);
                 }
             }
@@ -1601,24 +2258,244 @@ impl Client {
         Ok(vec![Event::Single(message, self.nickname().to_owned())])
     }
 
-    pub fn send_markread(&mut self, target: &str, read_marker: ReadMarker) -> Result<()> {
-        if self.supports_read_marker {
-            self.handle.try_send(command!(
-                "MARKREAD",
-                target.to_string(),
-                format!("timestamp={read_marker}"),
-            ))?;
+    fn update_user_modes(&mut self, modes: &str) {
+        let mut add = true;
+
+        for c in modes.chars() {
+            match c {
+                '+' => add = true,
+                '-' => add = false,
+                c if add => {
+                    self.user_modes.insert(c);
+                }
+                c => {
+                    self.user_modes.remove(&c);
+                }
+            }
         }
-        Ok(())
     }
 
-    // TODO allow configuring the "sorting method"
-    // this function sorts channels together which have similar names when the chantype prefix
-    // (sometimes multipled) is removed
-    // e.g. '#chat', '##chat-offtopic' and '&chat-local' all get sorted together instead of in
-    // wildly different places.
-    fn compare_channels(&self, a: &str, b: &str) -> Ordering {
-        let (Some(a_chantype), Some(b_chantype)) = (a.chars().nth(0), b.chars().nth(0)) else {
+    pub fn away_message(&self, nick: &Nick) -> Option<&str> {
+        self.away_replies
+            .get(&self.casemapping().fold(nick.as_ref()))
+            .map(|reply| reply.message.as_str())
+    }
+
+    /// Look up `nick` with `WHOIS` for a [`whois::Summary`], once per
+    /// connection. Only one lookup runs at a time so opening many queries
+    /// doesn't flood the server.
+    pub fn request_whois_summary(&mut self, nick: &Nick, whowas: bool) -> Result<()> {
+        self.whowas_summaries = whowas;
+
+        // Give up on lookups the server never answered
+        if self
+            .whois_requested_at
+            .is_some_and(|requested_at| requested_at.elapsed() >= WHOIS_LOOKUP_TIMEOUT)
+        {
+            for lookup in self.whois_lookups.values_mut() {
+                if lookup.is_pending() {
+                    *lookup = whois::Lookup::Failed;
+                }
+            }
+        }
+
+        if self.whois_lookups.contains_key(nick)
+            || self.whois_lookups.values().any(whois::Lookup::is_pending)
+        {
+            return Ok(());
+        }
+
+        self.whois_lookups.insert(
+            nick.clone(),
+            whois::Lookup::Pending(whois::Summary::default()),
+        );
+        self.handle.try_send(command!("WHOIS", nick.to_string()))?;
+        self.whois_requested_at = Some(Instant::now());
+
+        Ok(())
+    }
+
+    pub fn whois_summary(&self, nick: &Nick) -> Option<&whois::Summary> {
+        self.whois_lookups
+            .get(nick)
+            .and_then(whois::Lookup::summary)
+    }
+
+    pub fn whowas_summary(&self, nick: &Nick) -> Option<&whois::Whowas> {
+        self.whois_lookups.get(nick).and_then(whois::Lookup::whowas)
+    }
+
+    pub fn peer_read_marker(&self, nick: &Nick) -> Option<ReadMarker> {
+        self.peer_read_markers.get(nick).copied()
+    }
+
+    /// Record replies to our own `WHOIS` lookups, which aren't shown. Replies
+    /// to labeled requests are left alone, they're from `/whois`.
+    fn record_whois_lookup(&mut self, message: &message::Encoded) -> bool {
+        if message
+            .tags
+            .iter()
+            .any(|tag| tag.key == "label" || tag.key == "batch")
+        {
+            return false;
+        }
+
+        let Some(reply_nick) = whois::reply_nick(&message.command) else {
+            return false;
+        };
+
+        let Some(nick) = self.whois_lookups.iter().find_map(|(nick, lookup)| {
+            (lookup.is_pending() && nick.as_ref().eq_ignore_ascii_case(reply_nick.as_ref()))
+                .then(|| nick.clone())
+        }) else {
+            return false;
+        };
+
+        let Some(whois::Lookup::Pending(summary)) = self.whois_lookups.remove(&nick) else {
+            return false;
+        };
+
+        let lookup = summary.record(&message.command, self.chantypes(), |channel| {
+            self.chanmap.contains_key(channel)
+        });
+
+        if lookup == whois::Lookup::Failed && self.whowas_summaries {
+            self.request_whowas(&nick, None);
+        }
+
+        self.whois_lookups.insert(nick, lookup);
+
+        true
+    }
+
+    /// Follow up `/whois` of a user who doesn't exist with `WHOWAS`, to show
+    /// when they were last seen after the error
+    fn follow_up_whois(&mut self, message: &message::Encoded) {
+        use irc::proto::command::Numeric::*;
+
+        let Command::Numeric(numeric @ (RPL_ENDOFWHOIS | ERR_NOSUCHNICK), _) = &message.command
+        else {
+            return;
+        };
+        let Some(reply_nick) = whois::reply_nick(&message.command) else {
+            return;
+        };
+        let Some(nick) = self
+            .whois_requests
+            .keys()
+            .find(|nick| nick.as_ref().eq_ignore_ascii_case(reply_nick.as_ref()))
+            .cloned()
+        else {
+            return;
+        };
+        let Some(buffer) = self.whois_requests.remove(&nick) else {
+            return;
+        };
+
+        if *numeric == ERR_NOSUCHNICK {
+            self.request_whowas(&nick, Some(buffer));
+        }
+    }
+
+    /// Look up `nick` with `WHOWAS`, to show in `buffer` or in place of
+    /// their [`whois::Summary`]
+    fn request_whowas(&mut self, nick: &Nick, buffer: Option<buffer::Upstream>) {
+        let in_flight = self.whowas_lookups.contains_key(nick);
+        let lookup = self.whowas_lookups.entry(nick.clone()).or_default();
+
+        match buffer {
+            Some(buffer) => lookup.buffers.push(buffer),
+            None => lookup.summary = true,
+        }
+
+        if in_flight {
+            return;
+        }
+
+        if let Err(error) = self
+            .handle
+            .try_send(command!("WHOWAS", nick.to_string(), "1"))
+        {
+            log::warn!("Error sending WHOWAS: {error}");
+        }
+    }
+
+    /// Record replies to our own `WHOWAS` lookups. Once complete, the user's
+    /// history is shown in the buffers `/whois` was run from. Networks
+    /// without history of the user only show the `WHOIS` error.
+    fn record_whowas_lookup(&mut self, message: &message::Encoded) -> Option<Vec<Event>> {
+        if message
+            .tags
+            .iter()
+            .any(|tag| tag.key == "label" || tag.key == "batch")
+        {
+            return None;
+        }
+
+        let reply_nick = whois::whowas_reply_nick(&message.command)?;
+
+        let nick = self
+            .whowas_lookups
+            .keys()
+            .find(|nick| nick.as_ref().eq_ignore_ascii_case(reply_nick.as_ref()))?
+            .clone();
+
+        let lookup = self.whowas_lookups.get_mut(&nick)?;
+
+        if !lookup.whowas.record(&message.command) {
+            return Some(vec![]);
+        }
+
+        let lookup = self.whowas_lookups.remove(&nick)?;
+
+        if lookup.whowas.is_empty() {
+            return Some(vec![]);
+        }
+
+        let text = format!("{nick} is offline, {}", lookup.whowas);
+
+        if lookup.summary {
+            self.whois_lookups
+                .insert(nick, whois::Lookup::Offline(lookup.whowas));
+        }
+
+        Some(
+            lookup
+                .buffers
+                .into_iter()
+                .map(|buffer| {
+                    Event::WithTarget(
+                        message::Encoded::from(command!("NOTICE", "*", text.clone())),
+                        self.nickname().to_owned(),
+                        buffer.server_message_target(None),
+                    )
+                })
+                .collect(),
+        )
+    }
+
+    pub fn user_modes(&self) -> Option<String> {
+        (!self.user_modes.is_empty()).then(|| format!("+{}", self.user_modes.iter().join("")))
+    }
+
+    pub fn send_markread(&mut self, target: &str, read_marker: ReadMarker) -> Result<()> {
+        if self.supports_read_marker {
+            self.handle.try_send(command!(
+                "MARKREAD",
+                target.to_string(),
+                format!("timestamp={read_marker}"),
+            ))?;
+        }
+        Ok(())
+    }
+
+    // TODO allow configuring the "sorting method"
+    // this function sorts channels together which have similar names when the chantype prefix
+    // (sometimes multipled) is removed
+    // e.g. '#chat', '##chat-offtopic' and '&chat-local' all get sorted together instead of in
+    // wildly different places.
+    fn compare_channels(&self, a: &str, b: &str) -> Ordering {
+        let (Some(a_chantype), Some(b_chantype)) = (a.chars().nth(0), b.chars().nth(0)) else {
             return a.cmp(b);
         };
 
@@ -1667,7 +2544,21 @@ impl Client {
     pub fn send_chathistory_request(&mut self, subcommand: ChatHistorySubcommand) {
         if self.supports_chathistory {
             if let Some(target) = subcommand.target() {
-                if self.chathistory_requests.contains_key(target) {
+                if let Some(request) = self.chathistory_requests.get(target) {
+                    let queue = self
+                        .chathistory_queue
+                        .entry(target.to_string())
+                        .or_default();
+
+                    if request.subcommand != subcommand && !queue.contains(&subcommand) {
+                        log::debug!(
+                            "[{}] queueing chathistory request for {target} behind pending request",
+                            self.server,
+                        );
+
+                        queue.push_back(subcommand);
+                    }
+
                     return;
                 } else {
                     self.chathistory_requests.insert(
@@ -1675,6 +2566,7 @@ impl Client {
                         ChatHistoryRequest {
                             subcommand: subcommand.clone(),
                             requested_at: Instant::now(),
+                            batch: None,
                         },
                     );
                 }
@@ -1684,6 +2576,7 @@ impl Client {
                 self.chathistory_targets_request = Some(ChatHistoryRequest {
                     subcommand: subcommand.clone(),
                     requested_at: Instant::now(),
+                    batch: None,
                 });
             }
 
@@ -1795,11 +2688,27 @@ impl Client {
     pub fn clear_chathistory_request(&mut self, target: Option<&str>) {
         if let Some(target) = target {
             self.chathistory_requests.remove(target);
+
+            if let Some(subcommand) = self
+                .chathistory_queue
+                .get_mut(target)
+                .and_then(VecDeque::pop_front)
+            {
+                self.send_chathistory_request(subcommand);
+            }
         } else {
             self.chathistory_targets_request = None;
         }
     }
 
+    /// Whether `target` is configured to never be backfilled
+    pub fn backfill_disabled(&self, target: &str) -> bool {
+        self.config
+            .disable_backfill
+            .iter()
+            .any(|disabled| disabled.eq_ignore_ascii_case(target))
+    }
+
     pub fn chathistory_exhausted(&self, target: &str) -> bool {
         self.chathistory_exhausted
             .get(target)
@@ -1880,6 +2789,10 @@ impl Client {
         self.chanmap.get(channel).map(|channel| &channel.topic)
     }
 
+    fn kick<'a>(&'a self, channel: &str) -> Option<&'a Kick> {
+        self.kicks.get(channel)
+    }
+
     fn resolve_user_attributes<'a>(&'a self, channel: &str, user: &User) -> Option<&'a User> {
         self.chanmap
             .get(channel)
@@ -1905,6 +2818,18 @@ impl Client {
             .collect()
     }
 
+    fn casemapping(&self) -> isupport::CaseMap {
+        match self.isupport.get(&isupport::Kind::CASEMAPPING) {
+            Some(isupport::Parameter::CASEMAPPING(casemapping)) => casemapping.clone(),
+            _ => isupport::CaseMap::default(),
+        }
+    }
+
+    /// Whether `nick` is our nickname, under the server's `CASEMAPPING`
+    fn is_own_nickname(&self, nick: &str) -> bool {
+        self.casemapping().eq_names(nick, self.nickname().as_ref())
+    }
+
     pub fn nickname(&self) -> NickRef {
         // TODO: Fallback nicks
         NickRef::from(
@@ -1914,7 +2839,24 @@ impl Client {
         )
     }
 
+    /// Our nickname when connected with a fallback to the configured one
+    pub fn fallback_nickname(&self) -> Option<NickRef> {
+        let nickname = self.resolved_nick.as_deref()?;
+
+        (nickname != self.config.nickname).then(|| NickRef::from(nickname))
+    }
+
     pub fn tick(&mut self, now: Instant) -> Result<()> {
+        if let Some(last_attempt) = &mut self.nick_recovery {
+            if last_attempt.map_or(true, |last| {
+                now.duration_since(last) >= NICK_RECOVERY_INTERVAL
+            }) {
+                self.handle
+                    .try_send(command!("NICK", &self.config.nickname))?;
+                *last_attempt = Some(now);
+            }
+        }
+
         match self.highlight_blackout {
             HighlightBlackout::Blackout(instant) => {
                 if now.duration_since(instant) >= HIGHLIGHT_BLACKOUT_INTERVAL {
@@ -1977,9 +2919,33 @@ impl Client {
             }
         }
 
-        self.chathistory_requests.retain(|_, chathistory_request| {
-            now.duration_since(chathistory_request.requested_at) < CHATHISTORY_REQUEST_TIMEOUT
-        });
+        let timed_out = self
+            .chathistory_requests
+            .iter()
+            .filter(|(_, chathistory_request)| {
+                now.duration_since(chathistory_request.requested_at) >= CHATHISTORY_REQUEST_TIMEOUT
+            })
+            .map(|(target, _)| target.clone())
+            .collect::<Vec<_>>();
+
+        for target in timed_out {
+            log::debug!(
+                "[{}] chathistory request for {target} timed out",
+                self.server
+            );
+
+            self.clear_chathistory_request(Some(&target));
+        }
+
+        while self
+            .scheduled_commands
+            .front()
+            .is_some_and(|(send_at, _)| *send_at <= now)
+        {
+            if let Some((_, message)) = self.scheduled_commands.pop_front() {
+                self.handle.try_send(message)?;
+            }
+        }
 
         Ok(())
     }
@@ -2080,6 +3046,28 @@ pub async fn overwrite_chathistory_targets_timestamp(
     Ok(())
 }
 
+/// A channel shared with `nick` in which we're at least voiced, as required
+/// to message them with CPRIVMSG/CNOTICE
+fn shared_voiced_channel<'a>(
+    chanmap: &'a BTreeMap<String, Channel>,
+    our_nick: NickRef,
+    nick: &str,
+) -> Option<&'a str> {
+    let ourself = User::from(our_nick.to_owned());
+    let user = User::from(Nick::from(nick));
+
+    chanmap
+        .iter()
+        .find(|(_, channel)| {
+            channel.users.contains(&user)
+                && channel
+                    .users
+                    .get(&ourself)
+                    .is_some_and(|ourself| ourself.highest_access_level() >= AccessLevel::Voice)
+        })
+        .map(|(name, _)| name.as_str())
+}
+
 #[derive(Debug)]
 enum HighlightBlackout {
     Blackout(Instant),
@@ -2095,35 +3083,54 @@ impl HighlightBlackout {
     }
 }
 
+/// Client state per server
 #[derive(Debug, Default)]
-pub struct Map(BTreeMap<Server, State>);
+pub struct Map {
+    clients: BTreeMap<Server, State>,
+    /// Account color seeds of disconnected servers, so they survive a
+    /// reconnect
+    account_seeds: BTreeMap<Server, HashMap<String, Nick>>,
+}
 
 impl Map {
     pub fn len(&self) -> usize {
-        self.0.len()
+        self.clients.len()
+    }
+
+    pub fn contains(&self, server: &Server) -> bool {
+        self.clients.contains_key(server)
     }
 
     pub fn disconnected(&mut self, server: Server) {
-        self.0.insert(server, State::Disconnected);
+        if let Some(State::Ready(client)) = self.clients.insert(server.clone(), State::Disconnected)
+        {
+            self.account_seeds.insert(server, client.account_seeds);
+        }
     }
 
-    pub fn ready(&mut self, server: Server, client: Client) {
-        self.0.insert(server, State::Ready(client));
+    pub fn ready(&mut self, server: Server, mut client: Client) {
+        if let Some(account_seeds) = self.account_seeds.remove(&server) {
+            client.account_seeds.extend(account_seeds);
+        }
+
+        self.clients.insert(server, State::Ready(client));
     }
 
     pub fn is_empty(&self) -> bool {
-        self.0.is_empty()
+        self.clients.is_empty()
     }
 
     pub fn remove(&mut self, server: &Server) -> Option<Client> {
-        self.0.remove(server).and_then(|state| match state {
+        self.account_seeds.remove(server);
+
+        self.clients.remove(server).and_then(|state| match state {
             State::Disconnected => None,
             State::Ready(client) => Some(client),
         })
     }
 
     pub fn client(&self, server: &Server) -> Option<&Client> {
-        if let Some(State::Ready(client)) = self.0.get(server) {
+        if let Some(State::Ready(client)) = self.clients.get(server) {
             Some(client)
         } else {
             None
@@ -2131,7 +3138,7 @@ impl Map {
     }
 
     pub fn client_mut(&mut self, server: &Server) -> Option<&mut Client> {
-        if let Some(State::Ready(client)) = self.0.get_mut(server) {
+        if let Some(State::Ready(client)) = self.clients.get_mut(server) {
             Some(client)
         } else {
             None
@@ -2142,6 +3149,15 @@ impl Map {
         self.client(server).map(Client::nickname)
     }
 
+    pub fn take_previous_nickname(
+        &mut self,
+        server: &Server,
+        message: &message::Encoded,
+    ) -> Option<Nick> {
+        self.client_mut(server)
+            .and_then(|client| client.take_previous_nickname(message))
+    }
+
     pub fn receive(&mut self, server: &Server, message: message::Encoded) -> Result<Vec<Event>> {
         if let Some(client) = self.client_mut(server) {
             client.receive(message)
@@ -2151,7 +3167,7 @@ impl Map {
     }
 
     pub fn sync(&mut self, server: &Server) {
-        if let Some(State::Ready(client)) = self.0.get_mut(server) {
+        if let Some(State::Ready(client)) = self.clients.get_mut(server) {
             client.sync();
         }
     }
@@ -2180,6 +3196,12 @@ impl Map {
         }
     }
 
+    pub fn update_identity(&mut self, server: &Server, identity: &config::Identity) {
+        if let Some(client) = self.client_mut(server) {
+            client.update_identity(identity);
+        }
+    }
+
     pub fn quit(&mut self, server: &Server, reason: Option<String>) {
         if let Some(client) = self.client_mut(server) {
             client.quit(reason);
@@ -2187,7 +3209,7 @@ impl Map {
     }
 
     pub fn exit(&mut self) -> HashSet<Server> {
-        self.0
+        self.clients
             .iter_mut()
             .filter_map(|(server, state)| {
                 if let State::Ready(client) = state {
@@ -2228,6 +3250,59 @@ impl Map {
             .unwrap_or_default()
     }
 
+    /// Why we're no longer in `channel`, if we were kicked and haven't rejoined
+    pub fn get_channel_kick<'a>(&'a self, server: &Server, channel: &str) -> Option<&'a Kick> {
+        self.client(server).and_then(|client| client.kick(channel))
+    }
+
+    pub fn get_away_message<'a>(&'a self, server: &Server, nick: &Nick) -> Option<&'a str> {
+        self.client(server)
+            .and_then(|client| client.away_message(nick))
+    }
+
+    pub fn request_whois_summary(&mut self, server: &Server, nick: &Nick, whowas: bool) {
+        if let Some(client) = self.client_mut(server) {
+            if let Err(error) = client.request_whois_summary(nick, whowas) {
+                log::warn!("Error requesting whois summary: {error}");
+            }
+        }
+    }
+
+    pub fn get_whois_summary<'a>(
+        &'a self,
+        server: &Server,
+        nick: &Nick,
+    ) -> Option<&'a whois::Summary> {
+        self.client(server)
+            .and_then(|client| client.whois_summary(nick))
+    }
+
+    pub fn get_whowas_summary<'a>(
+        &'a self,
+        server: &Server,
+        nick: &Nick,
+    ) -> Option<&'a whois::Whowas> {
+        self.client(server)
+            .and_then(|client| client.whowas_summary(nick))
+    }
+
+    pub fn get_peer_read_marker(&self, server: &Server, nick: &Nick) -> Option<ReadMarker> {
+        self.client(server)
+            .and_then(|client| client.peer_read_marker(nick))
+    }
+
+    pub fn join_with_key(&mut self, server: &Server, channel: &str, key: &str) {
+        if let Some(client) = self.client_mut(server) {
+            if let Err(error) = client.join_with_key(channel, key) {
+                log::warn!("Error joining {channel} with key: {error}");
+            }
+        }
+    }
+
+    pub fn get_user_modes(&self, server: &Server) -> Option<String> {
+        self.client(server).and_then(Client::user_modes)
+    }
+
     pub fn get_channels<'a>(&'a self, server: &Server) -> &'a [String] {
         self.client(server)
             .map(|client| client.channels())
@@ -2273,6 +3348,14 @@ impl Map {
             .unwrap_or_default()
     }
 
+    pub fn get_server_caps(&self, server: &Server) -> history::ServerCaps {
+        history::ServerCaps {
+            supports_chathistory: self.get_server_supports_chathistory(server),
+            message_reference_types: self.get_server_chathistory_message_reference_types(server),
+            chathistory_limit: self.get_server_chathistory_limit(server),
+        }
+    }
+
     pub fn get_chathistory_request(
         &self,
         server: &Server,
@@ -2300,9 +3383,15 @@ impl Map {
             .unwrap_or_default()
     }
 
+    pub fn get_backfill_disabled(&self, server: &Server, target: &str) -> bool {
+        self.client(server)
+            .map(|client| client.backfill_disabled(target))
+            .unwrap_or_default()
+    }
+
     pub fn get_chathistory_state(&self, server: &Server, target: &str) -> Option<ChatHistoryState> {
         self.client(server).and_then(|client| {
-            if client.supports_chathistory {
+            if client.supports_chathistory && !client.backfill_disabled(target) {
                 if client.chathistory_request(target).is_some() {
                     Some(ChatHistoryState::PendingRequest)
                 } else if client.chathistory_exhausted(target) {
@@ -2339,7 +3428,7 @@ impl Map {
     }
 
     pub fn connected_servers(&self) -> impl Iterator<Item = &Server> {
-        self.0.iter().filter_map(|(server, state)| {
+        self.clients.iter().filter_map(|(server, state)| {
             if let State::Ready(_) = state {
                 Some(server)
             } else {
@@ -2349,11 +3438,11 @@ impl Map {
     }
 
     pub fn iter(&self) -> std::collections::btree_map::Iter<Server, State> {
-        self.0.iter()
+        self.clients.iter()
     }
 
     pub fn status(&self, server: &Server) -> Status {
-        self.0
+        self.clients
             .get(server)
             .map(|s| match s {
                 State::Disconnected => Status::Disconnected,
@@ -2363,7 +3452,7 @@ impl Map {
     }
 
     pub fn tick(&mut self, now: Instant) -> Result<()> {
-        for client in self.0.values_mut() {
+        for client in self.clients.values_mut() {
             if let State::Ready(client) = client {
                 client.tick(now)?;
             }
@@ -2419,6 +3508,7 @@ pub struct Batch {
     context: Option<Context>,
     events: Vec<Event>,
     chathistory: Option<ChatHistoryBatch>,
+    net: Option<NetBatch>,
 }
 
 impl Batch {
@@ -2427,8 +3517,69 @@ impl Batch {
             context,
             events: vec![],
             chathistory: None,
+            net: None,
+        }
+    }
+}
+
+/// `netsplit` or `netjoin` batch, with the servers involved
+#[derive(Debug, Clone)]
+enum NetBatch {
+    Split(Vec<String>),
+    Join(Vec<String>),
+}
+
+/// Collapse the quits of a `netsplit` or the joins of a `netjoin` into a
+/// single broadcast, leaving other events in the batch as they are
+fn collapse_net_batch(net: NetBatch, events: Vec<Event>, sent_time: DateTime<Utc>) -> Vec<Event> {
+    let mut users: Vec<(User, Vec<String>)> = vec![];
+    let mut rest = vec![];
+
+    for event in events {
+        match event {
+            Event::Broadcast(Broadcast::Quit { user, channels, .. })
+                if matches!(net, NetBatch::Split(_)) =>
+            {
+                users.push((user, channels));
+            }
+            Event::Single(message, our_nick) if matches!(net, NetBatch::Join(_)) => {
+                match (&message.command, message.user()) {
+                    (Command::JOIN(channel, _), Some(user)) => {
+                        match users
+                            .iter_mut()
+                            .find(|(joined, _)| joined.nickname() == user.nickname())
+                        {
+                            Some((_, channels)) => channels.push(channel.clone()),
+                            None => users.push((user, vec![channel.clone()])),
+                        }
+                    }
+                    _ => rest.push(Event::Single(message, our_nick)),
+                }
+            }
+            event => rest.push(event),
         }
     }
+
+    if users.is_empty() {
+        return rest;
+    }
+
+    let broadcast = match net {
+        NetBatch::Split(servers) => Broadcast::Netsplit {
+            servers,
+            users,
+            sent_time,
+        },
+        NetBatch::Join(servers) => Broadcast::Netjoin {
+            servers,
+            users,
+            sent_time,
+        },
+    };
+
+    std::iter::once(Event::Broadcast(broadcast))
+        .chain(rest)
+        .collect()
 }
 
 fn generate_label() -> String {
@@ -2471,12 +3622,26 @@ enum RegistrationStep {
     End,
 }
 
+#[derive(Debug, Clone)]
+pub struct Kick {
+    pub by: Option<Nick>,
+    pub reason: Option<String>,
+}
+
 #[derive(Debug, Default)]
 pub struct Channel {
     pub users: HashSet<User>,
     pub last_who: Option<WhoStatus>,
     pub topic: Topic,
     pub names_init: bool,
+    joining: Option<Joining>,
+}
+
+/// Replies to us joining a channel, until the names list ends
+#[derive(Debug)]
+struct Joining {
+    sent_time: DateTime<Utc>,
+    topic: Option<String>,
 }
 
 impl Channel {
@@ -2498,6 +3663,15 @@ impl Channel {
         }
     }
 
+    pub fn update_user_bot(&mut self, user: &str, flags: &str, bot_mode: char) {
+        let user = User::from(Nick::from(user));
+
+        if let Some(mut user) = self.users.take(&user) {
+            user.update_bot(flags.contains(bot_mode));
+            self.users.insert(user);
+        }
+    }
+
     pub fn update_user_accountname(&mut self, user: &str, accountname: &str) {
         let user = User::from(Nick::from(user));
 
@@ -2541,6 +3715,31 @@ fn group_capability_requests<'a>(
         .map(|capabilities| command!("CAP", "REQ", capabilities.into_iter().join(" ")))
 }
 
+/// JOIN `chanlist` with the keys found by `key`. Keyed channels are listed
+/// first, since keys apply to channels in order.
+fn join_with_keys<'a>(chanlist: &'a str, key: impl Fn(&'a str) -> Option<&'a str>) -> Command {
+    let (without_keys, with_keys): (Vec<_>, Vec<_>) =
+        chanlist
+            .split(',')
+            .partition_map(|channel| match key(channel) {
+                Some(key) => Either::Right((channel, key)),
+                None => Either::Left(channel),
+            });
+
+    if with_keys.is_empty() {
+        return Command::JOIN(chanlist.to_string(), None);
+    }
+
+    let channels = with_keys
+        .iter()
+        .map(|(channel, _)| *channel)
+        .chain(without_keys)
+        .join(",");
+    let keys = with_keys.iter().map(|(_, key)| *key).join(",");
+
+    Command::JOIN(channels, Some(keys))
+}
+
 /// Group channels together into as few JOIN messages as possible
 fn group_joins<'a>(
     channels: &'a [String],
@@ -2625,3 +3824,329 @@ pub enum Error {
     #[error(transparent)]
     SerdeJson(#[from] serde_json::Error),
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn channel(users: &[&str]) -> Channel {
+        Channel {
+            users: users
+                .iter()
+                .map(|user| User::try_from(*user).unwrap())
+                .collect(),
+            ..Channel::default()
+        }
+    }
+
+    #[test]
+    fn shared_voiced_channel_detection() {
+        let chanmap = BTreeMap::from([
+            ("#member".to_string(), channel(&["me", "alice", "bob"])),
+            ("#voiced".to_string(), channel(&["+me", "alice"])),
+            ("#op".to_string(), channel(&["@me", "carol"])),
+            ("#other".to_string(), channel(&["@dave"])),
+        ]);
+        let me = NickRef::from("me");
+
+        assert_eq!(
+            shared_voiced_channel(&chanmap, me, "alice"),
+            Some("#voiced")
+        );
+        assert_eq!(shared_voiced_channel(&chanmap, me, "carol"), Some("#op"));
+        // Shared, but we're neither op nor voiced
+        assert_eq!(shared_voiced_channel(&chanmap, me, "bob"), None);
+        // Op, but not in a channel with us
+        assert_eq!(shared_voiced_channel(&chanmap, me, "dave"), None);
+        assert_eq!(shared_voiced_channel(&chanmap, me, "erin"), None);
+    }
+
+    #[test]
+    fn join_adds_known_keys() {
+        let keys = HashMap::from([("#private".to_string(), "hunter2".to_string())]);
+        let key = |channel: &str| keys.get(channel).map(String::as_str);
+
+        assert_eq!(
+            join_with_keys("#halloy,#private", key),
+            Command::JOIN("#private,#halloy".to_string(), Some("hunter2".to_string()))
+        );
+        assert_eq!(
+            join_with_keys("#halloy", key),
+            Command::JOIN("#halloy".to_string(), None)
+        );
+    }
+
+    #[test]
+    fn whois_of_offline_user_follows_up_with_whowas() {
+        let (sender, mut receiver) = mpsc::channel(100);
+        let mut client = Client::new(Server::from("test"), config::Server::default(), sender);
+        let buffer = buffer::Upstream::Server(Server::from("test"));
+
+        let receive = |client: &mut Client, line: &str| {
+            client
+                .receive(message::Encoded::from(proto::parse::message(line).unwrap()))
+                .unwrap()
+        };
+
+        client.send(&buffer, message::Encoded::from(command!("WHOIS", "casper")));
+
+        // The error is shown as usual, followed by the user's history
+        assert_eq!(
+            receive(&mut client, ":server 401 me Casper :No such nick/channel").len(),
+            1
+        );
+        assert!(receive(
+            &mut client,
+            ":server 314 me casper ~casper host * :Casper Storm"
+        )
+        .is_empty());
+        assert!(receive(
+            &mut client,
+            ":server 312 me casper tungsten.libera.chat :Fri Oct 16 12:00:00 2026"
+        )
+        .is_empty());
+
+        let events = receive(&mut client, ":server 369 me casper :End of WHOWAS");
+        let [Event::WithTarget(message, _, _)] = events.as_slice() else {
+            panic!("expected a single line of history");
+        };
+        assert_eq!(
+            message.command,
+            command!(
+                "NOTICE",
+                "*",
+                "casper is offline, last seen as ~casper@host · Casper Storm · tungsten.libera.chat · signed off Fri Oct 16 12:00:00 2026"
+            )
+            .command
+        );
+
+        // Networks without history of the user only show the error
+        client.send(&buffer, message::Encoded::from(command!("WHOIS", "ghost")));
+
+        assert_eq!(
+            receive(&mut client, ":server 401 me ghost :No such nick/channel").len(),
+            1
+        );
+        assert!(receive(
+            &mut client,
+            ":server 406 me ghost :There was no such nickname"
+        )
+        .is_empty());
+        assert!(receive(&mut client, ":server 369 me ghost :End of WHOWAS").is_empty());
+
+        let sent = iter::from_fn(|| receiver.try_next().ok().flatten())
+            .map(|message| message.command)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            sent,
+            [
+                command!("WHOIS", "casper"),
+                command!("WHOWAS", "casper", "1"),
+                command!("WHOIS", "ghost"),
+                command!("WHOWAS", "ghost", "1"),
+            ]
+            .map(|message| message.command)
+        );
+    }
+
+    #[test]
+    fn isupport_changes_after_registration() {
+        let (sender, _receiver) = mpsc::channel(100);
+        let mut client = Client::new(Server::from("test"), config::Server::default(), sender);
+
+        let notices = |events: Vec<Event>| {
+            events
+                .into_iter()
+                .filter_map(|event| match event {
+                    Event::WithTarget(message, _, _) => match &message.command {
+                        Command::NOTICE(_, text) => Some(text.clone()),
+                        _ => None,
+                    },
+                    _ => None,
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let events = receive(
+            &mut client,
+            ":server 005 me WHOX NICKLEN=30 TOPICLEN=300 :are supported by this server",
+        );
+        assert!(notices(events).is_empty());
+
+        receive(&mut client, ":server 376 me :End of /MOTD command.");
+
+        let events = receive(
+            &mut client,
+            ":server 005 me -WHOX -KNOCK NICKLEN=31 TOPICLEN=300 :are supported by this server",
+        );
+        assert_eq!(
+            notices(events),
+            vec!["Server changed ISUPPORT: -WHOX NICKLEN=31".to_string()]
+        );
+        assert!(!client.isupport.contains_key(&isupport::Kind::WHOX));
+        assert!(matches!(
+            client.isupport.get(&isupport::Kind::NICKLEN),
+            Some(isupport::Parameter::NICKLEN(31))
+        ));
+    }
+
+    fn chathistory_client() -> (Client, mpsc::Receiver<proto::Message>) {
+        let (sender, receiver) = mpsc::channel(100);
+        let mut client = Client::new(Server::from("test"), config::Server::default(), sender);
+        client.supports_chathistory = true;
+
+        (client, receiver)
+    }
+
+    fn receive(client: &mut Client, line: &str) -> Vec<Event> {
+        client
+            .handle(
+                message::Encoded::from(proto::parse::message(line).unwrap()),
+                None,
+            )
+            .unwrap()
+    }
+
+    fn sent_requests(receiver: &mut mpsc::Receiver<proto::Message>) -> usize {
+        iter::from_fn(|| receiver.try_next().ok().flatten())
+            .filter(|message| matches!(message.command, Command::CHATHISTORY(..)))
+            .count()
+    }
+
+    fn privmsg_targets(events: &[Event]) -> Vec<&str> {
+        events
+            .iter()
+            .filter_map(|event| match event {
+                Event::Single(message, _) => match &message.command {
+                    Command::PRIVMSG(target, _) => Some(target.as_str()),
+                    _ => None,
+                },
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn latest(target: &str) -> ChatHistorySubcommand {
+        ChatHistorySubcommand::Latest(target.to_string(), MessageReference::None, 100)
+    }
+
+    #[test]
+    fn chathistory_interleaved_batches() {
+        let (mut client, mut receiver) = chathistory_client();
+
+        client.send_chathistory_request(latest("#a"));
+        client.send_chathistory_request(latest("#b"));
+        // Follow-up for a target with a request in flight is queued
+        let before = ChatHistorySubcommand::Before(
+            "#a".to_string(),
+            MessageReference::MessageId("abc".to_string()),
+            100,
+        );
+        client.send_chathistory_request(before.clone());
+        assert_eq!(sent_requests(&mut receiver), 2);
+
+        assert!(receive(&mut client, "BATCH +1 chathistory #a").is_empty());
+        assert!(receive(&mut client, "BATCH +2 chathistory #b").is_empty());
+        for line in [
+            "@batch=2 :bob!b@host PRIVMSG #b :one",
+            "@batch=1 :alice!a@host PRIVMSG #a :one",
+            "@batch=2 :bob!b@host PRIVMSG #b :two",
+            "@batch=1 :alice!a@host PRIVMSG #a :two",
+        ] {
+            assert!(receive(&mut client, line).is_empty());
+        }
+
+        let events = receive(&mut client, "BATCH -2");
+        assert_eq!(privmsg_targets(&events), vec!["#b", "#b"]);
+        assert!(client.chathistory_request("#b").is_none());
+        assert_eq!(client.chathistory_request("#a"), Some(latest("#a")));
+
+        let events = receive(&mut client, "BATCH -1");
+        assert_eq!(privmsg_targets(&events), vec!["#a", "#a"]);
+        // Queued request is sent once the first completes
+        assert_eq!(client.chathistory_request("#a"), Some(before));
+        assert_eq!(sent_requests(&mut receiver), 1);
+    }
+
+    #[test]
+    fn chathistory_retry_after_incomplete_batch() {
+        let (mut client, mut receiver) = chathistory_client();
+
+        client.send_chathistory_request(latest("#a"));
+        receive(&mut client, "BATCH +1 chathistory #a");
+        receive(&mut client, "@batch=1 :alice!a@host PRIVMSG #a :stale");
+
+        // Batch never completes, so the request times out
+        client
+            .tick(Instant::now() + CHATHISTORY_REQUEST_TIMEOUT)
+            .unwrap();
+        assert!(client.chathistory_request("#a").is_none());
+
+        client.send_chathistory_request(latest("#a"));
+        assert_eq!(sent_requests(&mut receiver), 2);
+
+        receive(&mut client, "BATCH +2 chathistory #a");
+        receive(&mut client, "@batch=2 :alice!a@host PRIVMSG #a :fresh");
+
+        // Late end of the abandoned batch leaves the retry in flight
+        let events = receive(&mut client, "BATCH -1");
+        assert_eq!(privmsg_targets(&events), vec!["#a"]);
+        assert_eq!(client.chathistory_request("#a"), Some(latest("#a")));
+
+        let events = receive(&mut client, "BATCH -2");
+        assert_eq!(privmsg_targets(&events), vec!["#a"]);
+        assert!(client.chathistory_request("#a").is_none());
+    }
+
+    #[test]
+    fn net_batch_collapses_joins() {
+        let join = |line: &str| {
+            Event::Single(
+                message::Encoded::from(proto::parse::message(line).unwrap()),
+                Nick::from("me"),
+            )
+        };
+        let events = vec![
+            join(":alice!a@host JOIN #halloy"),
+            join(":bob!b@host JOIN #halloy"),
+            join(":alice!a@host JOIN #rust"),
+            join(":irc.example MODE #halloy +o alice"),
+        ];
+
+        let servers = vec!["a.example".to_string(), "b.example".to_string()];
+        let collapsed = collapse_net_batch(NetBatch::Join(servers.clone()), events, Utc::now());
+
+        assert_eq!(collapsed.len(), 2);
+        let Event::Broadcast(Broadcast::Netjoin {
+            servers: joined_servers,
+            users,
+            ..
+        }) = &collapsed[0]
+        else {
+            panic!("joins weren't collapsed");
+        };
+        assert_eq!(joined_servers, &servers);
+        assert_eq!(
+            users
+                .iter()
+                .map(|(user, channels)| (user.nickname().to_string(), channels.clone()))
+                .collect::<Vec<_>>(),
+            vec![
+                (
+                    "alice".to_string(),
+                    vec!["#halloy".to_string(), "#rust".to_string()]
+                ),
+                ("bob".to_string(), vec!["#halloy".to_string()]),
+            ]
+        );
+        assert!(matches!(collapsed[1], Event::Single(..)));
+
+        // Nothing to collapse
+        let collapsed = collapse_net_batch(
+            NetBatch::Split(servers),
+            vec![join(":alice!a@host JOIN #halloy")],
+            Utc::now(),
+        );
+        assert!(matches!(collapsed.as_slice(), [Event::Single(..)]));
+    }
+}
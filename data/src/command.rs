@@ -4,7 +4,9 @@ use irc::proto;
 use itertools::Itertools;
 use regex::Regex;
 
-use crate::{buffer, ctcp, message::formatting};
+use crate::isupport::TextLimit;
+use crate::user::{ListFormat, NickRef};
+use crate::{buffer, ctcp, message::formatting, Server};
 
 #[derive(Debug, Clone, Copy)]
 pub enum Kind {
@@ -19,9 +21,21 @@ pub enum Kind {
     Topic,
     Kick,
     Mode,
+    Umode,
     Format,
     Away,
     Raw,
+    Clear,
+    ClearAll,
+    RawLog,
+    Translate,
+    Profile,
+    Note,
+    Theme,
+    Connect,
+    Disconnect,
+    Names,
+    MarkRead,
 }
 
 impl FromStr for Kind {
@@ -40,9 +54,21 @@ impl FromStr for Kind {
             "topic" | "t" => Ok(Kind::Topic),
             "kick" => Ok(Kind::Kick),
             "mode" | "m" => Ok(Kind::Mode),
+            "umode" => Ok(Kind::Umode),
             "format" | "f" => Ok(Kind::Format),
             "away" => Ok(Kind::Away),
             "raw" => Ok(Kind::Raw),
+            "clear" => Ok(Kind::Clear),
+            "clearall" => Ok(Kind::ClearAll),
+            "raw-log" => Ok(Kind::RawLog),
+            "translate" => Ok(Kind::Translate),
+            "profile" => Ok(Kind::Profile),
+            "note" => Ok(Kind::Note),
+            "theme" => Ok(Kind::Theme),
+            "connect" => Ok(Kind::Connect),
+            "disconnect" => Ok(Kind::Disconnect),
+            "names" => Ok(Kind::Names),
+            "markread" => Ok(Kind::MarkRead),
             _ => Err(()),
         }
     }
@@ -63,15 +89,86 @@ pub enum Command {
     Mode(String, Option<String>, Option<Vec<String>>),
     Away(Option<String>),
     Raw(String),
+    Clear(Clear),
+    /// Turn logging of raw protocol lines to the debug console on or off
+    RawLog(bool),
+    /// Translate text, or turn automatic translation on or off
+    Translate(Translate),
+    /// Switch to the named notification profile, or back to the top level
+    /// settings without a name
+    Profile(Option<String>),
+    /// Set the private note of the buffer, or edit it without text
+    Note(Option<String>),
+    /// Switch theme for the session, or save it to the config
+    Theme(Theme),
+    /// Connect to or disconnect from a configured server
+    Connection(Connection),
+    /// Copy the current channel's member list to the clipboard
+    CopyNames(ListFormat),
+    /// Mark the buffer as read, forced even when its stored read marker is
+    /// newer than the latest message
+    MarkRead(bool),
     Unknown(String, Vec<String>),
 }
 
-pub fn parse(s: &str, buffer: Option<&buffer::Upstream>) -> Result<Command, Error> {
+/// Clear buffers client-side, without sending anything to the server
+#[derive(Debug, Clone, Copy)]
+pub struct Clear {
+    /// Clear every open buffer instead of only the current one
+    pub all: bool,
+    /// Also delete the history stored on disk
+    pub history: bool,
+}
+
+/// Machine translation with the configured command, client-side
+#[derive(Debug, Clone)]
+pub enum Translate {
+    /// Turn automatic translation of the buffer's incoming messages on or off
+    Auto(bool),
+    /// Translate text into the input, to be reviewed before sending
+    Text(String),
+}
+
+#[derive(Debug, Clone)]
+pub enum Theme {
+    /// Pick a theme from the installed themes, previewing them live
+    Preview,
+    Switch(String),
+    Random,
+    /// Write the current theme to the config file
+    Save,
+}
+
+/// Connect to or disconnect from a configured server client-side, the
+/// current buffer's server unless named
+#[derive(Debug, Clone)]
+pub enum Connection {
+    Connect(Server),
+    Disconnect(Server),
+}
+
+/// Whether `input` starts with a slash followed by whitespace, e.g.
+/// `/ shrug`, which is sent as text rather than run as a command
+pub fn is_stray_slash(input: &str) -> bool {
+    input
+        .strip_prefix('/')
+        .is_some_and(|rest| rest.starts_with(char::is_whitespace) && !rest.trim().is_empty())
+}
+
+pub fn parse(
+    s: &str,
+    buffer: Option<&buffer::Upstream>,
+    our_nickname: Option<NickRef>,
+) -> Result<Command, Error> {
     let (head, rest) = s.split_once('/').ok_or(Error::MissingSlash)?;
     // Don't allow leading whitespace before slash
     if !head.is_empty() {
         return Err(Error::MissingSlash);
     }
+    // Nor whitespace after it, see `is_stray_slash`
+    if is_stray_slash(s) {
+        return Err(Error::MissingSlash);
+    }
 
     let mut split = rest.split_ascii_whitespace();
 
@@ -125,8 +222,7 @@ pub fn parse(s: &str, buffer: Option<&buffer::Upstream>) -> Result<Command, Erro
             Kind::Mode => {
                 if let Some((target, rest)) = args.split_first() {
                     if let Some((mode_string, mode_arguments)) = rest.split_first() {
-                        let mode_string_regex = Regex::new(r"^((\+|\-)[A-Za-z]*)+$").unwrap();
-                        if !mode_string_regex.is_match(mode_string) {
+                        if !is_mode_string(mode_string) {
                             Err(Error::InvalidModeString)
                         } else {
                             let mode_arguments: Vec<String> =
@@ -144,7 +240,115 @@ pub fn parse(s: &str, buffer: Option<&buffer::Upstream>) -> Result<Command, Erro
                     Err(Error::MissingArgs)
                 }
             }
+            Kind::Umode => {
+                if let Some(nick) = our_nickname {
+                    match args.as_slice() {
+                        [] => Ok(Command::Mode(nick.to_string(), None, None)),
+                        [mode_string] if is_mode_string(mode_string) => Ok(Command::Mode(
+                            nick.to_string(),
+                            Some(mode_string.to_string()),
+                            None,
+                        )),
+                        [_] => Err(Error::InvalidModeString),
+                        _ => Err(Error::IncorrectArgCount {
+                            min: 0,
+                            max: 1,
+                            actual: args.len(),
+                        }),
+                    }
+                } else {
+                    Ok(unknown())
+                }
+            }
             Kind::Away => validated::<0, 1, true>(args, |_, [comment]| Command::Away(comment)),
+            Kind::Clear | Kind::ClearAll => {
+                let all = matches!(kind, Kind::ClearAll);
+
+                match args.as_slice() {
+                    [] => Ok(Command::Clear(Clear {
+                        all,
+                        history: false,
+                    })),
+                    ["-history"] => Ok(Command::Clear(Clear { all, history: true })),
+                    [flag] => Err(Error::UnknownFlag(flag.to_string())),
+                    _ => Err(Error::IncorrectArgCount {
+                        min: 0,
+                        max: 1,
+                        actual: args.len(),
+                    }),
+                }
+            }
+            Kind::RawLog => match args.as_slice() {
+                [toggle] if toggle.eq_ignore_ascii_case("on") => Ok(Command::RawLog(true)),
+                [toggle] if toggle.eq_ignore_ascii_case("off") => Ok(Command::RawLog(false)),
+                [toggle] => Err(Error::InvalidToggle(toggle.to_string())),
+                _ => Err(Error::IncorrectArgCount {
+                    min: 1,
+                    max: 1,
+                    actual: args.len(),
+                }),
+            },
+            Kind::Translate => match args.as_slice() {
+                [] => Err(Error::IncorrectArgCount {
+                    min: 1,
+                    max: 1,
+                    actual: 0,
+                }),
+                [toggle] if toggle.eq_ignore_ascii_case("on") => {
+                    Ok(Command::Translate(Translate::Auto(true)))
+                }
+                [toggle] if toggle.eq_ignore_ascii_case("off") => {
+                    Ok(Command::Translate(Translate::Auto(false)))
+                }
+                _ => Ok(Command::Translate(Translate::Text(raw.to_string()))),
+            },
+            Kind::Profile => validated::<0, 1, false>(args, |_, [name]| Command::Profile(name)),
+            Kind::Note => validated::<0, 1, true>(args, |_, [note]| Command::Note(note)),
+            Kind::Theme => validated::<0, 1, true>(args, |_, [name]| {
+                Command::Theme(match name {
+                    None => Theme::Preview,
+                    Some(name) if name.eq_ignore_ascii_case("random") => Theme::Random,
+                    Some(name) if name.eq_ignore_ascii_case("save") => Theme::Save,
+                    Some(name) => Theme::Switch(name),
+                })
+            }),
+            Kind::Connect | Kind::Disconnect => {
+                // Server names may contain spaces, so take all of it
+                let server = match raw.trim() {
+                    "" => buffer
+                        .map(|buffer| buffer.server().clone())
+                        .ok_or(Error::MissingArgs)?,
+                    name => Server::from(name),
+                };
+
+                Ok(Command::Connection(match kind {
+                    Kind::Connect => Connection::Connect(server),
+                    _ => Connection::Disconnect(server),
+                }))
+            }
+            Kind::Names => match args.as_slice() {
+                ["-copy"] => Ok(Command::CopyNames(ListFormat::default())),
+                ["-copy", format] => format
+                    .parse()
+                    .map(Command::CopyNames)
+                    .map_err(|_| Error::InvalidListFormat(format.to_string())),
+                ["-copy", ..] => Err(Error::IncorrectArgCount {
+                    min: 0,
+                    max: 1,
+                    actual: args.len() - 1,
+                }),
+                _ => Ok(unknown()),
+            },
+            Kind::MarkRead => match args.as_slice() {
+                [] => Ok(Command::MarkRead(false)),
+                ["--force"] | ["-force"] => Ok(Command::MarkRead(true)),
+                [flag] => Err(Error::UnknownFlag(flag.to_string())),
+                _ => Err(Error::IncorrectArgCount {
+                    min: 0,
+                    max: 1,
+                    actual: args.len(),
+                }),
+            },
             Kind::Raw => Ok(Command::Raw(raw.to_string())),
             Kind::Format => {
                 if let Some(target) = buffer.and_then(|b| b.target()) {
@@ -158,6 +362,30 @@ pub fn parse(s: &str, buffer: Option<&buffer::Upstream>) -> Result<Command, Erro
     }
 }
 
+/// Text of an `/away`, `/topic` or `/kick` command in `input` which the
+/// server limits the length of, as it will be sent
+pub fn limited_text(input: &str) -> Option<(TextLimit, String)> {
+    let rest = input.strip_prefix('/')?;
+    let mut split = rest.split_ascii_whitespace();
+
+    let (limit, skip) = match split.next()?.parse::<Kind>().ok()? {
+        Kind::Away => (TextLimit::Away, 0),
+        Kind::Topic => (TextLimit::Topic, 1),
+        Kind::Kick => (TextLimit::Kick, 2),
+        _ => return None,
+    };
+
+    let text = split.skip(skip).join(" ");
+
+    (!text.is_empty()).then_some((limit, text))
+}
+
+fn is_mode_string(s: &str) -> bool {
+    let mode_string_regex = Regex::new(r"^((\+|\-)[A-Za-z]*)+$").unwrap();
+
+    mode_string_regex.is_match(s)
+}
+
 // TODO: Expand `validated` so we can better indicate which parameters is optional.
 fn validated<const EXACT: usize, const OPT: usize, const TEXT: bool>(
     args: Vec<&str>,
@@ -219,6 +447,15 @@ impl TryFrom<Command> for proto::Command {
             }
             Command::Away(comment) => proto::Command::AWAY(comment),
             Command::Raw(raw) => proto::Command::Raw(raw),
+            Command::Clear(_)
+            | Command::RawLog(_)
+            | Command::Translate(_)
+            | Command::Profile(_)
+            | Command::Note(_)
+            | Command::Theme(_)
+            | Command::Connection(_)
+            | Command::CopyNames(_)
+            | Command::MarkRead(_) => return Err(()),
             Command::Unknown(command, args) => proto::Command::new(&command, args),
         })
     }
@@ -240,6 +477,12 @@ pub enum Error {
     MissingArgs,
     #[error("invalid modestring")]
     InvalidModeString,
+    #[error("unknown flag {0}")]
+    UnknownFlag(String),
+    #[error("expected on or off, received {0}")]
+    InvalidToggle(String),
+    #[error("expected nicks, prefixes or hostmasks, received {0}")]
+    InvalidListFormat(String),
 }
 
 fn fmt_incorrect_arg_count(min: usize, max: usize, actual: usize) -> String {
@@ -23,6 +23,12 @@ pub fn decompress<T: DeserializeOwned>(data: &[u8]) -> Result<T, Error> {
     serde_json::from_slice(&bytes).map_err(Error::Decode)
 }
 
+/// Reader over the decompressed `data`, to decode only as much of it as
+/// is needed
+pub fn reader(data: &[u8]) -> impl Read + '_ {
+    io::BufReader::new(GzDecoder::new(data))
+}
+
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
     #[error("compression failed")]
@@ -1,3 +1,4 @@
+use std::collections::BTreeMap;
 use std::path::PathBuf;
 use std::{str, string};
 
@@ -13,8 +14,11 @@ use thiserror::Error;
 pub use self::buffer::Buffer;
 pub use self::channel::Channel;
 pub use self::file_transfer::FileTransfer;
+pub use self::identity::Identity;
 pub use self::keys::Keyboard;
+pub use self::logs::Logs;
 pub use self::notification::Notifications;
+pub use self::profile::Profile;
 pub use self::proxy::Proxy;
 pub use self::server::Server;
 pub use self::sidebar::Sidebar;
@@ -29,8 +33,12 @@ use crate::{environment, Theme};
 pub mod buffer;
 pub mod channel;
 pub mod file_transfer;
+pub mod identity;
 pub mod keys;
+pub mod logs;
+pub mod network;
 pub mod notification;
+pub mod profile;
 pub mod proxy;
 pub mod server;
 pub mod sidebar;
@@ -49,8 +57,13 @@ pub struct Config {
     pub sidebar: Sidebar,
     pub keyboard: Keyboard,
     pub notifications: Notifications<Sound>,
+    pub highlights: profile::Highlights,
+    pub profiles: BTreeMap<String, Profile<Sound>>,
     pub file_transfer: FileTransfer,
+    pub logs: Logs,
     pub tooltips: bool,
+    /// Max # servers connecting at the same time, unlimited when unset
+    pub max_concurrent_connections: Option<usize>,
 }
 
 #[derive(Debug, Clone, Copy, Deserialize)]
@@ -80,6 +93,63 @@ pub struct Font {
     pub size: Option<u8>,
 }
 
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+enum ThemeKeys {
+    Static(String),
+    Dynamic { light: String, dark: String },
+}
+
+impl Default for ThemeKeys {
+    fn default() -> Self {
+        Self::Static(Default::default())
+    }
+}
+
+impl ThemeKeys {
+    fn keys(&self) -> (&str, Option<&str>) {
+        match self {
+            ThemeKeys::Static(manual) => (manual, None),
+            ThemeKeys::Dynamic { light, dark } => (light, Some(dark)),
+        }
+    }
+}
+
+/// The config file as written, before it's loaded into [`Config`]
+#[derive(Deserialize)]
+struct Configuration {
+    #[serde(default)]
+    theme: ThemeKeys,
+    #[serde(default)]
+    identity: Identity,
+    servers: ServerMap,
+    proxy: Option<Proxy>,
+    #[serde(default)]
+    font: Font,
+    #[serde(default)]
+    scale_factor: ScaleFactor,
+    #[serde(default)]
+    buffer: Buffer,
+    #[serde(default)]
+    sidebar: Sidebar,
+    #[serde(default)]
+    keyboard: Keyboard,
+    #[serde(default)]
+    notifications: Notifications,
+    #[serde(default)]
+    highlights: profile::Highlights,
+    #[serde(default)]
+    profiles: BTreeMap<String, Profile>,
+    #[serde(default)]
+    file_transfer: FileTransfer,
+    #[serde(default)]
+    logs: Logs,
+    #[serde(default = "default_tooltip")]
+    tooltips: bool,
+    #[serde(default)]
+    max_concurrent_connections: Option<usize>,
+}
+
 impl Config {
     pub fn config_dir() -> PathBuf {
         let dir = environment::config_dir();
@@ -118,54 +188,36 @@ impl Config {
         Self::config_dir().join(environment::CONFIG_FILE_NAME)
     }
 
-    pub async fn load() -> Result<Self, Error> {
-        use tokio::fs;
-
-        #[derive(Deserialize, Debug)]
-        #[serde(untagged)]
-        pub enum ThemeKeys {
-            Static(String),
-            Dynamic { light: String, dark: String },
-        }
+    /// Notifications of the active `profile`, or the top level ones
+    pub fn notifications(&self, profile: Option<&str>) -> &Notifications<Sound> {
+        profile
+            .and_then(|profile| self.profiles.get(profile))
+            .map_or(&self.notifications, |profile| &profile.notifications)
+    }
 
-        impl Default for ThemeKeys {
-            fn default() -> Self {
-                Self::Static(Default::default())
-            }
-        }
+    /// Highlight settings of the active `profile`, or the top level ones
+    pub fn highlights(&self, profile: Option<&str>) -> &profile::Highlights {
+        profile
+            .and_then(|profile| self.profiles.get(profile))
+            .map_or(&self.highlights, |profile| &profile.highlights)
+    }
 
-        impl ThemeKeys {
-            pub fn keys(&self) -> (&str, Option<&str>) {
-                match self {
-                    ThemeKeys::Static(manual) => (manual, None),
-                    ThemeKeys::Dynamic { light, dark } => (light, Some(dark)),
-                }
-            }
+    /// Profile following `current` in name order, wrapping around to the
+    /// top level settings after the last one
+    pub fn next_profile(&self, current: Option<&str>) -> Option<String> {
+        let mut names = self.profiles.keys();
+
+        match current {
+            Some(current) => names
+                .skip_while(|name| name.as_str() != current)
+                .nth(1)
+                .cloned(),
+            None => names.next().cloned(),
         }
+    }
 
-        #[derive(Deserialize)]
-        pub struct Configuration {
-            #[serde(default)]
-            pub theme: ThemeKeys,
-            pub servers: ServerMap,
-            pub proxy: Option<Proxy>,
-            #[serde(default)]
-            pub font: Font,
-            #[serde(default)]
-            pub scale_factor: ScaleFactor,
-            #[serde(default)]
-            pub buffer: Buffer,
-            #[serde(default)]
-            pub sidebar: Sidebar,
-            #[serde(default)]
-            pub keyboard: Keyboard,
-            #[serde(default)]
-            pub notifications: Notifications,
-            #[serde(default)]
-            pub file_transfer: FileTransfer,
-            #[serde(default = "default_tooltip")]
-            pub tooltips: bool,
-        }
+    pub async fn load() -> Result<Self, Error> {
+        use tokio::fs;
 
         let path = Self::path();
         if !path.try_exists()? {
@@ -179,6 +231,7 @@ impl Config {
 
         let Configuration {
             theme,
+            identity,
             mut servers,
             font,
             proxy,
@@ -187,13 +240,22 @@ impl Config {
             sidebar,
             keyboard,
             notifications,
+            highlights,
+            profiles,
             file_transfer,
+            logs,
             tooltips,
+            max_concurrent_connections,
         } = toml::from_str(content.as_ref()).map_err(|e| Error::Parse(e.to_string()))?;
 
+        servers.inherit_identity(&identity)?;
         servers.read_passwords().await?;
 
         let loaded_notifications = notifications.load_sounds()?;
+        let loaded_profiles = profiles
+            .into_iter()
+            .map(|(name, profile)| Ok((name, profile.load_sounds()?)))
+            .collect::<Result<_, audio::LoadError>>()?;
 
         let appearance = Self::load_appearance(theme.keys())
             .await
@@ -209,12 +271,35 @@ impl Config {
             sidebar,
             keyboard,
             notifications: loaded_notifications,
+            highlights,
+            profiles: loaded_profiles,
             file_transfer,
+            logs,
             tooltips,
+            max_concurrent_connections,
         })
     }
 
     async fn load_appearance(theme_keys: (&str, Option<&str>)) -> Result<Appearance, Error> {
+        let all = Self::load_themes().await?;
+
+        let find = |key: &str| all.iter().find(|theme| theme.name == key).cloned();
+
+        let first_theme = find(theme_keys.0).unwrap_or_default();
+        let second_theme = theme_keys.1.map(|key| find(key).unwrap_or_default());
+
+        let selected = if let Some(second_theme) = second_theme {
+            appearance::Selected::dynamic(first_theme, second_theme)
+        } else {
+            appearance::Selected::specific(first_theme)
+        };
+
+        Ok(Appearance { selected, all })
+    }
+
+    /// Themes installed in the themes directory, read anew on each call so
+    /// themes added while running are picked up
+    pub async fn load_themes() -> Result<Vec<Theme>, Error> {
         use tokio::fs;
 
         #[derive(Deserialize)]
@@ -240,8 +325,6 @@ impl Config {
         };
 
         let mut all = vec![];
-        let mut first_theme = Theme::default();
-        let mut second_theme = theme_keys.1.map(|_| Theme::default());
         let mut has_halloy_theme = false;
 
         let mut stream = ReadDirStream::new(fs::read_dir(Self::themes_dir()).await?);
@@ -256,14 +339,6 @@ impl Config {
 
             if let Some(file_name) = file_name.strip_suffix(".toml") {
                 if let Some(theme) = read_entry(entry).await {
-                    if file_name == theme_keys.0 {
-                        first_theme = theme.clone();
-                    }
-
-                    if Some(file_name) == theme_keys.1 {
-                        second_theme = Some(theme.clone());
-                    }
-
                     if file_name.to_lowercase() == DEFAULT_THEME_NAME {
                         has_halloy_theme = true;
                     }
@@ -277,13 +352,74 @@ impl Config {
             all.push(Theme::default());
         }
 
-        let selected = if let Some(second_theme) = second_theme {
-            appearance::Selected::dynamic(first_theme, second_theme)
-        } else {
-            appearance::Selected::specific(first_theme)
-        };
+        Ok(all)
+    }
 
-        Ok(Appearance { selected, all })
+    /// Write `name` as the theme of the config file, leaving the rest of
+    /// the file as is
+    pub async fn save_theme(name: String) -> Result<(), Error> {
+        use tokio::fs;
+
+        let path = Self::path();
+
+        let content = fs::read_to_string(&path)
+            .await
+            .map_err(|e| Error::LoadConfigFile(e.to_string()))?;
+
+        Self::write(with_theme(&content, &name)).await
+    }
+
+    /// Write the server from `network` to the config file, over the server
+    /// `editing` if set, leaving the rest of the file as is
+    pub async fn save_server(
+        network: network::Network,
+        editing: Option<crate::Server>,
+    ) -> Result<(), Error> {
+        use tokio::fs;
+
+        let path = Self::path();
+
+        let content = fs::read_to_string(&path)
+            .await
+            .map_err(|e| Error::LoadConfigFile(e.to_string()))?;
+
+        let content = network::with_server(&content, &network, editing.as_ref())
+            .map_err(|e| Error::Parse(e.to_string()))?;
+
+        Self::write(content).await
+    }
+
+    /// Remove the server `server` from the config file
+    pub async fn remove_server(server: crate::Server) -> Result<(), Error> {
+        use tokio::fs;
+
+        let path = Self::path();
+
+        let content = fs::read_to_string(&path)
+            .await
+            .map_err(|e| Error::LoadConfigFile(e.to_string()))?;
+
+        let content = network::without_server(&content, server.as_ref())
+            .map_err(|e| Error::Parse(e.to_string()))?;
+
+        Self::write(content).await
+    }
+
+    /// Write `content` to the config file once it's known to load, through
+    /// a temporary file so a failed write never leaves it half written
+    async fn write(content: String) -> Result<(), Error> {
+        use tokio::fs;
+
+        toml::from_str::<Configuration>(&content).map_err(|e| Error::Parse(e.to_string()))?;
+
+        // The file a symlinked config points to is written, not the link
+        let path = fs::canonicalize(Self::path()).await?;
+        let temporary = path.with_extension("toml.tmp");
+
+        fs::write(&temporary, content).await?;
+        fs::rename(&temporary, &path).await?;
+
+        Ok(())
     }
 
     pub fn create_initial_config() {
@@ -328,6 +464,34 @@ fn default_tooltip() -> bool {
     true
 }
 
+/// `content` of a config file with its top level `theme` set to `name`
+fn with_theme(content: &str, name: &str) -> String {
+    let theme = format!("theme = {}", toml::Value::String(name.to_string()));
+
+    let mut lines = content.lines().map(String::from).collect::<Vec<_>>();
+
+    // Top level keys come before the first table
+    let top_level = lines
+        .iter()
+        .position(|line| line.trim_start().starts_with('['))
+        .unwrap_or(lines.len());
+
+    let existing = lines[..top_level].iter().position(|line| {
+        line.trim_start()
+            .strip_prefix("theme")
+            .is_some_and(|rest| rest.trim_start().starts_with('='))
+    });
+
+    match existing {
+        Some(index) => lines[index] = theme,
+        None => lines.insert(0, theme),
+    }
+
+    let mut content = lines.join("\n");
+    content.push('\n');
+    content
+}
+
 #[derive(Debug, Error, Clone)]
 pub enum Error {
     #[error("config could not be read: {0}")]
@@ -352,6 +516,10 @@ pub enum Error {
     DuplicateSaslPassword,
     #[error("Config does not exist")]
     ConfigMissing { has_yaml_config: bool },
+    #[error("No nickname set for {0}, set one for the server or in [identity].")]
+    MissingNickname(String),
+    #[error("Invalid nickname {nickname} for {server}, nicknames must start with a letter or one of []\\`_^{{|}} and may also contain digits and -.")]
+    InvalidNickname { server: String, nickname: String },
 }
 
 impl From<std::io::Error> for Error {
@@ -359,3 +527,78 @@ impl From<std::io::Error> for Error {
         Self::Io(error.to_string())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn with_theme_replaces_top_level_theme() {
+        let content = "# My config\ntheme = \"ferra\"\n\n[servers.libera]\ntheme = \"not-this\"\n";
+
+        assert_eq!(
+            with_theme(content, "halloy"),
+            "# My config\ntheme = \"halloy\"\n\n[servers.libera]\ntheme = \"not-this\"\n"
+        );
+
+        let dynamic = "theme = { light = \"a\", dark = \"b\" }\n";
+        assert_eq!(with_theme(dynamic, "c"), "theme = \"c\"\n");
+
+        // Without a theme it's added above the first table
+        assert_eq!(
+            with_theme("[servers.libera]\nnickname = \"me\"\n", "halloy"),
+            "theme = \"halloy\"\n[servers.libera]\nnickname = \"me\"\n"
+        );
+    }
+
+    #[test]
+    fn servers_inherit_identity() {
+        let identity: Identity = toml::from_str(
+            r#"
+            nickname = "casper"
+            alt_nicks = ["casper_"]
+            realname = "Casper"
+            "#,
+        )
+        .unwrap();
+        let mut servers: ServerMap = toml::from_str(
+            r#"
+            [libera]
+            server = "irc.libera.chat"
+
+            [oftc]
+            nickname = "storm"
+            realname = "Storm"
+            server = "irc.oftc.net"
+            "#,
+        )
+        .unwrap();
+
+        servers.inherit_identity(&identity).unwrap();
+
+        let identities = servers
+            .entries()
+            .map(|entry| entry.config.identity())
+            .collect::<Vec<_>>();
+
+        assert_eq!(identities[0].nickname.as_deref(), Some("casper"));
+        assert_eq!(identities[0].realname.as_deref(), Some("Casper"));
+        assert_eq!(identities[1].nickname.as_deref(), Some("storm"));
+        assert_eq!(identities[1].alt_nicks, vec!["casper_"]);
+        assert_eq!(identities[1].realname.as_deref(), Some("Storm"));
+
+        let mut servers: ServerMap =
+            toml::from_str("[libera]\nserver = \"irc.libera.chat\"\n").unwrap();
+        assert!(matches!(
+            servers.inherit_identity(&Identity::default()),
+            Err(Error::MissingNickname(_))
+        ));
+
+        let mut servers: ServerMap =
+            toml::from_str("[libera]\nnickname = \"1st\"\nserver = \"irc.libera.chat\"\n").unwrap();
+        assert!(matches!(
+            servers.inherit_identity(&Identity::default()),
+            Err(Error::InvalidNickname { .. })
+        ));
+    }
+}
@@ -1,10 +1,13 @@
+use std::time::Duration;
+
 use chrono::{DateTime, Local, Utc};
 use serde::Deserialize;
 
 use super::Channel;
 use crate::{
-    buffer::{Away, Nickname, StatusMessagePrefix, TextInput, Timestamp},
-    message::source,
+    buffer::{Away, Bot, Nickname, StatusMessagePrefix, TextInput, Timestamp},
+    message::{source, Source},
+    time, Message,
 };
 
 #[derive(Debug, Clone, Deserialize, Default)]
@@ -12,6 +15,8 @@ pub struct Buffer {
     #[serde(default)]
     pub away: Away,
     #[serde(default)]
+    pub bot: Bot,
+    #[serde(default)]
     pub timestamp: Timestamp,
     #[serde(default)]
     pub nickname: Nickname,
@@ -27,6 +32,18 @@ pub struct Buffer {
     pub status_message_prefix: StatusMessagePrefix,
     #[serde(default)]
     pub chathistory: ChatHistory,
+    #[serde(default)]
+    pub history: History,
+    #[serde(default)]
+    pub grouping: Grouping,
+    #[serde(default)]
+    pub translation: Translation,
+    #[serde(default)]
+    pub query: Query,
+    #[serde(default)]
+    pub corrections: Corrections,
+    #[serde(default)]
+    pub close: Close,
 }
 
 #[derive(Debug, Clone, Default, Deserialize)]
@@ -149,6 +166,160 @@ pub struct ChatHistory {
     pub infinite_scroll: bool,
 }
 
+#[derive(Debug, Clone, Deserialize)]
+pub struct History {
+    #[serde(default = "default_max_in_memory")]
+    pub max_in_memory: usize,
+    #[serde(default)]
+    pub timestamp_format: time::Format,
+    #[serde(default)]
+    pub retain_raw_messages: bool,
+    #[serde(default = "default_max_highlights")]
+    pub max_highlights: usize,
+    /// Max # seconds a message's server time may be ahead of the local clock
+    #[serde(default = "default_future_tolerance")]
+    pub future_tolerance: u64,
+    /// Max # bytes of history to write per session before warning
+    #[serde(default)]
+    pub write_budget: Option<u64>,
+    /// Mark buffers with only status messages (e.g. joins and parts) as read
+    #[serde(default)]
+    pub mark_status_only_read: bool,
+    /// Only mark buffers as read while they're focused and scrolled to the
+    /// bottom, with the window focused
+    #[serde(default = "default_bool_true")]
+    pub mark_read_when_focused: bool,
+    /// # seconds a buffer must be focused before it's marked as read
+    #[serde(default = "default_mark_read_delay")]
+    pub mark_read_delay: u64,
+}
+
+impl History {
+    pub fn mark_read_delay(&self) -> Duration {
+        Duration::from_secs(self.mark_read_delay)
+    }
+}
+
+impl Default for History {
+    fn default() -> Self {
+        Self {
+            max_in_memory: default_max_in_memory(),
+            timestamp_format: time::Format::default(),
+            retain_raw_messages: false,
+            max_highlights: default_max_highlights(),
+            future_tolerance: default_future_tolerance(),
+            write_budget: None,
+            mark_status_only_read: false,
+            mark_read_when_focused: true,
+            mark_read_delay: default_mark_read_delay(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Translation {
+    /// Shell command translating the text on its stdin to its stdout
+    #[serde(default)]
+    pub command: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Query {
+    /// Look up users with `WHOIS` when their query is open, and show a
+    /// summary at the top of it
+    #[serde(default)]
+    pub whois_summary: bool,
+    /// Look up users who've gone offline with `WHOWAS`, and show when they
+    /// were last seen in place of the summary
+    #[serde(default)]
+    pub whowas_summary: bool,
+}
+
+/// `s/typo/fix/` messages shown as the corrected text of the sender's
+/// previous message
+#[derive(Debug, Clone, Deserialize)]
+pub struct Corrections {
+    #[serde(default = "default_bool_true")]
+    pub enabled: bool,
+}
+
+impl Default for Corrections {
+    fn default() -> Self {
+        Self { enabled: true }
+    }
+}
+
+/// Safeguards when closing channels and queries
+#[derive(Debug, Clone, Deserialize)]
+pub struct Close {
+    /// Confirm leaving a channel or closing a query with unread highlights
+    /// or an unsent draft
+    #[serde(default)]
+    pub confirm: bool,
+    /// # seconds a closed buffer can be reopened with undo, 0 to disable
+    #[serde(default = "default_undo_duration")]
+    pub undo_duration: u64,
+}
+
+impl Default for Close {
+    fn default() -> Self {
+        Self {
+            confirm: false,
+            undo_duration: default_undo_duration(),
+        }
+    }
+}
+
+impl Close {
+    pub fn undo_duration(&self) -> Option<Duration> {
+        (self.undo_duration > 0).then(|| Duration::from_secs(self.undo_duration))
+    }
+}
+
+fn default_undo_duration() -> u64 {
+    10
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Grouping {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Max # seconds between two messages of a group
+    #[serde(default = "default_grouping_window")]
+    pub window: i64,
+}
+
+impl Default for Grouping {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            window: default_grouping_window(),
+        }
+    }
+}
+
+impl Grouping {
+    /// Whether `message` continues the group of `previous`, which requires
+    /// they're from the same user, on the same day and within `window`
+    pub fn continues(&self, previous: &Message, message: &Message) -> bool {
+        if !self.enabled {
+            return false;
+        }
+
+        let (Source::User(previous_user), Source::User(user)) =
+            (previous.target.source(), message.target.source())
+        else {
+            return false;
+        };
+
+        previous_user.nickname() == user.nickname()
+            && previous.target.prefixes() == message.target.prefixes()
+            && previous.server_time.with_timezone(&Local).date_naive()
+                == message.server_time.with_timezone(&Local).date_naive()
+            && (message.server_time - previous.server_time).num_seconds() <= self.window
+    }
+}
+
 #[derive(Debug, Copy, Clone, Default, Deserialize)]
 #[serde(rename_all = "kebab-case")]
 pub enum UsernameFormat {
@@ -177,3 +348,62 @@ impl Buffer {
 fn default_bool_true() -> bool {
     true
 }
+
+fn default_max_in_memory() -> usize {
+    2_000
+}
+
+fn default_grouping_window() -> i64 {
+    120
+}
+
+fn default_max_highlights() -> usize {
+    10_000
+}
+
+fn default_future_tolerance() -> u64 {
+    60
+}
+
+fn default_mark_read_delay() -> u64 {
+    2
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+
+    use super::*;
+    use crate::message::{self, Target};
+    use crate::user::Nick;
+    use crate::User;
+
+    fn from(nick: &str, seconds: i64) -> Message {
+        let mut message = Message::sent(
+            Target::Channel {
+                channel: "#halloy".to_string(),
+                source: Source::User(User::from(Nick::from(nick))),
+                prefixes: vec![],
+            },
+            message::plain("hello".to_string()),
+        );
+        message.server_time = "2024-11-01T12:00:00.000Z".parse::<DateTime<Utc>>().unwrap()
+            + Duration::seconds(seconds);
+        message
+    }
+
+    #[test]
+    fn grouping_continues() {
+        let grouping = Grouping {
+            enabled: true,
+            window: 120,
+        };
+
+        assert!(grouping.continues(&from("casperstorm", 0), &from("casperstorm", 120)));
+        assert!(!grouping.continues(&from("casperstorm", 0), &from("casperstorm", 121)));
+        assert!(!grouping.continues(&from("casperstorm", 0), &from("tarkah", 1)));
+
+        let disabled = Grouping::default();
+        assert!(!disabled.continues(&from("casperstorm", 0), &from("casperstorm", 1)));
+    }
+}
@@ -11,6 +11,77 @@ pub struct Channel {
     pub topic: Topic,
     #[serde(default)]
     pub message: Message,
+    #[serde(default)]
+    pub join: Join,
+    #[serde(default)]
+    pub mentions: Mentions,
+    #[serde(default)]
+    pub member_count: MemberCount,
+}
+
+/// How a channel's member count changed today, shown beside the count in
+/// the title bar
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct MemberCount {
+    /// Show e.g. "+12 today"
+    #[serde(default = "default_bool_true")]
+    pub show_delta: bool,
+    #[serde(default)]
+    pub show_sparkline: bool,
+    /// Channels with fewer members skip the delta and sparkline
+    #[serde(default = "default_member_count_min_members")]
+    pub min_members: usize,
+}
+
+impl Default for MemberCount {
+    fn default() -> Self {
+        Self {
+            show_delta: true,
+            show_sparkline: false,
+            min_members: default_member_count_min_members(),
+        }
+    }
+}
+
+fn default_member_count_min_members() -> usize {
+    20
+}
+
+/// Strip beside the scrollbar marking where the loaded messages highlight us
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct Mentions {
+    #[serde(default = "default_bool_true")]
+    pub enabled: bool,
+    /// Mark our own messages as well
+    #[serde(default)]
+    pub show_own_messages: bool,
+}
+
+impl Default for Mentions {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            show_own_messages: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+pub struct Join {
+    #[serde(default)]
+    pub replies: JoinReplies,
+}
+
+/// How the server's replies to us joining a channel are shown
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum JoinReplies {
+    /// A single line with the topic and # users
+    #[default]
+    Summary,
+    /// The topic and who set it as separate lines
+    Separate,
+    Hidden,
 }
 
 #[derive(Debug, Clone, Default, Deserialize)]
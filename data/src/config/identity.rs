@@ -0,0 +1,49 @@
+use serde::Deserialize;
+
+/// Nickname, username and realname used by servers which don't set their own
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+pub struct Identity {
+    pub nickname: Option<String>,
+    #[serde(default)]
+    pub alt_nicks: Vec<String>,
+    pub username: Option<String>,
+    pub realname: Option<String>,
+}
+
+/// Whether `nickname` only contains the characters allowed by RFC 2812, i.e.
+/// a letter or special character followed by letters, digits, special
+/// characters and hyphens. The length is left to the server's `NICKLEN`.
+pub fn is_valid_nickname(nickname: &str) -> bool {
+    let is_special = |c: char| "[]\\`_^{|}".contains(c);
+
+    let mut chars = nickname.chars();
+
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || is_special(c))
+        && chars.all(|c| c.is_ascii_alphanumeric() || is_special(c) || c == '-')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn valid_nicknames() {
+        for nickname in ["casper", "[away]", "tarkah_", "Dan-1", "`x^{|}"] {
+            assert!(is_valid_nickname(nickname), "{nickname}");
+        }
+
+        for nickname in [
+            "",
+            "1casper",
+            "-dash",
+            "with space",
+            "#channel",
+            "nick!",
+            "ünï",
+        ] {
+            assert!(!is_valid_nickname(nickname), "{nickname}");
+        }
+    }
+}
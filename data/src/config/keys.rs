@@ -42,6 +42,12 @@ pub struct Keyboard {
     pub theme_editor: KeyBind,
     #[serde(default = "KeyBind::highlight")]
     pub highlight: KeyBind,
+    #[serde(default = "KeyBind::previous_highlight")]
+    pub previous_highlight: KeyBind,
+    #[serde(default = "KeyBind::next_highlight")]
+    pub next_highlight: KeyBind,
+    #[serde(default = "KeyBind::undo_close_buffer")]
+    pub undo_close_buffer: KeyBind,
 }
 
 impl Default for Keyboard {
@@ -66,6 +72,9 @@ impl Default for Keyboard {
             logs: KeyBind::logs(),
             theme_editor: KeyBind::theme_editor(),
             highlight: KeyBind::highlight(),
+            previous_highlight: KeyBind::previous_highlight(),
+            next_highlight: KeyBind::next_highlight(),
+            undo_close_buffer: KeyBind::undo_close_buffer(),
         }
     }
 }
@@ -94,6 +103,9 @@ impl Keyboard {
             shortcut(self.logs.clone(), Logs),
             shortcut(self.theme_editor.clone(), ThemeEditor),
             shortcut(self.highlight.clone(), Highlight),
+            shortcut(self.previous_highlight.clone(), PreviousHighlight),
+            shortcut(self.next_highlight.clone(), NextHighlight),
+            shortcut(self.undo_close_buffer.clone(), UndoCloseBuffer),
         ]
     }
 }
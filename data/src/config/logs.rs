@@ -0,0 +1,160 @@
+use std::str::FromStr;
+
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Deserializer};
+
+use crate::log::Level;
+use crate::message::Content;
+use crate::Message;
+
+/// Which logs are kept on disk. Logs shown in the logs buffer during the
+/// session aren't affected.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Logs {
+    /// Keep logs on disk across sessions
+    #[serde(default = "default_bool_true")]
+    pub persist: bool,
+    /// Min level of logs kept on disk
+    #[serde(default = "default_level", deserialize_with = "deserialize_level")]
+    pub level: Level,
+    /// Max # logs kept on disk
+    #[serde(default)]
+    pub max_entries: Option<usize>,
+    /// Max # days logs are kept on disk
+    #[serde(default)]
+    pub max_age: Option<u32>,
+}
+
+impl Default for Logs {
+    fn default() -> Self {
+        Self {
+            persist: true,
+            level: default_level(),
+            max_entries: None,
+            max_age: None,
+        }
+    }
+}
+
+impl Logs {
+    /// The logs of `messages` to keep on disk at `now`
+    pub fn retain(&self, messages: &[Message], now: DateTime<Utc>) -> Vec<Message> {
+        if !self.persist {
+            return vec![];
+        }
+
+        let cutoff = self
+            .max_age
+            .map(|days| now - Duration::days(i64::from(days)));
+
+        let retained = messages
+            .iter()
+            .filter(|message| match &message.content {
+                Content::Log(record) => record.level <= self.level,
+                _ => true,
+            })
+            .filter(|message| cutoff.map_or(true, |cutoff| message.server_time >= cutoff))
+            .cloned()
+            .collect::<Vec<_>>();
+
+        match self.max_entries {
+            Some(max_entries) if retained.len() > max_entries => {
+                retained[retained.len() - max_entries..].to_vec()
+            }
+            _ => retained,
+        }
+    }
+}
+
+fn default_bool_true() -> bool {
+    true
+}
+
+fn default_level() -> Level {
+    Level::Trace
+}
+
+fn deserialize_level<'de, D>(deserializer: D) -> Result<Level, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let level: String = Deserialize::deserialize(deserializer)?;
+
+    log::Level::from_str(&level)
+        .map(Level::from)
+        .map_err(serde::de::Error::custom)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::log::Record;
+
+    fn log(level: Level, age: i64, now: DateTime<Utc>) -> Message {
+        Message::log(Record {
+            timestamp: now - Duration::days(age),
+            level,
+            message: format!("{level} {age}"),
+        })
+    }
+
+    #[test]
+    fn retain_by_level_age_and_count() {
+        let now = Utc::now();
+        let messages = [
+            log(Level::Info, 10, now),
+            log(Level::Debug, 2, now),
+            log(Level::Warn, 2, now),
+            log(Level::Error, 1, now),
+            log(Level::Trace, 0, now),
+            log(Level::Info, 0, now),
+        ];
+
+        let retain = |logs: Logs| {
+            logs.retain(&messages, now)
+                .into_iter()
+                .map(|message| message.server_time)
+                .collect::<Vec<_>>()
+        };
+        let times = |indices: &[usize]| {
+            indices
+                .iter()
+                .map(|index| messages[*index].server_time)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(retain(Logs::default()), times(&[0, 1, 2, 3, 4, 5]));
+        assert_eq!(
+            retain(Logs {
+                level: Level::Info,
+                ..Logs::default()
+            }),
+            times(&[0, 2, 3, 5])
+        );
+        assert_eq!(
+            retain(Logs {
+                level: Level::Info,
+                max_age: Some(7),
+                max_entries: Some(2),
+                ..Logs::default()
+            }),
+            times(&[3, 5])
+        );
+        assert!(retain(Logs {
+            persist: false,
+            ..Logs::default()
+        })
+        .is_empty());
+    }
+
+    #[test]
+    fn level_is_case_insensitive() {
+        let logs: Logs = toml::from_str(r#"level = "warn""#).unwrap();
+        assert_eq!(logs.level, Level::Warn);
+
+        let logs: Logs = toml::from_str(r#"level = "DEBUG""#).unwrap();
+        assert_eq!(logs.level, Level::Debug);
+
+        assert!(toml::from_str::<Logs>(r#"level = "loud""#).is_err());
+    }
+}
@@ -0,0 +1,500 @@
+use irc::proto;
+use thiserror::Error;
+use toml_edit::{Array, DocumentMut, InlineTable, Item, Table, TableLike, Value};
+
+use crate::config::identity;
+use crate::config::server::Sasl;
+use crate::server::Map as ServerMap;
+use crate::{config, Server};
+
+/// A server as entered in the network dialog
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Network {
+    pub name: String,
+    pub server: String,
+    pub port: String,
+    pub use_tls: bool,
+    pub nickname: String,
+    /// Left empty to connect without SASL
+    pub sasl_username: String,
+    /// Left empty when editing to keep the configured password
+    pub sasl_password: String,
+    /// Whether `sasl_password` is a command which outputs the password,
+    /// e.g. a lookup in the system keyring
+    pub sasl_password_is_command: bool,
+    /// Channels to join, separated by commas or spaces
+    pub channels: String,
+    /// Whether SASL is configured in a way the dialog can't edit, i.e.
+    /// EXTERNAL, and is left as is
+    pub keeps_sasl: bool,
+    has_sasl_password: bool,
+}
+
+impl Default for Network {
+    fn default() -> Self {
+        Self {
+            name: String::new(),
+            server: String::new(),
+            port: "6697".to_string(),
+            use_tls: true,
+            nickname: String::new(),
+            sasl_username: String::new(),
+            sasl_password: String::new(),
+            sasl_password_is_command: false,
+            channels: String::new(),
+            keeps_sasl: false,
+            has_sasl_password: false,
+        }
+    }
+}
+
+impl Network {
+    pub fn from_config(name: &Server, config: &config::Server) -> Self {
+        let mut network = Self {
+            name: name.to_string(),
+            server: config.server.clone(),
+            port: config.port.to_string(),
+            use_tls: config.use_tls,
+            nickname: config.nickname.clone(),
+            channels: config.channels.join(", "),
+            ..Self::default()
+        };
+
+        match &config.sasl {
+            Some(Sasl::Plain {
+                username,
+                password,
+                password_file,
+                password_command,
+            }) => {
+                network.sasl_username = username.clone();
+                network.has_sasl_password = true;
+
+                // A password read from a file stays in the file
+                if let Some(command) = password_command {
+                    network.sasl_password = command.clone();
+                    network.sasl_password_is_command = true;
+                } else if password_file.is_none() {
+                    network.sasl_password = password.clone().unwrap_or_default();
+                }
+            }
+            Some(Sasl::External { .. }) => network.keeps_sasl = true,
+            None => {}
+        }
+
+        network
+    }
+
+    /// Whether leaving the SASL password empty keeps the configured one,
+    /// which is only possible for a password rather than a command
+    pub fn keeps_sasl_password(&self) -> bool {
+        self.has_sasl_password && !self.sasl_password_is_command
+    }
+
+    pub fn channels(&self) -> impl Iterator<Item = &str> {
+        self.channels
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|channel| !channel.is_empty())
+    }
+
+    /// Check the network can be written as the server `editing`, or as a
+    /// new server when `None`
+    pub fn validate(&self, servers: &ServerMap, editing: Option<&Server>) -> Result<(), Error> {
+        let name = self.name.trim();
+
+        if name.is_empty() {
+            return Err(Error::MissingName);
+        }
+
+        if editing.map(AsRef::as_ref) != Some(name) && servers.contains(&Server::from(name)) {
+            return Err(Error::DuplicateName(name.to_string()));
+        }
+
+        let server = self.server.trim();
+
+        if server.is_empty() {
+            return Err(Error::MissingServer);
+        }
+
+        if server.contains(char::is_whitespace) {
+            return Err(Error::InvalidServer(server.to_string()));
+        }
+
+        if !self.port.trim().parse::<u16>().is_ok_and(|port| port != 0) {
+            return Err(Error::InvalidPort(self.port.trim().to_string()));
+        }
+
+        let nickname = self.nickname.trim();
+
+        if nickname.is_empty() {
+            return Err(Error::MissingNickname);
+        }
+
+        if !identity::is_valid_nickname(nickname) {
+            return Err(Error::InvalidNickname(nickname.to_string()));
+        }
+
+        if let Some(channel) = self
+            .channels()
+            .find(|channel| !proto::is_channel(channel, proto::DEFAULT_CHANNEL_PREFIXES))
+        {
+            return Err(Error::InvalidChannel(channel.to_string()));
+        }
+
+        if !self.keeps_sasl {
+            let has_username = !self.sasl_username.trim().is_empty();
+            let has_password = !self.sasl_password.is_empty();
+
+            if has_password && !has_username {
+                return Err(Error::MissingSaslUsername);
+            }
+
+            if has_username && !has_password && !self.keeps_sasl_password() {
+                return Err(Error::MissingSaslPassword);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum Error {
+    #[error("Enter a name for the network.")]
+    MissingName,
+    #[error("A network named {0} already exists.")]
+    DuplicateName(String),
+    #[error("Enter the server to connect to.")]
+    MissingServer,
+    #[error("Invalid server {0}, enter a hostname or address.")]
+    InvalidServer(String),
+    #[error("Invalid port {0}, enter a number between 1 and 65535.")]
+    InvalidPort(String),
+    #[error("Enter a nickname.")]
+    MissingNickname,
+    #[error("Invalid nickname {0}, nicknames must start with a letter or one of []\\`_^{{|}} and may also contain digits and -.")]
+    InvalidNickname(String),
+    #[error("Invalid channel {0}, channels start with # or &.")]
+    InvalidChannel(String),
+    #[error("Enter the SASL username for the password.")]
+    MissingSaslUsername,
+    #[error("Enter the SASL password, or a command which outputs it.")]
+    MissingSaslPassword,
+    #[error("The config file could not be edited: {0}")]
+    Edit(String),
+}
+
+/// `content` of a config file with the server from `network` added, or
+/// written over the server `editing`. Only the keys the dialog edits are
+/// written, the rest of the file is left as is.
+pub fn with_server(
+    content: &str,
+    network: &Network,
+    editing: Option<&Server>,
+) -> Result<String, Error> {
+    let mut document = parse(content)?;
+    let name = network.name.trim();
+
+    let servers = table_mut(document.as_item_mut(), "servers", true)?;
+
+    // Moved to its new name, keeping its place in the file
+    if let Some(previous) = editing.filter(|previous| previous.as_ref() != name) {
+        let servers = as_table(servers)?;
+
+        if let Some(server) = servers.remove(previous.as_ref()) {
+            servers.insert(name, server);
+        }
+    }
+
+    let server = table_mut(servers, name, false)?;
+    let table = as_table(server)?;
+
+    set(table, "server", network.server.trim());
+    set(
+        table,
+        "port",
+        network.port.trim().parse::<i64>().unwrap_or_default(),
+    );
+    set(table, "use_tls", network.use_tls);
+    set(table, "nickname", network.nickname.trim());
+
+    let channels = network.channels().collect::<Array>();
+
+    if channels.is_empty() {
+        table.remove("channels");
+    } else {
+        set(table, "channels", channels);
+    }
+
+    if !network.keeps_sasl {
+        let username = network.sasl_username.trim();
+        let password = network.sasl_password.as_str();
+
+        if username.is_empty() {
+            as_table(server)?.remove("sasl");
+        } else {
+            let sasl = table_mut(server, "sasl", true)?;
+            let plain = as_table(table_mut(sasl, "plain", false)?)?;
+
+            set(plain, "username", username);
+
+            if !password.is_empty() {
+                let (key, others) = if network.sasl_password_is_command {
+                    ("password_command", ["password", "password_file"])
+                } else {
+                    ("password", ["password_file", "password_command"])
+                };
+
+                set(plain, key, password);
+
+                for other in others {
+                    plain.remove(other);
+                }
+            }
+        }
+    }
+
+    Ok(document.to_string())
+}
+
+/// `content` of a config file without the server `name`
+pub fn without_server(content: &str, name: &str) -> Result<String, Error> {
+    let mut document = parse(content)?;
+
+    if let Some(servers) = document
+        .get_mut("servers")
+        .and_then(Item::as_table_like_mut)
+    {
+        servers.remove(name);
+    }
+
+    Ok(document.to_string())
+}
+
+fn parse(content: &str) -> Result<DocumentMut, Error> {
+    content
+        .parse::<DocumentMut>()
+        .map_err(|error| Error::Edit(error.to_string()))
+}
+
+fn as_table(item: &mut Item) -> Result<&mut dyn TableLike, Error> {
+    item.as_table_like_mut()
+        .ok_or_else(|| Error::Edit("expected a table".to_string()))
+}
+
+/// The table `key` of `item`, added when missing. A table added within an
+/// inline table is inline, and one added within dotted keys is dotted.
+fn table_mut<'a>(item: &'a mut Item, key: &str, is_implicit: bool) -> Result<&'a mut Item, Error> {
+    let is_inline = item.is_inline_table();
+    let is_dotted = item.as_table().is_some_and(Table::is_dotted);
+
+    let table = as_table(item)?.entry(key).or_insert_with(|| {
+        if is_inline {
+            Item::Value(Value::InlineTable(InlineTable::new()))
+        } else {
+            let mut table = Table::new();
+            table.set_implicit(is_implicit);
+            table.set_dotted(is_dotted);
+            Item::Table(table)
+        }
+    });
+
+    if table.is_table_like() {
+        Ok(table)
+    } else {
+        Err(Error::Edit(format!("expected {key} to be a table")))
+    }
+}
+
+/// Assign `value` to `key`, keeping the comments around a value it replaces
+fn set(table: &mut dyn TableLike, key: &str, value: impl Into<Value>) {
+    let mut value = value.into();
+
+    match table.get_mut(key).and_then(Item::as_value_mut) {
+        Some(existing) => {
+            *value.decor_mut() = existing.decor().clone();
+            *existing = value;
+        }
+        None => {
+            table.insert(key, Item::Value(value));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn network() -> Network {
+        Network {
+            name: "libera".to_string(),
+            server: "irc.libera.chat".to_string(),
+            nickname: "casper".to_string(),
+            channels: "#halloy, #rust".to_string(),
+            ..Network::default()
+        }
+    }
+
+    fn server(content: &str, name: &str) -> toml::Value {
+        toml::from_str::<toml::Value>(content).unwrap()["servers"][name].clone()
+    }
+
+    #[test]
+    fn adds_server_after_other_servers() {
+        let content =
+            "# Servers\n[servers.oftc]\nserver = \"irc.oftc.net\"\n\n# Fonts\n[font]\nsize = 13\n";
+
+        let written = with_server(content, &network(), None).unwrap();
+        let libera = server(&written, "libera");
+
+        assert_eq!(libera["server"].as_str(), Some("irc.libera.chat"));
+        assert_eq!(libera["port"].as_integer(), Some(6697));
+        assert_eq!(libera["use_tls"].as_bool(), Some(true));
+        assert_eq!(libera["nickname"].as_str(), Some("casper"));
+        assert_eq!(
+            libera["channels"],
+            toml::Value::Array(vec!["#halloy".into(), "#rust".into()])
+        );
+
+        let position = |text: &str| written.find(text).unwrap();
+
+        assert!(written.starts_with("# Servers\n[servers.oftc]\n"));
+        assert!(position("[servers.oftc]") < position("[servers.libera]"));
+        assert!(position("[servers.libera]") < position("# Fonts\n[font]"));
+    }
+
+    #[test]
+    fn edits_only_dialog_keys() {
+        let content = "[servers.libera]\nnickname = \"casper\" # mine\nserver = \"irc.libera.chat\"\nchannels = [\n  \"#halloy\",\n]\nping_time = 60\n\n[servers.libera.sasl.plain]\nusername = \"casper\"\npassword_file = \"/secret\"\n";
+
+        let mut network = network();
+        network.name = "libera-chat".to_string();
+        network.port = "6667".to_string();
+        network.use_tls = false;
+        network.channels = String::new();
+        network.sasl_username = "casper".to_string();
+        network.has_sasl_password = true;
+
+        let written = with_server(content, &network, Some(&Server::from("libera"))).unwrap();
+        let libera = server(&written, "libera-chat");
+
+        assert!(written.contains("nickname = \"casper\" # mine\n"));
+        assert!(toml::from_str::<toml::Value>(&written).unwrap()["servers"]
+            .get("libera")
+            .is_none());
+        assert_eq!(libera["port"].as_integer(), Some(6667));
+        assert_eq!(libera["use_tls"].as_bool(), Some(false));
+        assert_eq!(libera["ping_time"].as_integer(), Some(60));
+        assert!(libera.get("channels").is_none());
+        assert_eq!(
+            libera["sasl"]["plain"]["password_file"].as_str(),
+            Some("/secret")
+        );
+
+        network.sasl_password = "secret-tool lookup irc libera".to_string();
+        network.sasl_password_is_command = true;
+
+        let written = with_server(content, &network, Some(&Server::from("libera"))).unwrap();
+        let plain = &server(&written, "libera-chat")["sasl"]["plain"];
+
+        assert_eq!(
+            plain["password_command"].as_str(),
+            Some("secret-tool lookup irc libera")
+        );
+        assert!(plain.get("password_file").is_none());
+
+        network.sasl_username = String::new();
+
+        let written = with_server(content, &network, Some(&Server::from("libera"))).unwrap();
+
+        assert!(server(&written, "libera-chat").get("sasl").is_none());
+        assert!(!written.contains("sasl"));
+    }
+
+    #[test]
+    fn edits_dotted_keys_and_inline_tables() {
+        let mut network = network();
+        network.sasl_username = "casper".to_string();
+        network.sasl_password = "hunter2".to_string();
+
+        let content = "[servers.libera]\nserver = \"irc.libera.chat\"\nsasl.plain.username = \"casper\"\nsasl.plain.password = \"hunter1\"\non_connect = \"\"\"\n[servers.libera.sasl.plain]\n\"\"\"\n";
+
+        let written = with_server(content, &network, Some(&Server::from("libera"))).unwrap();
+        let libera = server(&written, "libera");
+
+        assert_eq!(
+            libera["sasl"]["plain"]["password"].as_str(),
+            Some("hunter2")
+        );
+        assert_eq!(
+            libera["on_connect"].as_str(),
+            Some("[servers.libera.sasl.plain]\n")
+        );
+
+        let content = "servers = { libera = { server = \"irc.libera.chat\" } }\n";
+
+        let written = with_server(content, &network, Some(&Server::from("libera"))).unwrap();
+        let libera = server(&written, "libera");
+
+        assert_eq!(libera["port"].as_integer(), Some(6697));
+        assert_eq!(
+            libera["sasl"]["plain"]["password"].as_str(),
+            Some("hunter2")
+        );
+    }
+
+    #[test]
+    fn removes_server() {
+        let content = "[servers.libera]\nserver = \"irc.libera.chat\"\n\n[servers.libera.sasl.plain]\nusername = \"casper\"\n\n# OFTC\n[servers.\"oftc\"]\nserver = \"irc.oftc.net\"\n";
+
+        let written = without_server(content, "libera").unwrap();
+
+        assert!(!written.contains("libera"));
+        assert!(written.contains("# OFTC\n[servers.\"oftc\"]\nserver = \"irc.oftc.net\"\n"));
+
+        let written = without_server(content, "oftc").unwrap();
+
+        assert!(!written.contains("oftc"));
+        assert_eq!(
+            server(&written, "libera")["sasl"]["plain"]["username"].as_str(),
+            Some("casper")
+        );
+    }
+
+    #[test]
+    fn validates() {
+        let servers =
+            toml::from_str::<ServerMap>("[libera]\nserver = \"irc.libera.chat\"\n").unwrap();
+
+        assert_eq!(
+            network().validate(&servers, None),
+            Err(Error::DuplicateName("libera".to_string()))
+        );
+        assert_eq!(
+            network().validate(&servers, Some(&Server::from("libera"))),
+            Ok(())
+        );
+
+        let mut network = network();
+        network.name = "oftc".to_string();
+        network.port = "70000".to_string();
+        assert_eq!(
+            network.validate(&servers, None),
+            Err(Error::InvalidPort("70000".to_string()))
+        );
+
+        network.port = "6697".to_string();
+        network.channels = "#halloy rust".to_string();
+        assert_eq!(
+            network.validate(&servers, None),
+            Err(Error::InvalidChannel("rust".to_string()))
+        );
+
+        network.channels = String::new();
+        network.sasl_username = "casper".to_string();
+        assert_eq!(
+            network.validate(&servers, None),
+            Err(Error::MissingSaslPassword)
+        );
+    }
+}
@@ -1,3 +1,5 @@
+use std::time::Duration;
+
 use serde::Deserialize;
 
 use crate::audio::{self, Sound};
@@ -35,9 +37,15 @@ pub struct Notifications<T = String> {
     #[serde(default)]
     pub file_transfer_request: Notification<T>,
     #[serde(default)]
+    pub file_transfer_completed: Notification<T>,
+    #[serde(default)]
+    pub file_transfer_failed: Notification<T>,
+    #[serde(default)]
     pub monitored_online: Notification<T>,
     #[serde(default)]
     pub monitored_offline: Notification<T>,
+    #[serde(default)]
+    pub rate_limit: RateLimit,
 }
 
 impl<T> Default for Notifications<T> {
@@ -49,8 +57,11 @@ impl<T> Default for Notifications<T> {
             direct_message: Notification::default(),
             highlight: Notification::default(),
             file_transfer_request: Notification::default(),
+            file_transfer_completed: Notification::default(),
+            file_transfer_failed: Notification::default(),
             monitored_online: Notification::default(),
             monitored_offline: Notification::default(),
+            rate_limit: RateLimit::default(),
         }
     }
 }
@@ -71,8 +82,74 @@ impl Notifications {
             direct_message: load(&self.direct_message)?,
             highlight: load(&self.highlight)?,
             file_transfer_request: load(&self.file_transfer_request)?,
+            file_transfer_completed: load(&self.file_transfer_completed)?,
+            file_transfer_failed: load(&self.file_transfer_failed)?,
             monitored_online: load(&self.monitored_online)?,
             monitored_offline: load(&self.monitored_offline)?,
+            rate_limit: self.rate_limit.clone(),
         })
     }
 }
+
+/// Caps on highlights and direct messages from a single sender or buffer,
+/// past which they're coalesced into a single notification
+#[derive(Debug, Clone, Deserialize)]
+pub struct RateLimit {
+    #[serde(default = "default_bool_true")]
+    pub enabled: bool,
+    /// Max # notifications from a sender within `window`
+    #[serde(default = "default_per_sender")]
+    pub per_sender: usize,
+    /// Max # notifications from a buffer within `window`
+    #[serde(default = "default_per_buffer")]
+    pub per_buffer: usize,
+    /// # seconds notifications are counted over
+    #[serde(default = "default_window")]
+    pub window: u64,
+    /// # seconds without notifications from a limited sender or buffer
+    /// before the limit resets
+    #[serde(default = "default_quiet_period")]
+    pub quiet_period: u64,
+}
+
+impl Default for RateLimit {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            per_sender: default_per_sender(),
+            per_buffer: default_per_buffer(),
+            window: default_window(),
+            quiet_period: default_quiet_period(),
+        }
+    }
+}
+
+impl RateLimit {
+    pub fn window(&self) -> Duration {
+        Duration::from_secs(self.window)
+    }
+
+    pub fn quiet_period(&self) -> Duration {
+        Duration::from_secs(self.quiet_period)
+    }
+}
+
+fn default_bool_true() -> bool {
+    true
+}
+
+fn default_per_sender() -> usize {
+    5
+}
+
+fn default_per_buffer() -> usize {
+    10
+}
+
+fn default_window() -> u64 {
+    60
+}
+
+fn default_quiet_period() -> u64 {
+    30
+}
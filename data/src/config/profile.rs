@@ -0,0 +1,92 @@
+use serde::Deserialize;
+
+use super::notification::Notifications;
+use crate::audio::{self, Sound};
+
+/// Notification and highlight settings replacing the top level ones while
+/// the profile is active
+#[derive(Debug, Clone, Deserialize)]
+pub struct Profile<T = String> {
+    #[serde(default)]
+    pub notifications: Notifications<T>,
+    #[serde(default)]
+    pub highlights: Highlights,
+}
+
+impl Profile {
+    pub fn load_sounds(&self) -> Result<Profile<Sound>, audio::LoadError> {
+        Ok(Profile {
+            notifications: self.notifications.load_sounds()?,
+            highlights: self.highlights.clone(),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Highlights {
+    #[serde(default = "default_bool_true")]
+    pub enabled: bool,
+    #[serde(default)]
+    pub exclude: Vec<String>,
+    #[serde(default)]
+    pub include: Vec<String>,
+}
+
+impl Default for Highlights {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            exclude: Default::default(),
+            include: Default::default(),
+        }
+    }
+}
+
+impl Highlights {
+    pub fn should_highlight(&self, channel: &str) -> bool {
+        if !self.enabled {
+            return false;
+        }
+
+        let is_channel_filtered = |list: &Vec<String>, channel: &str| -> bool {
+            let wildcards = ["*", "all"];
+
+            list.iter()
+                .any(|item| wildcards.contains(&item.as_str()) || item == channel)
+        };
+
+        let channel_included = is_channel_filtered(&self.include, channel);
+        let channel_excluded = is_channel_filtered(&self.exclude, channel);
+
+        // If the channel is included, it has precedence over excluded.
+        channel_included || !channel_excluded
+    }
+}
+
+fn default_bool_true() -> bool {
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn include_has_precedence() {
+        let highlights = Highlights {
+            enabled: true,
+            exclude: vec!["*".to_string()],
+            include: vec!["#team".to_string()],
+        };
+
+        assert!(highlights.should_highlight("#team"));
+        assert!(!highlights.should_highlight("#random"));
+
+        let highlights = Highlights {
+            enabled: false,
+            ..highlights
+        };
+
+        assert!(!highlights.should_highlight("#team"));
+    }
+}
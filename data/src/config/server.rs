@@ -3,13 +3,19 @@ use std::path::PathBuf;
 use std::time::Duration;
 
 use irc::connection;
+use rand::Rng;
 use serde::{Deserialize, Deserializer};
 
 use crate::config;
+use crate::config::Identity;
+
+/// Max # nicknames tried from `nick_pattern`
+const MAX_NICK_PATTERN_ATTEMPTS: usize = 9;
 
 #[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
 pub struct Server {
-    /// The client's nickname.
+    /// The client's nickname, from `[identity]` if not set.
+    #[serde(default)]
     pub nickname: String,
     /// The client's NICKSERV password.
     pub nick_password: Option<String>,
@@ -22,6 +28,16 @@ pub struct Server {
     /// Alternative nicknames for the client, if the default is taken.
     #[serde(default)]
     pub alt_nicks: Vec<String>,
+    /// Pattern for nicknames tried once `alt_nicks` are taken, where `{n}` is
+    /// replaced with 1, 2, etc. and `{random}` with random digits.
+    pub nick_pattern: Option<String>,
+    /// What to do when the nickname is taken while connecting.
+    #[serde(default)]
+    pub nick_in_use: NickInUse,
+    /// Whether to keep trying to change to `nickname` when connected with
+    /// another one.
+    #[serde(default = "default_recover_nick")]
+    pub recover_nick: bool,
     /// The client's username.
     pub username: Option<String>,
     /// The client's real name.
@@ -52,6 +68,13 @@ pub struct Server {
     /// The amount of time in seconds before attempting to reconnect to the server when disconnected.
     #[serde(default = "default_reconnect_delay")]
     pub reconnect_delay: u64,
+    /// Whether to connect to the server on startup. Otherwise it's listed as disconnected until
+    /// connected to with `/connect`.
+    #[serde(default = "default_autoconnect")]
+    pub autoconnect: bool,
+    /// The amount of time in seconds to wait on startup before connecting to the server.
+    #[serde(default)]
+    pub connect_delay: u64,
     /// Whether the client should use NickServ GHOST to reclaim its primary nickname if it is in
     /// use. This has no effect if `nick_password` is not set.
     #[serde(default)]
@@ -63,6 +86,7 @@ pub struct Server {
     #[serde(default = "default_ghost_sequence")]
     pub ghost_sequence: Vec<String>,
     /// User modestring to set on connect. Example: "+RB-x"
+    #[serde(alias = "on_connect_modes")]
     pub umodes: Option<String>,
     /// Whether or not to use TLS.
     /// Clients will automatically panic if this is enabled without TLS support.
@@ -77,7 +101,10 @@ pub struct Server {
     pub sasl: Option<Sasl>,
     /// Commands which are executed once connected.
     #[serde(default)]
-    pub on_connect: Vec<String>,
+    pub on_connect: Vec<Command>,
+    /// Commands which are executed once logged in to an account, through SASL or NickServ.
+    #[serde(default)]
+    pub after_identify: Vec<Command>,
     /// Enable WHO polling. Defaults to `true`.
     #[serde(default = "default_who_poll_enabled")]
     pub who_poll_enabled: bool,
@@ -93,11 +120,25 @@ pub struct Server {
         deserialize_with = "deserialize_duration_from_u64"
     )]
     pub who_retry_interval: Duration,
+    /// Interval during which repeated away replies from the same user are hidden,
+    /// unless their away message changes.
+    #[serde(
+        default = "default_away_reply_interval",
+        deserialize_with = "deserialize_duration_from_u64"
+    )]
+    pub away_reply_interval: Duration,
     /// A list of nicknames to monitor (if MONITOR is supported by the server).
     #[serde(default)]
     pub monitor: Vec<String>,
     #[serde(default = "default_chathistory")]
     pub chathistory: bool,
+    /// Channels and queries which are never backfilled with CHATHISTORY.
+    #[serde(default)]
+    pub disable_backfill: Vec<String>,
+    /// Directory to store this server's history in, instead of the default
+    /// history directory.
+    #[serde(default)]
+    pub history_dir: Option<PathBuf>,
 }
 
 impl Server {
@@ -142,6 +183,67 @@ impl Server {
             proxy: proxy.map(From::from),
         }
     }
+
+    /// Nickname to try when the previous `attempt` is taken while
+    /// connecting, `None` once there are none left
+    pub fn fallback_nickname(&self, attempt: usize) -> Option<String> {
+        match self.nick_in_use {
+            NickInUse::Fail => None,
+            NickInUse::Fallback => {
+                if let Some(nickname) = self.alt_nicks.get(attempt) {
+                    return Some(nickname.clone());
+                }
+
+                let n = attempt - self.alt_nicks.len() + 1;
+
+                self.nick_pattern
+                    .as_ref()
+                    .filter(|_| n <= MAX_NICK_PATTERN_ATTEMPTS)
+                    .map(|pattern| {
+                        pattern.replace("{n}", &n.to_string()).replace(
+                            "{random}",
+                            &rand::thread_rng().gen_range(100..10000).to_string(),
+                        )
+                    })
+            }
+        }
+    }
+
+    /// Take the parts of `identity` which aren't set for this server
+    pub fn inherit(&mut self, identity: &Identity) {
+        if self.nickname.is_empty() {
+            self.nickname = identity.nickname.clone().unwrap_or_default();
+        }
+        if self.alt_nicks.is_empty() {
+            self.alt_nicks = identity.alt_nicks.clone();
+        }
+        if self.username.is_none() {
+            self.username = identity.username.clone();
+        }
+        if self.realname.is_none() {
+            self.realname = identity.realname.clone();
+        }
+    }
+
+    pub fn identity(&self) -> Identity {
+        Identity {
+            nickname: Some(self.nickname.clone()),
+            alt_nicks: self.alt_nicks.clone(),
+            username: self.username.clone(),
+            realname: self.realname.clone(),
+        }
+    }
+
+    /// Replace the identity of this server with `identity`
+    pub fn with_identity(self, identity: &Identity) -> Self {
+        Self {
+            nickname: identity.nickname.clone().unwrap_or(self.nickname),
+            alt_nicks: identity.alt_nicks.clone(),
+            username: identity.username.clone(),
+            realname: identity.realname.clone(),
+            ..self
+        }
+    }
 }
 
 impl Default for Server {
@@ -153,6 +255,9 @@ impl Default for Server {
             nick_password_command: Default::default(),
             nick_identify_syntax: Default::default(),
             alt_nicks: Default::default(),
+            nick_pattern: Default::default(),
+            nick_in_use: Default::default(),
+            recover_nick: default_recover_nick(),
             username: Default::default(),
             realname: Default::default(),
             server: Default::default(),
@@ -165,6 +270,8 @@ impl Default for Server {
             ping_time: default_ping_time(),
             ping_timeout: default_ping_timeout(),
             reconnect_delay: default_reconnect_delay(),
+            autoconnect: default_autoconnect(),
+            connect_delay: Default::default(),
             should_ghost: Default::default(),
             ghost_sequence: default_ghost_sequence(),
             umodes: Default::default(),
@@ -173,15 +280,29 @@ impl Default for Server {
             root_cert_path: Default::default(),
             sasl: Default::default(),
             on_connect: Default::default(),
+            after_identify: Default::default(),
             who_poll_enabled: default_who_poll_enabled(),
             who_poll_interval: default_who_poll_interval(),
             who_retry_interval: default_who_retry_interval(),
+            away_reply_interval: default_away_reply_interval(),
             monitor: Default::default(),
             chathistory: default_chathistory(),
+            disable_backfill: Default::default(),
+            history_dir: Default::default(),
         }
     }
 }
 
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum NickInUse {
+    /// Try `alt_nicks`, then `nick_pattern`
+    #[default]
+    Fallback,
+    /// Disconnect rather than connect with another nickname
+    Fail,
+}
+
 #[derive(PartialEq, Eq, Debug, Clone, Deserialize)]
 #[serde(rename_all = "kebab-case")]
 pub enum IdentifySyntax {
@@ -189,6 +310,33 @@ pub enum IdentifySyntax {
     PasswordNick,
 }
 
+#[derive(PartialEq, Eq, Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum Command {
+    Immediate(String),
+    Delayed {
+        command: String,
+        /// Seconds to wait after the previous command before sending this one
+        #[serde(default)]
+        delay: u64,
+    },
+}
+
+impl Command {
+    pub fn command(&self) -> &str {
+        match self {
+            Command::Immediate(command) | Command::Delayed { command, .. } => command,
+        }
+    }
+
+    pub fn delay(&self) -> Duration {
+        match self {
+            Command::Immediate(_) => Duration::ZERO,
+            Command::Delayed { delay, .. } => Duration::from_secs(*delay),
+        }
+    }
+}
+
 #[derive(PartialEq, Eq, Debug, Clone, Deserialize)]
 #[serde(rename_all = "kebab-case")]
 pub enum Sasl {
@@ -285,6 +433,14 @@ fn default_reconnect_delay() -> u64 {
     10
 }
 
+fn default_autoconnect() -> bool {
+    true
+}
+
+fn default_recover_nick() -> bool {
+    true
+}
+
 fn default_ghost_sequence() -> Vec<String> {
     vec!["REGAIN".into()]
 }
@@ -301,6 +457,48 @@ fn default_who_retry_interval() -> Duration {
     Duration::from_secs(10)
 }
 
+fn default_away_reply_interval() -> Duration {
+    Duration::from_secs(600)
+}
+
 fn default_chathistory() -> bool {
     true
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fallback_nicknames() {
+        let server = Server {
+            nickname: "halloy".to_string(),
+            alt_nicks: vec!["halloy_".to_string()],
+            nick_pattern: Some("halloy{n}".to_string()),
+            ..Server::default()
+        };
+
+        let fallbacks = (0..)
+            .map_while(|attempt| server.fallback_nickname(attempt))
+            .collect::<Vec<_>>();
+
+        assert_eq!(fallbacks.len(), 1 + MAX_NICK_PATTERN_ATTEMPTS);
+        assert_eq!(fallbacks[0], "halloy_");
+        assert_eq!(fallbacks[1], "halloy1");
+        assert_eq!(fallbacks[9], "halloy9");
+
+        let random = Server {
+            nick_pattern: Some("halloy_{random}".to_string()),
+            ..server.clone()
+        }
+        .fallback_nickname(1)
+        .unwrap();
+        assert!(random["halloy_".len()..].parse::<u16>().is_ok());
+
+        let fail = Server {
+            nick_in_use: NickInUse::Fail,
+            ..server
+        };
+        assert_eq!(fail.fallback_nickname(0), None);
+    }
+}
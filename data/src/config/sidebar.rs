@@ -1,3 +1,4 @@
+use iced_core::keyboard;
 use serde::Deserialize;
 
 use crate::dashboard::{BufferAction, BufferFocusedAction};
@@ -8,6 +9,12 @@ pub struct Sidebar {
     pub buffer_action: BufferAction,
     #[serde(default)]
     pub buffer_focused_action: Option<BufferFocusedAction>,
+    #[serde(default = "default_ctrl_click_action")]
+    pub ctrl_click_action: BufferAction,
+    #[serde(default = "default_shift_click_action")]
+    pub shift_click_action: BufferAction,
+    #[serde(default)]
+    pub allow_duplicate_buffers: bool,
     #[serde(default = "default_sidebar_width")]
     pub width: u16,
     #[serde(default)]
@@ -46,11 +53,28 @@ impl Position {
     }
 }
 
+impl Sidebar {
+    /// Action when pressing a buffer with `modifiers` held, which is also
+    /// honored when selecting a buffer from the command bar
+    pub fn modified_buffer_action(&self, modifiers: keyboard::Modifiers) -> Option<BufferAction> {
+        if modifiers.command() {
+            Some(self.ctrl_click_action)
+        } else if modifiers.shift() {
+            Some(self.shift_click_action)
+        } else {
+            None
+        }
+    }
+}
+
 impl Default for Sidebar {
     fn default() -> Self {
         Sidebar {
             buffer_action: Default::default(),
             buffer_focused_action: Default::default(),
+            ctrl_click_action: default_ctrl_click_action(),
+            shift_click_action: default_shift_click_action(),
+            allow_duplicate_buffers: false,
             width: default_sidebar_width(),
             unread_indicator: UnreadIndicator::default(),
             position: Position::default(),
@@ -63,6 +87,14 @@ fn default_sidebar_width() -> u16 {
     120
 }
 
+fn default_ctrl_click_action() -> BufferAction {
+    BufferAction::NewPane
+}
+
+fn default_shift_click_action() -> BufferAction {
+    BufferAction::ReplaceLargestPane
+}
+
 fn default_bool_true() -> bool {
     true
 }
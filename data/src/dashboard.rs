@@ -11,6 +11,8 @@ pub struct Dashboard {
     pub pane: Pane,
     #[serde(default)]
     pub popout_panes: Vec<Pane>,
+    #[serde(default)]
+    pub profile: Option<String>,
 }
 
 #[derive(Debug, Clone, Copy, Default, Deserialize, Serialize)]
@@ -19,6 +21,7 @@ pub enum BufferAction {
     #[default]
     NewPane,
     ReplacePane,
+    ReplaceLargestPane,
     NewWindow,
 }
 
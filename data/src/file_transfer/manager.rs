@@ -1,7 +1,7 @@
 use std::{
     collections::{HashMap, VecDeque},
     num::NonZeroU16,
-    path::PathBuf,
+    path::{Path, PathBuf},
     time::Duration,
 };
 
@@ -47,6 +47,8 @@ pub struct Manager {
     /// Queued = waiting for port assignment
     queued: VecDeque<Id>,
     used_ports: HashMap<Id, NonZeroU16>,
+    /// Where approved downloads are being saved to
+    save_paths: HashMap<Id, PathBuf>,
 }
 
 impl Manager {
@@ -56,6 +58,7 @@ impl Manager {
             items: HashMap::new(),
             queued: VecDeque::new(),
             used_ports: HashMap::new(),
+            save_paths: HashMap::new(),
         }
     }
 
@@ -209,7 +212,9 @@ impl Manager {
         Some(Event::NewTransfer(file_transfer, stream.boxed()))
     }
 
-    pub fn update(&mut self, update: task::Update) {
+    /// Apply an update from a transfer task, returning the transfer if it
+    /// just completed or failed
+    pub fn update(&mut self, update: task::Update) -> Option<FileTransfer> {
         match update {
             task::Update::Metadata(id, size) => {
                 if let Some(item) = self.items.get_mut(&id) {
@@ -280,15 +285,16 @@ impl Manager {
                         elapsed.as_secs_f32()
                     );
 
-                    self.items.insert(
-                        id,
-                        Item::Finished(FileTransfer {
-                            status: Status::Completed { elapsed, sha256 },
-                            ..file_transfer
-                        }),
-                    );
+                    let file_transfer = FileTransfer {
+                        status: Status::Completed { elapsed, sha256 },
+                        ..file_transfer
+                    };
+
+                    self.items.insert(id, Item::Finished(file_transfer.clone()));
 
                     self.recycle_port(id);
+
+                    return Some(file_transfer);
                 }
             }
             task::Update::Failed(id, error) => {
@@ -303,12 +309,20 @@ impl Manager {
                         &file_transfer.remote_user,
                         &file_transfer.filename,
                     );
+                    let already_failed = matches!(file_transfer.status, Status::Failed { .. });
                     file_transfer.status = Status::Failed { error };
+                    let file_transfer = file_transfer.clone();
 
                     self.recycle_port(id);
+
+                    if !already_failed {
+                        return Some(file_transfer);
+                    }
                 }
             }
         }
+
+        None
     }
 
     fn get_available_port(&self) -> Option<NonZeroU16> {
@@ -340,15 +354,22 @@ impl Manager {
     pub fn remove(&mut self, id: &Id) {
         let _ = self.items.remove(id);
         self.queued.retain(|i| i != id);
+        self.save_paths.remove(id);
         self.recycle_port(*id);
     }
 
     pub fn approve(&mut self, id: &Id, save_to: PathBuf) {
         if let Some(Item::Working { task, .. }) = self.items.get_mut(id) {
-            task.approve(save_to);
+            task.approve(save_to.clone());
+            self.save_paths.insert(*id, save_to);
         }
     }
 
+    /// Path an approved download is saved to
+    pub fn save_path(&self, id: &Id) -> Option<&Path> {
+        self.save_paths.get(id).map(PathBuf::as_path)
+    }
+
     pub fn get<'a>(&'a self, id: &Id) -> Option<&'a FileTransfer> {
         self.items.get(id).map(Item::file_transfer)
     }
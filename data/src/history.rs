@@ -1,4 +1,6 @@
-use std::path::PathBuf;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::time::Duration;
 use std::{fmt, io};
 
@@ -6,16 +8,20 @@ use chrono::{DateTime, Utc};
 use futures::future::BoxFuture;
 use futures::{Future, FutureExt};
 use irc::proto;
+use once_cell::sync::Lazy;
 use tokio::fs;
 use tokio::time::Instant;
 
+use crate::isupport::{ChatHistorySubcommand, MessageReferenceType};
+use crate::message::correction::Corrections;
 use crate::message::{self, MessageReferences};
 use crate::user::Nick;
-use crate::{buffer, compression, environment, Buffer, Message, Server};
+use crate::{buffer, compression, config, environment, time, Buffer, Message, Server};
 
 pub use self::manager::{Manager, Resource};
-pub use self::metadata::{Metadata, ReadMarker};
+pub use self::metadata::{FieldChange, Metadata, MetadataStore, ReadMarker};
 
+pub mod format;
 pub mod manager;
 pub mod metadata;
 
@@ -26,6 +32,11 @@ const MAX_MESSAGES: usize = 10_000;
 const TRUNC_COUNT: usize = 500;
 /// Duration to wait after receiving last message before flushing
 const FLUSH_AFTER_LAST_RECEIVED: Duration = Duration::from_secs(5);
+/// # messages allowed over the in-memory cap before evicting, so evictions
+/// are batched instead of hitting the disk on every new message
+const EVICT_THRESHOLD: usize = 100;
+/// # messages to load back from disk when scrolling past the in-memory window
+const LOAD_EVICTED_COUNT: usize = 500;
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum Kind {
@@ -86,6 +97,49 @@ impl Kind {
             Kind::Highlights => None,
         }
     }
+
+    /// Unambiguous name of the kind, hashed into the names of its history
+    /// and metadata files and stored inside them
+    pub fn name(&self) -> String {
+        let parts = match self {
+            Kind::Server(server) => vec!["server", server.as_ref()],
+            Kind::Channel(server, channel) => vec!["channel", server.as_ref(), channel],
+            Kind::Query(server, nick) => vec!["query", server.as_ref(), nick.as_ref()],
+            Kind::Logs => vec!["logs"],
+            Kind::Highlights => vec!["highlights"],
+        };
+
+        serde_json::Value::from(parts).to_string()
+    }
+
+    /// The kind named `name`, see [`Kind::name`]
+    pub fn from_name(name: &str) -> Option<Self> {
+        let parts = serde_json::from_str::<Vec<String>>(name).ok()?;
+
+        match parts.iter().map(String::as_str).collect::<Vec<_>>()[..] {
+            ["server", server] => Some(Kind::Server(Server::from(server))),
+            ["channel", server, channel] => {
+                Some(Kind::Channel(Server::from(server), channel.to_string()))
+            }
+            ["query", server, nick] => Some(Kind::Query(Server::from(server), Nick::from(nick))),
+            ["logs"] => Some(Kind::Logs),
+            ["highlights"] => Some(Kind::Highlights),
+            _ => None,
+        }
+    }
+
+    /// Name hashed into file names before [`Kind::name`]. It's ambiguous,
+    /// e.g. channel `#b` on server `a` shares it with server `achannel#b`,
+    /// and is only used to find files to migrate.
+    fn legacy_name(&self) -> String {
+        match self {
+            Kind::Server(server) => format!("{server}"),
+            Kind::Channel(server, channel) => format!("{server}channel{channel}"),
+            Kind::Query(server, nick) => format!("{server}nickname{nick}"),
+            Kind::Logs => "logs".to_string(),
+            Kind::Highlights => "highlights".to_string(),
+        }
+    }
 }
 
 impl fmt::Display for Kind {
@@ -120,32 +174,112 @@ pub struct Loaded {
     pub metadata: Metadata,
 }
 
-pub async fn load(kind: Kind) -> Result<Loaded, Error> {
-    let path = path(&kind).await?;
+pub async fn load(kind: Kind, config: &Config) -> Result<Loaded, Error> {
+    let path = path(&kind, config).await?;
 
-    let messages = read_all(&path).await.unwrap_or_default();
-    let metadata = metadata::load(kind).await.unwrap_or_default();
+    let messages = read_all(&kind, &path, config).await?;
+    let metadata = metadata::load(kind, config).await.unwrap_or_default();
 
     Ok(Loaded { messages, metadata })
 }
 
+/// What a server supports of `CHATHISTORY`, see [`resume`]
+#[derive(Debug, Clone, Default)]
+pub struct ServerCaps {
+    pub supports_chathistory: bool,
+    pub message_reference_types: Vec<MessageReferenceType>,
+    pub chathistory_limit: u16,
+}
+
+/// Everything needed to reopen a buffer, see [`resume`]
+#[derive(Debug)]
+pub struct ResumeState {
+    pub messages: Vec<Message>,
+    pub metadata: Metadata,
+    /// Request catching up on what was missed while the buffer was closed,
+    /// see [`Metadata::backfill_request`]
+    pub chathistory: Option<ChatHistorySubcommand>,
+}
+
+/// Held while writing history and its metadata together, so they're never
+/// read half written
+static FILES: Lazy<tokio::sync::RwLock<()>> = Lazy::new(Default::default);
+
+/// Load the history of `kind` along with how to backfill it from a server
+/// with `caps`
+pub async fn resume(kind: Kind, caps: ServerCaps, config: &Config) -> Result<ResumeState, Error> {
+    let Loaded { messages, metadata } = {
+        let _files = FILES.read().await;
+
+        load(kind.clone(), config).await?
+    };
+
+    let chathistory = kind
+        .target()
+        .and_then(|target| metadata.backfill_request(target, &caps));
+
+    Ok(ResumeState {
+        messages,
+        metadata,
+        chathistory,
+    })
+}
+
 pub async fn overwrite(
     kind: &Kind,
     messages: &[Message],
     read_marker: Option<ReadMarker>,
+    config: &Config,
+) -> Result<(), Error> {
+    let _files = FILES.write().await;
+
+    write_all(kind, messages, read_marker, config).await
+}
+
+async fn write_all(
+    kind: &Kind,
+    messages: &[Message],
+    read_marker: Option<ReadMarker>,
+    config: &Config,
 ) -> Result<(), Error> {
     if messages.is_empty() {
-        return metadata::save(kind, messages, read_marker).await;
+        return metadata::save(kind, messages, read_marker, false, config).await;
+    }
+
+    let max_messages = match kind {
+        Kind::Highlights => config.max_highlights,
+        _ => MAX_MESSAGES,
+    };
+
+    let latest = &messages[messages.len().saturating_sub(max_messages)..];
+
+    let path = path(kind, config).await?;
+
+    if is_unreadable(&path) {
+        return Err(Error::Unreadable(path));
     }
 
-    let latest = &messages[messages.len().saturating_sub(MAX_MESSAGES)..];
+    // Logs are pruned as configured, and not written at all when kept for
+    // the session only. The read marker is still saved.
+    let retained_logs;
+    let latest = if matches!(kind, Kind::Logs) {
+        if !config.logs.persist {
+            remove_if_exists(&path).await?;
 
-    let path = path(kind).await?;
-    let compressed = compression::compress(&latest)?;
+            return metadata::save(kind, latest, read_marker, false, config).await;
+        }
+
+        retained_logs = config.logs.retain(latest, Utc::now());
+        &retained_logs
+    } else {
+        latest
+    };
+    let compressed = format::encode(&kind.name(), latest)?;
 
     fs::write(path, &compressed).await?;
+    record_bytes_written(compressed.len(), config);
 
-    metadata::save(kind, latest, read_marker).await?;
+    metadata::save(kind, latest, read_marker, false, config).await?;
 
     Ok(())
 }
@@ -154,20 +288,244 @@ pub async fn append(
     kind: &Kind,
     messages: Vec<Message>,
     read_marker: Option<ReadMarker>,
+    config: &Config,
 ) -> Result<(), Error> {
-    let loaded = load(kind.clone()).await?;
+    let _files = FILES.write().await;
+
+    let loaded = load(kind.clone(), config).await?;
 
     let mut all_messages = loaded.messages;
     messages.into_iter().for_each(|message| {
         insert_message(&mut all_messages, message);
     });
 
-    overwrite(kind, &all_messages, read_marker).await
+    write_all(kind, &all_messages, read_marker, config).await
+}
+
+/// Load the latest `count` messages stored on disk prior to `before`
+pub async fn load_before(
+    kind: Kind,
+    before: DateTime<Utc>,
+    count: usize,
+    config: &Config,
+) -> Result<Vec<Message>, Error> {
+    let path = path(&kind, config).await?;
+
+    let mut messages = read_all(&kind, &path, config).await?;
+
+    let end = messages.partition_point(|message| message.server_time < before);
+    messages.truncate(end);
+
+    Ok(messages.split_off(end.saturating_sub(count)))
+}
+
+/// Delete the history of `kind` stored on disk and clear its metadata
+pub async fn delete(kind: &Kind, config: &Config) -> Result<(), Error> {
+    let _files = FILES.write().await;
+
+    let path = path(kind, config).await?;
+
+    remove_if_exists(&path).await?;
+    UNREADABLE.lock().unwrap().remove(&path);
+
+    metadata::clear(kind, config).await
+}
+
+async fn remove_if_exists(path: &PathBuf) -> Result<(), Error> {
+    match fs::remove_file(path).await {
+        Err(error) if error.kind() != io::ErrorKind::NotFound => Err(error.into()),
+        _ => Ok(()),
+    }
+}
+
+/// Paths of history which failed to load this session, never written to
+/// so what's there isn't lost
+static UNREADABLE: Lazy<std::sync::Mutex<HashSet<PathBuf>>> = Lazy::new(Default::default);
+
+fn is_unreadable(path: &Path) -> bool {
+    UNREADABLE.lock().unwrap().contains(path)
 }
 
-async fn read_all(path: &PathBuf) -> Result<Vec<Message>, Error> {
-    let bytes = fs::read(path).await?;
-    Ok(compression::decompress(&bytes)?)
+/// Read the history at `path`, empty if there's none yet. Files from older
+/// versions are migrated and rewritten, and the original of a file with
+/// messages which failed to load is kept beside it before they're dropped.
+/// A file which fails to load as a whole is kept and left as is.
+async fn read_all(kind: &Kind, path: &PathBuf, config: &Config) -> Result<Vec<Message>, Error> {
+    let bytes = match fs::read(path).await {
+        Ok(bytes) => bytes,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(error) => return Err(error.into()),
+    };
+    let name = kind.name();
+
+    let decoded = match format::decode(&bytes) {
+        Ok(decoded) => decoded,
+        Err(error) => {
+            log::warn!("history of {kind} failed to load: {error}");
+
+            keep_original(kind, path, &bytes).await?;
+            UNREADABLE.lock().unwrap().insert(path.clone());
+
+            return Err(Error::Unreadable(path.clone()));
+        }
+    };
+
+    if let Some(stored) = decoded.kind.clone().filter(|stored| *stored != name) {
+        UNREADABLE.lock().unwrap().insert(path.clone());
+
+        return Err(Error::OtherKind {
+            path: path.clone(),
+            stored,
+            kind: name,
+        });
+    }
+
+    if decoded.skipped > 0 {
+        log::warn!(
+            "skipped {} messages of {kind} which failed to load",
+            decoded.skipped
+        );
+
+        keep_original(kind, path, &bytes).await?;
+    }
+
+    // Also written when the kind isn't recorded yet, so it can be verified
+    if decoded.is_outdated() || decoded.kind.is_none() {
+        let compressed = format::encode(&name, &decoded.messages)?;
+
+        fs::write(path, &compressed).await?;
+        record_bytes_written(compressed.len(), config);
+
+        if decoded.is_outdated() {
+            log::debug!(
+                "migrated history of {kind} from version {} to {}",
+                decoded.version,
+                format::HISTORY_VERSION
+            );
+        }
+    }
+
+    Ok(decoded.messages)
+}
+
+/// Keep `bytes` read from `path` beside it, unless an original was kept
+/// already
+async fn keep_original(kind: &Kind, path: &Path, bytes: &[u8]) -> Result<(), Error> {
+    let backup = path.with_extension("gz.bak");
+
+    if !backup.exists() {
+        fs::write(&backup, bytes).await?;
+        log::info!(
+            "kept the original history of {kind} at {}",
+            backup.display()
+        );
+    }
+
+    Ok(())
+}
+
+/// Settings of the history kept on disk, taken from [`crate::Config`] and
+/// passed along to everything reading or writing it
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// Per-server history directories
+    pub directories: BTreeMap<Server, PathBuf>,
+    /// Max # highlights to persist
+    pub max_highlights: usize,
+    /// Max # messages of an open buffer kept in memory, the rest are
+    /// evicted and loaded back from disk when scrolled to
+    pub max_in_memory: usize,
+    /// How far ahead of now a message's server time may be before it's
+    /// ignored for read markers and references
+    pub future_tolerance: Duration,
+    /// Bytes which may be written this session before warning
+    pub write_budget: Option<u64>,
+    /// Whether buffers with only status messages get a read marker
+    pub mark_status_only_read: bool,
+    /// Whether open buffers are only read once seen, rather than as soon as
+    /// messages arrive
+    pub mark_read_when_focused: bool,
+    /// Which logs are kept on disk
+    pub logs: config::Logs,
+    /// How timestamps are written to metadata
+    pub timestamp_format: time::Format,
+}
+
+impl Config {
+    pub fn new(config: &crate::Config) -> Self {
+        let history = &config.buffer.history;
+
+        Self {
+            directories: config
+                .servers
+                .entries()
+                .filter_map(|entry| Some((entry.server, entry.config.history_dir?)))
+                .collect(),
+            max_highlights: history.max_highlights,
+            max_in_memory: history.max_in_memory,
+            future_tolerance: Duration::from_secs(history.future_tolerance),
+            write_budget: history.write_budget,
+            mark_status_only_read: history.mark_status_only_read,
+            mark_read_when_focused: history.mark_read_when_focused,
+            logs: config.logs.clone(),
+            timestamp_format: history.timestamp_format,
+        }
+    }
+
+    fn directory(&self, kind: &Kind) -> Option<&PathBuf> {
+        self.directories.get(kind.server()?)
+    }
+
+    /// Whether `message` claims to be from further in the future than clock
+    /// skew explains, e.g. sent by a misbehaving server or bot
+    fn is_from_future(&self, message: &Message) -> bool {
+        let is_from_future = self.is_ahead_of(message.server_time, Utc::now());
+
+        if is_from_future {
+            log::debug!(
+                "ignoring message from the future ({}) for read marker and references",
+                message.server_time
+            );
+        }
+
+        is_from_future
+    }
+
+    /// Whether `time` is ahead of `reference` by more than the future tolerance
+    fn is_ahead_of(&self, time: DateTime<Utc>, reference: DateTime<Utc>) -> bool {
+        chrono::Duration::from_std(self.future_tolerance)
+            .ok()
+            .and_then(|tolerance| reference.checked_add_signed(tolerance))
+            .is_some_and(|limit| time > limit)
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self::new(&crate::Config::default())
+    }
+}
+
+/// Bytes of history and metadata written this session
+static BYTES_WRITTEN: AtomicU64 = AtomicU64::new(0);
+
+/// Total bytes of history and metadata written to disk this session
+pub fn bytes_written() -> u64 {
+    BYTES_WRITTEN.load(Ordering::Relaxed)
+}
+
+fn record_bytes_written(bytes: usize, config: &Config) {
+    let before = BYTES_WRITTEN.fetch_add(bytes as u64, Ordering::Relaxed);
+    let after = before + bytes as u64;
+
+    // Only warn when crossing, not on every write past the budget
+    if let Some(budget) = config.write_budget {
+        if before <= budget && after > budget {
+            log::warn!(
+                "history write budget of {budget} bytes exceeded, {after} written this session"
+            );
+        }
+    }
 }
 
 pub async fn dir_path() -> Result<PathBuf, Error> {
@@ -182,20 +540,139 @@ pub async fn dir_path() -> Result<PathBuf, Error> {
     Ok(history_dir)
 }
 
-async fn path(kind: &Kind) -> Result<PathBuf, Error> {
-    let dir = dir_path().await?;
+/// [`dir_path`] followed by every configured `history_dir`
+pub async fn dir_paths(config: &Config) -> Result<Vec<PathBuf>, Error> {
+    let overrides = config
+        .directories
+        .values()
+        .filter(|dir| dir.exists())
+        .cloned()
+        .collect::<Vec<_>>();
+
+    Ok(std::iter::once(dir_path().await?)
+        .chain(overrides)
+        .collect())
+}
+
+/// The directory holding `kind`'s history and metadata, which is the
+/// server's configured `history_dir` if set, else [`dir_path`]
+pub async fn kind_dir_path(kind: &Kind, config: &Config) -> Result<PathBuf, Error> {
+    let Some(dir) = config.directory(kind).cloned() else {
+        return dir_path().await;
+    };
+
+    if !dir.exists() {
+        fs::create_dir_all(&dir).await?;
+    }
+
+    Ok(dir)
+}
+
+async fn path(kind: &Kind, config: &Config) -> Result<PathBuf, Error> {
+    let dir = kind_dir_path(kind, config).await?;
+
+    let file_name = |name: String| format!("{}.json.gz", seahash::hash(name.as_bytes()));
+
+    resolve_path(
+        &dir,
+        kind,
+        &file_name(kind.name()),
+        &file_name(kind.legacy_name()),
+        format::stored_kind,
+    )
+    .await
+}
+
+/// Paths resolved this session by [`resolve_path`], keyed by the file name
+/// they were resolved from and [`Kind::name`]. Held for the whole of a
+/// resolution, so two kinds can't reserve the same path.
+static RESOLVED_PATHS: Lazy<tokio::sync::Mutex<HashMap<(PathBuf, String), PathBuf>>> =
+    Lazy::new(Default::default);
+
+/// Path of the file of `kind` named `file_name` in `dir`. A file there
+/// which holds another kind, told by `stored_kind`, is left alone and
+/// `kind` uses the first free name with a numeric suffix instead, e.g.
+/// `123-1.json`. A file at `legacy_file_name` is moved to the new name.
+async fn resolve_path(
+    dir: &Path,
+    kind: &Kind,
+    file_name: &str,
+    legacy_file_name: &str,
+    stored_kind: fn(&[u8]) -> Option<String>,
+) -> Result<PathBuf, Error> {
+    let name = kind.name();
+    let key = (dir.join(file_name), name.clone());
+
+    let mut resolved_paths = RESOLVED_PATHS.lock().await;
+
+    if let Some(path) = resolved_paths.get(&key) {
+        return Ok(path.clone());
+    }
+
+    let holds_kind = |bytes: &[u8]| stored_kind(bytes).map_or(true, |stored| stored == name);
+
+    let legacy = dir.join(legacy_file_name);
+
+    if !key.0.exists() {
+        match fs::read(&legacy).await {
+            Ok(bytes) if holds_kind(&bytes) => {
+                fs::rename(&legacy, &key.0).await?;
+
+                log::debug!(
+                    "moved {kind} from {} to {}",
+                    legacy.display(),
+                    key.0.display()
+                );
+            }
+            Err(error) if error.kind() != io::ErrorKind::NotFound => return Err(error.into()),
+            _ => {}
+        }
+    }
+
+    let (stem, extension) = file_name.split_once('.').unwrap_or((file_name, ""));
+
+    let mut free = None;
+    let mut suffix = 0;
+
+    let path = loop {
+        let path = if suffix == 0 {
+            key.0.clone()
+        } else {
+            dir.join(format!("{stem}-{suffix}.{extension}"))
+        };
+
+        // Resolved for another kind, which may not have written it yet
+        let is_taken = resolved_paths.values().any(|resolved| *resolved == path);
+
+        match fs::read(&path).await {
+            Ok(bytes) if !holds_kind(&bytes) => {
+                log::warn!(
+                    "{} holds {}, not {kind}",
+                    path.display(),
+                    stored_kind(&bytes).unwrap_or_else(|| "another kind".to_string())
+                );
+            }
+            Ok(_) if !is_taken => break path,
+            Ok(_) => {}
+            Err(error) if error.kind() != io::ErrorKind::NotFound => return Err(error.into()),
+            Err(_) if !is_taken => {
+                // Suffixed files are taken in order, so the first missing
+                // one ends them
+                if suffix > 0 {
+                    break free.unwrap_or(path);
+                }
+
+                free = Some(path);
+            }
+            Err(_) => {}
+        }
 
-    let name = match kind {
-        Kind::Server(server) => format!("{server}"),
-        Kind::Channel(server, channel) => format!("{server}channel{channel}"),
-        Kind::Query(server, nick) => format!("{server}nickname{}", nick),
-        Kind::Logs => "logs".to_string(),
-        Kind::Highlights => "highlights".to_string(),
+        suffix += 1;
     };
 
-    let hashed_name = seahash::hash(name.as_bytes());
+    resolved_paths.insert(key, path.clone());
 
-    Ok(dir.join(format!("{hashed_name}.json.gz")))
+    Ok(path)
 }
 
 #[derive(Debug)]
@@ -213,9 +690,73 @@ pub enum History {
         messages: Vec<Message>,
         last_updated_at: Option<Instant>,
         read_marker: Option<ReadMarker>,
+        /// Latest message seen in the focused buffer, kept apart from the
+        /// read marker so the backlog divider stays put while it's open
+        seen: Option<ReadMarker>,
+        evicted: Option<Evicted>,
+        /// Messages loaded back from disk, kept in memory over the limit
+        /// until the next eviction
+        reloaded: usize,
+        /// Evicted messages are being loaded back from disk
+        loading_evicted: bool,
+        corrections: Corrections,
     },
 }
 
+/// Tracks messages which have been dropped from memory but still
+/// exist on disk
+#[derive(Debug, Clone)]
+pub struct Evicted {
+    /// Server time of the oldest message still held in memory
+    pub before: DateTime<Utc>,
+    last_triggers_unread: Option<DateTime<Utc>>,
+    last_can_reference: Option<MessageReferences>,
+}
+
+impl Evicted {
+    /// Drop all but the newest [`Config::max_in_memory`] of `messages` just
+    /// loaded from disk, where the rest stay
+    fn from_loaded(messages: &mut Vec<Message>, config: &Config) -> Option<Self> {
+        let excess = messages.len().saturating_sub(config.max_in_memory.max(1));
+
+        if excess == 0 {
+            return None;
+        }
+
+        let drained = messages.drain(..excess).collect::<Vec<_>>();
+
+        Some(Self::extend(
+            None,
+            &drained,
+            messages[0].server_time,
+            config,
+        ))
+    }
+
+    fn extend(
+        evicted: Option<Self>,
+        messages: &[Message],
+        before: DateTime<Utc>,
+        config: &Config,
+    ) -> Self {
+        let last_triggers_unread = metadata::latest_triggers_unread(messages, config);
+        let last_can_reference = metadata::latest_can_reference(messages, config);
+
+        match evicted {
+            Some(evicted) => Self {
+                before,
+                last_triggers_unread: last_triggers_unread.or(evicted.last_triggers_unread),
+                last_can_reference: last_can_reference.or(evicted.last_can_reference),
+            },
+            None => Self {
+                before,
+                last_triggers_unread,
+                last_can_reference,
+            },
+        }
+    }
+}
+
 impl History {
     fn partial(kind: Kind) -> Self {
         Self::Partial {
@@ -244,7 +785,7 @@ impl History {
         }
     }
 
-    fn has_unread(&self) -> bool {
+    fn has_unread(&self, config: &Config) -> bool {
         match self {
             History::Partial {
                 max_triggers_unread,
@@ -260,7 +801,50 @@ impl History {
                     max_triggers_unread.is_some()
                 }
             }
-            History::Full { .. } => false,
+            History::Full {
+                messages,
+                read_marker,
+                seen,
+                evicted,
+                ..
+            } => {
+                // Open buffers are read as messages arrive, unless they need
+                // to be seen first
+                if !config.mark_read_when_focused {
+                    return false;
+                }
+
+                let max_triggers_unread =
+                    metadata::latest_triggers_unread(messages, config).or(evicted
+                        .as_ref()
+                        .and_then(|evicted| evicted.last_triggers_unread));
+
+                match (*read_marker).max(*seen) {
+                    Some(read) => max_triggers_unread.is_some_and(|max| read.date_time() < max),
+                    None => max_triggers_unread.is_some(),
+                }
+            }
+        }
+    }
+
+    /// Read marker of an open buffer once it's no longer open
+    fn closing_read_marker(
+        messages: &[Message],
+        read_marker: Option<ReadMarker>,
+        seen: Option<ReadMarker>,
+        config: &Config,
+    ) -> Option<ReadMarker> {
+        if config.mark_read_when_focused {
+            read_marker.max(seen)
+        } else {
+            ReadMarker::latest(messages, config).max(read_marker)
+        }
+    }
+
+    /// Mark messages of an open buffer as seen, up to its latest message
+    fn mark_seen(&mut self, config: &Config) {
+        if let History::Full { messages, seen, .. } = self {
+            *seen = ReadMarker::latest(messages, config).max(*seen);
         }
     }
 
@@ -280,20 +864,38 @@ impl History {
                 messages,
                 last_updated_at,
                 ..
+            } => {
+                *last_updated_at = Some(Instant::now());
+
+                insert_message(messages, message);
             }
-            | History::Full {
+            History::Full {
                 messages,
                 last_updated_at,
+                corrections,
                 ..
             } => {
                 *last_updated_at = Some(Instant::now());
 
+                let hash = message.hash;
                 insert_message(messages, message);
+
+                // A message landing before the latest can change what
+                // corrections after it apply to
+                match messages.last() {
+                    Some(latest) if latest.hash == hash => corrections.push(latest),
+                    _ => *corrections = Corrections::new(messages.iter()),
+                }
             }
         }
     }
 
-    fn flush(&mut self, now: Instant) -> Option<BoxFuture<'static, Result<(), Error>>> {
+    fn flush(
+        &mut self,
+        now: Instant,
+        max_in_memory: usize,
+        config: &Config,
+    ) -> Option<BoxFuture<'static, Result<(), Error>>> {
         match self {
             History::Partial {
                 kind,
@@ -309,11 +911,13 @@ impl History {
                         let kind = kind.clone();
                         let messages = std::mem::take(messages);
                         let read_marker = *read_marker;
+                        let config = config.clone();
 
                         *last_updated_at = None;
 
                         return Some(
-                            async move { append(&kind, messages, read_marker).await }.boxed(),
+                            async move { append(&kind, messages, read_marker, &config).await }
+                                .boxed(),
                         );
                     }
                 }
@@ -325,24 +929,60 @@ impl History {
                 messages,
                 last_updated_at,
                 read_marker,
+                evicted,
+                reloaded,
+                loading_evicted,
                 ..
             } => {
+                let max_in_memory = max_in_memory.max(1);
+                // Evicting while loading back would leave a gap between the
+                // loaded messages and those in memory
+                let should_evict = !*loading_evicted
+                    && messages.len() > max_in_memory + *reloaded + EVICT_THRESHOLD;
+
                 if let Some(last_received) = *last_updated_at {
                     let since = now.duration_since(last_received);
 
-                    if since >= FLUSH_AFTER_LAST_RECEIVED && !messages.is_empty() {
+                    if (since >= FLUSH_AFTER_LAST_RECEIVED || should_evict) && !messages.is_empty()
+                    {
                         let kind = kind.clone();
                         let read_marker = *read_marker;
+                        let config = config.clone();
                         *last_updated_at = None;
 
                         if messages.len() > MAX_MESSAGES {
                             messages.drain(0..messages.len() - (MAX_MESSAGES - TRUNC_COUNT));
                         }
 
-                        let messages = messages.clone();
+                        // Evicted messages are written out with the rest so
+                        // they're persisted before being dropped from memory
+                        let all_messages = messages.clone();
+
+                        if should_evict {
+                            // Reloaded messages are evicted along with the rest
+                            let drained = messages
+                                .drain(0..messages.len() - max_in_memory)
+                                .collect::<Vec<_>>();
+                            *reloaded = 0;
+
+                            *evicted = Some(Evicted::extend(
+                                evicted.take(),
+                                &drained,
+                                messages[0].server_time,
+                                &config,
+                            ));
+                        } else if evicted.is_none() {
+                            return Some(
+                                async move {
+                                    overwrite(&kind, &all_messages, read_marker, &config).await
+                                }
+                                .boxed(),
+                            );
+                        }
 
                         return Some(
-                            async move { overwrite(&kind, &messages, read_marker).await }.boxed(),
+                            async move { append(&kind, all_messages, read_marker, &config).await }
+                                .boxed(),
                         );
                     }
                 }
@@ -352,21 +992,121 @@ impl History {
         }
     }
 
-    fn make_partial(&mut self) -> Option<impl Future<Output = Result<Option<ReadMarker>, Error>>> {
+    /// Drop all messages from memory for the rest of the session, they're
+    /// kept on disk and can be loaded back by scrolling up
+    fn clear(&mut self, config: &Config) -> Option<BoxFuture<'static, Result<(), Error>>> {
         match self {
             History::Partial { .. } => None,
             History::Full {
                 kind,
                 messages,
+                last_updated_at,
                 read_marker,
+                evicted,
+                reloaded,
+                corrections,
                 ..
             } => {
+                let newest = messages.last()?.server_time;
+
                 let kind = kind.clone();
                 let messages = std::mem::take(messages);
+                let had_evicted = evicted.is_some();
+
+                *read_marker = ReadMarker::latest(&messages, config).max(*read_marker);
+                *last_updated_at = None;
+                *reloaded = 0;
+                *corrections = Corrections::default();
+                *evicted = Some(Evicted::extend(
+                    evicted.take(),
+                    &messages,
+                    newest + chrono::Duration::nanoseconds(1),
+                    config,
+                ));
+
+                let read_marker = *read_marker;
+                let config = config.clone();
+
+                Some(
+                    async move {
+                        if had_evicted {
+                            append(&kind, messages, read_marker, &config).await
+                        } else {
+                            overwrite(&kind, &messages, read_marker, &config).await
+                        }
+                    }
+                    .boxed(),
+                )
+            }
+        }
+    }
 
-                let read_marker = ReadMarker::latest(&messages).max(*read_marker);
-                let max_triggers_unread = metadata::latest_triggers_unread(&messages);
-                let chathistory_references = metadata::latest_can_reference(&messages);
+    /// Forget everything held in memory, including the read marker
+    fn reset(&mut self) {
+        match self {
+            History::Partial {
+                messages,
+                last_updated_at,
+                max_triggers_unread,
+                read_marker,
+                chathistory_references,
+                ..
+            } => {
+                messages.clear();
+                *last_updated_at = None;
+                *max_triggers_unread = None;
+                *read_marker = None;
+                *chathistory_references = None;
+            }
+            History::Full {
+                messages,
+                last_updated_at,
+                read_marker,
+                seen,
+                evicted,
+                reloaded,
+                corrections,
+                ..
+            } => {
+                messages.clear();
+                *last_updated_at = None;
+                *read_marker = None;
+                *seen = None;
+                *evicted = None;
+                *reloaded = 0;
+                *corrections = Corrections::default();
+            }
+        }
+    }
+
+    fn make_partial(
+        &mut self,
+        config: &Config,
+    ) -> Option<impl Future<Output = Result<Option<ReadMarker>, Error>>> {
+        match self {
+            History::Partial { .. } => None,
+            History::Full {
+                kind,
+                messages,
+                read_marker,
+                seen,
+                evicted,
+                ..
+            } => {
+                let kind = kind.clone();
+                let messages = std::mem::take(messages);
+                let evicted = evicted.take();
+
+                let read_marker = Self::closing_read_marker(&messages, *read_marker, *seen, config);
+                let max_triggers_unread =
+                    metadata::latest_triggers_unread(&messages, config).or(evicted
+                        .as_ref()
+                        .and_then(|evicted| evicted.last_triggers_unread));
+                let chathistory_references =
+                    metadata::latest_can_reference(&messages, config).or(evicted
+                        .as_ref()
+                        .and_then(|evicted| evicted.last_can_reference.clone()));
+                let config = config.clone();
 
                 *self = Self::Partial {
                     kind: kind.clone(),
@@ -378,15 +1118,19 @@ impl History {
                 };
 
                 Some(async move {
-                    overwrite(&kind, &messages, read_marker)
-                        .await
-                        .map(|_| read_marker)
+                    if evicted.is_some() {
+                        append(&kind, messages, read_marker, &config).await?;
+                    } else {
+                        overwrite(&kind, &messages, read_marker, &config).await?;
+                    }
+
+                    Ok(read_marker)
                 })
             }
         }
     }
 
-    async fn close(self) -> Result<Option<ReadMarker>, Error> {
+    async fn close(self, config: Config) -> Result<Option<ReadMarker>, Error> {
         match self {
             History::Partial {
                 kind,
@@ -394,7 +1138,7 @@ impl History {
                 read_marker,
                 ..
             } => {
-                append(&kind, messages, read_marker).await?;
+                append(&kind, messages, read_marker, &config).await?;
 
                 Ok(None)
             }
@@ -402,17 +1146,31 @@ impl History {
                 kind,
                 messages,
                 read_marker,
+                seen,
+                evicted,
                 ..
             } => {
-                let read_marker = ReadMarker::latest(&messages).max(read_marker);
+                let read_marker = Self::closing_read_marker(&messages, read_marker, seen, &config);
 
-                overwrite(&kind, &messages, read_marker).await?;
+                if evicted.is_some() {
+                    append(&kind, messages, read_marker, &config).await?;
+                } else {
+                    overwrite(&kind, &messages, read_marker, &config).await?;
+                }
 
                 Ok(read_marker)
             }
         }
     }
 
+    pub fn latest_sent_read(&self, read_marker: ReadMarker) -> Option<DateTime<Utc>> {
+        match self {
+            History::Partial { messages, .. } | History::Full { messages, .. } => {
+                metadata::latest_sent_read(messages, read_marker)
+            }
+        }
+    }
+
     pub fn first_can_reference(&self) -> Option<&Message> {
         match self {
             History::Partial { messages, .. } | History::Full { messages, .. } => {
@@ -447,23 +1205,165 @@ impl History {
                     },
                     |message| Some(message.references()),
                 ),
-            History::Full { messages, .. } => messages
+            History::Full {
+                messages, evicted, ..
+            } => messages
                 .iter()
                 .rev()
                 .find(|message| message.can_reference() && message.server_time < server_time)
-                .map(|message| message.references()),
+                .map(|message| message.references())
+                .or_else(|| {
+                    evicted
+                        .as_ref()
+                        .and_then(|evicted| evicted.last_can_reference.clone())
+                        .filter(|references| references.timestamp < server_time)
+                }),
         }
     }
 
-    pub fn update_read_marker(&mut self, read_marker: ReadMarker) {
-        let stored = match self {
-            History::Partial { read_marker, .. } => read_marker,
-            History::Full { read_marker, .. } => read_marker,
+    pub fn evicted(&self) -> Option<&Evicted> {
+        match self {
+            History::Partial { .. } => None,
+            History::Full { evicted, .. } => evicted.as_ref(),
+        }
+    }
+
+    /// Start loading evicted messages back from disk, returning the time
+    /// they're older than. `None` if there are none or they're already
+    /// being loaded.
+    fn load_evicted(&mut self) -> Option<DateTime<Utc>> {
+        match self {
+            History::Partial { .. } => None,
+            History::Full {
+                evicted,
+                loading_evicted,
+                ..
+            } => {
+                if *loading_evicted {
+                    return None;
+                }
+
+                let before = evicted.as_ref()?.before;
+                *loading_evicted = true;
+
+                Some(before)
+            }
+        }
+    }
+
+    /// Evicted messages failed to load back from disk
+    fn failed_loading_evicted(&mut self) {
+        if let History::Full {
+            loading_evicted, ..
+        } = self
+        {
+            *loading_evicted = false;
+        }
+    }
+
+    /// Insert messages loaded back from disk, older than anything currently
+    /// held in memory
+    fn insert_evicted(&mut self, loaded: Vec<Message>) {
+        if let History::Full {
+            messages,
+            evicted,
+            reloaded,
+            loading_evicted,
+            corrections,
+            ..
+        } = self
+        {
+            *loading_evicted = false;
+
+            let count = loaded.len();
+            let oldest = loaded.first().map(|message| message.server_time);
+
+            *reloaded += count;
+
+            loaded.into_iter().for_each(|message| {
+                insert_message(messages, message);
+            });
+
+            *corrections = Corrections::new(messages.iter());
+
+            if let (Some(evicted), Some(oldest)) = (evicted.as_mut(), oldest) {
+                evicted.before = oldest;
+            }
+
+            // Received less than requested, everything on disk is now in memory
+            if count < LOAD_EVICTED_COUNT {
+                *evicted = None;
+            }
+        }
+    }
+
+    pub fn update_read_marker(&mut self, read_marker: ReadMarker, config: &Config) {
+        let (latest, stored) = match self {
+            History::Partial {
+                messages,
+                max_triggers_unread,
+                read_marker,
+                ..
+            } => (
+                messages
+                    .last()
+                    .map(|message| message.server_time)
+                    .max(*max_triggers_unread),
+                read_marker,
+            ),
+            History::Full {
+                messages,
+                read_marker,
+                ..
+            } => (
+                messages.last().map(|message| message.server_time),
+                read_marker,
+            ),
         };
 
+        // A marker from a fast clock, e.g. relayed back by the server, would
+        // hold back every marker after it
+        if read_marker.is_skewed(latest, Utc::now(), config) {
+            log::debug!("ignoring read marker {read_marker} ahead of the clock and messages");
+            return;
+        }
+
         *stored = (*stored).max(Some(read_marker));
     }
 
+    /// Mark read up to the latest message. With `force` the read marker is
+    /// set even when it's ahead of the latest message.
+    pub fn mark_read(&mut self, force: bool, config: &Config) -> Option<ReadMarker> {
+        let (History::Partial {
+            messages,
+            read_marker,
+            ..
+        }
+        | History::Full {
+            messages,
+            read_marker,
+            ..
+        }) = self;
+
+        let latest = ReadMarker::latest(messages, config)?;
+
+        *read_marker = if force {
+            Some(latest)
+        } else {
+            (*read_marker).max(Some(latest))
+        };
+
+        let read_marker = *read_marker;
+
+        if let History::Full { seen, .. } = self {
+            if force {
+                *seen = Some(latest);
+            }
+        }
+
+        read_marker
+    }
+
     pub fn read_marker(&self) -> Option<ReadMarker> {
         match self {
             History::Partial { read_marker, .. } | History::Full { read_marker, .. } => {
@@ -482,6 +1382,10 @@ impl History {
 pub fn insert_message(messages: &mut Vec<Message>, message: Message) {
     const FUZZ_SECONDS: chrono::Duration = chrono::Duration::seconds(1);
 
+    if session(&message) == Some(message::source::Session::Started) {
+        close_unclean_session(messages, message.server_time);
+    }
+
     if messages.is_empty() {
         messages.push(message);
 
@@ -534,6 +1438,33 @@ pub fn insert_message(messages: &mut Vec<Message>, message: Message) {
     }
 }
 
+fn session(message: &Message) -> Option<message::source::Session> {
+    match message.target.source() {
+        message::Source::Internal(message::source::Internal::Session(session)) => Some(*session),
+        _ => None,
+    }
+}
+
+/// If the most recent session prior to `started_at` never recorded an end
+/// marker, the previous run didn't shut down cleanly. Close it out at the
+/// time of the last message we have from that session.
+fn close_unclean_session(messages: &mut Vec<Message>, started_at: DateTime<Utc>) {
+    let mut prior = messages
+        .iter()
+        .rev()
+        .skip_while(|message| message.server_time >= started_at);
+
+    let Some(last_seen) = prior.clone().next().map(|message| message.server_time) else {
+        return;
+    };
+
+    if prior.find_map(session) == Some(message::source::Session::Started) {
+        for ended in message::broadcast::session_ended_unexpectedly(last_seen) {
+            insert_message(messages, ended);
+        }
+    }
+}
+
 /// The content of JOIN, PART, and QUIT messages may be dependent on how
 /// the user attributes are resolved.  Match those messages based on Nick
 /// alone (covered by comparing target components) to avoid false negatives.
@@ -566,6 +1497,7 @@ pub struct View<'a> {
     pub new_messages: Vec<&'a Message>,
     pub max_nick_chars: Option<usize>,
     pub max_prefix_chars: Option<usize>,
+    pub has_evicted: bool,
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -576,4 +1508,65 @@ pub enum Error {
     Io(#[from] io::Error),
     #[error(transparent)]
     SerdeJson(#[from] serde_json::Error),
+    #[error("history at {} failed to load and is left as is", .0.display())]
+    Unreadable(PathBuf),
+    #[error("history at {} holds {stored}, not {kind}", path.display())]
+    OtherKind {
+        path: PathBuf,
+        stored: String,
+        kind: String,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message::{Source, Target};
+
+    #[test]
+    fn unreadable_history_is_kept() {
+        let server = Server::from("libera");
+        let kind = Kind::Channel(server.clone(), "#halloy".to_string());
+        let dir = std::env::temp_dir().join(format!("halloy-history-{}", std::process::id()));
+
+        let mut config = Config::default();
+        config.directories.insert(server, dir.clone());
+
+        let message = Message::sent(
+            Target::Channel {
+                channel: "#halloy".to_string(),
+                source: Source::Action,
+                prefixes: vec![],
+            },
+            message::plain("hello".to_string()),
+        );
+
+        tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap()
+            .block_on(async {
+                let path = path(&kind, &config).await.unwrap();
+
+                // Truncated after the gzip header
+                let stored = b"\x1f\x8b\x08\x00".to_vec();
+                fs::write(&path, &stored).await.unwrap();
+
+                assert!(matches!(
+                    load(kind.clone(), &config).await,
+                    Err(Error::Unreadable(_))
+                ));
+                assert_eq!(
+                    fs::read(path.with_extension("gz.bak")).await.unwrap(),
+                    stored
+                );
+
+                assert!(append(&kind, vec![message.clone()], None, &config)
+                    .await
+                    .is_err());
+                assert!(overwrite(&kind, &[message], None, &config).await.is_err());
+                assert_eq!(fs::read(&path).await.unwrap(), stored);
+            });
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
 }
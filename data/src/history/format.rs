@@ -0,0 +1,495 @@
+use std::fmt;
+
+use serde::de::{self, MapAccess, SeqAccess, Visitor};
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::{Map, Value};
+
+use crate::history::Metadata;
+use crate::{compression, time, Message};
+
+/// Version of the history files written by this build. Bump it along with
+/// a step in [`migrate_message`] when the stored shape of [`Message`]
+/// changes in a way older builds can't read.
+pub const HISTORY_VERSION: u32 = 1;
+/// Version of the metadata files written by this build, see
+/// [`migrate_metadata`]
+pub const METADATA_VERSION: u32 = 1;
+
+/// Messages read back from a history file
+#[derive(Debug)]
+pub struct Decoded {
+    pub messages: Vec<Message>,
+    /// Version the file was written with, `0` for the bare array of
+    /// messages written before files were versioned
+    pub version: u32,
+    /// [`Kind::name`](crate::history::Kind::name) of the buffer the file
+    /// was written for, `None` before it was recorded
+    pub kind: Option<String>,
+    /// Records which failed to deserialize and were left out
+    pub skipped: usize,
+}
+
+impl Decoded {
+    /// Whether the file was written by an older build and should be
+    /// rewritten in the current format
+    pub fn is_outdated(&self) -> bool {
+        self.version < HISTORY_VERSION
+    }
+}
+
+#[derive(Serialize)]
+struct Envelope<'a> {
+    version: u32,
+    kind: &'a str,
+    messages: &'a [Message],
+}
+
+/// Encode the history of the buffer named `kind`, see
+/// [`Kind::name`](crate::history::Kind::name)
+pub fn encode(kind: &str, messages: &[Message]) -> Result<Vec<u8>, compression::Error> {
+    compression::compress(&Envelope {
+        version: HISTORY_VERSION,
+        kind,
+        messages,
+    })
+}
+
+/// Decode a history file, leaving out records which fail to deserialize
+/// rather than losing the whole file to one of them
+pub fn decode(bytes: &[u8]) -> Result<Decoded, compression::Error> {
+    let Stored {
+        version,
+        kind,
+        records,
+    } = compression::decompress(bytes)?;
+
+    let total = records.len();
+    let messages = records
+        .into_iter()
+        .filter_map(|record| Message::deserialize(migrate_message(version, record)).ok())
+        .collect::<Vec<_>>();
+
+    Ok(Decoded {
+        skipped: total - messages.len(),
+        messages,
+        version,
+        kind,
+    })
+}
+
+/// Name of the buffer a history file was written for, without decoding
+/// its messages. `None` for files from before it was recorded.
+pub fn stored_kind(bytes: &[u8]) -> Option<String> {
+    struct KindVisitor<'a>(&'a mut Option<String>);
+
+    impl<'de> Visitor<'de> for KindVisitor<'_> {
+        type Value = ();
+
+        fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+            formatter.write_str("a versioned history")
+        }
+
+        fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<(), A::Error> {
+            while let Some(key) = map.next_key::<String>()? {
+                match key.as_str() {
+                    "kind" => {
+                        *self.0 = map.next_value()?;
+                        break;
+                    }
+                    // The kind is written before the messages
+                    "messages" => break,
+                    _ => {
+                        map.next_value::<de::IgnoredAny>()?;
+                    }
+                }
+            }
+
+            Ok(())
+        }
+    }
+
+    let mut kind = None;
+    let mut deserializer = serde_json::Deserializer::from_reader(compression::reader(bytes));
+
+    // Leaving the map early fails the parse, but the kind has been read by
+    // then and the messages are never decompressed
+    let _ = (&mut deserializer).deserialize_map(KindVisitor(&mut kind));
+
+    kind
+}
+
+/// Upgrade a message record written with `version` to the current shape,
+/// one version at a time
+fn migrate_message(version: u32, record: Value) -> Value {
+    (version..HISTORY_VERSION).fold(record, |record, from| match from {
+        // Only lacked the envelope
+        0 => record,
+        _ => record,
+    })
+}
+
+/// Either the bare array of records written before versioning, or the
+/// [`Envelope`]. Records are kept as JSON until they're migrated.
+struct Stored {
+    version: u32,
+    kind: Option<String>,
+    records: Vec<Value>,
+}
+
+impl<'de> Deserialize<'de> for Stored {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct StoredVisitor;
+
+        impl<'de> Visitor<'de> for StoredVisitor {
+            type Value = Stored;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("an array of messages or a versioned history")
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Stored, A::Error> {
+                let mut records = vec![];
+
+                while let Some(record) = seq.next_element()? {
+                    records.push(record);
+                }
+
+                Ok(Stored {
+                    version: 0,
+                    kind: None,
+                    records,
+                })
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Stored, A::Error> {
+                let mut version = None;
+                let mut kind = None;
+                let mut records = None;
+
+                while let Some(key) = map.next_key::<String>()? {
+                    match key.as_str() {
+                        "version" => version = Some(map.next_value()?),
+                        "kind" => kind = map.next_value()?,
+                        "messages" => records = Some(map.next_value()?),
+                        _ => {
+                            map.next_value::<de::IgnoredAny>()?;
+                        }
+                    }
+                }
+
+                Ok(Stored {
+                    version: version.ok_or_else(|| de::Error::missing_field("version"))?,
+                    kind,
+                    records: records.ok_or_else(|| de::Error::missing_field("messages"))?,
+                })
+            }
+        }
+
+        deserializer.deserialize_any(StoredVisitor)
+    }
+}
+
+/// Metadata read back from a metadata file
+#[derive(Debug)]
+pub struct DecodedMetadata {
+    pub metadata: Metadata,
+    /// Version the file was written with, `0` before files were versioned
+    pub version: u32,
+    /// See [`Decoded::kind`]
+    pub kind: Option<String>,
+    /// Fields which failed to deserialize and were left at their default
+    pub skipped: Vec<String>,
+}
+
+impl DecodedMetadata {
+    /// See [`Decoded::is_outdated`]
+    pub fn is_outdated(&self) -> bool {
+        self.version < METADATA_VERSION
+    }
+}
+
+#[derive(Serialize)]
+struct MetadataEnvelope<'a> {
+    version: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    kind: Option<&'a str>,
+    // Flattened so builds from before versioning can still read it
+    #[serde(flatten)]
+    metadata: Map<String, Value>,
+}
+
+/// Encode the metadata of the buffer named `kind`, see [`encode`]
+pub fn encode_metadata(
+    kind: Option<&str>,
+    metadata: &Metadata,
+    timestamp_format: time::Format,
+) -> Result<Vec<u8>, serde_json::Error> {
+    serde_json::to_vec(&MetadataEnvelope {
+        version: METADATA_VERSION,
+        kind,
+        metadata: metadata.to_stored(timestamp_format)?,
+    })
+}
+
+/// Decode a metadata file. Fields which fail to deserialize are left at
+/// their default rather than taking the rest of the metadata with them.
+pub fn decode_metadata(bytes: &[u8]) -> Result<DecodedMetadata, serde_json::Error> {
+    let mut fields = serde_json::from_slice::<Map<String, Value>>(bytes)?;
+
+    let version = fields
+        .remove("version")
+        .and_then(|version| version.as_u64())
+        .map_or(0, |version| version as u32);
+    let kind = match fields.remove("kind") {
+        Some(Value::String(kind)) => Some(kind),
+        _ => None,
+    };
+    let fields = migrate_metadata(version, fields);
+
+    if let Ok(metadata) = serde_json::from_value(Value::Object(fields.clone())) {
+        return Ok(DecodedMetadata {
+            metadata,
+            version,
+            kind,
+            skipped: vec![],
+        });
+    }
+
+    // Every field has a default, so keep each one which deserializes
+    let mut kept = Map::new();
+    let mut skipped = vec![];
+
+    for (field, value) in fields {
+        let mut candidate = kept.clone();
+        candidate.insert(field.clone(), value);
+
+        if serde_json::from_value::<Metadata>(Value::Object(candidate.clone())).is_ok() {
+            kept = candidate;
+        } else {
+            skipped.push(field);
+        }
+    }
+
+    Ok(DecodedMetadata {
+        metadata: serde_json::from_value(Value::Object(kept))?,
+        version,
+        kind,
+        skipped,
+    })
+}
+
+/// See [`stored_kind`]
+pub fn stored_metadata_kind(bytes: &[u8]) -> Option<String> {
+    decode_metadata(bytes).ok().and_then(|decoded| decoded.kind)
+}
+
+/// Upgrade metadata fields written with `version` to the current shape,
+/// one version at a time
+fn migrate_metadata(version: u32, fields: Map<String, Value>) -> Map<String, Value> {
+    (version..METADATA_VERSION).fold(fields, |fields, from| match from {
+        // Only lacked the version field
+        0 => fields,
+        _ => fields,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::message::{Content, Direction, Target};
+    use crate::user::Nick;
+
+    // Messages as written by the current build. Changing how any of these
+    // are read loses the history of everyone upgrading.
+    fn stored_messages() -> Value {
+        json!([
+            {
+                "received_at": 1730462400000000000u64,
+                "server_time": "2024-11-01T12:00:00.000Z",
+                "direction": "Received",
+                "target": {"Query": {"nick": "casperstorm", "source": "Action"}},
+                "content": {"Plain": "waves"},
+                "text": "waves"
+            },
+            {
+                "received_at": 1730462460000000000u64,
+                "server_time": "2024-11-01T12:01:00.000Z",
+                "direction": "Sent",
+                "target": {"Query": {"nick": "casperstorm", "source": "Action"}},
+                "content": {"Plain": "waves back"},
+                "text": "waves back"
+            }
+        ])
+    }
+
+    #[test]
+    fn reads_current_format() {
+        let bytes = compression::compress(&json!({
+            "version": HISTORY_VERSION,
+            "messages": stored_messages(),
+        }))
+        .unwrap();
+
+        let decoded = decode(&bytes).unwrap();
+
+        assert_eq!(decoded.version, HISTORY_VERSION);
+        assert_eq!(decoded.skipped, 0);
+        assert!(!decoded.is_outdated());
+        assert_eq!(decoded.messages.len(), 2);
+
+        let message = &decoded.messages[0];
+        assert!(matches!(message.direction, Direction::Received));
+        assert!(matches!(
+            &message.target,
+            Target::Query { nick, .. } if *nick == Nick::from("casperstorm")
+        ));
+        assert!(matches!(&message.content, Content::Plain(text) if text == "waves"));
+        assert_eq!(
+            message.server_time,
+            "2024-11-01T12:00:00Z"
+                .parse::<chrono::DateTime<chrono::Utc>>()
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn reads_unversioned_format() {
+        let bytes = compression::compress(&stored_messages()).unwrap();
+
+        let decoded = decode(&bytes).unwrap();
+
+        assert_eq!(decoded.version, 0);
+        assert!(decoded.is_outdated());
+        assert_eq!(decoded.kind, None);
+        assert_eq!(stored_kind(&bytes), None);
+        assert_eq!(decoded.messages.len(), 2);
+    }
+
+    #[test]
+    fn skips_records_which_fail() {
+        let mut messages = stored_messages();
+        messages
+            .as_array_mut()
+            .unwrap()
+            .insert(1, json!({"direction": "Sideways"}));
+
+        let bytes = compression::compress(&json!({
+            "version": HISTORY_VERSION,
+            "messages": messages,
+        }))
+        .unwrap();
+
+        let decoded = decode(&bytes).unwrap();
+
+        assert_eq!(decoded.skipped, 1);
+        assert_eq!(decoded.messages.len(), 2);
+    }
+
+    #[test]
+    fn stored_kind_stops_before_messages() {
+        use std::io::Write;
+
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::fast());
+        encoder
+            .write_all(br#"{"version":1,"kind":"[\"logs\"]","messages":[{"#)
+            .unwrap();
+        let bytes = encoder.finish().unwrap();
+
+        assert!(decode(&bytes).is_err());
+        assert_eq!(stored_kind(&bytes).as_deref(), Some(r#"["logs"]"#));
+    }
+
+    #[test]
+    fn round_trip() {
+        let bytes = compression::compress(&stored_messages()).unwrap();
+        let messages = decode(&bytes).unwrap().messages;
+
+        let encoded = encode(r#"["logs"]"#, &messages).unwrap();
+        let written = compression::decompress::<Value>(&encoded).unwrap();
+
+        assert_eq!(written["version"], json!(HISTORY_VERSION));
+        assert_eq!(stored_kind(&encoded).as_deref(), Some(r#"["logs"]"#));
+
+        let fields = written["messages"][0].as_object().unwrap();
+        for field in [
+            "received_at",
+            "server_time",
+            "direction",
+            "target",
+            "content",
+            "text",
+        ] {
+            assert!(fields.contains_key(field), "{field}");
+        }
+
+        let decoded = decode(&encoded).unwrap();
+
+        assert_eq!(decoded.kind.as_deref(), Some(r#"["logs"]"#));
+        assert_eq!(decoded.skipped, 0);
+        assert_eq!(decoded.messages.len(), messages.len());
+
+        for (decoded, message) in decoded.messages.iter().zip(&messages) {
+            assert_eq!(decoded.server_time, message.server_time);
+            assert_eq!(decoded.received_at, message.received_at);
+            assert_eq!(decoded.content, message.content);
+            assert_eq!(decoded.target, message.target);
+            assert_eq!(decoded.hash, message.hash);
+        }
+    }
+
+    #[test]
+    fn metadata_round_trip() {
+        let stored = json!({
+            "read_marker": "2024-11-01T12:00:00.000Z",
+            "last_triggers_unread": "2024-11-01T12:00:00.000Z",
+            "chathistory_references": {"timestamp": "2024-11-01T12:00:00.000Z", "id": "abc"},
+            "highlight_read_marker": null,
+            "last_self_activity": null,
+            "member_counts": [{"at": "2024-11-01T12:00:00.000Z", "count": 42}]
+        });
+
+        let decoded = decode_metadata(&serde_json::to_vec(&stored).unwrap()).unwrap();
+
+        assert_eq!(decoded.version, 0);
+        assert!(decoded.is_outdated());
+        assert!(decoded.skipped.is_empty());
+        assert!(decoded.metadata.read_marker.is_some());
+        assert_eq!(decoded.metadata.member_counts.len(), 1);
+
+        let encoded = encode_metadata(
+            Some(r#"["logs"]"#),
+            &decoded.metadata,
+            time::Format::default(),
+        )
+        .unwrap();
+        let written = serde_json::from_slice::<Value>(&encoded).unwrap();
+
+        assert_eq!(written["version"], json!(METADATA_VERSION));
+        // Fields stay at the top level for builds without versioning
+        assert!(serde_json::from_slice::<Metadata>(&encoded)
+            .unwrap()
+            .eq_exact(&decoded.metadata));
+
+        let decoded = decode_metadata(&encoded).unwrap();
+
+        assert_eq!(decoded.version, METADATA_VERSION);
+        assert_eq!(decoded.kind.as_deref(), Some(r#"["logs"]"#));
+        assert!(!decoded.is_outdated());
+    }
+
+    #[test]
+    fn metadata_keeps_fields_which_deserialize() {
+        let stored = br#"{"version":1,"read_marker":"2024-11-01T12:00:00.000Z","last_self_activity":"yesterday"}"#;
+
+        let decoded = decode_metadata(stored).unwrap();
+
+        assert_eq!(decoded.skipped, vec!["last_self_activity".to_string()]);
+        assert_eq!(
+            decoded.metadata.read_marker,
+            "2024-11-01T12:00:00.000Z".parse().ok()
+        );
+        assert_eq!(decoded.metadata.last_self_activity, None);
+    }
+}
@@ -1,16 +1,23 @@
 use std::collections::{HashMap, HashSet};
+use std::time::Duration;
 
 use chrono::{DateTime, Utc};
 use futures::future::BoxFuture;
 use futures::{future, Future, FutureExt};
 use tokio::time::Instant;
 
+use crate::history::metadata::{self, MemberCount};
 use crate::history::{self, History, MessageReferences};
+use crate::isupport::ChatHistorySubcommand;
+use crate::message::correction::Corrections;
 use crate::message::{self, Limit};
 use crate::user::Nick;
 use crate::{buffer, config, input};
 use crate::{server, Config, Input, Server, User};
 
+/// Minimum time between the member counts recorded for a channel
+const MEMBER_COUNT_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct Resource {
     pub kind: history::Kind,
@@ -32,18 +39,31 @@ impl Resource {
 
 #[derive(Debug)]
 pub enum Message {
-    LoadFull(history::Kind, Result<history::Loaded, history::Error>),
+    LoadFull(history::Kind, Result<history::ResumeState, history::Error>),
     UpdatePartial(history::Kind, Result<history::Metadata, history::Error>),
     UpdateReadMarker(
         history::Kind,
         history::ReadMarker,
         Result<(), history::Error>,
     ),
+    SeededReadMarker(
+        history::Kind,
+        Result<Option<history::ReadMarker>, history::Error>,
+    ),
+    MarkedHighlightsRead(
+        history::Kind,
+        history::ReadMarker,
+        Result<(), history::Error>,
+    ),
     Closed(
         history::Kind,
         Result<Option<history::ReadMarker>, history::Error>,
     ),
     Flushed(history::Kind, Result<(), history::Error>),
+    UpdatedMemberCount(history::Kind, Result<(), history::Error>),
+    Cleared(history::Kind, Result<(), history::Error>),
+    Deleted(history::Kind, Result<(), history::Error>),
+    LoadedEvicted(history::Kind, Result<Vec<crate::Message>, history::Error>),
     Exited(
         Vec<(
             history::Kind,
@@ -53,7 +73,10 @@ pub enum Message {
 }
 
 pub enum Event {
-    Loaded(history::Kind),
+    /// Loaded along with the request to backfill what was missed while the
+    /// buffer was closed, if any
+    Loaded(history::Kind, Option<ChatHistorySubcommand>),
+    LoadedEvicted(history::Kind),
     Closed(history::Kind, Option<history::ReadMarker>),
     Exited(Vec<(history::Kind, Option<history::ReadMarker>)>),
 }
@@ -62,16 +85,42 @@ pub enum Event {
 pub struct Manager {
     resources: HashSet<Resource>,
     data: Data,
+    config: history::Config,
 }
 
 impl Manager {
-    pub fn track(&mut self, new_resources: HashSet<Resource>) -> Vec<BoxFuture<'static, Message>> {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            config: history::Config::new(config),
+            ..Self::default()
+        }
+    }
+
+    /// Apply the history settings of a reloaded `config`
+    pub fn set_config(&mut self, config: &Config) {
+        self.config = history::Config::new(config);
+    }
+
+    pub fn config(&self) -> &history::Config {
+        &self.config
+    }
+
+    /// Load the history of newly opened buffers and close the rest, `caps`
+    /// being what each server supports of `CHATHISTORY` to backfill with
+    pub fn track(
+        &mut self,
+        new_resources: HashSet<Resource>,
+        caps: impl Fn(&Server) -> history::ServerCaps,
+    ) -> Vec<BoxFuture<'static, Message>> {
         let added = new_resources.difference(&self.resources).cloned();
         let removed = self.resources.difference(&new_resources).cloned();
 
         let added = added.into_iter().map(|resource| {
+            let config = self.config.clone();
+            let caps = resource.kind.server().map(&caps).unwrap_or_default();
+
             async move {
-                history::load(resource.kind.clone())
+                history::resume(resource.kind.clone(), caps, &config)
                     .map(move |result| Message::LoadFull(resource.kind, result))
                     .await
             }
@@ -79,7 +128,7 @@ impl Manager {
         });
 
         let removed = removed.into_iter().filter_map(|resource| {
-            self.data.untrack(&resource.kind).map(|task| {
+            self.data.untrack(&resource.kind, &self.config).map(|task| {
                 task.map(|result| Message::Closed(resource.kind, result))
                     .boxed()
             })
@@ -94,13 +143,23 @@ impl Manager {
 
     pub fn update(&mut self, message: Message) -> Option<Event> {
         match message {
-            Message::LoadFull(kind, Ok(loaded)) => {
-                log::debug!(
-                    "loaded history for {kind}: {} messages",
-                    loaded.messages.len()
+            Message::LoadFull(kind, Ok(resumed)) => {
+                let history::ResumeState {
+                    messages,
+                    metadata,
+                    chathistory,
+                } = resumed;
+
+                log::debug!("loaded history for {kind}: {} messages", messages.len());
+                self.data.load_member_counts(&kind, &metadata.member_counts);
+                self.data
+                    .load_highlight_read_marker(&kind, metadata.highlight_read_marker);
+                self.data.load_full(
+                    kind.clone(),
+                    history::Loaded { messages, metadata },
+                    &self.config,
                 );
-                self.data.load_full(kind.clone(), loaded);
-                return Some(Event::Loaded(kind));
+                return Some(Event::Loaded(kind, chathistory));
             }
             Message::LoadFull(kind, Err(error)) => {
                 log::warn!("failed to load history for {kind}: {error}");
@@ -121,8 +180,44 @@ impl Manager {
             Message::Flushed(kind, Err(error)) => {
                 log::warn!("failed to flush history for {kind}: {error}")
             }
+            Message::UpdatedMemberCount(kind, Ok(_)) => {
+                log::trace!("updated member count of {kind}");
+            }
+            Message::UpdatedMemberCount(kind, Err(error)) => {
+                log::warn!("failed to update member count of {kind}: {error}");
+            }
+            Message::Cleared(kind, Ok(_)) => {
+                log::debug!("cleared history for {kind}");
+            }
+            Message::Cleared(kind, Err(error)) => {
+                log::warn!("failed to clear history for {kind}: {error}");
+            }
+            Message::Deleted(kind, Ok(_)) => {
+                log::debug!("deleted history for {kind}");
+            }
+            Message::Deleted(kind, Err(error)) => {
+                log::warn!("failed to delete history for {kind}: {error}");
+            }
+            Message::LoadedEvicted(kind, Ok(messages)) => {
+                log::debug!(
+                    "loaded evicted history for {kind}: {} messages",
+                    messages.len()
+                );
+                self.data.insert_evicted(kind.clone(), messages);
+                return Some(Event::LoadedEvicted(kind));
+            }
+            Message::LoadedEvicted(kind, Err(error)) => {
+                log::warn!("failed to load evicted history for {kind}: {error}");
+
+                if let Some(history) = self.data.map.get_mut(&kind) {
+                    history.failed_loading_evicted();
+                }
+            }
             Message::UpdatePartial(kind, Ok(metadata)) => {
                 log::debug!("loaded metadata for {kind}");
+                self.data.load_member_counts(&kind, &metadata.member_counts);
+                self.data
+                    .load_highlight_read_marker(&kind, metadata.highlight_read_marker);
                 self.data.update_partial(kind, metadata);
             }
             Message::UpdatePartial(kind, Err(error)) => {
@@ -134,6 +229,25 @@ impl Manager {
             Message::UpdateReadMarker(kind, read_marker, Err(error)) => {
                 log::warn!("failed to update read marker for {kind} to {read_marker}: {error}");
             }
+            Message::SeededReadMarker(kind, Ok(Some(read_marker))) => {
+                log::debug!("seeded read marker for {kind} to {read_marker}");
+
+                if let Some(history) = self.data.map.get_mut(&kind) {
+                    history.update_read_marker(read_marker, &self.config);
+                }
+            }
+            Message::SeededReadMarker(_, Ok(None)) => {}
+            Message::SeededReadMarker(kind, Err(error)) => {
+                log::warn!("failed to seed read marker for {kind}: {error}");
+            }
+            Message::MarkedHighlightsRead(kind, read_marker, Ok(_)) => {
+                log::debug!("updated highlight read marker for {kind} to {read_marker}");
+            }
+            Message::MarkedHighlightsRead(kind, read_marker, Err(error)) => {
+                log::warn!(
+                    "failed to update highlight read marker for {kind} to {read_marker}: {error}"
+                );
+            }
             Message::Exited(results) => {
                 let mut output = vec![];
 
@@ -158,24 +272,149 @@ impl Manager {
     }
 
     pub fn tick(&mut self, now: Instant) -> Vec<BoxFuture<'static, Message>> {
-        self.data.flush_all(now)
+        self.data
+            .flush_all(now, self.config.max_in_memory, &self.config)
+    }
+
+    /// Record the member counts of joined channels with the history flush.
+    /// Counts are written to the channel's metadata when they've changed,
+    /// at most once per [`MEMBER_COUNT_INTERVAL`].
+    pub fn record_member_counts(
+        &mut self,
+        now: DateTime<Utc>,
+        counts: impl IntoIterator<Item = (Server, String, usize)>,
+    ) -> Vec<BoxFuture<'static, Message>> {
+        counts
+            .into_iter()
+            .filter_map(|(server, channel, count)| {
+                let kind = history::Kind::Channel(server, channel);
+                let counts = self.data.member_counts.entry(kind.clone()).or_default();
+
+                if counts.last().is_some_and(|last| {
+                    (now - last.at)
+                        .to_std()
+                        .is_ok_and(|elapsed| elapsed < MEMBER_COUNT_INTERVAL)
+                }) || !metadata::record_member_count(counts, now, count)
+                {
+                    return None;
+                }
+
+                let member_count = MemberCount { at: now, count };
+                let config = self.config.clone();
+
+                Some(
+                    async move {
+                        let result =
+                            metadata::update_member_count(&kind, member_count, &config).await;
+
+                        Message::UpdatedMemberCount(kind, result)
+                    }
+                    .boxed(),
+                )
+            })
+            .collect()
+    }
+
+    /// Member counts recorded for the channel `kind`, oldest first
+    pub fn member_counts(&self, kind: &history::Kind) -> &[MemberCount] {
+        self.data
+            .member_counts
+            .get(kind)
+            .map(Vec::as_slice)
+            .unwrap_or_default()
     }
 
     pub fn close(&mut self, kind: history::Kind) -> Option<impl Future<Output = Message>> {
         let history = self.data.map.remove(&kind)?;
 
-        Some(history.close().map(|result| Message::Closed(kind, result)))
+        Some(
+            history
+                .close(self.config.clone())
+                .map(|result| Message::Closed(kind, result)),
+        )
+    }
+
+    /// Clear the messages of `kind` from memory for the rest of the session,
+    /// leaving the history on disk untouched
+    pub fn clear(&mut self, kind: history::Kind) -> Option<impl Future<Output = Message>> {
+        let task = self.data.map.get_mut(&kind)?.clear(&self.config)?;
+
+        Some(task.map(move |result| Message::Cleared(kind, result)))
+    }
+
+    /// Mark `kind` as read up to its latest message, writing the read
+    /// marker right away. With `force` it's set even when it's behind the
+    /// current read marker, e.g. one set while the clock was fast.
+    pub fn mark_read(
+        &mut self,
+        kind: history::Kind,
+        force: bool,
+    ) -> Option<(history::ReadMarker, impl Future<Output = Message>)> {
+        let read_marker = self
+            .data
+            .map
+            .get_mut(&kind)?
+            .mark_read(force, &self.config)?;
+        let config = self.config.clone();
+
+        Some((read_marker, async move {
+            let updated = if force {
+                history::metadata::overwrite_read_marker(&kind, &read_marker, &config).await
+            } else {
+                history::metadata::update(&kind, &read_marker, &config).await
+            };
+
+            Message::UpdateReadMarker(kind, read_marker, updated)
+        }))
+    }
+
+    /// Clear the messages and read marker of `kind` and delete its history
+    /// from disk
+    pub fn delete(&mut self, kind: history::Kind) -> impl Future<Output = Message> {
+        if let Some(history) = self.data.map.get_mut(&kind) {
+            history.reset();
+        }
+
+        let config = self.config.clone();
+
+        async move {
+            let result = history::delete(&kind, &config).await;
+
+            Message::Deleted(kind, result)
+        }
     }
 
     pub fn exit(&mut self) -> impl Future<Output = Message> {
-        let map = std::mem::take(&mut self.data).map;
+        let mut map = std::mem::take(&mut self.data).map;
+        let config = self.config.clone();
+
+        let ended_at = Utc::now();
+
+        // Mark a clean end of the session for every server, the absence of
+        // this marker is how an unclean shutdown is detected on next startup
+        for (kind, history) in map.iter_mut() {
+            if matches!(kind, history::Kind::Server(_)) {
+                for message in message::broadcast::session_ended(ended_at) {
+                    history.add_message(message);
+                }
+            }
+        }
 
         async move {
-            let tasks = map
-                .into_iter()
-                .map(|(kind, state)| state.close().map(move |result| (kind, result)));
+            let tasks = map.into_iter().map(|(kind, state)| {
+                state
+                    .close(config.clone())
+                    .map(move |result| (kind, result))
+            });
+
+            let results = future::join_all(tasks).await;
 
-            Message::Exited(future::join_all(tasks).await)
+            log::info!(
+                "{} bytes of history written this session",
+                history::bytes_written()
+            );
+
+            Message::Exited(results)
         }
     }
 
@@ -212,22 +451,26 @@ impl Manager {
         message: crate::Message,
     ) -> Option<impl Future<Output = Message>> {
         history::Kind::from_server_message(server.clone(), &message)
-            .and_then(|kind| self.data.add_message(kind, message))
+            .and_then(|kind| self.data.add_message(kind, message, &self.config))
     }
 
     pub fn record_log(
         &mut self,
         record: crate::log::Record,
     ) -> Option<impl Future<Output = Message>> {
-        self.data
-            .add_message(history::Kind::Logs, crate::Message::log(record))
+        self.data.add_message(
+            history::Kind::Logs,
+            crate::Message::log(record),
+            &self.config,
+        )
     }
 
     pub fn record_highlight(
         &mut self,
         message: crate::Message,
     ) -> Option<impl Future<Output = Message>> {
-        self.data.add_message(history::Kind::Highlights, message)
+        self.data
+            .add_message(history::Kind::Highlights, message, &self.config)
     }
 
     pub fn update_read_marker(
@@ -235,7 +478,85 @@ impl Manager {
         kind: impl Into<history::Kind>,
         read_marker: history::ReadMarker,
     ) -> Option<impl Future<Output = Message>> {
-        self.data.update_read_marker(kind, read_marker)
+        self.data
+            .update_read_marker(kind, read_marker, &self.config)
+    }
+
+    /// Mark the open buffer `kind` as seen up to its latest message, read
+    /// once it's no longer open
+    pub fn mark_seen(&mut self, kind: &history::Kind) {
+        if let Some(history) = self.data.map.get_mut(kind) {
+            history.mark_seen(&self.config);
+        }
+    }
+
+    /// Seed the read marker of `target` from `source`, only if `target`
+    /// has no read marker of its own yet
+    pub fn seed_read_marker(
+        &mut self,
+        target: history::Kind,
+        source: history::Kind,
+    ) -> Option<impl Future<Output = Message>> {
+        self.data.seed_read_marker(target, source, &self.config)
+    }
+
+    /// Advance the highlight read marker of the channel `kind` to its
+    /// latest highlight held in memory, `None` if it's already there
+    pub fn mark_highlights_read(
+        &mut self,
+        kind: history::Kind,
+    ) -> Option<impl Future<Output = Message>> {
+        let latest = match self.data.map.get(&history::Kind::Highlights)? {
+            History::Partial { messages, .. } | History::Full { messages, .. } => {
+                metadata::latest_highlight(messages, &kind)?
+            }
+        };
+
+        if self
+            .data
+            .highlight_read_markers
+            .get(&kind)
+            .is_some_and(|read_marker| *read_marker >= latest)
+        {
+            return None;
+        }
+
+        self.data
+            .highlight_read_markers
+            .insert(kind.clone(), latest);
+
+        let config = self.config.clone();
+
+        Some(async move {
+            let result = metadata::update_highlight_read_marker(&kind, &latest, &config).await;
+
+            Message::MarkedHighlightsRead(kind, latest, result)
+        })
+    }
+
+    /// Advance the highlight read marker of every channel with highlights
+    /// held in memory, e.g. once the highlights buffer has been seen
+    pub fn mark_all_highlights_read(&mut self) -> Vec<impl Future<Output = Message>> {
+        let Some(History::Partial { messages, .. } | History::Full { messages, .. }) =
+            self.data.map.get(&history::Kind::Highlights)
+        else {
+            return vec![];
+        };
+
+        let channels = messages
+            .iter()
+            .filter_map(|message| match &message.target {
+                message::Target::Highlights {
+                    server, channel, ..
+                } => Some(history::Kind::Channel(server.clone(), channel.clone())),
+                _ => None,
+            })
+            .collect::<HashSet<_>>();
+
+        channels
+            .into_iter()
+            .filter_map(|kind| self.mark_highlights_read(kind))
+            .collect()
     }
 
     pub fn load_metadata(
@@ -243,7 +564,7 @@ impl Manager {
         server: Server,
         channel: String,
     ) -> Option<impl Future<Output = Message>> {
-        self.data.load_metadata(server, channel)
+        self.data.load_metadata(server, channel, &self.config)
     }
 
     pub fn first_can_reference(
@@ -275,6 +596,77 @@ impl Manager {
         self.data.history_view(kind, limit, buffer_config)
     }
 
+    /// Corrections made with `s/pattern/replacement/` in `kind`, kept up to
+    /// date as its messages are added
+    pub fn corrections(
+        &self,
+        kind: &history::Kind,
+        buffer_config: &config::Buffer,
+    ) -> Option<&Corrections> {
+        if !buffer_config.corrections.enabled {
+            return None;
+        }
+
+        match self.data.map.get(kind) {
+            Some(History::Full { corrections, .. }) => Some(corrections),
+            _ => None,
+        }
+    }
+
+    /// Highlights of the channel `kind` held in memory, oldest first
+    pub fn highlights(&self, kind: &history::Kind) -> Vec<message::Hash> {
+        let history::Kind::Channel(server, channel) = kind else {
+            return vec![];
+        };
+
+        let Some(History::Partial { messages, .. } | History::Full { messages, .. }) =
+            self.data.map.get(&history::Kind::Highlights)
+        else {
+            return vec![];
+        };
+
+        let mut highlights = messages
+            .iter()
+            .filter(|message| {
+                matches!(
+                    &message.target,
+                    message::Target::Highlights {
+                        server: highlight_server,
+                        channel: highlight_channel,
+                        ..
+                    } if highlight_server == server && highlight_channel == channel
+                )
+            })
+            .collect::<Vec<_>>();
+
+        highlights.sort_by_key(|message| message.server_time);
+
+        highlights.into_iter().map(|message| message.hash).collect()
+    }
+
+    pub fn has_evicted(&self, kind: &history::Kind) -> bool {
+        self.data
+            .map
+            .get(kind)
+            .is_some_and(|history| history.evicted().is_some())
+    }
+
+    /// Load messages which were evicted from memory back in from disk, `None`
+    /// if there are none or they're already being loaded
+    pub fn load_evicted(&mut self, kind: history::Kind) -> Option<impl Future<Output = Message>> {
+        let before = self.data.map.get_mut(&kind)?.load_evicted()?;
+
+        Some(
+            history::load_before(
+                kind.clone(),
+                before,
+                history::LOAD_EVICTED_COUNT,
+                &self.config,
+            )
+            .map(move |result| Message::LoadedEvicted(kind, result)),
+        )
+    }
+
     pub fn get_unique_queries(&self, server: &Server) -> Vec<&Nick> {
         let queries = self
             .data
@@ -293,10 +685,44 @@ impl Manager {
         self.data
             .map
             .get(kind)
-            .map(|history| history.has_unread())
+            .map(|history| history.has_unread(&self.config))
             .unwrap_or_default()
     }
 
+    /// Whether the channel `kind` has highlights newer than both its read
+    /// marker and its highlight read marker, or the query `kind` has unread
+    /// messages
+    pub fn has_unread_highlights(&self, kind: &history::Kind) -> bool {
+        match kind {
+            history::Kind::Channel(server, channel) => {
+                let read_marker = self
+                    .read_marker(kind)
+                    .max(self.data.highlight_read_markers.get(kind).copied());
+
+                let Some(History::Partial { messages, .. } | History::Full { messages, .. }) =
+                    self.data.map.get(&history::Kind::Highlights)
+                else {
+                    return false;
+                };
+
+                messages.iter().any(|message| {
+                    matches!(
+                        &message.target,
+                        message::Target::Highlights {
+                            server: highlight_server,
+                            channel: highlight_channel,
+                            ..
+                        } if highlight_server == server && highlight_channel == channel
+                    ) && read_marker.map_or(true, |read_marker| {
+                        message.server_time > read_marker.date_time()
+                    })
+                })
+            }
+            history::Kind::Query(..) => self.has_unread(kind),
+            _ => false,
+        }
+    }
+
     pub fn read_marker(&self, kind: &history::Kind) -> Option<history::ReadMarker> {
         self.data
             .map
@@ -305,6 +731,18 @@ impl Manager {
             .unwrap_or_default()
     }
 
+    /// Our latest message in `kind` which `read_marker` covers
+    pub fn latest_sent_read(
+        &self,
+        kind: &history::Kind,
+        read_marker: history::ReadMarker,
+    ) -> Option<DateTime<Utc>> {
+        self.data
+            .map
+            .get(kind)
+            .and_then(|history| history.latest_sent_read(read_marker))
+    }
+
     pub fn broadcast(
         &mut self,
         server: &Server,
@@ -338,6 +776,7 @@ impl Manager {
             .cloned();
 
         let messages = match broadcast {
+            Broadcast::SessionStarted => message::broadcast::session_started(sent_time),
             Broadcast::Connecting => message::broadcast::connecting(sent_time),
             Broadcast::Connected => message::broadcast::connected(sent_time),
             Broadcast::ConnectionFailed { error } => {
@@ -429,6 +868,39 @@ impl Manager {
                     )
                 }
             }
+            Broadcast::Back { user } => {
+                let user_query = queries.find(|nick| user.nickname() == *nick);
+
+                if config.buffer.away.show_back {
+                    message::broadcast::back(user_query, &user, sent_time)
+                } else {
+                    vec![]
+                }
+            }
+            Broadcast::Joined {
+                channel,
+                users,
+                topic,
+                topic_who,
+                topic_time,
+            } => message::broadcast::joined(
+                channel,
+                users,
+                topic,
+                topic_who,
+                topic_time,
+                config.buffer.channel.join.replies,
+                sent_time,
+            ),
+            Broadcast::Netsplit { servers, users } => {
+                let user_queries =
+                    queries.filter(|nick| users.iter().any(|(user, _)| user.nickname() == *nick));
+
+                message::broadcast::netsplit(&servers, &users, user_queries, config, sent_time)
+            }
+            Broadcast::Netjoin { servers, users } => {
+                message::broadcast::netjoin(&servers, &users, config, sent_time)
+            }
         };
 
         messages
@@ -464,10 +936,40 @@ fn with_limit<'a>(
 struct Data {
     map: HashMap<history::Kind, History>,
     input: input::Storage,
+    member_counts: HashMap<history::Kind, Vec<MemberCount>>,
+    highlight_read_markers: HashMap<history::Kind, history::ReadMarker>,
 }
 
 impl Data {
-    fn load_full(&mut self, kind: history::Kind, data: history::Loaded) {
+    fn load_highlight_read_marker(
+        &mut self,
+        kind: &history::Kind,
+        loaded: Option<history::ReadMarker>,
+    ) {
+        let Some(loaded) = loaded else {
+            return;
+        };
+
+        let read_marker = self
+            .highlight_read_markers
+            .entry(kind.clone())
+            .or_insert(loaded);
+
+        *read_marker = (*read_marker).max(loaded);
+    }
+
+    fn load_member_counts(&mut self, kind: &history::Kind, loaded: &[MemberCount]) {
+        if loaded.is_empty() {
+            return;
+        }
+
+        let counts = self.member_counts.remove(kind).unwrap_or_default();
+
+        self.member_counts
+            .insert(kind.clone(), metadata::merge_member_counts(counts, loaded));
+    }
+
+    fn load_full(&mut self, kind: history::Kind, data: history::Loaded, config: &history::Config) {
         use std::collections::hash_map;
 
         let history::Loaded {
@@ -475,6 +977,10 @@ impl Data {
             metadata,
         } = data;
 
+        // Only what's stored is evicted, messages received since opening
+        // aren't on disk yet
+        let evicted = history::Evicted::from_loaded(&mut messages, config);
+
         match self.map.entry(kind.clone()) {
             hash_map::Entry::Occupied(mut entry) => match entry.get_mut() {
                 History::Partial {
@@ -491,33 +997,57 @@ impl Data {
                         .for_each(|message| {
                             history::insert_message(&mut messages, message);
                         });
+                    let corrections = Corrections::new(messages.iter());
                     entry.insert(History::Full {
                         kind,
                         messages,
                         last_updated_at,
                         read_marker,
+                        seen: None,
+                        evicted,
+                        reloaded: 0,
+                        loading_evicted: false,
+                        corrections,
                     });
                 }
                 _ => {
+                    let corrections = Corrections::new(messages.iter());
                     entry.insert(History::Full {
                         kind,
                         messages,
                         last_updated_at: None,
                         read_marker: metadata.read_marker,
+                        seen: None,
+                        evicted,
+                        reloaded: 0,
+                        loading_evicted: false,
+                        corrections,
                     });
                 }
             },
             hash_map::Entry::Vacant(entry) => {
+                let corrections = Corrections::new(messages.iter());
                 entry.insert(History::Full {
                     kind,
                     messages,
                     last_updated_at: None,
                     read_marker: metadata.read_marker,
+                    seen: None,
+                    evicted,
+                    reloaded: 0,
+                    loading_evicted: false,
+                    corrections,
                 });
             }
         }
     }
 
+    fn insert_evicted(&mut self, kind: history::Kind, messages: Vec<crate::Message>) {
+        if let Some(history) = self.map.get_mut(&kind) {
+            history.insert_evicted(messages);
+        }
+    }
+
     fn update_partial(&mut self, kind: history::Kind, data: history::Metadata) {
         if let Some(history) = self.map.get_mut(&kind) {
             history.update_partial(data);
@@ -533,6 +1063,7 @@ impl Data {
         let History::Full {
             messages,
             read_marker,
+            evicted,
             ..
         } = self.map.get(kind)?
         else {
@@ -646,33 +1177,26 @@ impl Data {
             }
         });
 
+        // Anything evicted from memory is older than what we hold, so
+        // there are read messages on disk if the marker predates our window
         let has_read_messages = read_marker
             .map(|marker| {
-                filtered
-                    .iter()
-                    .any(|message| message.server_time <= marker.date_time())
+                evicted.is_some()
+                    || filtered
+                        .iter()
+                        .any(|message| message.server_time <= marker.date_time())
             })
             .unwrap_or_default();
 
         let limited = with_limit(limit, filtered.into_iter());
 
-        let split_at = read_marker.map_or(0, |read_marker| {
-            limited
-                .iter()
-                .rev()
-                .position(|message| message.server_time <= read_marker.date_time())
-                .map_or_else(
-                    || {
-                        // Backlog is before this limit view of messages
-                        if has_read_messages {
-                            0
-                        } else {
-                            limited.len()
-                        }
-                    },
-                    |position| limited.len() - position,
-                )
-        });
+        let split_at = match metadata::divider_index(*read_marker, limited.iter().copied()) {
+            // Backlog is before this limit view of messages, without any
+            // there's nothing to divide
+            Some(0) if read_marker.is_some() && !has_read_messages => limited.len(),
+            Some(index) => index,
+            None => limited.len(),
+        };
 
         let (old, new) = limited.split_at(split_at);
 
@@ -682,6 +1206,7 @@ impl Data {
             new_messages: new.to_vec(),
             max_nick_chars,
             max_prefix_chars,
+            has_evicted: evicted.is_some(),
         })
     }
 
@@ -689,6 +1214,7 @@ impl Data {
         &mut self,
         kind: history::Kind,
         message: crate::Message,
+        config: &history::Config,
     ) -> Option<impl Future<Output = Message>> {
         use std::collections::hash_map;
 
@@ -703,9 +1229,11 @@ impl Data {
                     .insert(History::partial(kind.clone()))
                     .add_message(message);
 
+                let config = config.clone();
+
                 Some(
                     async move {
-                        let loaded = history::metadata::load(kind.clone()).await;
+                        let loaded = history::metadata::load(kind.clone(), &config).await;
 
                         Message::UpdatePartial(kind, loaded)
                     }
@@ -719,6 +1247,7 @@ impl Data {
         &mut self,
         kind: impl Into<history::Kind>,
         read_marker: history::ReadMarker,
+        config: &history::Config,
     ) -> Option<impl Future<Output = Message>> {
         use std::collections::hash_map;
 
@@ -726,25 +1255,68 @@ impl Data {
 
         match self.map.entry(kind.clone()) {
             hash_map::Entry::Occupied(mut entry) => {
-                entry.get_mut().update_read_marker(read_marker);
+                entry.get_mut().update_read_marker(read_marker, config);
 
                 None
             }
-            hash_map::Entry::Vacant(_) => Some(
-                async move {
-                    let updated = history::metadata::update(&kind, &read_marker).await;
+            hash_map::Entry::Vacant(_) => {
+                let config = config.clone();
 
-                    Message::UpdateReadMarker(kind, read_marker, updated)
-                }
-                .boxed(),
-            ),
+                Some(
+                    async move {
+                        let updated = history::metadata::update(&kind, &read_marker, &config).await;
+
+                        Message::UpdateReadMarker(kind, read_marker, updated)
+                    }
+                    .boxed(),
+                )
+            }
         }
     }
 
+    fn seed_read_marker(
+        &mut self,
+        target: history::Kind,
+        source: history::Kind,
+        config: &history::Config,
+    ) -> Option<BoxFuture<'static, Message>> {
+        if self
+            .map
+            .get(&target)
+            .is_some_and(|history| history.read_marker().is_some())
+        {
+            return None;
+        }
+
+        // Prefer the in-memory read marker, it may be ahead of what's on disk
+        let source_read_marker = self.map.get(&source).and_then(History::read_marker);
+
+        if let (Some(history), Some(read_marker)) = (self.map.get_mut(&target), source_read_marker)
+        {
+            history.update_read_marker(read_marker, config);
+
+            return None;
+        }
+
+        let config = config.clone();
+
+        Some(
+            async move {
+                let seeded =
+                    history::metadata::seed_from(&target, &source, source_read_marker, &config)
+                        .await;
+
+                Message::SeededReadMarker(target, seeded)
+            }
+            .boxed(),
+        )
+    }
+
     fn load_metadata(
         &mut self,
         server: server::Server,
         channel: String,
+        config: &history::Config,
     ) -> Option<impl Future<Output = Message>> {
         use std::collections::hash_map;
 
@@ -755,9 +1327,11 @@ impl Data {
             hash_map::Entry::Vacant(entry) => {
                 entry.insert(History::partial(kind.clone()));
 
+                let config = config.clone();
+
                 Some(
                     async move {
-                        let loaded = history::metadata::load(kind.clone()).await;
+                        let loaded = history::metadata::load(kind.clone(), &config).await;
 
                         Message::UpdatePartial(kind, loaded)
                     }
@@ -797,17 +1371,25 @@ impl Data {
     fn untrack(
         &mut self,
         kind: &history::Kind,
+        config: &history::Config,
     ) -> Option<impl Future<Output = Result<Option<history::ReadMarker>, history::Error>>> {
-        self.map.get_mut(kind).and_then(History::make_partial)
+        self.map
+            .get_mut(kind)
+            .and_then(|history| history.make_partial(config))
     }
 
-    fn flush_all(&mut self, now: Instant) -> Vec<BoxFuture<'static, Message>> {
+    fn flush_all(
+        &mut self,
+        now: Instant,
+        max_in_memory: usize,
+        config: &history::Config,
+    ) -> Vec<BoxFuture<'static, Message>> {
         self.map
             .iter_mut()
             .filter_map(|(kind, state)| {
                 let kind = kind.clone();
 
-                state.flush(now).map(move |task| {
+                state.flush(now, max_in_memory, config).map(move |task| {
                     task.map(move |result| Message::Flushed(kind, result))
                         .boxed()
                 })
@@ -845,6 +1427,7 @@ fn smart_filter_internal_message(message: &crate::Message, seconds: &i64) -> boo
 
 #[derive(Debug, Clone)]
 pub enum Broadcast {
+    SessionStarted,
     Connecting,
     Connected,
     ConnectionFailed {
@@ -877,4 +1460,262 @@ pub enum Broadcast {
         ourself: bool,
         user_channels: Vec<String>,
     },
+    Back {
+        user: User,
+    },
+    Joined {
+        channel: String,
+        users: usize,
+        topic: Option<String>,
+        topic_who: Option<String>,
+        topic_time: Option<DateTime<Utc>>,
+    },
+    Netsplit {
+        servers: Vec<String>,
+        users: Vec<(User, Vec<String>)>,
+    },
+    Netjoin {
+        servers: Vec<String>,
+        users: Vec<(User, Vec<String>)>,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message::{Direction, Source, Target};
+    use crate::{Server, User};
+
+    fn highlight(server: &Server, channel: &str) -> crate::Message {
+        let source = Source::User(User::from(Nick::from("casperstorm")));
+
+        crate::Message::test(Direction::Received, source.clone(), Utc::now()).with_target(
+            Target::Highlights {
+                server: server.clone(),
+                channel: channel.to_string(),
+                source,
+            },
+        )
+    }
+
+    #[test]
+    fn loaded_with_backfill() {
+        let kind = history::Kind::Channel(Server::from("libera"), "#halloy".to_string());
+        let subcommand = ChatHistorySubcommand::Latest(
+            "#halloy".to_string(),
+            crate::isupport::MessageReference::None,
+            100,
+        );
+
+        let mut manager = Manager::default();
+
+        let event = manager.update(Message::LoadFull(
+            kind.clone(),
+            Ok(history::ResumeState {
+                messages: vec![],
+                metadata: history::Metadata::default(),
+                chathistory: Some(subcommand.clone()),
+            }),
+        ));
+
+        assert!(matches!(
+            event,
+            Some(Event::Loaded(loaded, Some(chathistory)))
+                if loaded == kind && chathistory == subcommand
+        ));
+    }
+
+    fn messages(range: std::ops::Range<i64>) -> Vec<crate::Message> {
+        let source = Source::User(User::from(Nick::from("casperstorm")));
+        let start = DateTime::from_timestamp(1_730_000_000, 0).unwrap();
+
+        range
+            .map(|i| {
+                crate::Message::test(
+                    Direction::Received,
+                    source.clone(),
+                    start + chrono::Duration::seconds(i),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn evict_and_reload() {
+        let kind = history::Kind::Channel(Server::from("libera"), "#halloy".to_string());
+        let config = history::Config::default();
+        let max_in_memory = 10;
+        let len = |manager: &Manager| match &manager.data.map[&kind] {
+            History::Full { messages, .. } | History::Partial { messages, .. } => messages.len(),
+        };
+
+        let mut manager = Manager::default();
+        manager.data.load_full(
+            kind.clone(),
+            history::Loaded {
+                messages: vec![],
+                metadata: history::Metadata::default(),
+            },
+            &config,
+        );
+
+        let evicting = max_in_memory + history::EVICT_THRESHOLD + 1;
+        for message in messages(0..evicting as i64) {
+            assert!(manager
+                .data
+                .add_message(kind.clone(), message, &config)
+                .is_none());
+        }
+
+        let history = manager.data.map.get_mut(&kind).unwrap();
+        assert!(history
+            .flush(Instant::now(), max_in_memory, &config)
+            .is_some());
+        assert_eq!(len(&manager), max_in_memory);
+
+        // Only one load at a time, a failed one can be retried
+        assert!(manager.load_evicted(kind.clone()).is_some());
+        assert!(manager.load_evicted(kind.clone()).is_none());
+        manager.update(Message::LoadedEvicted(
+            kind.clone(),
+            Err(std::io::Error::other("unreadable").into()),
+        ));
+        assert!(manager.load_evicted(kind.clone()).is_some());
+
+        // Everything evicted is back, and kept over the limit
+        let reloaded = evicting - max_in_memory;
+        manager.update(Message::LoadedEvicted(
+            kind.clone(),
+            Ok(messages(0..reloaded as i64)),
+        ));
+        assert_eq!(len(&manager), evicting);
+        assert!(!manager.has_evicted(&kind));
+
+        let history = manager.data.map.get_mut(&kind).unwrap();
+        assert!(history
+            .flush(Instant::now(), max_in_memory, &config)
+            .is_none());
+
+        // Until the next eviction, which drops the reloaded messages again
+        let newer = evicting as i64..(2 * evicting) as i64;
+        for message in messages(newer) {
+            manager.data.add_message(kind.clone(), message, &config);
+        }
+
+        let history = manager.data.map.get_mut(&kind).unwrap();
+        assert!(history
+            .flush(Instant::now(), max_in_memory, &config)
+            .is_some());
+        assert_eq!(len(&manager), max_in_memory);
+        assert!(manager.has_evicted(&kind));
+        assert!(matches!(
+            manager.data.map[&kind],
+            History::Full { reloaded: 0, .. }
+        ));
+    }
+
+    #[test]
+    fn open_large_history() {
+        let kind = history::Kind::Channel(Server::from("libera"), "#halloy".to_string());
+        let stored = messages(0..1_000);
+
+        let mut manager = Manager::default();
+        manager.config.max_in_memory = 10;
+
+        manager.update(Message::LoadFull(
+            kind.clone(),
+            Ok(history::ResumeState {
+                messages: stored.clone(),
+                metadata: history::Metadata::default(),
+                chathistory: None,
+            }),
+        ));
+
+        let History::Full { messages, .. } = &manager.data.map[&kind] else {
+            panic!("history of {kind} isn't loaded");
+        };
+
+        // Only the newest are kept in memory, the rest can be loaded back
+        assert_eq!(messages.len(), 10);
+        assert_eq!(
+            messages[0].server_time,
+            stored[stored.len() - 10].server_time
+        );
+        assert!(manager.has_evicted(&kind));
+        assert!(manager.load_evicted(kind.clone()).is_some());
+    }
+
+    #[test]
+    fn view_divides_at_first_unread() {
+        let kind = history::Kind::Channel(Server::from("libera"), "#halloy".to_string());
+        let mut stored = messages(0..5);
+
+        // Our own messages never trigger unread, so stay above the divider
+        let sent = crate::Message::test(
+            Direction::Sent,
+            Source::User(User::from(Nick::from("casperstorm"))),
+            stored[1].server_time + chrono::Duration::milliseconds(500),
+        );
+        stored.insert(2, sent);
+
+        let mut manager = Manager::default();
+        manager.update(Message::LoadFull(
+            kind.clone(),
+            Ok(history::ResumeState {
+                messages: stored.clone(),
+                metadata: history::Metadata {
+                    read_marker: history::ReadMarker::latest(
+                        &stored[..2],
+                        &history::Config::default(),
+                    ),
+                    ..history::Metadata::default()
+                },
+                chathistory: None,
+            }),
+        ));
+
+        let view = manager
+            .get_messages(&kind, None, &config::Buffer::default())
+            .unwrap();
+
+        assert_eq!(view.old_messages.len(), 3);
+        assert_eq!(view.new_messages.len(), 3);
+    }
+
+    #[test]
+    fn mark_highlights_read() {
+        let server = Server::from("libera");
+        let channel = history::Kind::Channel(server.clone(), "#halloy".to_string());
+        let other = history::Kind::Channel(server.clone(), "#rust".to_string());
+
+        let mut manager = Manager::default();
+
+        assert!(manager
+            .record_highlight(highlight(&server, "#halloy"))
+            .is_some());
+        assert!(manager
+            .record_highlight(highlight(&server, "#rust"))
+            .is_none());
+
+        assert!(manager.has_unread_highlights(&channel));
+        assert!(manager.has_unread_highlights(&other));
+
+        assert!(manager.mark_highlights_read(channel.clone()).is_some());
+        assert!(!manager.has_unread_highlights(&channel));
+        assert!(manager.has_unread_highlights(&other));
+
+        // Already read up to the latest highlight
+        assert!(manager.mark_highlights_read(channel.clone()).is_none());
+
+        // Only the highlight read marker moves
+        assert_eq!(manager.read_marker(&channel), None);
+
+        assert_eq!(manager.mark_all_highlights_read().len(), 1);
+        assert!(!manager.has_unread_highlights(&other));
+
+        assert!(manager
+            .record_highlight(highlight(&server, "#halloy"))
+            .is_none());
+        assert!(manager.has_unread_highlights(&channel));
+    }
 }
@@ -1,38 +1,426 @@
+use std::collections::BTreeSet;
 use std::fmt;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
 
-use chrono::{format::SecondsFormat, DateTime, Utc};
+use chrono::{DateTime, Local, Utc};
+use itertools::Itertools;
 use serde::{Deserialize, Serialize};
 use tokio::fs;
 
-use crate::history::{dir_path, Error, Kind};
-use crate::message::{source, MessageReferences};
-use crate::Message;
+use crate::history::{
+    format, record_bytes_written, remove_if_exists, Config, Error, Kind, ServerCaps,
+};
+use crate::isupport::{ChatHistorySubcommand, MessageReference, MessageReferenceType};
+use crate::message::{source, Direction, MessageReferences, Target};
+use crate::{time, Message};
+
+pub use self::storage::Storage;
+
+pub mod storage;
 
 #[derive(Debug, Clone, Default, Deserialize, Serialize)]
 pub struct Metadata {
     pub read_marker: Option<ReadMarker>,
+    #[serde(default, with = "time::serde_format::option")]
     pub last_triggers_unread: Option<DateTime<Utc>>,
     pub chathistory_references: Option<MessageReferences>,
+    /// Tracks the last highlight seen, independent of `read_marker`
+    #[serde(default)]
+    pub highlight_read_marker: Option<ReadMarker>,
+    /// Last time we sent a message to the buffer
+    #[serde(default, with = "time::serde_format::option")]
+    pub last_self_activity: Option<DateTime<Utc>>,
+    /// Member counts of a channel as they changed, oldest first
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub member_counts: Vec<MemberCount>,
+}
+
+impl Metadata {
+    /// Fill empty fields with their value in `stored`, so a bug producing
+    /// empty metadata can't erase what's on disk. With `force_clear` the
+    /// empty fields are kept as is.
+    fn carry_forward(self, stored: Metadata, force_clear: bool) -> Self {
+        if force_clear {
+            return self;
+        }
+
+        Self {
+            read_marker: self.read_marker.or(stored.read_marker),
+            last_triggers_unread: self.last_triggers_unread.or(stored.last_triggers_unread),
+            chathistory_references: self
+                .chathistory_references
+                .or(stored.chathistory_references),
+            highlight_read_marker: self.highlight_read_marker.or(stored.highlight_read_marker),
+            last_self_activity: self.last_self_activity.or(stored.last_self_activity),
+            member_counts: if self.member_counts.is_empty() {
+                stored.member_counts
+            } else {
+                self.member_counts
+            },
+        }
+    }
+
+    /// Newest message known from the metadata
+    fn latest_message(&self) -> Option<DateTime<Utc>> {
+        [
+            self.last_triggers_unread,
+            self.chathistory_references
+                .as_ref()
+                .map(|references| references.timestamp),
+            self.last_self_activity,
+        ]
+        .into_iter()
+        .flatten()
+        .max()
+    }
+
+    /// Clamp skewed read markers, see [`ReadMarker::is_skewed`], to the
+    /// newest known message. Returns whether any were clamped.
+    fn clamp_skewed(&mut self, now: DateTime<Utc>, config: &Config) -> bool {
+        let latest = self.latest_message();
+        let clamped = ReadMarker(latest.filter(|latest| *latest <= now).unwrap_or(now));
+
+        let mut is_clamped = false;
+
+        for read_marker in [&mut self.read_marker, &mut self.highlight_read_marker] {
+            if read_marker.is_some_and(|read_marker| read_marker.is_skewed(latest, now, config)) {
+                *read_marker = Some(clamped);
+                is_clamped = true;
+            }
+        }
+
+        is_clamped
+    }
+
+    /// Index of the first unread message of `messages` which triggers
+    /// unread, where the unread divider is drawn, see [`divider_index`]
+    pub fn divider_index(&self, messages: &[Message]) -> Option<usize> {
+        divider_index(self.read_marker, messages)
+    }
+
+    /// Whether a `CHATHISTORY` request can be made with our reference,
+    /// given the server's current capabilities and `MSGREFTYPES`. Without a
+    /// reference, or when the server doesn't advertise its reference types,
+    /// the latest messages are requested instead.
+    pub fn is_backfillable(
+        &self,
+        server_supports_chathistory: bool,
+        types: &[MessageReferenceType],
+    ) -> bool {
+        if !server_supports_chathistory {
+            return false;
+        }
+
+        match &self.chathistory_references {
+            Some(references) if !types.is_empty() => {
+                !matches!(references.message_reference(types), MessageReference::None)
+            }
+            _ => true,
+        }
+    }
+
+    /// `CHATHISTORY LATEST` request for the messages of `target` after our
+    /// reference, `None` if it isn't backfillable from a server with `caps`
+    pub fn backfill_request(
+        &self,
+        target: &str,
+        caps: &ServerCaps,
+    ) -> Option<ChatHistorySubcommand> {
+        if !self.is_backfillable(caps.supports_chathistory, &caps.message_reference_types) {
+            return None;
+        }
+
+        let message_reference = self
+            .chathistory_references
+            .as_ref()
+            .map_or(MessageReference::None, |references| {
+                references.message_reference(&caps.message_reference_types)
+            });
+
+        Some(ChatHistorySubcommand::Latest(
+            target.to_string(),
+            message_reference,
+            caps.chathistory_limit,
+        ))
+    }
+
+    /// Combine with `other`, keeping the newest value of each field
+    pub fn merge(self, other: Metadata) -> Self {
+        Self {
+            read_marker: self.read_marker.max(other.read_marker),
+            last_triggers_unread: self.last_triggers_unread.max(other.last_triggers_unread),
+            chathistory_references: match (
+                self.chathistory_references,
+                other.chathistory_references,
+            ) {
+                (Some(a), Some(b)) => Some(if b.timestamp > a.timestamp { b } else { a }),
+                (a, b) => a.or(b),
+            },
+            highlight_read_marker: self.highlight_read_marker.max(other.highlight_read_marker),
+            last_self_activity: self.last_self_activity.max(other.last_self_activity),
+            member_counts: merge_member_counts(self.member_counts, &other.member_counts),
+        }
+    }
+
+    /// Compare every field, including message ids which are ignored
+    /// by the equality of [`MessageReferences`]
+    pub fn eq_exact(&self, other: &Self) -> bool {
+        self.read_marker == other.read_marker
+            && self.highlight_read_marker == other.highlight_read_marker
+            && self.last_triggers_unread == other.last_triggers_unread
+            && self.last_self_activity == other.last_self_activity
+            && self.member_counts == other.member_counts
+            && match (&self.chathistory_references, &other.chathistory_references) {
+                (Some(a), Some(b)) => a.timestamp == b.timestamp && a.id == b.id,
+                (None, None) => true,
+                _ => false,
+            }
+    }
+
+    /// Fields as written to disk, with timestamps in `format`. Read markers
+    /// are rounded up, see [`time::Format::format_ceil`].
+    pub fn to_stored(
+        &self,
+        format: time::Format,
+    ) -> Result<serde_json::Map<String, serde_json::Value>, serde_json::Error> {
+        let serde_json::Value::Object(mut fields) = serde_json::to_value(self)? else {
+            return Ok(serde_json::Map::new());
+        };
+
+        let timestamp =
+            |date_time: &DateTime<Utc>| serde_json::Value::from(format.format(date_time));
+        let optional = |date_time: Option<DateTime<Utc>>| {
+            date_time.map_or(serde_json::Value::Null, |date_time| timestamp(&date_time))
+        };
+        let read_marker = |read_marker: Option<ReadMarker>| {
+            read_marker.map_or(serde_json::Value::Null, |read_marker| {
+                format.format_ceil(&read_marker.date_time()).into()
+            })
+        };
+
+        fields.insert("read_marker".into(), read_marker(self.read_marker));
+        fields.insert(
+            "highlight_read_marker".into(),
+            read_marker(self.highlight_read_marker),
+        );
+        fields.insert(
+            "last_triggers_unread".into(),
+            optional(self.last_triggers_unread),
+        );
+        fields.insert(
+            "last_self_activity".into(),
+            optional(self.last_self_activity),
+        );
+
+        if let (Some(references), Some(stored)) = (
+            &self.chathistory_references,
+            fields
+                .get_mut("chathistory_references")
+                .and_then(serde_json::Value::as_object_mut),
+        ) {
+            stored.insert("timestamp".into(), timestamp(&references.timestamp));
+        }
+
+        if let Some(stored) = fields
+            .get_mut("member_counts")
+            .and_then(serde_json::Value::as_array_mut)
+        {
+            for (stored, member_count) in stored.iter_mut().zip(&self.member_counts) {
+                stored["at"] = timestamp(&member_count.at);
+            }
+        }
+
+        Ok(fields)
+    }
+
+    /// Fields which differ from `self` to `other`. Fields are compared by
+    /// their serialized form, so new fields are covered without changes here.
+    /// A field left out when empty is compared as `null`.
+    pub fn diff(&self, other: &Metadata) -> Vec<FieldChange> {
+        let fields = |metadata: &Metadata| match serde_json::to_value(metadata) {
+            Ok(serde_json::Value::Object(fields)) => fields,
+            _ => serde_json::Map::new(),
+        };
+
+        let mut old_fields = fields(self);
+        let mut new_fields = fields(other);
+
+        let names = old_fields
+            .keys()
+            .chain(new_fields.keys())
+            .cloned()
+            .collect::<BTreeSet<_>>();
+
+        names
+            .into_iter()
+            .filter_map(|field| {
+                let old = old_fields.remove(&field).unwrap_or_default();
+                let new = new_fields.remove(&field).unwrap_or_default();
+
+                (old != new).then_some(FieldChange { field, old, new })
+            })
+            .collect()
+    }
+}
+
+/// A field of [`Metadata`] changed by an operation, see [`Metadata::diff`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldChange {
+    pub field: String,
+    pub old: serde_json::Value,
+    pub new: serde_json::Value,
+}
+
+/// Member count of a channel from the time it was recorded until the next
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub struct MemberCount {
+    #[serde(with = "time::serde_format")]
+    pub at: DateTime<Utc>,
+    pub count: usize,
+}
+
+/// Days member counts are kept, besides the latest count before them
+const MEMBER_COUNT_RETENTION_DAYS: i64 = 7;
+const MAX_MEMBER_COUNTS: usize = 500;
+
+const SPARKLINE_BARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// Record `count` at `at` unless it's the same as the latest count. Counts
+/// older than the retention are dropped, except the latest of them which
+/// stays as the baseline for [`member_count_delta`]. Returns whether
+/// `counts` changed.
+pub fn record_member_count(counts: &mut Vec<MemberCount>, at: DateTime<Utc>, count: usize) -> bool {
+    if counts.last().is_some_and(|last| last.count == count) {
+        return false;
+    }
+
+    counts.push(MemberCount { at, count });
+
+    let cutoff = at - chrono::Duration::days(MEMBER_COUNT_RETENTION_DAYS);
+    let expired = counts.iter().take_while(|count| count.at < cutoff).count();
+    counts.drain(..expired.saturating_sub(1));
+
+    if counts.len() > MAX_MEMBER_COUNTS {
+        counts.drain(..counts.len() - MAX_MEMBER_COUNTS);
+    }
+
+    true
+}
+
+/// Counts of both, ordered by the time they were recorded
+pub fn merge_member_counts(counts: Vec<MemberCount>, other: &[MemberCount]) -> Vec<MemberCount> {
+    let mut merged = counts;
+    merged.extend_from_slice(other);
+    merged.sort_by_key(|count| count.at);
+    merged.dedup_by_key(|count| count.at);
+    merged
+}
+
+/// Change from the member count at `since` to `current`. Without a count
+/// recorded before `since`, the first count after it is the baseline.
+pub fn member_count_delta(
+    counts: &[MemberCount],
+    since: DateTime<Utc>,
+    current: usize,
+) -> Option<i64> {
+    let baseline = counts
+        .iter()
+        .rev()
+        .find(|count| count.at <= since)
+        .or(counts.first())?;
+
+    Some(current as i64 - baseline.count as i64)
+}
+
+/// Member counts from `since` to `current` as a sparkline of at most
+/// `width` bars, `None` without a change to show
+pub fn member_count_sparkline(
+    counts: &[MemberCount],
+    since: DateTime<Utc>,
+    current: usize,
+    width: usize,
+) -> Option<String> {
+    let baseline = counts.iter().rev().find(|count| count.at <= since);
+    let values = baseline
+        .into_iter()
+        .chain(counts.iter().filter(|count| count.at > since))
+        .map(|count| count.count)
+        .chain([current])
+        .collect::<Vec<_>>();
+    let values = &values[values.len().saturating_sub(width)..];
+
+    let min = values.iter().copied().min()?;
+    let max = values.iter().copied().max()?;
+
+    if values.len() < 2 || min == max {
+        return None;
+    }
+
+    let top = SPARKLINE_BARS.len() - 1;
+
+    Some(
+        values
+            .iter()
+            .map(|value| SPARKLINE_BARS[(value - min) * top / (max - min)])
+            .collect(),
+    )
+}
+
+/// Local midnight at the start of the day of `date_time`
+pub fn start_of_day(date_time: DateTime<Utc>) -> DateTime<Utc> {
+    date_time
+        .with_timezone(&Local)
+        .date_naive()
+        .and_hms_opt(0, 0, 0)
+        .and_then(|midnight| midnight.and_local_timezone(Local).earliest())
+        .map_or(date_time, |midnight| midnight.with_timezone(&Utc))
+}
+
+impl fmt::Display for FieldChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {} -> {}", self.field, self.old, self.new)
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default, Deserialize, Serialize)]
-pub struct ReadMarker(DateTime<Utc>);
+pub struct ReadMarker(
+    // Rounded up so messages read at a finer precision stay read
+    #[serde(
+        serialize_with = "time::serde_format::serialize_ceil",
+        deserialize_with = "time::serde_format::deserialize"
+    )]
+    DateTime<Utc>,
+);
 
 impl ReadMarker {
-    pub fn latest(messages: &[Message]) -> Option<Self> {
-        messages
+    /// Latest message which isn't internal status. With
+    /// `mark_status_only_read`, a slice of only such messages falls back to
+    /// its latest message, so buffers with e.g. just joins and parts can
+    /// still be marked as read.
+    pub fn latest(messages: &[Message], config: &Config) -> Option<Self> {
+        let mut present = messages
             .iter()
             .rev()
+            .filter(|message| !config.is_from_future(message));
+
+        present
+            .clone()
             .find(|message| match message.target.source() {
                 source::Source::Internal(source) => match source {
                     source::Internal::Status(_) => false,
+                    source::Internal::Session(_) => false,
                     // Logs are in their own buffer and this gives us backlog support there
                     source::Internal::Logs => true,
                 },
                 _ => true,
             })
+            .or_else(|| {
+                config
+                    .mark_status_only_read
+                    .then(|| present.next())
+                    .flatten()
+            })
             .map(|message| message.server_time)
             .map(Self)
     }
@@ -40,6 +428,20 @@ impl ReadMarker {
     pub fn date_time(self) -> DateTime<Utc> {
         self.0
     }
+
+    /// Whether the marker is ahead of both `now` and `latest`, the newest
+    /// known message, by more than the future tolerance. It was likely set
+    /// while the clock was fast, and would keep newer markers from being
+    /// set until the clock caught up.
+    pub fn is_skewed(
+        self,
+        latest: Option<DateTime<Utc>>,
+        now: DateTime<Utc>,
+        config: &Config,
+    ) -> bool {
+        config.is_ahead_of(self.0, now)
+            && latest.map_or(true, |latest| config.is_ahead_of(self.0, latest))
+    }
 }
 
 impl FromStr for ReadMarker {
@@ -54,89 +456,1688 @@ impl FromStr for ReadMarker {
 
 impl fmt::Display for ReadMarker {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        self.0.to_rfc3339_opts(SecondsFormat::Millis, true).fmt(f)
+        time::Format::Canonical.format_ceil(&self.0).fmt(f)
     }
 }
 
-pub fn latest_triggers_unread(messages: &[Message]) -> Option<DateTime<Utc>> {
+/// Index of the first message after `read_marker` which triggers unread.
+/// All messages are unread without a read marker, `None` if there are no
+/// unread messages.
+pub fn divider_index<'a>(
+    read_marker: Option<ReadMarker>,
+    messages: impl IntoIterator<Item = &'a Message>,
+) -> Option<usize> {
+    let mut messages = messages.into_iter();
+
+    let Some(read_marker) = read_marker else {
+        return messages.next().map(|_| 0);
+    };
+
+    messages.position(|message| {
+        message.server_time > read_marker.date_time() && message.triggers_unread()
+    })
+}
+
+pub fn latest_triggers_unread(messages: &[Message], config: &Config) -> Option<DateTime<Utc>> {
     messages
         .iter()
         .rev()
+        .filter(|message| !config.is_from_future(message))
         .find(|message| message.triggers_unread())
         .map(|message| message.server_time)
 }
 
-pub fn latest_can_reference(messages: &[Message]) -> Option<MessageReferences> {
+pub fn latest_self_activity(messages: &[Message]) -> Option<DateTime<Utc>> {
+    messages
+        .iter()
+        .rev()
+        .find(|message| matches!(message.direction, Direction::Sent))
+        .map(|message| message.server_time)
+}
+
+/// Latest sent message which `read_marker` covers. Markers older than all
+/// of `messages` can't be placed and give `None`.
+pub fn latest_sent_read(messages: &[Message], read_marker: ReadMarker) -> Option<DateTime<Utc>> {
+    if messages.first().map_or(true, |message| {
+        message.server_time > read_marker.date_time()
+    }) {
+        return None;
+    }
+
     messages
         .iter()
         .rev()
+        .filter(|message| message.server_time <= read_marker.date_time())
+        .find(|message| matches!(message.direction, Direction::Sent))
+        .map(|message| message.server_time)
+}
+
+pub fn latest_can_reference(messages: &[Message], config: &Config) -> Option<MessageReferences> {
+    messages
+        .iter()
+        .rev()
+        .filter(|message| !config.is_from_future(message))
         .find(|message| message.can_reference())
         .map(|message| message.references())
 }
 
-pub async fn load(kind: Kind) -> Result<Metadata, Error> {
-    let path = path(&kind).await?;
+/// Latest highlight of the channel `kind`, found among the messages of
+/// the highlights buffer
+pub fn latest_highlight(messages: &[Message], kind: &Kind) -> Option<ReadMarker> {
+    let Kind::Channel(server, channel) = kind else {
+        return None;
+    };
 
-    if let Ok(bytes) = fs::read(path).await {
-        Ok(serde_json::from_slice(&bytes).unwrap_or_default())
-    } else {
-        Ok(Metadata::default())
-    }
+    messages
+        .iter()
+        .rev()
+        .find(|message| {
+            matches!(
+                &message.target,
+                Target::Highlights {
+                    server: highlight_server,
+                    channel: highlight_channel,
+                    ..
+                } if highlight_server == server && highlight_channel == channel
+            )
+        })
+        .map(|message| ReadMarker(message.server_time))
+}
+
+pub async fn load(kind: Kind, config: &Config) -> Result<Metadata, Error> {
+    MetadataStore::files(config).load(kind).await
 }
 
 pub async fn save(
     kind: &Kind,
     messages: &[Message],
     read_marker: Option<ReadMarker>,
+    force_clear: bool,
+    config: &Config,
 ) -> Result<(), Error> {
-    let bytes = serde_json::to_vec(&Metadata {
-        read_marker,
-        last_triggers_unread: latest_triggers_unread(messages),
-        chathistory_references: latest_can_reference(messages),
-    })?;
+    MetadataStore::files(config)
+        .save(kind, messages, read_marker, force_clear)
+        .await
+}
 
-    let path = path(kind).await?;
+pub async fn recent_self_activity(
+    kinds: impl IntoIterator<Item = Kind>,
+    config: &Config,
+) -> Result<Vec<(Kind, DateTime<Utc>)>, Error> {
+    MetadataStore::files(config)
+        .recent_self_activity(kinds)
+        .await
+}
 
-    fs::write(path, &bytes).await?;
+pub async fn update(kind: &Kind, read_marker: &ReadMarker, config: &Config) -> Result<(), Error> {
+    MetadataStore::files(config).update(kind, read_marker).await
+}
+
+pub async fn overwrite_read_marker(
+    kind: &Kind,
+    read_marker: &ReadMarker,
+    config: &Config,
+) -> Result<(), Error> {
+    MetadataStore::files(config)
+        .overwrite_read_marker(kind, read_marker)
+        .await
+}
 
-    Ok(())
+pub async fn update_highlight_read_marker(
+    kind: &Kind,
+    highlight_read_marker: &ReadMarker,
+    config: &Config,
+) -> Result<(), Error> {
+    MetadataStore::files(config)
+        .update_highlight_read_marker(kind, highlight_read_marker)
+        .await
 }
 
-pub async fn update(kind: &Kind, read_marker: &ReadMarker) -> Result<(), Error> {
-    let metadata = load(kind.clone()).await?;
+pub async fn update_member_count(
+    kind: &Kind,
+    member_count: MemberCount,
+    config: &Config,
+) -> Result<(), Error> {
+    MetadataStore::files(config)
+        .update_member_count(kind, member_count)
+        .await
+}
 
-    if metadata
-        .read_marker
-        .is_some_and(|metadata_read_marker| metadata_read_marker >= *read_marker)
-    {
-        return Ok(());
+pub async fn seed_from(
+    target: &Kind,
+    source: &Kind,
+    source_read_marker: Option<ReadMarker>,
+    config: &Config,
+) -> Result<Option<ReadMarker>, Error> {
+    MetadataStore::files(config)
+        .seed_from(target, source, source_read_marker)
+        .await
+}
+
+pub async fn clear(kind: &Kind, config: &Config) -> Result<(), Error> {
+    MetadataStore::files(config).clear(kind).await
+}
+
+/// Reads and writes [`Metadata`] through a [`Storage`] backend, files in
+/// the history directory by default
+#[derive(Debug, Clone, Default)]
+pub struct MetadataStore<S = storage::File> {
+    storage: S,
+    config: Config,
+}
+
+impl MetadataStore {
+    /// Metadata files in the history directories of `config`
+    pub fn files(config: &Config) -> Self {
+        Self::new(storage::File::new(config.clone()), config.clone())
     }
+}
+
+impl<S: Storage> MetadataStore<S> {
+    pub fn new(storage: S, config: Config) -> Self {
+        Self { storage, config }
+    }
+
+    /// Load the metadata of `kind`. Files from older versions are migrated
+    /// and rewritten.
+    pub async fn load(&self, kind: Kind) -> Result<Metadata, Error> {
+        let Some(bytes) = self.storage.read(&kind).await? else {
+            return Ok(Metadata::default());
+        };
+
+        let Ok(mut decoded) = format::decode_metadata(&bytes) else {
+            log::warn!("failed to load metadata of {kind}");
+            return Ok(Metadata::default());
+        };
+
+        let name = kind.name();
+
+        if let Some(stored) = decoded.kind.as_ref().filter(|stored| **stored != name) {
+            log::warn!("metadata of {kind} holds that of {stored}, ignoring it");
+            return Ok(Metadata::default());
+        }
 
-    let bytes = serde_json::to_vec(&Metadata {
-        read_marker: Some(*read_marker),
-        last_triggers_unread: metadata.last_triggers_unread,
-        chathistory_references: metadata.chathistory_references,
-    })?;
+        if !decoded.skipped.is_empty() {
+            log::warn!(
+                "skipped fields of the metadata of {kind} which failed to load: {}",
+                decoded.skipped.join(", ")
+            );
+        }
 
-    let path = path(kind).await?;
+        let is_clamped = decoded.metadata.clamp_skewed(Utc::now(), &self.config);
 
-    fs::write(path, &bytes).await?;
+        if is_clamped {
+            log::warn!("clamped read markers of {kind} which were ahead of the clock and messages");
+        }
+
+        // Also written when the kind isn't recorded yet, so it can be verified
+        if decoded.is_outdated() || decoded.kind.is_none() || is_clamped {
+            let bytes = format::encode_metadata(
+                Some(name.as_str()),
+                &decoded.metadata,
+                self.config.timestamp_format,
+            )?;
+            let len = bytes.len();
+
+            self.storage.write(&kind, bytes).await?;
+            record_bytes_written(len, &self.config);
+
+            if decoded.is_outdated() {
+                log::debug!(
+                    "migrated metadata of {kind} from version {} to {}",
+                    decoded.version,
+                    format::METADATA_VERSION
+                );
+            }
+        }
+
+        Ok(decoded.metadata)
+    }
+
+    /// Save the metadata of `messages`. Fields which come out empty keep
+    /// their stored value, unless `force_clear` is set
+    pub async fn save(
+        &self,
+        kind: &Kind,
+        messages: &[Message],
+        read_marker: Option<ReadMarker>,
+        force_clear: bool,
+    ) -> Result<(), Error> {
+        let stored = self.load(kind.clone()).await?;
+
+        let metadata = Metadata {
+            read_marker,
+            last_triggers_unread: latest_triggers_unread(messages, &self.config),
+            chathistory_references: latest_can_reference(messages, &self.config),
+            // Not tracked in memory, carried over below
+            highlight_read_marker: None,
+            // `messages` may not reach back to our last message
+            last_self_activity: if force_clear {
+                latest_self_activity(messages)
+            } else {
+                latest_self_activity(messages).max(stored.last_self_activity)
+            },
+            // Recorded separately, carried over below
+            member_counts: vec![],
+        };
+
+        self.write(kind, &metadata.carry_forward(stored, force_clear))
+            .await
+    }
+
+    /// `kinds` we've sent messages to, most recently active first
+    pub async fn recent_self_activity(
+        &self,
+        kinds: impl IntoIterator<Item = Kind>,
+    ) -> Result<Vec<(Kind, DateTime<Utc>)>, Error> {
+        let mut recent = vec![];
+
+        for kind in kinds {
+            if let Some(last_self_activity) = self.load(kind.clone()).await?.last_self_activity {
+                recent.push((kind, last_self_activity));
+            }
+        }
+
+        recent.sort_by(|(_, a), (_, b)| b.cmp(a));
+
+        Ok(recent)
+    }
+
+    /// Advance the read marker of `kind`, never moving it backwards unless
+    /// the stored one is skewed
+    pub async fn update(&self, kind: &Kind, read_marker: &ReadMarker) -> Result<(), Error> {
+        let metadata = self.load(kind.clone()).await?;
+
+        if read_marker.is_skewed(metadata.latest_message(), Utc::now(), &self.config) {
+            log::debug!("ignoring read marker {read_marker} of {kind} ahead of the clock");
+            return Ok(());
+        }
+
+        if metadata
+            .read_marker
+            .is_some_and(|metadata_read_marker| metadata_read_marker >= *read_marker)
+        {
+            return Ok(());
+        }
+
+        self.write(
+            kind,
+            &Metadata {
+                read_marker: Some(*read_marker),
+                ..metadata
+            },
+        )
+        .await
+    }
+
+    /// Set the read marker of `kind`, even when it's behind the stored one
+    pub async fn overwrite_read_marker(
+        &self,
+        kind: &Kind,
+        read_marker: &ReadMarker,
+    ) -> Result<(), Error> {
+        let metadata = self.load(kind.clone()).await?;
+
+        self.write(
+            kind,
+            &Metadata {
+                read_marker: Some(*read_marker),
+                ..metadata
+            },
+        )
+        .await
+    }
+
+    /// Advance the highlight read marker of `kind`, never moving it backwards
+    pub async fn update_highlight_read_marker(
+        &self,
+        kind: &Kind,
+        highlight_read_marker: &ReadMarker,
+    ) -> Result<(), Error> {
+        let metadata = self.load(kind.clone()).await?;
+
+        if metadata
+            .highlight_read_marker
+            .is_some_and(|metadata_marker| metadata_marker >= *highlight_read_marker)
+        {
+            return Ok(());
+        }
+
+        self.write(
+            kind,
+            &Metadata {
+                highlight_read_marker: Some(*highlight_read_marker),
+                ..metadata
+            },
+        )
+        .await
+    }
 
-    Ok(())
+    /// Record the member count of the channel `kind`, see [`record_member_count`]
+    pub async fn update_member_count(
+        &self,
+        kind: &Kind,
+        member_count: MemberCount,
+    ) -> Result<(), Error> {
+        let mut metadata = self.load(kind.clone()).await?;
+
+        if !record_member_count(
+            &mut metadata.member_counts,
+            member_count.at,
+            member_count.count,
+        ) {
+            return Ok(());
+        }
+
+        self.write(kind, &metadata).await
+    }
+
+    /// Seed the read marker of `target` with the read marker of `source`,
+    /// `source_read_marker` when it's held in memory and may be ahead of
+    /// what's stored. No-op if `target` already has a read marker.
+    pub async fn seed_from(
+        &self,
+        target: &Kind,
+        source: &Kind,
+        source_read_marker: Option<ReadMarker>,
+    ) -> Result<Option<ReadMarker>, Error> {
+        let metadata = self.load(target.clone()).await?;
+
+        if metadata.read_marker.is_some() {
+            return Ok(None);
+        }
+
+        let read_marker = match source_read_marker {
+            Some(read_marker) => Some(read_marker),
+            None => self.load(source.clone()).await?.read_marker,
+        };
+
+        let Some(read_marker) = read_marker else {
+            return Ok(None);
+        };
+
+        self.write(
+            target,
+            &Metadata {
+                read_marker: Some(read_marker),
+                ..metadata
+            },
+        )
+        .await?;
+
+        Ok(Some(read_marker))
+    }
+
+    pub async fn delete(&self, kind: &Kind) -> Result<(), Error> {
+        self.storage.remove(kind).await
+    }
+
+    /// Empty every field of the metadata of `kind`, the one save which
+    /// doesn't carry stored fields forward
+    pub async fn clear(&self, kind: &Kind) -> Result<(), Error> {
+        self.save(kind, &[], None, true).await
+    }
+
+    async fn write(&self, kind: &Kind, metadata: &Metadata) -> Result<(), Error> {
+        let bytes = format::encode_metadata(
+            Some(kind.name().as_str()),
+            metadata,
+            self.config.timestamp_format,
+        )?;
+
+        if let Some(existing) = self.storage.read(kind).await? {
+            if is_unchanged(&existing, &bytes) {
+                return Ok(());
+            }
+
+            if let Ok(existing) = format::decode_metadata(&existing) {
+                log::trace!(
+                    "metadata of {kind} changed: {}",
+                    existing.metadata.diff(metadata).iter().join(", ")
+                );
+            }
+        }
+
+        let len = bytes.len();
+
+        self.storage.write(kind, bytes).await?;
+        record_bytes_written(len, &self.config);
+
+        Ok(())
+    }
 }
 
-async fn path(kind: &Kind) -> Result<PathBuf, Error> {
-    let dir = dir_path().await?;
+/// What to do with the metadata in the other directory once it's merged,
+/// see [`reconcile_directories`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReconcilePolicy {
+    KeepDuplicate,
+    RemoveDuplicate,
+}
 
-    let name = match kind {
-        Kind::Server(server) => format!("{server}-metadata"),
-        Kind::Channel(server, channel) => format!("{server}channel{channel}-metadata"),
-        Kind::Query(server, nick) => format!("{server}nickname{}-metadata", nick),
-        Kind::Logs => "logs-metadata".to_string(),
-        Kind::Highlights => "highlights-metadata".to_string(),
-    };
+/// Outcome of reconciling the metadata of one buffer
+#[derive(Debug, Clone)]
+pub struct Reconciled {
+    pub file_name: String,
+    /// Fields of the primary metadata changed by the merge
+    pub changes: Vec<FieldChange>,
+    pub removed_duplicate: bool,
+}
+
+/// Merge the metadata of buffers present in both `primary` and `other` into
+/// `primary`, e.g. after switching between history directories of different
+/// installs. Buffers are matched by the kind recorded in their files, or by
+/// the file names of the kind for files from before it was recorded, so
+/// files named by other builds or suffixed to tell kinds apart still match.
+/// Files that can't be parsed are left alone.
+pub async fn reconcile_directories(
+    primary: &Path,
+    other: &Path,
+    policy: ReconcilePolicy,
+    config: &Config,
+) -> Result<Vec<Reconciled>, Error> {
+    let store = MetadataStore::new(
+        storage::File::in_dir(primary.to_path_buf(), config.clone()),
+        config.clone(),
+    );
+
+    let stored_files = metadata_files(primary).await?;
+    let mut reconciled = vec![];
 
-    let hashed_name = seahash::hash(name.as_bytes());
+    for duplicate in metadata_files(other).await? {
+        // A file without a kind is matched to the kind of a file it's
+        // named after
+        let kind = duplicate.kind().or_else(|| {
+            stored_files
+                .iter()
+                .filter_map(StoredFile::kind)
+                .find(|kind| duplicate.holds(kind))
+        });
 
-    Ok(dir.join(format!("{hashed_name}.json")))
+        let Some(kind) = kind else {
+            log::debug!(
+                "skipping reconciliation of {}, which holds no known kind",
+                duplicate.file_name
+            );
+            continue;
+        };
+
+        let Some(stored) = stored_files.iter().find(|stored| stored.holds(&kind)) else {
+            continue;
+        };
+
+        let merged = stored.metadata.clone().merge(duplicate.metadata);
+        let changes = stored.metadata.diff(&merged);
+
+        if !stored.metadata.eq_exact(&merged) {
+            store.write(&kind, &merged).await?;
+        }
+
+        let removed_duplicate = policy == ReconcilePolicy::RemoveDuplicate;
+
+        if removed_duplicate {
+            remove_if_exists(&duplicate.path).await?;
+        }
+
+        reconciled.push(Reconciled {
+            file_name: stored.file_name.clone(),
+            changes,
+            removed_duplicate,
+        });
+    }
+
+    Ok(reconciled)
+}
+
+/// A metadata file read by [`reconcile_directories`]
+struct StoredFile {
+    path: PathBuf,
+    file_name: String,
+    kind: Option<String>,
+    metadata: Metadata,
+}
+
+impl StoredFile {
+    fn kind(&self) -> Option<Kind> {
+        Kind::from_name(self.kind.as_deref()?)
+    }
+
+    /// Whether the file holds the metadata of `kind`
+    fn holds(&self, kind: &Kind) -> bool {
+        match &self.kind {
+            Some(stored) => *stored == kind.name(),
+            None => self.file_name == file_name(kind) || self.file_name == legacy_file_name(kind),
+        }
+    }
+}
+
+/// Metadata files in `dir` ordered by name, leaving out those which can't
+/// be parsed
+async fn metadata_files(dir: &Path) -> Result<Vec<StoredFile>, Error> {
+    let mut files = vec![];
+    let mut entries = fs::read_dir(dir).await?;
+
+    while let Some(entry) = entries.next_entry().await? {
+        let path = entry.path();
+
+        if !is_metadata_file(&path) {
+            continue;
+        }
+
+        let Some(file_name) = path.file_name().and_then(|name| name.to_str()) else {
+            continue;
+        };
+        let file_name = file_name.to_string();
+
+        let Ok(decoded) = format::decode_metadata(&fs::read(&path).await?) else {
+            log::warn!("skipping reconciliation of unparsable metadata {file_name}");
+            continue;
+        };
+
+        files.push(StoredFile {
+            path,
+            file_name,
+            kind: decoded.kind,
+            metadata: decoded.metadata,
+        });
+    }
+
+    files.sort_by(|a, b| a.file_name.cmp(&b.file_name));
+
+    Ok(files)
+}
+
+impl fmt::Display for Reconciled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.changes.is_empty() {
+            write!(f, "{}: unchanged", self.file_name)?;
+        } else {
+            write!(f, "{}: {}", self.file_name, self.changes.iter().join(", "))?;
+        }
+
+        if self.removed_duplicate {
+            write!(f, " (duplicate removed)")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Compared semantically once both are read back, so files which were only
+/// reformatted aren't rewritten, precision lost when stored doesn't count as
+/// a change, and real changes are never masked
+fn is_unchanged(existing: &[u8], encoded: &[u8]) -> bool {
+    let decode = |bytes| format::decode_metadata(bytes).map(|decoded| decoded.metadata);
+
+    match (decode(existing), decode(encoded)) {
+        (Ok(existing), Ok(encoded)) => existing.eq_exact(&encoded),
+        _ => false,
+    }
+}
+
+/// Extension of metadata files within the history directory
+pub const METADATA_EXTENSION: &str = "json";
+/// Suffix appended to the name of a [`Kind`] before it's hashed into the
+/// file name of its metadata
+pub const METADATA_SUFFIX: &str = "-metadata";
+
+/// Whether `path` names a metadata file, i.e. a hashed name, optionally
+/// followed by a numeric suffix telling apart kinds whose names hash the
+/// same, with [`METADATA_EXTENSION`] (history files end in `.json.gz`)
+pub fn is_metadata_file(path: &Path) -> bool {
+    let is_number = |part: &str| !part.is_empty() && part.chars().all(|c| c.is_ascii_digit());
+
+    path.extension()
+        .is_some_and(|ext| ext == METADATA_EXTENSION)
+        && path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .is_some_and(|stem| match stem.split_once('-') {
+                Some((hash, suffix)) => is_number(hash) && is_number(suffix),
+                None => is_number(stem),
+            })
+}
+
+fn file_name(kind: &Kind) -> String {
+    hashed_file_name(kind.name())
+}
+
+/// File name of the metadata of `kind` before [`Kind::name`], moved to
+/// [`file_name`] when found
+fn legacy_file_name(kind: &Kind) -> String {
+    hashed_file_name(kind.legacy_name())
+}
+
+fn hashed_file_name(name: String) -> String {
+    let hashed_name = seahash::hash(format!("{name}{METADATA_SUFFIX}").as_bytes());
+
+    format!("{hashed_name}.{METADATA_EXTENSION}")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Mutex;
+
+    use super::*;
+    use crate::user::Nick;
+    use crate::{Server, User};
+
+    // Changing any of these orphans existing metadata on upgrade
+    #[test]
+    fn stable_file_names() {
+        let tests = [
+            (
+                Kind::Server(Server::from("libera")),
+                "3675362981460082100.json",
+            ),
+            (
+                Kind::Channel(Server::from("libera"), "#halloy".to_string()),
+                "521291430908983050.json",
+            ),
+            (
+                Kind::Query(Server::from("libera"), Nick::from("casperstorm")),
+                "3812670742464494567.json",
+            ),
+            (Kind::Logs, "7735412900774182859.json"),
+            (Kind::Highlights, "13572235798989072161.json"),
+        ];
+
+        for (kind, expected) in tests {
+            assert_eq!(file_name(&kind), expected, "{kind}");
+        }
+    }
+
+    // Metadata is only moved over from these on upgrade if they're unchanged
+    #[test]
+    fn stable_legacy_file_names() {
+        let tests = [
+            (
+                Kind::Server(Server::from("libera")),
+                "8476995173127702211.json",
+            ),
+            (
+                Kind::Channel(Server::from("libera"), "#halloy".to_string()),
+                "8881394387914677869.json",
+            ),
+            (
+                Kind::Query(Server::from("libera"), Nick::from("casperstorm")),
+                "4371619311324874232.json",
+            ),
+            (Kind::Logs, "2782277266135177300.json"),
+            (Kind::Highlights, "11839014165225540768.json"),
+        ];
+
+        for (kind, expected) in tests {
+            assert_eq!(legacy_file_name(&kind), expected, "{kind}");
+        }
+    }
+
+    #[test]
+    fn kind_names_are_unambiguous() {
+        let channel = Kind::Channel(Server::from("a"), "#b".to_string());
+        let server = Kind::Server(Server::from("achannel#b"));
+
+        assert_eq!(legacy_file_name(&channel), legacy_file_name(&server));
+        assert_ne!(file_name(&channel), file_name(&server));
+
+        assert_eq!(channel.name(), r##"["channel","a","#b"]"##);
+        assert_eq!(
+            Kind::Query(Server::from(r#"a","#), Nick::from("b")).name(),
+            r#"["query","a\",","b"]"#
+        );
+    }
+
+    #[test]
+    fn metadata_file_detection() {
+        for kind in [
+            Kind::Server(Server::from("libera")),
+            Kind::Channel(Server::from("libera"), "#halloy".to_string()),
+            Kind::Logs,
+        ] {
+            assert!(is_metadata_file(Path::new(&file_name(&kind))), "{kind}");
+        }
+
+        assert!(is_metadata_file(Path::new(
+            "/data/history/8476995173127702211.json"
+        )));
+        assert!(is_metadata_file(Path::new("8476995173127702211-1.json")));
+
+        for path in [
+            "8476995173127702211.json.gz",
+            "8476995173127702211",
+            "window.json",
+            "dashboard.json.gz",
+            ".json",
+            "84769951a3127702211.json",
+            "8476995173127702211-.json",
+            "-1.json",
+            "8476995173127702211-1-2.json",
+        ] {
+            assert!(!is_metadata_file(Path::new(path)), "{path}");
+        }
+    }
+
+    #[test]
+    fn highlight_read_marker_defaults_to_none() {
+        let metadata = serde_json::from_slice::<Metadata>(
+            br#"{"read_marker":"2024-11-01T12:00:00.000Z","chathistory_references":null}"#,
+        )
+        .unwrap();
+
+        assert_eq!(metadata.highlight_read_marker, None);
+
+        let metadata = serde_json::from_slice::<Metadata>(
+            br#"{"read_marker":null,"chathistory_references":null,"highlight_read_marker":"2024-11-01T12:00:00.000Z"}"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            metadata.highlight_read_marker,
+            "2024-11-01T12:00:00.000Z".parse().ok()
+        );
+    }
+
+    #[test]
+    fn last_self_activity_defaults_to_none() {
+        let metadata = serde_json::from_slice::<Metadata>(
+            br#"{"read_marker":null,"chathistory_references":null}"#,
+        )
+        .unwrap();
+
+        assert_eq!(metadata.last_self_activity, None);
+    }
+
+    fn user_message(direction: Direction, server_time: DateTime<Utc>) -> Message {
+        let user = User::from(Nick::from("casperstorm"));
+
+        Message::test(direction, source::Source::User(user), server_time)
+    }
+
+    #[test]
+    fn latest_self_activity_is_last_sent() {
+        let server_time = Utc::now();
+
+        let received = user_message(Direction::Received, server_time);
+        assert_eq!(latest_self_activity(&[received.clone()]), None);
+
+        let sent = user_message(Direction::Sent, server_time);
+        assert_eq!(latest_self_activity(&[sent, received]), Some(server_time));
+    }
+
+    #[test]
+    fn latest_sent_read_within_loaded_range() {
+        let start = "2024-11-01T12:00:00.000Z".parse::<DateTime<Utc>>().unwrap();
+        let at = |seconds| start + chrono::Duration::seconds(seconds);
+
+        let messages = [
+            user_message(Direction::Received, start),
+            user_message(Direction::Sent, at(1)),
+            user_message(Direction::Sent, at(3)),
+        ];
+        let marker = |seconds| ReadMarker(at(seconds));
+
+        assert_eq!(latest_sent_read(&messages, marker(2)), Some(at(1)));
+        assert_eq!(latest_sent_read(&messages, marker(5)), Some(at(3)));
+        assert_eq!(latest_sent_read(&messages, marker(0)), None);
+        // Older than the loaded messages
+        assert_eq!(latest_sent_read(&messages, marker(-1)), None);
+        assert_eq!(latest_sent_read(&[], marker(5)), None);
+    }
+
+    #[test]
+    fn future_messages_are_ignored() {
+        let server_time = Utc::now();
+
+        let messages = [
+            user_message(Direction::Received, server_time),
+            user_message(Direction::Received, server_time + chrono::Duration::days(1)),
+        ];
+        let config = Config::default();
+
+        assert_eq!(
+            ReadMarker::latest(&messages, &config).map(ReadMarker::date_time),
+            Some(server_time)
+        );
+        assert_eq!(
+            latest_triggers_unread(&messages, &config),
+            Some(server_time)
+        );
+        assert_eq!(
+            latest_can_reference(&messages, &config).map(|references| references.timestamp),
+            Some(server_time)
+        );
+
+        // Within the tolerance for clock skew
+        let mut skewed = messages[0].clone();
+        skewed.server_time = server_time + chrono::Duration::seconds(5);
+
+        assert_eq!(
+            latest_can_reference(&[skewed], &config).map(|references| references.timestamp),
+            Some(server_time + chrono::Duration::seconds(5))
+        );
+    }
+
+    #[test]
+    fn status_only_read_marker_fallback() {
+        let server_time = Utc::now();
+
+        let status = Message::test(
+            Direction::Received,
+            source::Source::Internal(source::Internal::Status(source::Status::Success)),
+            server_time,
+        );
+
+        let config = |mark_status_only_read| Config {
+            mark_status_only_read,
+            ..Config::default()
+        };
+
+        assert_eq!(ReadMarker::latest(&[status.clone()], &config(false)), None);
+        assert_eq!(
+            ReadMarker::latest(&[status.clone()], &config(true)),
+            Some(ReadMarker(server_time))
+        );
+
+        // Only used when every message is filtered out
+        let received = user_message(
+            Direction::Received,
+            server_time - chrono::Duration::seconds(5),
+        );
+        let messages = [received.clone(), status];
+
+        for fallback in [false, true] {
+            assert_eq!(
+                ReadMarker::latest(&messages, &config(fallback)),
+                Some(ReadMarker(received.server_time))
+            );
+        }
+    }
+
+    fn populated() -> Metadata {
+        let timestamp = "2024-11-01T12:00:00.000Z".parse::<DateTime<Utc>>().unwrap();
+
+        Metadata {
+            read_marker: Some(ReadMarker(timestamp)),
+            last_triggers_unread: Some(timestamp),
+            chathistory_references: Some(MessageReferences {
+                timestamp,
+                id: Some("abc".to_string()),
+            }),
+            highlight_read_marker: Some(ReadMarker(timestamp)),
+            last_self_activity: Some(timestamp),
+            member_counts: vec![],
+        }
+    }
+
+    #[test]
+    fn merge_keeps_newest() {
+        let newer = "2024-11-02T12:00:00.000Z".parse::<DateTime<Utc>>().unwrap();
+        let other = Metadata {
+            read_marker: Some(ReadMarker(newer)),
+            chathistory_references: Some(MessageReferences {
+                timestamp: newer,
+                id: Some("def".to_string()),
+            }),
+            highlight_read_marker: None,
+            ..Metadata::default()
+        };
+
+        let merged = populated().merge(other);
+
+        assert_eq!(merged.read_marker, Some(ReadMarker(newer)));
+        assert_eq!(
+            merged
+                .chathistory_references
+                .and_then(|references| references.id),
+            Some("def".to_string())
+        );
+        assert_eq!(
+            merged.highlight_read_marker,
+            populated().highlight_read_marker
+        );
+        assert_eq!(merged.last_self_activity, populated().last_self_activity);
+
+        assert!(populated()
+            .merge(Metadata::default())
+            .eq_exact(&populated()));
+    }
+
+    #[test]
+    fn backfillable_with_server_capabilities() {
+        let metadata = populated();
+        let without_id = Metadata {
+            chathistory_references: metadata.chathistory_references.clone().map(|references| {
+                MessageReferences {
+                    id: None,
+                    ..references
+                }
+            }),
+            ..populated()
+        };
+
+        let msgid = [MessageReferenceType::MessageId];
+        let both = [
+            MessageReferenceType::MessageId,
+            MessageReferenceType::Timestamp,
+        ];
+
+        assert!(!metadata.is_backfillable(false, &both));
+        assert!(!Metadata::default().is_backfillable(false, &[]));
+
+        assert!(metadata.is_backfillable(true, &msgid));
+        assert!(metadata.is_backfillable(true, &both));
+        assert!(metadata.is_backfillable(true, &[]));
+
+        assert!(!without_id.is_backfillable(true, &msgid));
+        assert!(without_id.is_backfillable(true, &both));
+
+        assert!(Metadata::default().is_backfillable(true, &msgid));
+    }
+
+    #[test]
+    fn backfill_request_uses_reference() {
+        let metadata = populated();
+        let caps = ServerCaps {
+            supports_chathistory: true,
+            message_reference_types: vec![MessageReferenceType::MessageId],
+            chathistory_limit: 100,
+        };
+
+        assert_eq!(
+            metadata.backfill_request("#halloy", &caps),
+            Some(ChatHistorySubcommand::Latest(
+                "#halloy".to_string(),
+                MessageReference::MessageId("abc".to_string()),
+                100,
+            ))
+        );
+        assert_eq!(
+            Metadata::default().backfill_request("#halloy", &caps),
+            Some(ChatHistorySubcommand::Latest(
+                "#halloy".to_string(),
+                MessageReference::None,
+                100,
+            ))
+        );
+
+        let unsupported = ServerCaps {
+            supports_chathistory: false,
+            ..caps
+        };
+
+        assert_eq!(metadata.backfill_request("#halloy", &unsupported), None);
+    }
+
+    #[test]
+    fn diff_lists_changed_fields() {
+        let metadata = populated();
+
+        assert!(metadata.diff(&populated()).is_empty());
+
+        let changed = Metadata {
+            read_marker: "2024-11-01T13:00:00.000Z".parse().ok(),
+            ..populated()
+        };
+        let changes = metadata.diff(&changed);
+
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].field, "read_marker");
+        assert_ne!(changes[0].old, changes[0].new);
+
+        // Message ids are compared, unlike with `MessageReferences`' equality
+        let changed = Metadata {
+            chathistory_references: Some(MessageReferences {
+                id: Some("def".to_string()),
+                ..metadata.chathistory_references.clone().unwrap()
+            }),
+            ..populated()
+        };
+        let changes = metadata.diff(&changed);
+
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].field, "chathistory_references");
+
+        let changes = metadata.diff(&Metadata::default());
+
+        assert_eq!(changes.len(), 5);
+        assert!(changes
+            .iter()
+            .all(|change| change.new == serde_json::Value::Null));
+
+        // Fields left out when empty are compared too
+        let changed = Metadata {
+            member_counts: vec![MemberCount {
+                at: metadata.last_triggers_unread.unwrap(),
+                count: 42,
+            }],
+            ..populated()
+        };
+        let changes = metadata.diff(&changed);
+
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].field, "member_counts");
+        assert_eq!(changes[0].old, serde_json::Value::Null);
+    }
+
+    #[test]
+    fn empty_fields_carry_forward() {
+        let stored = populated();
+
+        let saved = Metadata::default().carry_forward(stored.clone(), false);
+        assert!(saved.eq_exact(&stored));
+
+        let read_marker = "2024-11-01T13:00:00.000Z".parse().ok();
+        let saved = Metadata {
+            read_marker,
+            ..Metadata::default()
+        }
+        .carry_forward(stored.clone(), false);
+
+        assert_eq!(saved.read_marker, read_marker);
+        assert_eq!(saved.last_triggers_unread, stored.last_triggers_unread);
+    }
+
+    #[test]
+    fn force_clear_empties_fields() {
+        let saved = Metadata::default().carry_forward(populated(), true);
+
+        assert!(saved.eq_exact(&Metadata::default()));
+    }
+
+    #[test]
+    fn divider_index() {
+        let sent = user_message(Direction::Sent, "2024-11-01T12:00:00.000Z".parse().unwrap());
+        let received = user_message(
+            Direction::Received,
+            "2024-11-01T12:01:00.000Z".parse().unwrap(),
+        );
+
+        let messages = [sent.clone(), received.clone(), sent, received];
+
+        assert_eq!(Metadata::default().divider_index(&messages), Some(0));
+        assert_eq!(Metadata::default().divider_index(&[]), None);
+
+        let metadata = |read_marker: &str| Metadata {
+            read_marker: read_marker.parse().ok(),
+            ..Metadata::default()
+        };
+
+        // Sent messages never trigger unread
+        assert_eq!(
+            metadata("2024-11-01T11:00:00.000Z").divider_index(&messages),
+            Some(1)
+        );
+        assert_eq!(
+            metadata("2024-11-01T12:01:00.000Z").divider_index(&messages),
+            None
+        );
+    }
+
+    #[test]
+    fn read_marker_round_trips_from_nanoseconds() {
+        let messages = [user_message(
+            Direction::Received,
+            "2024-11-01T12:00:00.123456789Z".parse().unwrap(),
+        )];
+        let metadata = Metadata {
+            read_marker: ReadMarker::latest(&messages, &Config::default()),
+            highlight_read_marker: ReadMarker::latest(&messages, &Config::default()),
+            last_triggers_unread: Some(messages[0].server_time),
+            ..Metadata::default()
+        };
+
+        assert_eq!(metadata.divider_index(&messages), None);
+
+        for format in [time::Format::Canonical, time::Format::Rfc3339] {
+            let encoded = format::encode_metadata(None, &metadata, format).unwrap();
+            let decoded = format::decode_metadata(&encoded).unwrap().metadata;
+
+            // Still read once stored at millisecond precision
+            assert_eq!(decoded.divider_index(&messages), None);
+            assert!(decoded.read_marker >= metadata.read_marker);
+            assert!(decoded.highlight_read_marker >= metadata.highlight_read_marker);
+        }
+
+        let encoded = format::encode_metadata(None, &metadata, time::Format::Canonical).unwrap();
+        let stored = serde_json::from_slice::<serde_json::Value>(&encoded).unwrap();
+
+        assert_eq!(stored["read_marker"], "2024-11-01T12:00:00.124Z");
+        assert_eq!(stored["last_triggers_unread"], "2024-11-01T12:00:00.123Z");
+    }
+
+    #[test]
+    fn unchanged_is_semantic() {
+        let compact = br#"{"read_marker":"2024-11-01T12:00:00.000Z","last_triggers_unread":"2024-11-01T12:00:00Z","chathistory_references":{"timestamp":"2024-11-01T12:00:00.000Z","id":"abc"}}"#;
+        let reformatted = br#"{
+  "read_marker": "2024-11-01T12:00:00Z",
+  "last_triggers_unread": "2024-11-01T13:00:00.000+01:00",
+  "chathistory_references": {
+    "timestamp": "2024-11-01T12:00:00.000Z",
+    "id": "abc"
+  }
+}
+   "#;
+
+        let metadata = serde_json::from_slice::<Metadata>(compact).unwrap();
+        let encode = |metadata: &Metadata| {
+            format::encode_metadata(None, metadata, time::Format::default()).unwrap()
+        };
+
+        assert!(is_unchanged(compact, &encode(&metadata)));
+        assert!(is_unchanged(reformatted, &encode(&metadata)));
+
+        let changed_id = Metadata {
+            chathistory_references: Some(MessageReferences {
+                id: Some("def".to_string()),
+                ..metadata.chathistory_references.clone().unwrap()
+            }),
+            ..metadata.clone()
+        };
+
+        assert!(!is_unchanged(reformatted, &encode(&changed_id)));
+
+        let changed_read_marker = Metadata {
+            read_marker: "2024-11-01T12:00:01.000Z".parse().ok(),
+            ..metadata
+        };
+
+        assert!(!is_unchanged(reformatted, &encode(&changed_read_marker)));
+    }
+
+    #[derive(Default)]
+    struct Memory {
+        files: Mutex<HashMap<String, Vec<u8>>>,
+        writes: AtomicUsize,
+    }
+
+    impl Memory {
+        fn writes(&self) -> usize {
+            self.writes.load(Ordering::Relaxed)
+        }
+    }
+
+    impl Storage for Memory {
+        async fn read(&self, kind: &Kind) -> Result<Option<Vec<u8>>, Error> {
+            Ok(self.files.lock().unwrap().get(&storage::key(kind)).cloned())
+        }
+
+        async fn write(&self, kind: &Kind, bytes: Vec<u8>) -> Result<(), Error> {
+            self.writes.fetch_add(1, Ordering::Relaxed);
+            self.files.lock().unwrap().insert(storage::key(kind), bytes);
+            Ok(())
+        }
+
+        async fn remove(&self, kind: &Kind) -> Result<(), Error> {
+            self.files.lock().unwrap().remove(&storage::key(kind));
+            Ok(())
+        }
+
+        async fn list(&self) -> Result<Vec<String>, Error> {
+            Ok(self.files.lock().unwrap().keys().cloned().collect())
+        }
+    }
+
+    #[test]
+    fn equal_metadata_is_not_written() {
+        let store = MetadataStore::new(Memory::default(), Config::default());
+        let channel = Kind::Channel(Server::from("libera"), "#halloy".to_string());
+        // Nanoseconds, as from the clock, which aren't kept when stored
+        let read_marker = ReadMarker("2024-11-01T12:00:00.123456789Z".parse().unwrap());
+
+        futures::executor::block_on(async {
+            store.update(&channel, &read_marker).await.unwrap();
+            assert_eq!(store.storage.writes(), 1);
+
+            store.update(&channel, &read_marker).await.unwrap();
+            assert_eq!(store.storage.writes(), 1);
+
+            // Byte-different but equal file
+            let stored = store.storage.read(&channel).await.unwrap().unwrap();
+            let reformatted = serde_json::to_vec_pretty(
+                &serde_json::from_slice::<serde_json::Value>(&stored).unwrap(),
+            )
+            .unwrap();
+            assert_ne!(stored, reformatted);
+            store
+                .storage
+                .files
+                .lock()
+                .unwrap()
+                .insert(storage::key(&channel), reformatted);
+
+            store.update(&channel, &read_marker).await.unwrap();
+            assert_eq!(store.storage.writes(), 1);
+
+            let later = ReadMarker("2024-11-01T12:00:01.000Z".parse().unwrap());
+            store.update(&channel, &later).await.unwrap();
+            assert_eq!(store.storage.writes(), 2);
+        });
+    }
+
+    #[test]
+    fn store_over_custom_storage() {
+        let store = MetadataStore::new(Memory::default(), Config::default());
+        let channel = Kind::Channel(Server::from("libera"), "#halloy".to_string());
+        let query = Kind::Query(Server::from("libera"), Nick::from("casperstorm"));
+        let read_marker = ReadMarker("2024-11-01T12:00:00.000Z".parse().unwrap());
+
+        futures::executor::block_on(async {
+            store.update(&channel, &read_marker).await.unwrap();
+            assert_eq!(
+                store.load(channel.clone()).await.unwrap().read_marker,
+                Some(read_marker)
+            );
+
+            assert_eq!(
+                store.seed_from(&query, &channel, None).await.unwrap(),
+                Some(read_marker)
+            );
+            assert_eq!(store.storage.list().await.unwrap().len(), 2);
+
+            // The read marker held in memory wins over the stored one
+            let server = Kind::Server(Server::from("libera"));
+            let ahead = ReadMarker("2024-11-01T12:00:01.000Z".parse().unwrap());
+            assert_eq!(
+                store
+                    .seed_from(&server, &channel, Some(ahead))
+                    .await
+                    .unwrap(),
+                Some(ahead)
+            );
+            assert_eq!(
+                store.load(server.clone()).await.unwrap().read_marker,
+                Some(ahead)
+            );
+
+            store.delete(&channel).await.unwrap();
+            assert_eq!(store.load(channel).await.unwrap().read_marker, None);
+        });
+    }
+
+    #[test]
+    fn metadata_of_another_kind_is_ignored() {
+        let store = MetadataStore::new(Memory::default(), Config::default());
+        let channel = Kind::Channel(Server::from("libera"), "#halloy".to_string());
+        let read_marker = ReadMarker("2024-11-01T12:00:00.000Z".parse().unwrap());
+
+        futures::executor::block_on(async {
+            store.update(&channel, &read_marker).await.unwrap();
+
+            let bytes = store.storage.read(&channel).await.unwrap().unwrap();
+            assert_eq!(format::stored_metadata_kind(&bytes), Some(channel.name()));
+
+            let server = Kind::Server(Server::from("libera"));
+            let metadata = Metadata {
+                read_marker: Some(read_marker),
+                ..Metadata::default()
+            };
+            store
+                .storage
+                .write(
+                    &channel,
+                    format::encode_metadata(
+                        Some(server.name().as_str()),
+                        &metadata,
+                        time::Format::default(),
+                    )
+                    .unwrap(),
+                )
+                .await
+                .unwrap();
+
+            assert_eq!(store.load(channel).await.unwrap().read_marker, None);
+        });
+    }
+
+    fn at(s: &str) -> DateTime<Utc> {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn member_counts_recorded_on_change() {
+        let mut counts = vec![];
+
+        assert!(record_member_count(
+            &mut counts,
+            at("2024-11-01T12:00:00Z"),
+            100
+        ));
+        assert!(!record_member_count(
+            &mut counts,
+            at("2024-11-01T13:00:00Z"),
+            100
+        ));
+        assert!(record_member_count(
+            &mut counts,
+            at("2024-11-02T12:00:00Z"),
+            90
+        ));
+        assert!(record_member_count(
+            &mut counts,
+            at("2024-11-03T12:00:00Z"),
+            95
+        ));
+        assert_eq!(counts.len(), 3);
+
+        // Only the latest count past the retention is kept
+        assert!(record_member_count(
+            &mut counts,
+            at("2024-11-09T18:00:00Z"),
+            120
+        ));
+        assert_eq!(
+            counts.iter().map(|count| count.count).collect::<Vec<_>>(),
+            vec![90, 95, 120]
+        );
+    }
+
+    #[test]
+    fn member_count_delta_from_baseline() {
+        let counts = [
+            MemberCount {
+                at: at("2024-11-01T12:00:00Z"),
+                count: 100,
+            },
+            MemberCount {
+                at: at("2024-11-02T09:00:00Z"),
+                count: 104,
+            },
+            MemberCount {
+                at: at("2024-11-02T15:00:00Z"),
+                count: 110,
+            },
+        ];
+
+        assert_eq!(
+            member_count_delta(&[], at("2024-11-02T00:00:00Z"), 10),
+            None
+        );
+        assert_eq!(
+            member_count_delta(&counts, at("2024-11-02T00:00:00Z"), 112),
+            Some(12)
+        );
+        assert_eq!(
+            member_count_delta(&counts, at("2024-11-02T12:00:00Z"), 101),
+            Some(-3)
+        );
+        // Nothing before, compared with the first count
+        assert_eq!(
+            member_count_delta(&counts, at("2024-10-01T00:00:00Z"), 100),
+            Some(0)
+        );
+
+        assert_eq!(
+            member_count_sparkline(&counts, at("2024-11-02T00:00:00Z"), 114, 10).as_deref(),
+            Some("▁▃▆█")
+        );
+        assert_eq!(
+            member_count_sparkline(&counts, at("2024-11-02T00:00:00Z"), 114, 2).as_deref(),
+            Some("▁█")
+        );
+        assert_eq!(
+            member_count_sparkline(&counts, at("2024-11-03T00:00:00Z"), 110, 10),
+            None
+        );
+    }
+
+    #[test]
+    fn reconcile_keeps_member_counts_of_duplicate() {
+        let root = std::env::temp_dir().join(format!("halloy-reconcile-{}", std::process::id()));
+        let (primary, other) = (root.join("primary"), root.join("other"));
+        let channel = Kind::Channel(Server::from("libera"), "#halloy".to_string());
+        let file_name = file_name(&channel);
+        let config = Config::default();
+        let member_count = MemberCount {
+            at: at("2024-11-01T12:00:00Z"),
+            count: 42,
+        };
+
+        let write = |dir: &Path, metadata: &Metadata| {
+            std::fs::create_dir_all(dir).unwrap();
+            std::fs::write(
+                dir.join(&file_name),
+                format::encode_metadata(Some(&channel.name()), metadata, config.timestamp_format)
+                    .unwrap(),
+            )
+            .unwrap();
+        };
+
+        write(&primary, &populated());
+        write(
+            &other,
+            &Metadata {
+                member_counts: vec![member_count],
+                ..populated()
+            },
+        );
+
+        let reconciled = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap()
+            .block_on(reconcile_directories(
+                &primary,
+                &other,
+                ReconcilePolicy::RemoveDuplicate,
+                &config,
+            ))
+            .unwrap();
+
+        assert_eq!(reconciled.len(), 1);
+        assert_eq!(reconciled[0].changes.len(), 1);
+        assert!(reconciled[0].removed_duplicate);
+
+        let stored = std::fs::read(primary.join(&file_name)).unwrap();
+        assert_eq!(
+            format::decode_metadata(&stored)
+                .unwrap()
+                .metadata
+                .member_counts,
+            vec![member_count]
+        );
+        assert!(!other.join(&file_name).exists());
+
+        let _ = std::fs::remove_dir_all(root);
+    }
+
+    #[test]
+    fn reconcile_matches_by_kind() {
+        let root = std::env::temp_dir().join(format!("halloy-match-{}", std::process::id()));
+        let (primary, other) = (root.join("primary"), root.join("other"));
+        let channel = Kind::Channel(Server::from("libera"), "#halloy".to_string());
+        let query = Kind::Query(Server::from("libera"), Nick::from("casperstorm"));
+        let config = Config::default();
+        let newer = Metadata {
+            read_marker: "2024-11-02T12:00:00.000Z".parse().ok(),
+            ..populated()
+        };
+
+        let write = |path: PathBuf, kind: Option<&Kind>, metadata: &Metadata| {
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(
+                path,
+                format::encode_metadata(
+                    kind.map(Kind::name).as_deref(),
+                    metadata,
+                    config.timestamp_format,
+                )
+                .unwrap(),
+            )
+            .unwrap();
+        };
+
+        write(
+            primary.join(file_name(&channel)),
+            Some(&channel),
+            &populated(),
+        );
+        // Suffixed to tell it apart from another kind
+        let suffixed = file_name(&query).replace(".json", "-1.json");
+        write(primary.join(&suffixed), Some(&query), &populated());
+
+        // Named and written by builds which didn't record the kind
+        write(other.join(legacy_file_name(&channel)), None, &newer);
+        write(other.join(file_name(&query)), Some(&query), &newer);
+
+        let reconciled = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap()
+            .block_on(reconcile_directories(
+                &primary,
+                &other,
+                ReconcilePolicy::KeepDuplicate,
+                &config,
+            ))
+            .unwrap();
+
+        assert_eq!(reconciled.len(), 2);
+
+        for file_name in [file_name(&channel), suffixed] {
+            let stored = std::fs::read(primary.join(file_name)).unwrap();
+
+            assert_eq!(
+                format::decode_metadata(&stored)
+                    .unwrap()
+                    .metadata
+                    .read_marker,
+                newer.read_marker
+            );
+        }
+
+        let _ = std::fs::remove_dir_all(root);
+    }
+
+    #[test]
+    fn member_counts_are_optional() {
+        let metadata = serde_json::from_slice::<Metadata>(
+            br#"{"read_marker":null,"chathistory_references":null}"#,
+        )
+        .unwrap();
+
+        assert!(metadata.member_counts.is_empty());
+        assert!(!serde_json::to_string(&metadata)
+            .unwrap()
+            .contains("member_counts"));
+
+        let store = MetadataStore::new(Memory::default(), Config::default());
+        let channel = Kind::Channel(Server::from("libera"), "#halloy".to_string());
+        let member_count = MemberCount {
+            at: at("2024-11-01T12:00:00Z"),
+            count: 42,
+        };
+
+        futures::executor::block_on(async {
+            store
+                .update_member_count(&channel, member_count)
+                .await
+                .unwrap();
+            store.save(&channel, &[], None, false).await.unwrap();
+
+            assert_eq!(
+                store.load(channel).await.unwrap().member_counts,
+                vec![member_count]
+            );
+        });
+    }
+
+    #[test]
+    fn clear_empties_stored_fields() {
+        let store = MetadataStore::new(Memory::default(), Config::default());
+        let channel = Kind::Channel(Server::from("libera"), "#halloy".to_string());
+        let read_marker = ReadMarker("2024-11-01T12:00:00.000Z".parse().unwrap());
+        let member_count = MemberCount {
+            at: at("2024-11-01T12:00:00Z"),
+            count: 42,
+        };
+
+        futures::executor::block_on(async {
+            store.update(&channel, &read_marker).await.unwrap();
+            store
+                .update_highlight_read_marker(&channel, &read_marker)
+                .await
+                .unwrap();
+            store
+                .update_member_count(&channel, member_count)
+                .await
+                .unwrap();
+
+            // Saving nothing carries the stored fields forward
+            store.save(&channel, &[], None, false).await.unwrap();
+            assert_eq!(
+                store.load(channel.clone()).await.unwrap().read_marker,
+                Some(read_marker)
+            );
+
+            store.clear(&channel).await.unwrap();
+            assert!(store
+                .load(channel)
+                .await
+                .unwrap()
+                .eq_exact(&Metadata::default()));
+        });
+    }
+
+    #[test]
+    fn skewed_read_marker_is_clamped() {
+        let store = MetadataStore::new(Memory::default(), Config::default());
+        let channel = Kind::Channel(Server::from("libera"), "#halloy".to_string());
+
+        // Whole seconds, as they're stored
+        let now = DateTime::from_timestamp(Utc::now().timestamp(), 0).unwrap();
+        let minutes = |minutes| now + chrono::Duration::minutes(minutes);
+        let skewed = ReadMarker(minutes(10));
+
+        futures::executor::block_on(async {
+            store
+                .write(
+                    &channel,
+                    &Metadata {
+                        read_marker: Some(skewed),
+                        last_triggers_unread: Some(minutes(-30)),
+                        ..Metadata::default()
+                    },
+                )
+                .await
+                .unwrap();
+
+            // Clamped to the latest message
+            assert_eq!(
+                store.load(channel.clone()).await.unwrap().read_marker,
+                Some(ReadMarker(minutes(-30)))
+            );
+
+            let read_marker = ReadMarker(minutes(-1));
+            store.update(&channel, &read_marker).await.unwrap();
+            assert_eq!(
+                store.load(channel.clone()).await.unwrap().read_marker,
+                Some(read_marker)
+            );
+
+            // A skewed marker isn't taken on again
+            store.update(&channel, &skewed).await.unwrap();
+            assert_eq!(
+                store.load(channel.clone()).await.unwrap().read_marker,
+                Some(read_marker)
+            );
+
+            let earlier = ReadMarker(minutes(-20));
+            store
+                .overwrite_read_marker(&channel, &earlier)
+                .await
+                .unwrap();
+            assert_eq!(
+                store.load(channel).await.unwrap().read_marker,
+                Some(earlier)
+            );
+        });
+
+        // Markers in step with the messages are left alone, even ahead of
+        // the clock
+        let config = Config::default();
+
+        assert!(!ReadMarker(minutes(10)).is_skewed(Some(minutes(10)), now, &config));
+        assert!(!ReadMarker(minutes(-10)).is_skewed(None, now, &config));
+        assert!(ReadMarker(minutes(10)).is_skewed(None, now, &config));
+    }
 }
@@ -0,0 +1,125 @@
+use std::future::Future;
+use std::io;
+use std::path::PathBuf;
+
+use tokio::fs;
+
+use super::{file_name, is_metadata_file, legacy_file_name};
+use crate::history::{
+    self, format, kind_dir_path, remove_if_exists, resolve_path, Config, Error, Kind,
+};
+
+/// Where serialized [`Metadata`](super::Metadata) is kept. Backends only
+/// move bytes around, (de)serialization stays with the store.
+pub trait Storage: Send + Sync {
+    /// The stored metadata of `kind`, `None` if there is none
+    fn read(&self, kind: &Kind) -> impl Future<Output = Result<Option<Vec<u8>>, Error>> + Send;
+
+    fn write(&self, kind: &Kind, bytes: Vec<u8>) -> impl Future<Output = Result<(), Error>> + Send;
+
+    /// Remove the metadata of `kind`, if any
+    fn remove(&self, kind: &Kind) -> impl Future<Output = Result<(), Error>> + Send;
+
+    /// Keys of all stored metadata, see [`key`]
+    fn list(&self) -> impl Future<Output = Result<Vec<String>, Error>> + Send;
+}
+
+/// Stable key identifying the metadata of `kind` across backends, which is
+/// also the file name used by [`File`] unless another kind's metadata hashed
+/// to it first
+pub fn key(kind: &Kind) -> String {
+    file_name(kind)
+}
+
+/// One JSON file per buffer in the history directory, or the server's
+/// `history_dir` when configured
+#[derive(Debug, Clone, Default)]
+pub struct File {
+    config: Config,
+    /// Directory holding every buffer's file, in place of the configured ones
+    dir: Option<PathBuf>,
+}
+
+impl File {
+    pub fn new(config: Config) -> Self {
+        Self { config, dir: None }
+    }
+
+    /// Files of every buffer in `dir`
+    pub fn in_dir(dir: PathBuf, config: Config) -> Self {
+        Self {
+            config,
+            dir: Some(dir),
+        }
+    }
+
+    async fn path(&self, kind: &Kind) -> Result<PathBuf, Error> {
+        let dir = match &self.dir {
+            Some(dir) => dir.clone(),
+            None => kind_dir_path(kind, &self.config).await?,
+        };
+
+        resolve_path(
+            &dir,
+            kind,
+            &key(kind),
+            &legacy_file_name(kind),
+            format::stored_metadata_kind,
+        )
+        .await
+    }
+}
+
+impl Storage for File {
+    async fn read(&self, kind: &Kind) -> Result<Option<Vec<u8>>, Error> {
+        let path = self.path(kind).await?;
+
+        match fs::read(path).await {
+            Ok(bytes) => Ok(Some(bytes)),
+            Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(error) => Err(error.into()),
+        }
+    }
+
+    async fn write(&self, kind: &Kind, bytes: Vec<u8>) -> Result<(), Error> {
+        let path = self.path(kind).await?;
+
+        fs::write(path, &bytes).await?;
+
+        Ok(())
+    }
+
+    async fn remove(&self, kind: &Kind) -> Result<(), Error> {
+        let path = self.path(kind).await?;
+
+        remove_if_exists(&path).await
+    }
+
+    async fn list(&self) -> Result<Vec<String>, Error> {
+        let mut keys = vec![];
+
+        let dirs = match &self.dir {
+            Some(dir) => vec![dir.clone()],
+            None => history::dir_paths(&self.config).await?,
+        };
+
+        for dir in dirs {
+            let mut entries = fs::read_dir(&dir).await?;
+
+            while let Some(entry) = entries.next_entry().await? {
+                let path = entry.path();
+
+                if is_metadata_file(&path) {
+                    if let Some(name) = path.file_name().and_then(|name| name.to_str()) {
+                        keys.push(name.to_string());
+                    }
+                }
+            }
+        }
+
+        keys.sort();
+        keys.dedup();
+
+        Ok(keys)
+    }
+}
@@ -5,7 +5,8 @@ use irc::proto::format;
 
 use crate::buffer::{self, AutoFormat};
 use crate::message::formatting;
-use crate::{command, message, Command, Message, Server, User};
+use crate::user::{ListFormat, NickRef};
+use crate::{command, isupport, message, Command, Message, Server, User};
 
 const INPUT_HISTORY_LENGTH: usize = 100;
 
@@ -13,8 +14,9 @@ pub fn parse(
     buffer: buffer::Upstream,
     auto_format: AutoFormat,
     input: &str,
+    our_nickname: Option<NickRef>,
 ) -> Result<Input, Error> {
-    let content = match command::parse(input, Some(&buffer)) {
+    let content = match command::parse(input, Some(&buffer), our_nickname) {
         Ok(command) => Content::Command(command),
         Err(command::Error::MissingSlash) => {
             let text = match auto_format {
@@ -104,6 +106,92 @@ impl Input {
         }
     }
 
+    /// Whether the text sent renders empty once formatting codes and
+    /// whitespace are stripped
+    pub fn is_blank(&self) -> bool {
+        match &self.content {
+            Content::Text(text) | Content::Command(Command::Msg(_, text)) => {
+                formatting::is_blank(text)
+            }
+            _ => false,
+        }
+    }
+
+    pub fn clear(&self) -> Option<command::Clear> {
+        match &self.content {
+            Content::Command(Command::Clear(clear)) => Some(*clear),
+            _ => None,
+        }
+    }
+
+    pub fn raw_log(&self) -> Option<bool> {
+        match &self.content {
+            Content::Command(Command::RawLog(enabled)) => Some(*enabled),
+            _ => None,
+        }
+    }
+
+    pub fn mark_read(&self) -> Option<bool> {
+        match &self.content {
+            Content::Command(Command::MarkRead(force)) => Some(*force),
+            _ => None,
+        }
+    }
+
+    pub fn profile(&self) -> Option<Option<&str>> {
+        match &self.content {
+            Content::Command(Command::Profile(name)) => Some(name.as_deref()),
+            _ => None,
+        }
+    }
+
+    pub fn note(&self) -> Option<Option<&str>> {
+        match &self.content {
+            Content::Command(Command::Note(note)) => Some(note.as_deref()),
+            _ => None,
+        }
+    }
+
+    pub fn theme(&self) -> Option<&command::Theme> {
+        match &self.content {
+            Content::Command(Command::Theme(theme)) => Some(theme),
+            _ => None,
+        }
+    }
+
+    pub fn connection(&self) -> Option<&command::Connection> {
+        match &self.content {
+            Content::Command(Command::Connection(connection)) => Some(connection),
+            _ => None,
+        }
+    }
+
+    pub fn copy_names(&self) -> Option<ListFormat> {
+        match &self.content {
+            Content::Command(Command::CopyNames(format)) => Some(*format),
+            _ => None,
+        }
+    }
+
+    pub fn translate(&self) -> Option<&command::Translate> {
+        match &self.content {
+            Content::Command(Command::Translate(translate)) => Some(translate),
+            _ => None,
+        }
+    }
+
+    /// Truncate the message of `/away` to `max_len` bytes, returning whether
+    /// it was truncated
+    pub fn truncate_away(&mut self, max_len: u16) -> bool {
+        match &mut self.content {
+            Content::Command(Command::Away(Some(comment))) if comment.len() > max_len as usize => {
+                *comment = isupport::truncate_to_limit(comment, max_len).to_string();
+                true
+            }
+            _ => false,
+        }
+    }
+
     pub fn encoded(&self) -> Option<message::Encoded> {
         self.content.proto(&self.buffer).map(message::Encoded::from)
     }
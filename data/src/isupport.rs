@@ -1,3 +1,4 @@
+use std::collections::HashMap;
 use std::fmt;
 use std::str::FromStr;
 
@@ -11,6 +12,8 @@ use crate::Message;
 #[derive(Clone, Debug, Eq, Hash, PartialEq)]
 pub enum Kind {
     AWAYLEN,
+    BOT,
+    CASEMAPPING,
     CHANLIMIT,
     CHANNELLEN,
     CHANTYPES,
@@ -477,6 +480,8 @@ impl Operation {
             Operation::Add(parameter) => parameter.kind(),
             Operation::Remove(parameter) => match parameter.as_ref() {
                 "AWAYLEN" => Some(Kind::AWAYLEN),
+                "BOT" => Some(Kind::BOT),
+                "CASEMAPPING" => Some(Kind::CASEMAPPING),
                 "CHANLIMIT" => Some(Kind::CHANLIMIT),
                 "CHANNELLEN" => Some(Kind::CHANNELLEN),
                 "CHANTYPES" => Some(Kind::CHANTYPES),
@@ -512,7 +517,7 @@ impl Operation {
 // - https://ircv3.net/specs/extensions/whox
 // - https://github.com/ircv3/ircv3-specifications/pull/464/files
 #[allow(non_camel_case_types)]
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum Parameter {
     ACCEPT(u16),
     ACCOUNTEXTBAN(Vec<String>),
@@ -576,6 +581,8 @@ impl Parameter {
     pub fn kind(&self) -> Option<Kind> {
         match self {
             Parameter::AWAYLEN(_) => Some(Kind::AWAYLEN),
+            Parameter::BOT(_) => Some(Kind::BOT),
+            Parameter::CASEMAPPING(_) => Some(Kind::CASEMAPPING),
             Parameter::CHANLIMIT(_) => Some(Kind::CHANLIMIT),
             Parameter::CHANNELLEN(_) => Some(Kind::CHANNELLEN),
             Parameter::CHANTYPES(_) => Some(Kind::CHANTYPES),
@@ -602,21 +609,49 @@ impl Parameter {
 }
 
 #[allow(non_camel_case_types)]
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Default, PartialEq)]
 pub enum CaseMap {
     ASCII,
+    /// Assumed by servers which don't advertise one
+    #[default]
     RFC1459,
     RFC1459_STRICT,
     RFC7613,
 }
 
-#[derive(Clone, Debug)]
+impl CaseMap {
+    /// Whether `a` and `b` are the same nickname or channel name
+    pub fn eq_names(&self, a: &str, b: &str) -> bool {
+        self.fold(a) == self.fold(b)
+    }
+
+    /// `name` folded to lowercase, the same for any two names
+    /// [`CaseMap::eq_names`] finds equal
+    pub fn fold(&self, name: &str) -> String {
+        match self {
+            CaseMap::ASCII => name.to_ascii_lowercase(),
+            CaseMap::RFC1459 | CaseMap::RFC1459_STRICT => name
+                .chars()
+                .map(|c| match c {
+                    '[' => '{',
+                    ']' => '}',
+                    '\\' => '|',
+                    '~' if *self == CaseMap::RFC1459 => '^',
+                    c => c.to_ascii_lowercase(),
+                })
+                .collect(),
+            CaseMap::RFC7613 => name.to_lowercase(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
 pub struct ChannelLimit {
     pub prefix: char,
     pub limit: Option<u16>,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct ChannelMode {
     pub letter: char,
     pub modes: String,
@@ -648,14 +683,14 @@ pub enum ChatHistoryState {
     Ready,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum ClientOnlyTags {
     Allowed(String),
     Denied(String),
     DenyAll,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct CommandTargetLimit {
     pub command: String,
     pub limit: Option<u16>,
@@ -692,19 +727,19 @@ impl PartialEq<Message> for MessageReference {
     }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum MessageReferenceType {
     Timestamp,
     MessageId,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct ModesLimit {
     pub modes: String,
     pub limit: u16,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct PrefixMap {
     pub prefix: char,
     pub mode: char,
@@ -718,6 +753,55 @@ const DEFAULT_DEAF_LETTER: char = 'D';
 
 const DEFAULT_INVITE_EXCEPTION_LETTER: char = 'I';
 
+/// Byte limit for away messages, topics and kick reasons when the server
+/// doesn't advertise one, about what fits in a line after the command
+pub const DEFAULT_TEXT_LIMIT: u16 = 390;
+
+/// Text which servers truncate past a length advertised in ISUPPORT
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextLimit {
+    Away,
+    Topic,
+    Kick,
+}
+
+/// Max length in bytes of `limit`'s text, from `AWAYLEN`, `TOPICLEN` or
+/// `KICKLEN`, falling back to [`DEFAULT_TEXT_LIMIT`]
+pub fn text_limit(isupport: &HashMap<Kind, Parameter>, limit: TextLimit) -> u16 {
+    let max_len = match limit {
+        TextLimit::Away => match isupport.get(&Kind::AWAYLEN) {
+            Some(Parameter::AWAYLEN(max_len)) => *max_len,
+            _ => None,
+        },
+        TextLimit::Topic => match isupport.get(&Kind::TOPICLEN) {
+            Some(Parameter::TOPICLEN(max_len)) => Some(*max_len),
+            _ => None,
+        },
+        TextLimit::Kick => match isupport.get(&Kind::KICKLEN) {
+            Some(Parameter::KICKLEN(max_len)) => Some(*max_len),
+            _ => None,
+        },
+    };
+
+    max_len.unwrap_or(DEFAULT_TEXT_LIMIT)
+}
+
+/// Truncate `text` to at most `max_len` bytes, without splitting a character
+pub fn truncate_to_limit(text: &str, max_len: u16) -> &str {
+    let max_len = max_len as usize;
+
+    if text.len() <= max_len {
+        return text;
+    }
+
+    let end = (0..=max_len)
+        .rev()
+        .find(|index| text.is_char_boundary(*index))
+        .unwrap_or_default();
+
+    &text[..end]
+}
+
 const FUZZ_SECONDS: chrono::Duration = chrono::Duration::seconds(5);
 
 pub fn fuzz_start_message_reference(message_reference: MessageReference) -> MessageReference {
@@ -850,3 +934,41 @@ fn parse_required_positive_integer(value: &str) -> Result<u16, &'static str> {
         Err("value required to be a positive integer")
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn text_limits_default_when_absent() {
+        let mut isupport = HashMap::new();
+
+        assert_eq!(text_limit(&isupport, TextLimit::Topic), DEFAULT_TEXT_LIMIT);
+
+        isupport.insert(Kind::TOPICLEN, Parameter::TOPICLEN(307));
+        isupport.insert(Kind::AWAYLEN, Parameter::AWAYLEN(None));
+
+        assert_eq!(text_limit(&isupport, TextLimit::Topic), 307);
+        assert_eq!(text_limit(&isupport, TextLimit::Away), DEFAULT_TEXT_LIMIT);
+        assert_eq!(text_limit(&isupport, TextLimit::Kick), DEFAULT_TEXT_LIMIT);
+    }
+
+    #[test]
+    fn casemapped_names() {
+        assert!(CaseMap::RFC1459.eq_names("Halloy[m]", "halloy{M}"));
+        assert!(CaseMap::RFC1459.eq_names("halloy~", "HALLOY^"));
+        assert!(!CaseMap::RFC1459_STRICT.eq_names("halloy~", "halloy^"));
+        assert!(!CaseMap::ASCII.eq_names("halloy[m]", "halloy{m}"));
+        assert!(CaseMap::ASCII.eq_names("Halloy", "hALLOY"));
+        assert!(CaseMap::RFC7613.eq_names("Ärger", "ärger"));
+    }
+
+    #[test]
+    fn truncate_to_bytes() {
+        assert_eq!(truncate_to_limit("gone fishing", 4), "gone");
+        assert_eq!(truncate_to_limit("gone", 10), "gone");
+        // `é` is 2 bytes, and isn't split
+        assert_eq!(truncate_to_limit("café", 4), "caf");
+        assert_eq!(truncate_to_limit("café", 5), "café");
+    }
+}
@@ -35,12 +35,17 @@ pub mod isupport;
 pub mod log;
 pub mod message;
 pub mod mode;
+pub mod note;
+pub mod notification;
 pub mod pane;
+pub mod raw_log;
 pub mod server;
 pub mod shortcut;
 pub mod stream;
 pub mod time;
+pub mod translation;
 pub mod url;
 pub mod user;
 pub mod version;
+pub mod whois;
 pub mod window;
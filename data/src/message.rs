@@ -1,4 +1,5 @@
 use std::borrow::Cow;
+use std::fmt;
 use std::hash::{DefaultHasher, Hash as _, Hasher};
 use std::iter;
 
@@ -18,7 +19,7 @@ pub use self::source::Source;
 use crate::config::buffer::UsernameFormat;
 use crate::time::{self, Posix};
 use crate::user::{Nick, NickRef};
-use crate::{ctcp, isupport, Config, Server, User};
+use crate::{ctcp, isupport, raw_log, Config, Server, User};
 
 // References:
 // - https://datatracker.ietf.org/doc/html/rfc1738#section-5
@@ -60,13 +61,25 @@ static URL_REGEX: Lazy<Regex> = Lazy::new(|| {
 pub type Channel = String;
 
 pub(crate) mod broadcast;
+pub mod correction;
 pub mod formatting;
 pub mod source;
 
 #[derive(Debug, Clone)]
-pub struct Encoded(proto::Message);
+pub struct Encoded {
+    message: proto::Message,
+    /// Received in a `chathistory` batch
+    backfilled: bool,
+}
 
 impl Encoded {
+    pub fn backfilled(self) -> Self {
+        Self {
+            backfilled: true,
+            ..self
+        }
+    }
+
     pub fn user(&self) -> Option<User> {
         let source = self.source.as_ref()?;
 
@@ -81,25 +94,28 @@ impl std::ops::Deref for Encoded {
     type Target = proto::Message;
 
     fn deref(&self) -> &Self::Target {
-        &self.0
+        &self.message
     }
 }
 
 impl std::ops::DerefMut for Encoded {
     fn deref_mut(&mut self) -> &mut Self::Target {
-        &mut self.0
+        &mut self.message
     }
 }
 
 impl From<proto::Message> for Encoded {
     fn from(proto: proto::Message) -> Self {
-        Self(proto)
+        Self {
+            message: proto,
+            backfilled: false,
+        }
     }
 }
 
 impl From<Encoded> for proto::Message {
     fn from(encoded: Encoded) -> Self {
-        encoded.0
+        encoded.message
     }
 }
 
@@ -168,6 +184,11 @@ pub struct Message {
     pub content: Content,
     pub id: Option<String>,
     pub hash: Hash,
+    /// Only retained in memory, see [`Raw`]
+    pub raw: Option<Raw>,
+    /// Nickname of the sender before their most recent nick change, shown
+    /// beside the message. Only retained in memory.
+    pub previous_nickname: Option<Nick>,
 }
 
 impl Message {
@@ -223,6 +244,24 @@ impl Message {
     ) -> Option<Message> {
         let server_time = server_time(&encoded);
         let id = message_id(&encoded);
+
+        if is_bot(&encoded)
+            && !config.buffer.bot.show_join_part
+            && matches!(encoded.command, Command::JOIN(..) | Command::PART(..))
+        {
+            return None;
+        }
+
+        let raw = config
+            .buffer
+            .history
+            .retain_raw_messages
+            .then(|| Raw::new(&encoded, &our_nick));
+        let direction = if is_sent_to_user(&encoded, &our_nick, chantypes, statusmsg) {
+            Direction::Sent
+        } else {
+            Direction::Received
+        };
         let content = content(
             &encoded,
             &our_nick,
@@ -244,11 +283,13 @@ impl Message {
         Some(Message {
             received_at,
             server_time,
-            direction: Direction::Received,
+            direction,
             target,
             content,
             id,
             hash,
+            raw,
+            previous_nickname: None,
         })
     }
 
@@ -264,6 +305,8 @@ impl Message {
             content,
             id: None,
             hash,
+            raw: None,
+            previous_nickname: None,
         }
     }
 
@@ -283,6 +326,8 @@ impl Message {
             content,
             id: None,
             hash,
+            raw: None,
+            previous_nickname: None,
         }
     }
 
@@ -302,6 +347,32 @@ impl Message {
             content,
             id: None,
             hash,
+            raw: None,
+            previous_nickname: None,
+        }
+    }
+
+    /// Plain message from `source` at `server_time`, with the time as its
+    /// text so messages at different times are never taken for the same
+    #[cfg(test)]
+    pub fn test(direction: Direction, source: Source, server_time: DateTime<Utc>) -> Message {
+        let received_at = Posix::now();
+        let content = plain(server_time.to_rfc3339());
+        let hash = Hash::new(&received_at, &content);
+
+        Message {
+            received_at,
+            server_time,
+            direction,
+            target: Target::Query {
+                nick: Nick::from("casperstorm"),
+                source,
+            },
+            content,
+            id: None,
+            hash,
+            raw: None,
+            previous_nickname: None,
         }
     }
 
@@ -309,6 +380,15 @@ impl Message {
         Self { target, ..self }
     }
 
+    /// Annotate the message with the nickname its sender had before their
+    /// most recent nick change
+    pub fn with_previous_nickname(self, previous_nickname: Option<Nick>) -> Self {
+        Self {
+            previous_nickname,
+            ..self
+        }
+    }
+
     pub fn plain(&self) -> Option<&str> {
         match &self.content {
             Content::Plain(s) => Some(s),
@@ -331,6 +411,8 @@ impl Message {
             content,
             id: None,
             hash,
+            raw: None,
+            previous_nickname: None,
         }
     }
 
@@ -430,11 +512,100 @@ impl<'de> Deserialize<'de> for Message {
             content,
             id,
             hash,
+            raw: None,
+            previous_nickname: None,
         })
     }
 }
 
+/// The protocol line a message was received as, along with its parsed
+/// tags and how it reached us. Kept when `buffer.history.retain_raw_messages`
+/// is enabled, but never written to history
+#[derive(Debug, Clone)]
+pub struct Raw {
+    pub line: String,
+    pub tags: Vec<proto::Tag>,
+    /// Full prefix of the sender, e.g. `nick!user@host`
+    pub prefix: Option<String>,
+    pub origin: Origin,
+}
+
+impl Raw {
+    fn new(encoded: &Encoded, our_nick: &Nick) -> Self {
+        let origin = if encoded.backfilled {
+            Origin::Backfill
+        } else if encoded
+            .user()
+            .is_some_and(|user| user.nickname() == *our_nick)
+        {
+            Origin::Echo
+        } else {
+            Origin::Live
+        };
+
+        Self {
+            line: raw_log::line(encoded),
+            tags: encoded.tags.clone(),
+            prefix: encoded.source.as_ref().map(raw_log::prefix),
+            origin,
+        }
+    }
+
+    /// Value of the tag `key`, if it's set
+    pub fn tag(&self, key: &str) -> Option<&str> {
+        self.tags
+            .iter()
+            .find(|tag| tag.key == key)
+            .map(|tag| tag.value.as_deref().unwrap_or_default())
+    }
+
+    /// Tags set by clients rather than the server, i.e. `+` prefixed
+    pub fn client_tags(&self) -> impl Iterator<Item = &proto::Tag> {
+        self.tags.iter().filter(|tag| tag.key.starts_with('+'))
+    }
+
+    /// Capabilities which affected how the message was handled
+    pub fn capabilities(&self) -> Vec<&'static str> {
+        let has = |key: &str| self.tags.iter().any(|tag| tag.key == key);
+
+        [
+            (has("time"), "server-time"),
+            (
+                has("msgid") || has("bot") || self.client_tags().next().is_some(),
+                "message-tags",
+            ),
+            (has("account"), "account-tag"),
+            (self.origin == Origin::Echo, "echo-message"),
+            (self.origin == Origin::Backfill, "batch"),
+            (self.origin == Origin::Backfill, "chathistory"),
+        ]
+        .into_iter()
+        .filter_map(|(used, capability)| used.then_some(capability))
+        .collect()
+    }
+}
+
+/// How a received message reached us
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Origin {
+    Live,
+    /// Our own message sent back to us, e.g. through `echo-message` or by a
+    /// bouncer for messages from our other clients
+    Echo,
+    Backfill,
+}
+
+impl fmt::Display for Origin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Origin::Live => write!(f, "live"),
+            Origin::Echo => write!(f, "echoed"),
+            Origin::Backfill => write!(f, "backfilled via chathistory"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct Hash(u64);
 
 impl Hash {
@@ -601,7 +772,7 @@ pub enum Content {
 }
 
 impl Content {
-    fn text(&self) -> Cow<str> {
+    pub fn text(&self) -> Cow<str> {
         match self {
             Content::Plain(s) => s.into(),
             Content::Fragments(fragments) => fragments.iter().map(Fragment::as_str).join("").into(),
@@ -657,6 +828,25 @@ impl From<formatting::Fragment> for Fragment {
     }
 }
 
+/// Whether `message` is a message or notice we sent to a user, e.g. from
+/// another client connected to the same bouncer. These are filed in the
+/// target's query as our own and never trigger unread.
+fn is_sent_to_user(
+    message: &Encoded,
+    our_nick: &Nick,
+    chantypes: &[char],
+    statusmsg: &[char],
+) -> bool {
+    let (Command::PRIVMSG(target, _) | Command::NOTICE(target, _)) = &message.command else {
+        return false;
+    };
+
+    proto::parse_channel_from_target(target, chantypes, statusmsg).is_none()
+        && message
+            .user()
+            .is_some_and(|user| user.nickname() == *our_nick)
+}
+
 fn target(
     message: Encoded,
     our_nick: &Nick,
@@ -666,9 +856,15 @@ fn target(
 ) -> Option<Target> {
     use proto::command::Numeric::*;
 
-    let user = message.user();
+    let is_bot = is_bot(&message);
+    let user = message.user().map(|mut user| {
+        if is_bot {
+            user.update_bot(true);
+        }
+        user
+    });
 
-    match message.0.command {
+    match message.message.command {
         // Channel
         Command::MODE(target, ..) if proto::is_channel(&target, chantypes) => {
             Some(Target::Channel {
@@ -788,10 +984,18 @@ fn target(
                 (None, Some(user)) => {
                     let target = User::try_from(target.as_str()).ok()?;
 
-                    (target.nickname() == *our_nick).then(|| Target::Query {
-                        nick: user.nickname().to_owned(),
-                        source: source(user),
-                    })
+                    if user.nickname() == *our_nick {
+                        // Notice from ourself, from another client.
+                        Some(Target::Query {
+                            nick: target.nickname().to_owned(),
+                            source: source(user),
+                        })
+                    } else {
+                        (target.nickname() == *our_nick).then(|| Target::Query {
+                            nick: user.nickname().to_owned(),
+                            source: source(user),
+                        })
+                    }
                 }
                 _ => Some(Target::Server {
                     source: Source::Server(None),
@@ -878,6 +1082,14 @@ pub fn message_id(message: &Encoded) -> Option<String> {
         .and_then(|tag| tag.value.clone())
 }
 
+/// Whether `message` is from a bot, per the IRCv3 bot mode tag
+pub fn is_bot(message: &Encoded) -> bool {
+    message
+        .tags
+        .iter()
+        .any(|tag| tag.key == "bot" || tag.key == "draft/bot")
+}
+
 pub fn server_time(message: &Encoded) -> DateTime<Utc> {
     message
         .tags
@@ -1066,7 +1278,7 @@ fn content<'a>(
 
             Some(parse_fragments(format!("{nick} {status_text} {ip}"), &[]))
         }
-        Command::Numeric(RPL_WHOISSECURE, params) => {
+        Command::Numeric(RPL_WHOISSECURE | RPL_WHOISBOT, params) => {
             let nick = params.get(1)?;
             let status_text = params.get(2)?;
 
@@ -1273,6 +1485,7 @@ where
 
 #[derive(Debug, Clone, Default, Deserialize, Serialize)]
 pub struct MessageReferences {
+    #[serde(with = "time::serde_format")]
     pub timestamp: DateTime<Utc>,
     pub id: Option<String>,
 }
@@ -1412,4 +1625,91 @@ mod test {
             assert_eq!(Content::Fragments(expected), actual);
         }
     }
+
+    #[test]
+    fn bot_tag_marks_source() {
+        let tests = [
+            ("@bot :helper!b@host PRIVMSG #halloy :hi\r\n", true),
+            ("@draft/bot :helper!b@host PRIVMSG #halloy :hi\r\n", true),
+            (":helper!b@host PRIVMSG #halloy :hi\r\n", false),
+        ];
+
+        for (line, expected) in tests {
+            let encoded = Encoded::from(proto::parse::message(line).unwrap());
+
+            assert_eq!(is_bot(&encoded), expected, "{line}");
+
+            let Some(Target::Channel {
+                source: Source::User(user),
+                ..
+            }) = target(encoded, &Nick::from("me"), &|_, _| None, &['#'], &[])
+            else {
+                panic!("expected a channel message from a user: {line}");
+            };
+
+            assert_eq!(user.is_bot(), expected, "{line}");
+        }
+    }
+
+    #[test]
+    fn sent_from_another_client() {
+        let our_nick = Nick::from("me");
+        let tests = [
+            (":me!m@host PRIVMSG friend :hi\r\n", true),
+            (":me!m@host NOTICE friend :hi\r\n", true),
+            (":me!m@host PRIVMSG #halloy :hi\r\n", false),
+            (":friend!f@host PRIVMSG me :hi\r\n", false),
+        ];
+
+        for (line, expected) in tests {
+            let encoded = Encoded::from(proto::parse::message(line).unwrap());
+
+            assert_eq!(
+                is_sent_to_user(&encoded, &our_nick, &['#'], &[]),
+                expected,
+                "{line}"
+            );
+
+            if let Some(Target::Query { nick, .. }) =
+                target(encoded, &our_nick, &|_, _| None, &['#'], &[])
+            {
+                assert_eq!(nick, Nick::from("friend"), "{line}");
+            }
+        }
+    }
+
+    #[test]
+    fn raw_details() {
+        let our_nick = Nick::from("me");
+        let line = "@time=2024-11-02T10:15:00.123Z;msgid=abc;account=casper;+draft/reply=xyz :casper!c@host PRIVMSG #halloy :hi\r\n";
+        let encoded = Encoded::from(proto::parse::message(line).unwrap());
+
+        let raw = Raw::new(&encoded, &our_nick);
+
+        assert_eq!(raw.origin, Origin::Live);
+        assert_eq!(raw.prefix.as_deref(), Some("casper!c@host"));
+        assert_eq!(raw.tag("time"), Some("2024-11-02T10:15:00.123Z"));
+        assert_eq!(raw.tag("msgid"), Some("abc"));
+        assert_eq!(raw.tag("label"), None);
+        assert_eq!(
+            raw.client_tags()
+                .map(|tag| tag.key.as_str())
+                .collect::<Vec<_>>(),
+            vec!["+draft/reply"]
+        );
+        assert_eq!(
+            raw.capabilities(),
+            vec!["server-time", "message-tags", "account-tag"]
+        );
+
+        let backfilled = Raw::new(&encoded.backfilled(), &our_nick);
+        assert_eq!(backfilled.origin, Origin::Backfill);
+        assert!(backfilled.capabilities().contains(&"chathistory"));
+
+        let echo =
+            Encoded::from(proto::parse::message(":me!m@host PRIVMSG #halloy :hi\r\n").unwrap());
+        let echo = Raw::new(&echo, &our_nick);
+        assert_eq!(echo.origin, Origin::Echo);
+        assert_eq!(echo.capabilities(), vec!["echo-message"]);
+    }
 }
@@ -1,8 +1,10 @@
 //! Generate messages that can be broadcast into every buffer
 use chrono::{DateTime, Utc};
+use itertools::Itertools;
 
 use super::{parse_fragments, plain, source, Content, Direction, Message, Source, Target};
 use crate::config::buffer::UsernameFormat;
+use crate::config::channel::JoinReplies;
 use crate::time::Posix;
 use crate::user::Nick;
 use crate::{message, Config, User};
@@ -10,6 +12,7 @@ use crate::{message, Config, User};
 enum Cause {
     Server(Option<source::Server>),
     Status(source::Status),
+    Session(source::Session),
 }
 
 fn expand(
@@ -32,12 +35,15 @@ fn expand(
             content,
             id: None,
             hash,
+            raw: None,
+            previous_nickname: None,
         }
     };
 
     let source = match cause {
         Cause::Server(server) => Source::Server(server),
         Cause::Status(status) => Source::Internal(source::Internal::Status(status)),
+        Cause::Session(session) => Source::Internal(source::Internal::Session(session)),
     };
 
     channels
@@ -96,6 +102,44 @@ pub fn connected(sent_time: DateTime<Utc>) -> Vec<Message> {
     )
 }
 
+pub fn session_started(sent_time: DateTime<Utc>) -> Vec<Message> {
+    let content = plain("session started".into());
+    expand(
+        [],
+        [],
+        true,
+        Cause::Session(source::Session::Started),
+        content,
+        sent_time,
+    )
+}
+
+pub fn session_ended(sent_time: DateTime<Utc>) -> Vec<Message> {
+    let content = plain("session ended".into());
+    expand(
+        [],
+        [],
+        true,
+        Cause::Session(source::Session::Ended),
+        content,
+        sent_time,
+    )
+}
+
+/// Marks the end of a session which was never closed cleanly, e.g.
+/// halloy crashed or was killed before it could write its own marker
+pub fn session_ended_unexpectedly(sent_time: DateTime<Utc>) -> Vec<Message> {
+    let content = plain("session ended unexpectedly".into());
+    expand(
+        [],
+        [],
+        true,
+        Cause::Session(source::Session::Ended),
+        content,
+        sent_time,
+    )
+}
+
 pub fn connection_failed(error: String, sent_time: DateTime<Utc>) -> Vec<Message> {
     let content = plain(format!("connection to server failed ({error})"));
     expand(
@@ -243,3 +287,205 @@ pub fn change_host(
         sent_time,
     )
 }
+
+pub fn back(
+    queries: impl IntoIterator<Item = Nick>,
+    user: &User,
+    sent_time: DateTime<Utc>,
+) -> Vec<Message> {
+    let content = plain(format!("{} is back", user.nickname()));
+
+    expand([], queries, false, Cause::Server(None), content, sent_time)
+}
+
+pub fn joined(
+    channel: String,
+    users: usize,
+    topic: Option<String>,
+    topic_who: Option<String>,
+    topic_time: Option<DateTime<Utc>>,
+    replies: JoinReplies,
+    sent_time: DateTime<Utc>,
+) -> Vec<Message> {
+    let topic_set = topic_who.map(|who| match topic_time {
+        Some(time) => format!("{who} at {}", time.to_rfc2822()),
+        None => who,
+    });
+
+    match replies {
+        JoinReplies::Summary => {
+            let users = if users == 1 {
+                "1 user".to_string()
+            } else {
+                format!("{users} users")
+            };
+            let topic = match (topic, topic_set) {
+                (Some(topic), Some(set)) => format!("topic is {topic} (set by {set})"),
+                (Some(topic), None) => format!("topic is {topic}"),
+                (None, _) => "no topic".to_string(),
+            };
+
+            let content = parse_fragments(format!("joined · {users} · {topic}"), &[]);
+
+            expand(
+                [channel],
+                [],
+                false,
+                Cause::Server(None),
+                content,
+                sent_time,
+            )
+        }
+        JoinReplies::Separate => {
+            let cause = || {
+                Cause::Server(Some(source::Server::new(
+                    source::server::Kind::ReplyTopic,
+                    None,
+                )))
+            };
+
+            topic
+                .map(|topic| {
+                    expand(
+                        [channel.clone()],
+                        [],
+                        false,
+                        cause(),
+                        parse_fragments(format!("topic is {topic}"), &[]),
+                        sent_time,
+                    )
+                })
+                .into_iter()
+                .chain(topic_set.map(|set| {
+                    expand(
+                        [channel.clone()],
+                        [],
+                        false,
+                        cause(),
+                        plain(format!("topic set by {set}")),
+                        sent_time,
+                    )
+                }))
+                .flatten()
+                .collect()
+        }
+        JoinReplies::Hidden => vec![],
+    }
+}
+
+pub fn netsplit(
+    servers: &[String],
+    users: &[(User, Vec<String>)],
+    queries: impl IntoIterator<Item = Nick>,
+    config: &Config,
+    sent_time: DateTime<Utc>,
+) -> Vec<Message> {
+    let servers = servers.join(" ");
+
+    let channels = by_channel(users)
+        .into_iter()
+        .filter(|(channel, _)| {
+            config
+                .buffer
+                .server_messages
+                .quit
+                .should_send_message(channel)
+        })
+        .flat_map(|(channel, nicks)| {
+            let content = parse_fragments(
+                format!(
+                    "⟵ {} quit in a netsplit ({servers})",
+                    nicks.iter().join(", ")
+                ),
+                &[],
+            );
+
+            expand(
+                [channel],
+                [],
+                false,
+                Cause::Server(None),
+                content,
+                sent_time,
+            )
+        });
+
+    // Queries show a quit as usual
+    let queries = queries.into_iter().flat_map(|nick| {
+        users
+            .iter()
+            .find(|(user, _)| user.nickname() == nick)
+            .map(|(user, _)| {
+                quit(
+                    [],
+                    [nick],
+                    user,
+                    &Some(format!("netsplit {servers}")),
+                    config,
+                    sent_time,
+                )
+            })
+            .unwrap_or_default()
+    });
+
+    channels.chain(queries).collect()
+}
+
+pub fn netjoin(
+    servers: &[String],
+    users: &[(User, Vec<String>)],
+    config: &Config,
+    sent_time: DateTime<Utc>,
+) -> Vec<Message> {
+    let servers = servers.join(" ");
+
+    by_channel(users)
+        .into_iter()
+        .filter(|(channel, _)| {
+            config
+                .buffer
+                .server_messages
+                .join
+                .should_send_message(channel)
+        })
+        .flat_map(|(channel, nicks)| {
+            let content = parse_fragments(
+                format!(
+                    "⟶ {} joined after a netsplit ({servers})",
+                    nicks.iter().join(", ")
+                ),
+                &[],
+            );
+
+            expand(
+                [channel],
+                [],
+                false,
+                Cause::Server(None),
+                content,
+                sent_time,
+            )
+        })
+        .collect()
+}
+
+/// Nicks of `users` in each of their channels, in the order first seen
+fn by_channel(users: &[(User, Vec<String>)]) -> Vec<(String, Vec<Nick>)> {
+    let mut channels: Vec<(String, Vec<Nick>)> = vec![];
+
+    for (user, user_channels) in users {
+        for channel in user_channels {
+            let nick = user.nickname().to_owned();
+
+            match channels
+                .iter_mut()
+                .find(|(existing, _)| existing == channel)
+            {
+                Some((_, nicks)) => nicks.push(nick),
+                None => channels.push((channel.clone(), vec![nick])),
+            }
+        }
+    }
+
+    channels
+}
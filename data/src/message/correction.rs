@@ -0,0 +1,233 @@
+use std::collections::HashMap;
+
+use regex::{Regex, RegexBuilder};
+
+use super::{Hash, Source};
+use crate::user::Nick;
+use crate::Message;
+
+/// A sed-like `s/pattern/replacement/flags` correction of the sender's
+/// previous message
+#[derive(Debug, Clone)]
+pub struct Substitution {
+    pattern: Regex,
+    replacement: String,
+    global: bool,
+}
+
+/// Text of a corrected message
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Correction(Vec<Part>);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Part {
+    Unchanged(String),
+    Replaced(String),
+}
+
+impl Correction {
+    pub fn parts(&self) -> &[Part] {
+        &self.0
+    }
+
+    pub fn text(&self) -> String {
+        self.0
+            .iter()
+            .map(|part| match part {
+                Part::Unchanged(text) | Part::Replaced(text) => text.as_str(),
+            })
+            .collect()
+    }
+}
+
+/// Parse `text` as a substitution. Any punctuation may delimit it, the
+/// final delimiter may be left out, and the `g` (replace all) and `i`
+/// (ignore case) flags are supported.
+pub fn parse(text: &str) -> Option<Substitution> {
+    let mut chars = text.trim().strip_prefix('s')?.chars();
+
+    let delimiter = chars
+        .next()
+        .filter(|c| c.is_ascii_punctuation() && *c != '\\')?;
+
+    let mut parts = vec![String::new()];
+    let mut escaped = false;
+
+    for c in chars {
+        if escaped {
+            // Only an escaped delimiter loses its backslash
+            if c != delimiter {
+                parts.last_mut()?.push('\\');
+            }
+            parts.last_mut()?.push(c);
+            escaped = false;
+        } else if c == '\\' {
+            escaped = true;
+        } else if c == delimiter {
+            parts.push(String::new());
+        } else {
+            parts.last_mut()?.push(c);
+        }
+    }
+
+    let (pattern, replacement, flags) = match parts.as_slice() {
+        [pattern, replacement] => (pattern, replacement, ""),
+        [pattern, replacement, flags] => (pattern, replacement, flags.as_str()),
+        _ => return None,
+    };
+
+    if pattern.is_empty() || !flags.chars().all(|flag| matches!(flag, 'g' | 'i')) {
+        return None;
+    }
+
+    let pattern = RegexBuilder::new(pattern)
+        .case_insensitive(flags.contains('i'))
+        .build()
+        .ok()?;
+
+    Some(Substitution {
+        pattern,
+        replacement: replacement.clone(),
+        global: flags.contains('g'),
+    })
+}
+
+impl Substitution {
+    /// `text` with the substitution applied, `None` if the pattern doesn't
+    /// match
+    pub fn apply(&self, text: &str) -> Option<Correction> {
+        let limit = if self.global { usize::MAX } else { 1 };
+
+        let mut parts = vec![];
+        let mut matched = false;
+        let mut end = 0;
+
+        for found in self.pattern.find_iter(text).take(limit) {
+            matched = true;
+
+            if found.start() > end {
+                parts.push(Part::Unchanged(text[end..found.start()].to_string()));
+            }
+            if !self.replacement.is_empty() {
+                parts.push(Part::Replaced(self.replacement.clone()));
+            }
+            end = found.end();
+        }
+
+        if !matched {
+            return None;
+        }
+
+        if end < text.len() {
+            parts.push(Part::Unchanged(text[end..].to_string()));
+        }
+
+        Some(Correction(parts))
+    }
+}
+
+/// Corrections of a buffer's messages, keyed by the hash of the message
+/// making the correction. Each correction applies to the latest message of
+/// its sender which isn't a correction itself.
+#[derive(Debug, Clone, Default)]
+pub struct Corrections {
+    latest: HashMap<Nick, String>,
+    corrections: HashMap<Hash, Correction>,
+}
+
+impl Corrections {
+    pub fn new<'a>(messages: impl IntoIterator<Item = &'a Message>) -> Self {
+        let mut corrections = Self::default();
+
+        for message in messages {
+            corrections.push(message);
+        }
+
+        corrections
+    }
+
+    /// Take in `message`, which must be newer than every message before it
+    pub fn push(&mut self, message: &Message) {
+        let Source::User(user) = message.target.source() else {
+            return;
+        };
+
+        let text = message.content.text();
+
+        match parse(&text) {
+            Some(substitution) => {
+                if let Some(correction) = self
+                    .latest
+                    .get(&user.nickname().to_owned())
+                    .and_then(|previous| substitution.apply(previous))
+                {
+                    self.corrections.insert(message.hash, correction);
+                }
+            }
+            None => {
+                self.latest
+                    .insert(user.nickname().to_owned(), text.into_owned());
+            }
+        }
+    }
+
+    pub fn get(&self, hash: &Hash) -> Option<&Correction> {
+        self.corrections.get(hash)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn corrected(substitution: &str, text: &str) -> Option<String> {
+        parse(substitution)?
+            .apply(text)
+            .map(|correction| correction.text())
+    }
+
+    #[test]
+    fn substitutions() {
+        let tests = [
+            ("s/teh/the/", "teh cat", Some("the cat")),
+            ("s/a/o", "a cat, a hat", Some("o cat, a hat")),
+            ("s/a/o/g", "a cat, a hat", Some("o cot, o hot")),
+            ("s/CAT/dog/i", "a cat", Some("a dog")),
+            ("s|/tmp|/var|", "cd /tmp", Some("cd /var")),
+            ("s/\\/tmp/\\/var/", "cd /tmp", Some("cd /var")),
+            ("s/ cat//", "a cat", Some("a")),
+            ("s/dog/cat/", "a cat", None),
+            ("s/a/b/x", "a cat", None),
+            ("s/(/b/", "a cat", None),
+            ("s//b/", "a cat", None),
+            ("s/a/b/c/d", "a cat", None),
+            ("sa/b/", "a cat", None),
+            ("so what", "a cat", None),
+        ];
+
+        for (substitution, text, expected) in tests {
+            assert_eq!(
+                corrected(substitution, text).as_deref(),
+                expected,
+                "{substitution}"
+            );
+        }
+    }
+
+    #[test]
+    fn replacements_are_marked() {
+        let correction = parse("s/a/o/g")
+            .and_then(|substitution| substitution.apply("a cat"))
+            .unwrap();
+
+        assert_eq!(
+            correction.parts(),
+            [
+                Part::Replaced("o".to_string()),
+                Part::Unchanged(" c".to_string()),
+                Part::Replaced("o".to_string()),
+                Part::Unchanged("t".to_string()),
+            ]
+        );
+    }
+}
@@ -11,6 +11,28 @@ pub use self::encode::encode;
 pub mod encode;
 
 pub fn parse(text: &str) -> Option<Vec<Fragment>> {
+    let fragments = fragments(text)?;
+
+    if fragments.is_empty()
+        || (fragments.len() == 1 && matches!(fragments.first(), Some(Fragment::Unformatted(_))))
+    {
+        None
+    } else {
+        Some(fragments)
+    }
+}
+
+/// Whether `text` renders empty, i.e. it only contains whitespace and
+/// formatting codes
+pub fn is_blank(text: &str) -> bool {
+    fragments(text).is_some_and(|fragments| {
+        fragments.iter().all(|fragment| match fragment {
+            Fragment::Unformatted(text) | Fragment::Formatted(text, _) => text.trim().is_empty(),
+        })
+    })
+}
+
+fn fragments(text: &str) -> Option<Vec<Fragment>> {
     let mut fragments = vec![];
 
     let mut current_text = String::new();
@@ -143,13 +165,7 @@ pub fn parse(text: &str) -> Option<Vec<Fragment>> {
         }
     }
 
-    if fragments.is_empty()
-        || (fragments.len() == 1 && matches!(fragments.first(), Some(Fragment::Unformatted(_))))
-    {
-        None
-    } else {
-        Some(fragments)
-    }
+    Some(fragments)
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
@@ -660,3 +676,26 @@ impl Color {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn blank_text() {
+        let tests = [
+            ("", true),
+            ("   ", true),
+            ("\x0304", true),
+            ("\x02 \x02\x0304,01  ", true),
+            ("\x04ff0000 ", true),
+            ("\x0304hi", false),
+            ("hi", false),
+            (" \x1d. ", false),
+        ];
+
+        for (text, expected) in tests {
+            assert_eq!(is_blank(text), expected, "{text:?}");
+        }
+    }
+}
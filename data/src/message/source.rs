@@ -16,6 +16,7 @@ pub enum Source {
 pub enum Internal {
     Status(Status),
     Logs,
+    Session(Session),
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
@@ -24,6 +25,12 @@ pub enum Status {
     Error,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Session {
+    Started,
+    Ended,
+}
+
 pub mod server {
     #![allow(deprecated)]
     use serde::{Deserialize, Serialize};
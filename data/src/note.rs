@@ -0,0 +1,337 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{buffer, environment, history};
+
+/// Max # characters of a note
+pub const MAX_LENGTH: usize = 500;
+
+/// Private notes on channels and queries. Notes live in a single file beside
+/// each history directory, the data directory unless a server sets its own
+/// `history_dir`, so they can be listed without opening each buffer's
+/// history. They're never sent to a server.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(from = "Vec<Entry>", into = "Vec<Entry>")]
+pub struct Notes {
+    notes: HashMap<buffer::Upstream, String>,
+    /// Directories whose notes failed to load, never written to so what's
+    /// there isn't lost
+    unreadable: BTreeSet<PathBuf>,
+    /// Whether the stored notes were loaded, nothing is saved before then
+    is_loaded: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Entry {
+    buffer: buffer::Upstream,
+    note: String,
+}
+
+impl Notes {
+    /// Load the notes stored in every directory. A file which fails to
+    /// load is logged and skipped, keeping the rest.
+    pub async fn load(config: history::Config) -> Self {
+        Self::load_from(dirs(&config)).await
+    }
+
+    async fn load_from(dirs: Vec<PathBuf>) -> Self {
+        let mut notes = Self {
+            is_loaded: true,
+            ..Self::default()
+        };
+
+        for dir in dirs {
+            let path = dir.join(FILE_NAME);
+
+            match read(&path).await {
+                Ok(Some(loaded)) => notes.notes.extend(loaded.notes),
+                Ok(None) => {}
+                Err(error) => {
+                    log::warn!("failed to load notes from {}: {error}", path.display());
+                    notes.unreadable.insert(dir);
+                }
+            }
+        }
+
+        notes
+    }
+
+    /// Take in the `stored` notes once loaded, keeping any set meanwhile.
+    /// Returns whether there were, so they can be saved.
+    pub fn merge_loaded(&mut self, stored: Notes) -> bool {
+        let set = std::mem::replace(self, stored);
+        let has_set = !set.notes.is_empty();
+
+        self.notes.extend(set.notes);
+
+        has_set
+    }
+
+    /// Write the notes of each server beside its history. Notes which were
+    /// stored elsewhere before its `history_dir` changed move along.
+    pub async fn save(self, config: history::Config) -> Result<(), Error> {
+        self.save_to(dirs(&config), &config).await
+    }
+
+    async fn save_to(self, dirs: Vec<PathBuf>, config: &history::Config) -> Result<(), Error> {
+        if !self.is_loaded {
+            return Ok(());
+        }
+
+        let unreadable = self.unreadable.clone();
+        let mut by_dir = self.by_dir(config);
+
+        for dir in dirs {
+            let path = dir.join(FILE_NAME);
+            let notes = by_dir.remove(&dir).unwrap_or_default();
+
+            if unreadable.contains(&dir) {
+                if !notes.notes.is_empty() {
+                    log::warn!(
+                        "not saving notes to {}, which failed to load",
+                        path.display()
+                    );
+                }
+
+                continue;
+            }
+
+            // Written even when empty if there's a file, to remove its notes
+            if notes.notes.is_empty() && !path.exists() {
+                continue;
+            }
+
+            if !dir.exists() {
+                tokio::fs::create_dir_all(&dir).await?;
+            }
+
+            let bytes = serde_json::to_vec(&notes)?;
+
+            tokio::fs::write(path, &bytes).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Notes grouped by the directory they're stored in
+    fn by_dir(self, config: &history::Config) -> BTreeMap<PathBuf, Notes> {
+        let mut by_dir = BTreeMap::<_, Notes>::new();
+
+        for (buffer, note) in self.notes {
+            by_dir
+                .entry(dir(&buffer, config))
+                .or_default()
+                .notes
+                .insert(buffer, note);
+        }
+
+        by_dir
+    }
+
+    pub fn get(&self, buffer: &buffer::Upstream) -> Option<&str> {
+        self.notes.get(buffer).map(String::as_str)
+    }
+
+    /// Set the note of `buffer`, truncated to [`MAX_LENGTH`]. A blank note
+    /// removes it. Returns whether anything changed.
+    pub fn set(&mut self, buffer: buffer::Upstream, note: &str) -> bool {
+        let note = truncate(note.trim());
+
+        if note.is_empty() {
+            return self.notes.remove(&buffer).is_some();
+        }
+
+        if self.get(&buffer) == Some(note) {
+            return false;
+        }
+
+        self.notes.insert(buffer, note.to_string());
+
+        true
+    }
+}
+
+/// `note` cut to [`MAX_LENGTH`] characters
+pub fn truncate(note: &str) -> &str {
+    match note.char_indices().nth(MAX_LENGTH) {
+        Some((index, _)) => &note[..index],
+        None => note,
+    }
+}
+
+impl From<Vec<Entry>> for Notes {
+    fn from(entries: Vec<Entry>) -> Self {
+        Self {
+            notes: entries
+                .into_iter()
+                .map(|entry| (entry.buffer, entry.note))
+                .collect(),
+            ..Self::default()
+        }
+    }
+}
+
+impl From<Notes> for Vec<Entry> {
+    fn from(notes: Notes) -> Self {
+        notes
+            .notes
+            .into_iter()
+            .map(|(buffer, note)| Entry { buffer, note })
+            .collect()
+    }
+}
+
+const FILE_NAME: &str = "notes.json";
+
+/// The notes stored at `path`, `None` if there's no file
+async fn read(path: &Path) -> Result<Option<Notes>, Error> {
+    match tokio::fs::read(path).await {
+        Ok(bytes) => Ok(Some(serde_json::from_slice(&bytes)?)),
+        Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(error) => Err(error.into()),
+    }
+}
+
+/// Directory holding the notes of `buffer`, its server's `history_dir` if
+/// set, else the data directory
+fn dir(buffer: &buffer::Upstream, config: &history::Config) -> PathBuf {
+    config
+        .directories
+        .get(buffer.server())
+        .cloned()
+        .unwrap_or_else(environment::data_dir)
+}
+
+/// Every directory notes may be stored in, the data directory first
+fn dirs(config: &history::Config) -> Vec<PathBuf> {
+    let mut dirs = vec![environment::data_dir()];
+
+    for dir in config.directories.values() {
+        if !dirs.contains(dir) {
+            dirs.push(dir.clone());
+        }
+    }
+
+    dirs
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error(transparent)]
+    Serde(#[from] serde_json::Error),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Server;
+
+    fn channel() -> buffer::Upstream {
+        buffer::Upstream::Channel(Server::from("libera"), "#halloy".to_string())
+    }
+
+    #[test]
+    fn set_truncates_and_removes_blank() {
+        let mut notes = Notes::default();
+
+        assert!(notes.set(channel(), "  ops contact: foo  "));
+        assert_eq!(notes.get(&channel()), Some("ops contact: foo"));
+        assert!(!notes.set(channel(), "ops contact: foo"));
+
+        assert!(notes.set(channel(), &"é".repeat(MAX_LENGTH + 10)));
+        assert_eq!(
+            notes.get(&channel()).map(|note| note.chars().count()),
+            Some(MAX_LENGTH)
+        );
+
+        assert!(notes.set(channel(), " "));
+        assert_eq!(notes.get(&channel()), None);
+        assert!(!notes.set(channel(), ""));
+    }
+
+    #[test]
+    fn stored_beside_history() {
+        let mut config = history::Config::default();
+        config
+            .directories
+            .insert(Server::from("oftc"), PathBuf::from("/srv/halloy/oftc"));
+
+        let query = buffer::Upstream::Query(Server::from("oftc"), "casperstorm".into());
+
+        let mut notes = Notes::default();
+        notes.set(query.clone(), "ops contact");
+
+        let by_dir = notes.by_dir(&config);
+
+        assert_eq!(by_dir.len(), 1);
+        assert_eq!(
+            by_dir[std::path::Path::new("/srv/halloy/oftc")].get(&query),
+            Some("ops contact")
+        );
+    }
+
+    #[test]
+    fn unreadable_file_is_kept() {
+        let root = std::env::temp_dir().join(format!("halloy-notes-{}", std::process::id()));
+        let (readable, unreadable) = (root.join("libera"), root.join("oftc"));
+
+        let mut config = history::Config::default();
+        config
+            .directories
+            .insert(Server::from("libera"), readable.clone());
+        config
+            .directories
+            .insert(Server::from("oftc"), unreadable.clone());
+
+        let query = buffer::Upstream::Query(Server::from("oftc"), "casperstorm".into());
+
+        let mut stored = Notes::default();
+        stored.set(channel(), "prefers German");
+
+        tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap()
+            .block_on(async {
+                std::fs::create_dir_all(&readable).unwrap();
+                std::fs::create_dir_all(&unreadable).unwrap();
+                std::fs::write(
+                    readable.join(FILE_NAME),
+                    serde_json::to_vec(&stored).unwrap(),
+                )
+                .unwrap();
+                std::fs::write(unreadable.join(FILE_NAME), b"[{").unwrap();
+
+                let dirs = vec![readable.clone(), unreadable.clone()];
+
+                let mut notes = Notes::load_from(dirs.clone()).await;
+                assert_eq!(notes.get(&channel()), Some("prefers German"));
+
+                notes.set(query.clone(), "ops contact");
+                notes.save_to(dirs.clone(), &config).await.unwrap();
+
+                assert_eq!(std::fs::read(unreadable.join(FILE_NAME)).unwrap(), b"[{");
+
+                let notes = Notes::load_from(dirs).await;
+                assert_eq!(notes.get(&channel()), Some("prefers German"));
+                assert_eq!(notes.get(&query), None);
+            });
+
+        let _ = std::fs::remove_dir_all(root);
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut notes = Notes::default();
+        notes.set(channel(), "prefers German");
+
+        let json = serde_json::to_vec(&notes).unwrap();
+        let loaded: Notes = serde_json::from_slice(&json).unwrap();
+
+        assert_eq!(loaded.get(&channel()), Some("prefers German"));
+    }
+}
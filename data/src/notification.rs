@@ -0,0 +1,332 @@
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+use crate::config::notification::RateLimit;
+use crate::user::Nick;
+use crate::Server;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    DirectMessage,
+    Highlight,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Connection {
+    Disconnected,
+    Reconnected,
+}
+
+/// Notifications held back once their sender or buffer went over its
+/// limit, shown as a single notification after a quiet period
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Coalesced {
+    pub kind: Kind,
+    pub server: Server,
+    pub sender: Nick,
+    /// Channel of highlights, `None` for direct messages
+    pub channel: Option<String>,
+    pub count: usize,
+    last: Instant,
+}
+
+impl Coalesced {
+    fn buffer(&self) -> &str {
+        self.channel.as_deref().unwrap_or(self.sender.as_ref())
+    }
+}
+
+/// Rate limits notifications per sender and buffer within a sliding window,
+/// so a misbehaving bot can't set off hundreds of toasts and sounds
+#[derive(Debug, Default)]
+pub struct Limiter {
+    senders: HashMap<(Server, Nick), VecDeque<Instant>>,
+    buffers: HashMap<(Server, String), VecDeque<Instant>>,
+    coalesced: Vec<Coalesced>,
+    connections: HashMap<(Server, Connection), Instant>,
+}
+
+impl Limiter {
+    /// Whether to notify of a highlight or direct message from `sender`.
+    /// Notifications past the limit are coalesced, see [`Limiter::flush`].
+    pub fn allow(
+        &mut self,
+        now: Instant,
+        rate_limit: &RateLimit,
+        kind: Kind,
+        server: &Server,
+        sender: &Nick,
+        channel: Option<&str>,
+    ) -> bool {
+        if !rate_limit.enabled {
+            return true;
+        }
+
+        let buffer = channel.unwrap_or(sender.as_ref());
+
+        let senders = self
+            .senders
+            .entry((server.clone(), sender.clone()))
+            .or_default();
+        let buffers = self
+            .buffers
+            .entry((server.clone(), buffer.to_string()))
+            .or_default();
+
+        prune(senders, now, rate_limit.window());
+        prune(buffers, now, rate_limit.window());
+
+        // Keep coalescing until the sender or buffer has quieted down
+        let coalescing = self.coalesced.iter().any(|coalesced| {
+            coalesced.server == *server
+                && (coalesced.sender == *sender || coalesced.buffer() == buffer)
+        });
+
+        if !coalescing
+            && senders.len() < rate_limit.per_sender
+            && buffers.len() < rate_limit.per_buffer
+        {
+            senders.push_back(now);
+            buffers.push_back(now);
+
+            return true;
+        }
+
+        if let Some(coalesced) = self.coalesced.iter_mut().find(|coalesced| {
+            coalesced.kind == kind
+                && coalesced.server == *server
+                && coalesced.sender == *sender
+                && coalesced.channel.as_deref() == channel
+        }) {
+            coalesced.count += 1;
+            coalesced.last = now;
+        } else {
+            self.coalesced.push(Coalesced {
+                kind,
+                server: server.clone(),
+                sender: sender.clone(),
+                channel: channel.map(ToString::to_string),
+                count: 1,
+                last: now,
+            });
+        }
+
+        false
+    }
+
+    /// Coalesced notifications whose sender or buffer has been quiet for
+    /// the quiet period, resetting their limits
+    pub fn flush(&mut self, now: Instant, rate_limit: &RateLimit) -> Vec<Coalesced> {
+        let quiet = |coalesced: &Coalesced| {
+            now.saturating_duration_since(coalesced.last) >= rate_limit.quiet_period()
+        };
+
+        // Entries sharing a sender or buffer stay until all have quieted
+        let (flushed, coalescing): (Vec<_>, Vec<_>) = self.coalesced.drain(..).partition(quiet);
+        let (flushed, held): (Vec<_>, Vec<_>) = flushed.into_iter().partition(|flushed| {
+            !coalescing.iter().any(|coalesced| {
+                coalesced.server == flushed.server
+                    && (coalesced.sender == flushed.sender
+                        || coalesced.buffer() == flushed.buffer())
+            })
+        });
+        self.coalesced = coalescing.into_iter().chain(held).collect();
+
+        for coalesced in &flushed {
+            self.senders
+                .remove(&(coalesced.server.clone(), coalesced.sender.clone()));
+            self.buffers
+                .remove(&(coalesced.server.clone(), coalesced.buffer().to_string()));
+        }
+
+        let window = rate_limit.window();
+        self.senders.retain(|_, times| {
+            prune(times, now, window);
+            !times.is_empty()
+        });
+        self.buffers.retain(|_, times| {
+            prune(times, now, window);
+            !times.is_empty()
+        });
+
+        flushed
+    }
+
+    /// Whether to notify of `connection` changing for `server`. Repeats
+    /// are skipped while the connection is flapping, until it's been
+    /// stable for the quiet period.
+    pub fn allow_connection(
+        &mut self,
+        now: Instant,
+        rate_limit: &RateLimit,
+        server: &Server,
+        connection: Connection,
+    ) -> bool {
+        let last = self.connections.insert((server.clone(), connection), now);
+
+        !rate_limit.enabled
+            || last.map_or(true, |last| {
+                now.saturating_duration_since(last) >= rate_limit.quiet_period()
+            })
+    }
+}
+
+fn prune(times: &mut VecDeque<Instant>, now: Instant, window: Duration) {
+    while times
+        .front()
+        .is_some_and(|time| now.saturating_duration_since(*time) >= window)
+    {
+        times.pop_front();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rate_limit() -> RateLimit {
+        RateLimit {
+            enabled: true,
+            per_sender: 3,
+            per_buffer: 5,
+            window: 60,
+            quiet_period: 30,
+        }
+    }
+
+    #[test]
+    fn storm_is_coalesced_until_quiet() {
+        let rate_limit = rate_limit();
+        let server = Server::from("libera");
+        let bot = Nick::from("bot");
+        let start = Instant::now();
+
+        let mut limiter = Limiter::default();
+        let allowed = (0..400)
+            .filter(|i| {
+                limiter.allow(
+                    start + Duration::from_millis(*i * 150),
+                    &rate_limit,
+                    Kind::Highlight,
+                    &server,
+                    &bot,
+                    Some("#halloy"),
+                )
+            })
+            .count();
+
+        assert_eq!(allowed, 3);
+
+        let last = start + Duration::from_millis(399 * 150);
+        assert!(limiter
+            .flush(last + Duration::from_secs(29), &rate_limit)
+            .is_empty());
+
+        let flushed = limiter.flush(last + Duration::from_secs(30), &rate_limit);
+        assert_eq!(flushed.len(), 1);
+        assert_eq!(flushed[0].sender, bot);
+        assert_eq!(flushed[0].channel.as_deref(), Some("#halloy"));
+        assert_eq!(flushed[0].count, 397);
+
+        // Limits reset once quiet
+        assert!(limiter.allow(
+            last + Duration::from_secs(31),
+            &rate_limit,
+            Kind::Highlight,
+            &server,
+            &bot,
+            Some("#halloy"),
+        ));
+    }
+
+    #[test]
+    fn buffer_limit_spans_senders() {
+        let rate_limit = rate_limit();
+        let server = Server::from("libera");
+        let now = Instant::now();
+
+        let mut limiter = Limiter::default();
+        let allowed = ["a", "b", "c", "d", "e", "f", "g"]
+            .into_iter()
+            .filter(|nick| {
+                limiter.allow(
+                    now,
+                    &rate_limit,
+                    Kind::Highlight,
+                    &server,
+                    &Nick::from(*nick),
+                    Some("#halloy"),
+                )
+            })
+            .count();
+        assert_eq!(allowed, 5);
+
+        // Other buffers and direct messages from other users are unaffected
+        assert!(limiter.allow(
+            now,
+            &rate_limit,
+            Kind::Highlight,
+            &server,
+            &Nick::from("a"),
+            Some("#rust"),
+        ));
+        assert!(limiter.allow(
+            now,
+            &rate_limit,
+            Kind::DirectMessage,
+            &server,
+            &Nick::from("h"),
+            None,
+        ));
+    }
+
+    #[test]
+    fn window_slides() {
+        let rate_limit = rate_limit();
+        let server = Server::from("libera");
+        let nick = Nick::from("casper");
+        let start = Instant::now();
+
+        let mut limiter = Limiter::default();
+        let mut allow = |secs| {
+            limiter.allow(
+                start + Duration::from_secs(secs),
+                &rate_limit,
+                Kind::DirectMessage,
+                &server,
+                &nick,
+                None,
+            )
+        };
+
+        assert!(allow(0));
+        assert!(allow(20));
+        assert!(allow(40));
+        // First message has left the window
+        assert!(allow(61));
+    }
+
+    #[test]
+    fn flapping_connection_is_deduplicated() {
+        let rate_limit = rate_limit();
+        let server = Server::from("libera");
+        let start = Instant::now();
+
+        let mut limiter = Limiter::default();
+        let mut allow = |secs, connection| {
+            limiter.allow_connection(
+                start + Duration::from_secs(secs),
+                &rate_limit,
+                &server,
+                connection,
+            )
+        };
+
+        assert!(allow(0, Connection::Disconnected));
+        assert!(allow(5, Connection::Reconnected));
+        assert!(!allow(10, Connection::Disconnected));
+        assert!(!allow(15, Connection::Reconnected));
+        // Stable for the quiet period
+        assert!(allow(60, Connection::Disconnected));
+    }
+}
@@ -0,0 +1,224 @@
+use std::collections::VecDeque;
+
+use chrono::{DateTime, Utc};
+use irc::proto::{self, format, Command};
+
+use crate::Server;
+
+/// Max # lines kept in the console, across all servers
+const MAX_LINES: usize = 5_000;
+
+const REDACTED: &str = "<redacted>";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Sent,
+    Received,
+}
+
+impl Direction {
+    pub fn marker(&self) -> &'static str {
+        match self {
+            Direction::Sent => ">>",
+            Direction::Received => "<<",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Line {
+    pub server: Server,
+    pub timestamp: DateTime<Utc>,
+    pub direction: Direction,
+    pub text: String,
+}
+
+impl Line {
+    pub fn new(server: Server, direction: Direction, message: &proto::Message) -> Self {
+        Self {
+            server,
+            timestamp: Utc::now(),
+            direction,
+            text: line(message),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Log {
+    lines: VecDeque<Line>,
+}
+
+impl Log {
+    pub fn push(&mut self, line: Line) {
+        if self.lines.len() >= MAX_LINES {
+            self.lines.pop_front();
+        }
+
+        self.lines.push_back(line);
+    }
+
+    pub fn lines(&self) -> impl DoubleEndedIterator<Item = &Line> {
+        self.lines.iter()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lines.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.lines.clear();
+    }
+}
+
+/// Format `message` as it appears on the wire (without CR-LF), with
+/// credentials redacted
+pub fn line(message: &proto::Message) -> String {
+    let source = message.source.as_ref().map(prefix);
+
+    let formatted = format::message(proto::Message {
+        tags: message.tags.clone(),
+        source: None,
+        command: redact(message.command.clone()),
+    });
+    let formatted = formatted.trim_end_matches("\r\n");
+
+    match source {
+        Some(source) => match formatted.split_once(' ') {
+            Some((tags, rest)) if tags.starts_with('@') => format!("{tags} :{source} {rest}"),
+            _ => format!(":{source} {formatted}"),
+        },
+        None => formatted.to_string(),
+    }
+}
+
+/// `source` as it appears in the prefix of a line, e.g. `nick!user@host`
+pub fn prefix(source: &proto::Source) -> String {
+    match source {
+        proto::Source::Server(server) => server.clone(),
+        proto::Source::User(user) => {
+            let mut source = user.nickname.clone();
+
+            if let Some(username) = &user.username {
+                source.push('!');
+                source.push_str(username);
+            }
+            if let Some(hostname) = &user.hostname {
+                source.push('@');
+                source.push_str(hostname);
+            }
+
+            source
+        }
+    }
+}
+
+fn redact(command: Command) -> Command {
+    match command {
+        Command::PASS(_) => Command::PASS(REDACTED.to_string()),
+        Command::OPER(name, _) => Command::OPER(name, REDACTED.to_string()),
+        Command::JOIN(chanlist, Some(_)) => Command::JOIN(chanlist, Some(REDACTED.to_string())),
+        Command::AUTHENTICATE(param) if !is_authenticate_keyword(&param) => {
+            Command::AUTHENTICATE(REDACTED.to_string())
+        }
+        Command::PRIVMSG(target, text) => match services_login(&text) {
+            Some(keyword) => Command::PRIVMSG(target, format!("{keyword} {REDACTED}")),
+            None => Command::PRIVMSG(target, text),
+        },
+        Command::Raw(raw) => {
+            let (command, params) = raw.split_once(' ').unwrap_or((&raw, ""));
+
+            match command.to_uppercase().as_str() {
+                "PASS" | "AUTHENTICATE" if !is_authenticate_keyword(params.trim()) => {
+                    Command::Raw(format!("{command} {REDACTED}"))
+                }
+                "OPER" => {
+                    let name = params.split_ascii_whitespace().next().unwrap_or_default();
+
+                    Command::Raw(format!("{command} {name} {REDACTED}"))
+                }
+                _ => Command::Raw(raw),
+            }
+        }
+        command => command,
+    }
+}
+
+/// Services login messages (NickServ `IDENTIFY`, Q `AUTH`), returning the
+/// keyword to keep in front of the redacted credentials
+fn services_login(text: &str) -> Option<&str> {
+    let (keyword, rest) = text.split_once(' ')?;
+
+    (["IDENTIFY", "AUTH"]
+        .iter()
+        .any(|login| keyword.eq_ignore_ascii_case(login))
+        && !rest.trim().is_empty())
+    .then_some(keyword)
+}
+
+/// AUTHENTICATE parameters which never carry credentials: the
+/// continuation / abort markers and mechanism names
+fn is_authenticate_keyword(param: &str) -> bool {
+    matches!(param, "+" | "*" | "PLAIN" | "EXTERNAL")
+}
+
+#[cfg(test)]
+mod tests {
+    use irc::proto::{self, command};
+
+    use super::*;
+
+    #[test]
+    fn credentials_are_redacted() {
+        let tests = [
+            (command!("PASS", "hunter2"), "PASS <redacted>"),
+            (
+                command!("JOIN", "#private", "hunter2"),
+                "JOIN #private <redacted>",
+            ),
+            (
+                command!("OPER", "admin", "hunter2"),
+                "OPER admin <redacted>",
+            ),
+            (command!("AUTHENTICATE", "PLAIN"), "AUTHENTICATE PLAIN"),
+            (command!("AUTHENTICATE", "+"), "AUTHENTICATE +"),
+            (
+                command!("AUTHENTICATE", "dXNlcgB1c2VyAGh1bnRlcjI="),
+                "AUTHENTICATE <redacted>",
+            ),
+            (
+                proto::Message::from(Command::Raw("pass hunter2".to_string())),
+                "pass <redacted>",
+            ),
+            (
+                command!("PRIVMSG", "NickServ", "IDENTIFY nick hunter2"),
+                "PRIVMSG NickServ :IDENTIFY <redacted>",
+            ),
+            (
+                command!("PRIVMSG", "Q@CServe.quakenet.org", "auth nick hunter2"),
+                "PRIVMSG Q@CServe.quakenet.org :auth <redacted>",
+            ),
+            (
+                command!("PRIVMSG", "#halloy", "hello"),
+                "PRIVMSG #halloy hello",
+            ),
+        ];
+
+        for (message, expected) in tests {
+            assert_eq!(line(&message), expected);
+        }
+    }
+
+    #[test]
+    fn source_follows_tags() {
+        let message = proto::parse::message(
+            "@time=2024-01-01T00:00:00.000Z :nick!user@host PRIVMSG #halloy :hello world\r\n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            line(&message),
+            "@time=2024-01-01T00:00:00.000Z :nick!user@host PRIVMSG #halloy :hello world"
+        );
+    }
+}
@@ -1,5 +1,5 @@
 use std::collections::BTreeMap;
-use std::{fmt, str};
+use std::{fmt, iter, str};
 use tokio::fs;
 use tokio::process::Command;
 
@@ -8,6 +8,7 @@ use irc::proto;
 use serde::{Deserialize, Serialize};
 
 use crate::config;
+use crate::config::identity;
 use crate::config::server::Sasl;
 use crate::config::Error;
 
@@ -98,6 +99,41 @@ impl Map {
         self.0.iter().map(Entry::from)
     }
 
+    /// Servers to connect to on startup
+    pub fn autoconnect(&self) -> Self {
+        Self(
+            self.0
+                .iter()
+                .filter(|(_, config)| config.autoconnect)
+                .map(|(server, config)| (server.clone(), config.clone()))
+                .collect(),
+        )
+    }
+
+    /// Fill in the identity of servers from `identity` and check their
+    /// nicknames
+    pub fn inherit_identity(&mut self, identity: &config::Identity) -> Result<(), Error> {
+        for (server, config) in self.0.iter_mut() {
+            config.inherit(identity);
+
+            if config.nickname.is_empty() {
+                return Err(Error::MissingNickname(server.to_string()));
+            }
+
+            if let Some(nickname) = iter::once(&config.nickname)
+                .chain(&config.alt_nicks)
+                .find(|nickname| !identity::is_valid_nickname(nickname))
+            {
+                return Err(Error::InvalidNickname {
+                    server: server.to_string(),
+                    nickname: nickname.clone(),
+                });
+            }
+        }
+
+        Ok(())
+    }
+
     pub async fn read_passwords(&mut self) -> Result<(), Error> {
         for (_, config) in self.0.iter_mut() {
             if let Some(pass_file) = &config.password_file {
@@ -42,6 +42,9 @@ pub enum Command {
     Logs,
     ThemeEditor,
     Highlight,
+    PreviousHighlight,
+    NextHighlight,
+    UndoCloseBuffer,
 }
 
 macro_rules! default {
@@ -132,6 +135,9 @@ impl KeyBind {
     default!(logs, "l", COMMAND);
     default!(theme_editor, "t", COMMAND);
     default!(highlight, "i", COMMAND);
+    default!(previous_highlight, PageUp, ALT);
+    default!(next_highlight, PageDown, ALT);
+    default!(undo_close_buffer, "t", COMMAND | SHIFT);
 
     pub fn is_pressed(
         &self,
@@ -1,8 +1,10 @@
 use chrono::{DateTime, Utc};
 use futures::never::Never;
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::sync::{Arc, Mutex, RwLock};
 use std::time::Duration;
 
-use futures::channel::mpsc;
+use futures::channel::{mpsc, oneshot};
 use futures::{future, stream, FutureExt, SinkExt, StreamExt};
 use irc::proto::{self, command, Command};
 use irc::{codec, connection, Connection};
@@ -11,7 +13,7 @@ use tokio::time::{self, Instant, Interval};
 use crate::client::Client;
 use crate::server::Server;
 use crate::time::Posix;
-use crate::{config, message, server};
+use crate::{config, message, raw_log, server, Config};
 
 pub type Result<T = Update, E = Error> = std::result::Result<T, E>;
 
@@ -40,6 +42,7 @@ pub enum Update {
         sent_time: DateTime<Utc>,
     },
     MessagesReceived(Server, Vec<message::Encoded>),
+    RawLog(raw_log::Line),
     Quit(Server, Option<String>),
 }
 
@@ -69,14 +72,138 @@ struct Stream {
     receiver: mpsc::Receiver<proto::Message>,
 }
 
+/// Settings of the streams of every server. Clones share them, so streams
+/// which are already running see changes from a reloaded config or from
+/// `/raw-log`, e.g. reconnects use the identity of the latest config.
+#[derive(Debug, Clone, Default)]
+pub struct Settings {
+    identities: Arc<RwLock<BTreeMap<Server, config::Identity>>>,
+    /// Servers with raw logging turned on, toggled at runtime with `/raw-log`
+    raw_logged: Arc<RwLock<BTreeSet<Server>>>,
+    /// Servers connecting at the same time, limited from config so a bouncer
+    /// or a slow network isn't flooded with connection attempts on startup
+    connections: Arc<Mutex<Connections>>,
+}
+
+impl Settings {
+    pub fn new(config: &Config) -> Self {
+        let settings = Self::default();
+        settings.update(config);
+        settings
+    }
+
+    /// Apply the settings of a loaded or reloaded `config`
+    pub fn update(&self, config: &Config) {
+        let identities = config
+            .servers
+            .entries()
+            .map(|entry| (entry.server, entry.config.identity()))
+            .collect();
+
+        *self.identities.write().unwrap() = identities;
+
+        let mut connections = self.connections.lock().unwrap();
+
+        connections.limit = config.max_concurrent_connections.filter(|limit| *limit > 0);
+        connections.admit();
+    }
+
+    pub fn set_raw_logged(&self, server: &Server, enabled: bool) {
+        let mut servers = self.raw_logged.write().unwrap();
+
+        if enabled {
+            servers.insert(server.clone());
+        } else {
+            servers.remove(server);
+        }
+    }
+
+    fn is_raw_logged(&self, server: &Server) -> bool {
+        self.raw_logged.read().unwrap().contains(server)
+    }
+
+    fn identity(&self, server: &Server) -> Option<config::Identity> {
+        self.identities.read().unwrap().get(server).cloned()
+    }
+}
+
+#[derive(Debug, Default)]
+struct Connections {
+    limit: Option<usize>,
+    connecting: usize,
+    /// Servers waiting for a free slot, in the order they asked for one
+    waiting: VecDeque<oneshot::Sender<()>>,
+}
+
+impl Connections {
+    fn has_slot(&self) -> bool {
+        self.limit.map_or(true, |limit| self.connecting < limit)
+    }
+
+    fn admit(&mut self) {
+        while self.has_slot() {
+            let Some(waiting) = self.waiting.pop_front() else {
+                break;
+            };
+
+            // Skip servers which stopped waiting
+            if waiting.send(()).is_ok() {
+                self.connecting += 1;
+            }
+        }
+    }
+}
+
+/// Slot to connect in, freed for the next waiting server once dropped
+struct Permit {
+    connections: Arc<Mutex<Connections>>,
+}
+
+impl Permit {
+    async fn acquire(connections: &Arc<Mutex<Connections>>) -> Self {
+        let receiver = {
+            let mut slots = connections.lock().unwrap();
+
+            if slots.waiting.is_empty() && slots.has_slot() {
+                slots.connecting += 1;
+
+                return Permit {
+                    connections: connections.clone(),
+                };
+            }
+
+            let (sender, receiver) = oneshot::channel();
+            slots.waiting.push_back(sender);
+
+            receiver
+        };
+
+        let _ = receiver.await;
+
+        Permit {
+            connections: connections.clone(),
+        }
+    }
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        let mut connections = self.connections.lock().unwrap();
+
+        connections.connecting = connections.connecting.saturating_sub(1);
+        connections.admit();
+    }
+}
+
 pub fn run(
     server: server::Entry,
     proxy: Option<config::Proxy>,
+    settings: Settings,
 ) -> impl futures::Stream<Item = Update> {
     let (sender, receiver) = mpsc::unbounded();
 
     // Spawn to unblock backend from iced stream which has backpressure
-    let runner = stream::once(async { tokio::spawn(_run(server, proxy, sender)).await })
+    let runner = stream::once(async { tokio::spawn(_run(server, proxy, settings, sender)).await })
         .map(|_| unreachable!());
 
     stream::select(receiver, runner)
@@ -85,6 +212,7 @@ pub fn run(
 async fn _run(
     server: server::Entry,
     proxy: Option<config::Proxy>,
+    settings: Settings,
     sender: mpsc::UnboundedSender<Update>,
 ) -> Never {
     let server::Entry { server, config } = server;
@@ -102,6 +230,12 @@ async fn _run(
         sent_time: Utc::now(),
     });
 
+    // Let other servers connect first. Servers which don't connect on
+    // startup connect right away once connected to.
+    if config.autoconnect && config.connect_delay > 0 {
+        time::sleep(Duration::from_secs(config.connect_delay)).await;
+    }
+
     loop {
         match &mut state {
             State::Disconnected { last_retry } => {
@@ -113,7 +247,24 @@ async fn _run(
                     }
                 }
 
-                match connect(server.clone(), config.clone(), proxy.clone()).await {
+                let permit = Permit::acquire(&settings.connections).await;
+
+                // Stop reconnecting once the app is no longer listening, e.g.
+                // the server was disconnected from while reconnecting
+                if sender.is_closed() {
+                    state = State::Quit;
+                    continue;
+                }
+
+                let config = match settings.identity(&server) {
+                    Some(identity) => config.clone().with_identity(&identity),
+                    None => config.clone(),
+                };
+
+                let connected = connect(server.clone(), config.clone(), proxy.clone()).await;
+                drop(permit);
+
+                match connected {
                     Ok((stream, client)) => {
                         log::info!("[{server}] connected");
 
@@ -179,33 +330,47 @@ async fn _run(
                     select.next().await.expect("stream input")
                 };
 
-                match input {
-                    Input::IrcMessage(Ok(Ok(message))) => match message.command {
-                        proto::Command::PING(token) => {
-                            let _ = stream.connection.send(command!("PONG", token)).await;
-                        }
-                        proto::Command::PONG(_, token) => {
-                            let token = token.unwrap_or_default();
-                            log::trace!("[{server}] pong received: {token}");
+                let log_raw = |direction, message: &proto::Message| {
+                    if settings.is_raw_logged(&server) {
+                        let line = raw_log::Line::new(server.clone(), direction, message);
+                        let _ = sender.unbounded_send(Update::RawLog(line));
+                    }
+                };
 
-                            *ping_timeout = None;
-                        }
-                        proto::Command::ERROR(error) => {
-                            log::warn!("[{server}] disconnected: {error}");
-                            let _ = sender.unbounded_send(Update::Disconnected {
-                                server: server.clone(),
-                                is_initial,
-                                error: Some(error),
-                                sent_time: Utc::now(),
-                            });
-                            state = State::Disconnected {
-                                last_retry: Some(Instant::now()),
-                            };
-                        }
-                        _ => {
-                            batch.messages.push(message.into());
+                match input {
+                    Input::IrcMessage(Ok(Ok(message))) => {
+                        log_raw(raw_log::Direction::Received, &message);
+
+                        match message.command {
+                            proto::Command::PING(token) => {
+                                let pong = command!("PONG", token);
+                                log_raw(raw_log::Direction::Sent, &pong);
+
+                                let _ = stream.connection.send(pong).await;
+                            }
+                            proto::Command::PONG(_, token) => {
+                                let token = token.unwrap_or_default();
+                                log::trace!("[{server}] pong received: {token}");
+
+                                *ping_timeout = None;
+                            }
+                            proto::Command::ERROR(error) => {
+                                log::warn!("[{server}] disconnected: {error}");
+                                let _ = sender.unbounded_send(Update::Disconnected {
+                                    server: server.clone(),
+                                    is_initial,
+                                    error: Some(error),
+                                    sent_time: Utc::now(),
+                                });
+                                state = State::Disconnected {
+                                    last_retry: Some(Instant::now()),
+                                };
+                            }
+                            _ => {
+                                batch.messages.push(message.into());
+                            }
                         }
-                    },
+                    }
                     Input::IrcMessage(Ok(Err(e))) => {
                         log::warn!("message decoding failed: {e}");
                     }
@@ -226,6 +391,8 @@ async fn _run(
                             .unbounded_send(Update::MessagesReceived(server.clone(), messages));
                     }
                     Input::Send(message) => {
+                        log_raw(raw_log::Direction::Sent, &message);
+
                         if let Command::QUIT(reason) = &message.command {
                             let reason = reason.clone();
 
@@ -243,7 +410,10 @@ async fn _run(
                         let now = Posix::now().as_nanos().to_string();
                         log::trace!("[{server}] ping sent: {now}");
 
-                        let _ = stream.connection.send(command!("PING", now)).await;
+                        let ping = command!("PING", now);
+                        log_raw(raw_log::Direction::Sent, &ping);
+
+                        let _ = stream.connection.send(ping).await;
 
                         if ping_timeout.is_none() {
                             *ping_timeout = Some(ping_timeout_interval(config.ping_timeout));
@@ -1,8 +1,97 @@
 use std::time::SystemTime;
 
-use chrono::{DateTime, Utc};
+use chrono::{format::SecondsFormat, DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
+/// How timestamps are written to history metadata
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Format {
+    /// Fixed-width `YYYY-MM-DDTHH:MM:SS.mmmZ`, sorts correctly as text
+    #[default]
+    Canonical,
+    /// RFC3339 with as many fractional digits as needed
+    Rfc3339,
+}
+
+impl Format {
+    pub fn format(self, date_time: &DateTime<Utc>) -> String {
+        match self {
+            Format::Canonical => date_time.to_rfc3339_opts(SecondsFormat::Millis, true),
+            Format::Rfc3339 => date_time.to_rfc3339_opts(SecondsFormat::AutoSi, true),
+        }
+    }
+
+    /// Like [`Format::format`], but rounded up rather than down, so a time
+    /// which bounds others, e.g. a read marker, still does once read back
+    pub fn format_ceil(self, date_time: &DateTime<Utc>) -> String {
+        let sub_millis = date_time.timestamp_subsec_nanos() % 1_000_000;
+
+        match self {
+            Format::Canonical if sub_millis > 0 => {
+                let rounded =
+                    *date_time + chrono::Duration::nanoseconds(i64::from(1_000_000 - sub_millis));
+
+                self.format(&rounded)
+            }
+            _ => self.format(date_time),
+        }
+    }
+}
+
+/// Serde helpers which write timestamps in [`Format::Canonical`]. Any
+/// RFC3339 form is accepted when reading.
+pub mod serde_format {
+    use chrono::{DateTime, Utc};
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    use super::Format;
+
+    pub fn serialize<S: Serializer>(
+        date_time: &DateTime<Utc>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&Format::Canonical.format(date_time))
+    }
+
+    /// See [`Format::format_ceil`]
+    pub fn serialize_ceil<S: Serializer>(
+        date_time: &DateTime<Utc>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&Format::Canonical.format_ceil(date_time))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<DateTime<Utc>, D::Error> {
+        DateTime::<Utc>::deserialize(deserializer)
+    }
+
+    pub mod option {
+        use chrono::{DateTime, Utc};
+        use serde::{Deserialize, Deserializer, Serializer};
+
+        use super::Format;
+
+        pub fn serialize<S: Serializer>(
+            date_time: &Option<DateTime<Utc>>,
+            serializer: S,
+        ) -> Result<S::Ok, S::Error> {
+            match date_time {
+                Some(date_time) => serializer.serialize_some(&Format::Canonical.format(date_time)),
+                None => serializer.serialize_none(),
+            }
+        }
+
+        pub fn deserialize<'de, D: Deserializer<'de>>(
+            deserializer: D,
+        ) -> Result<Option<DateTime<Utc>>, D::Error> {
+            Option::<DateTime<Utc>>::deserialize(deserializer)
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 pub struct Posix(u64);
 
@@ -31,3 +120,62 @@ impl Posix {
         DateTime::from_timestamp(seconds, nanos)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    #[test]
+    fn canonical_format_is_fixed_width() {
+        let whole = Utc.with_ymd_and_hms(2024, 11, 1, 12, 0, 0).unwrap();
+        let fractional = whole + chrono::Duration::nanoseconds(123_456_789);
+
+        assert_eq!(Format::Canonical.format(&whole), "2024-11-01T12:00:00.000Z");
+        assert_eq!(
+            Format::Canonical.format(&fractional),
+            "2024-11-01T12:00:00.123Z"
+        );
+        assert_eq!(Format::Rfc3339.format(&whole), "2024-11-01T12:00:00Z");
+        assert_eq!(
+            Format::Rfc3339.format(&fractional),
+            "2024-11-01T12:00:00.123456789Z"
+        );
+    }
+
+    #[test]
+    fn format_ceil_rounds_up() {
+        let whole = Utc.with_ymd_and_hms(2024, 11, 1, 12, 0, 0).unwrap();
+        let fractional = whole + chrono::Duration::nanoseconds(999_999_001);
+
+        assert_eq!(
+            Format::Canonical.format_ceil(&whole),
+            "2024-11-01T12:00:00.000Z"
+        );
+        assert_eq!(
+            Format::Canonical.format_ceil(&fractional),
+            "2024-11-01T12:00:01.000Z"
+        );
+        assert_eq!(
+            Format::Rfc3339.format_ceil(&fractional),
+            "2024-11-01T12:00:00.999999001Z"
+        );
+    }
+
+    #[test]
+    fn deserialize_accepts_variable_width() {
+        for (input, expected_millis) in [
+            ("\"2024-11-01T12:00:00Z\"", 0),
+            ("\"2024-11-01T12:00:00.5Z\"", 500),
+            ("\"2024-11-01T12:00:00.123456789Z\"", 123),
+            ("\"2024-11-01T13:00:00.250+01:00\"", 250),
+        ] {
+            let mut deserializer = serde_json::Deserializer::from_str(input);
+            let date_time = serde_format::deserialize(&mut deserializer).unwrap();
+
+            assert_eq!(date_time.timestamp_subsec_millis(), expected_millis);
+            assert_eq!(date_time.timestamp(), 1730462400);
+        }
+    }
+}
@@ -0,0 +1,173 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::process::Stdio;
+use std::{io, string};
+
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+
+use crate::message::{self, Direction, Source};
+use crate::{history, Message};
+
+/// Max # translations kept in memory, across all buffers
+const MAX_TRANSLATIONS: usize = 5_000;
+
+/// Translations of incoming messages for the buffers which have automatic
+/// translation turned on. Messages are translated one at a time, in the
+/// order received, and translations are only kept for the session.
+#[derive(Debug, Default)]
+pub struct Translations {
+    enabled: HashSet<history::Kind>,
+    translated: HashMap<message::Hash, String>,
+    /// Order of `translated`, oldest first
+    order: VecDeque<message::Hash>,
+    queue: VecDeque<(message::Hash, String)>,
+    in_flight: bool,
+    failures: usize,
+}
+
+impl Translations {
+    pub fn set_enabled(&mut self, kind: history::Kind, enabled: bool) {
+        if enabled {
+            self.enabled.insert(kind);
+        } else {
+            self.enabled.remove(&kind);
+        }
+    }
+
+    pub fn is_enabled(&self, kind: &history::Kind) -> bool {
+        self.enabled.contains(kind)
+    }
+
+    pub fn get(&self, hash: &message::Hash) -> Option<&str> {
+        self.translated.get(hash).map(String::as_str)
+    }
+
+    /// Queue `message` if it's a message from another user in a buffer
+    /// with translation enabled. Returns the next translation to run, if
+    /// none is running already.
+    pub fn queue(
+        &mut self,
+        kind: &history::Kind,
+        message: &Message,
+    ) -> Option<(message::Hash, String)> {
+        if !self.is_enabled(kind)
+            || !matches!(message.direction, Direction::Received)
+            || !matches!(message.target.source(), Source::User(_))
+        {
+            return None;
+        }
+
+        self.queue
+            .push_back((message.hash, message.content.text().into_owned()));
+
+        self.next()
+    }
+
+    /// Record the result of translating `hash`, returning the next
+    /// translation to run
+    pub fn finish(
+        &mut self,
+        hash: message::Hash,
+        result: Result<String, Error>,
+    ) -> Option<(message::Hash, String)> {
+        self.in_flight = false;
+
+        match result {
+            Ok(translation) => {
+                if self.order.len() >= MAX_TRANSLATIONS {
+                    if let Some(oldest) = self.order.pop_front() {
+                        self.translated.remove(&oldest);
+                    }
+                }
+
+                self.translated.insert(hash, translation);
+                self.order.push_back(hash);
+            }
+            Err(error) => {
+                self.failures += 1;
+
+                log::warn!(
+                    "failed to translate message ({} failed this session): {error}",
+                    self.failures
+                );
+            }
+        }
+
+        self.next()
+    }
+
+    fn next(&mut self) -> Option<(message::Hash, String)> {
+        if self.in_flight {
+            return None;
+        }
+
+        let next = self.queue.pop_front()?;
+        self.in_flight = true;
+
+        Some(next)
+    }
+}
+
+/// Translate `text` by running `command` with it on stdin, the
+/// translation being what the command writes to stdout
+pub async fn translate(command: String, text: String) -> Result<String, Error> {
+    let mut shell = if cfg!(target_os = "windows") {
+        let mut shell = Command::new("cmd");
+        shell.arg("/C");
+        shell
+    } else {
+        let mut shell = Command::new("sh");
+        shell.arg("-c");
+        shell
+    };
+
+    let mut child = shell
+        .arg(command)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()?;
+
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(text.as_bytes()).await?;
+    }
+
+    let output = child.wait_with_output().await?;
+
+    if !output.status.success() {
+        return Err(Error::Command(
+            String::from_utf8_lossy(&output.stderr)
+                .trim_end()
+                .to_string(),
+        ));
+    }
+
+    let translation = String::from_utf8(output.stdout)?.trim_end().to_string();
+
+    if translation.is_empty() {
+        Err(Error::Empty)
+    } else {
+        Ok(translation)
+    }
+}
+
+#[derive(Debug, Clone, thiserror::Error)]
+pub enum Error {
+    #[error("no translation command configured")]
+    NotConfigured,
+    #[error("command failed: {0}")]
+    Command(String),
+    #[error("command returned no translation")]
+    Empty,
+    #[error("{0}")]
+    Io(String),
+    #[error("UTF8 parsing error: {0}")]
+    Utf8(#[from] string::FromUtf8Error),
+}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Self {
+        Self::Io(error.to_string())
+    }
+}
@@ -20,6 +20,8 @@ pub struct User {
     accountname: Option<String>,
     access_levels: HashSet<AccessLevel>,
     away: bool,
+    bot: bool,
+    color_seed: Option<Nick>,
 }
 
 impl PartialEq for User {
@@ -118,6 +120,8 @@ impl<'a> TryFrom<&'a str> for User {
             accountname: None,
             access_levels,
             away: false,
+            bot: false,
+            color_seed: None,
         })
     }
 }
@@ -150,13 +154,17 @@ impl From<Nick> for User {
             accountname: None,
             access_levels: HashSet::default(),
             away: false,
+            bot: false,
+            color_seed: None,
         }
     }
 }
 
 impl User {
     pub fn seed(&self) -> &str {
-        self.as_str()
+        self.color_seed
+            .as_ref()
+            .map_or(self.as_str(), |seed| seed.as_ref())
     }
 
     pub fn display(&self, with_access_levels: bool) -> String {
@@ -174,6 +182,10 @@ impl User {
         self.away
     }
 
+    pub fn is_bot(&self) -> bool {
+        self.bot
+    }
+
     pub fn username(&self) -> Option<&str> {
         self.username.as_deref()
     }
@@ -190,8 +202,23 @@ impl User {
         self.accountname.as_deref()
     }
 
+    /// Renames the user, keeping the color seed of the original nickname so
+    /// the user keeps their color across nick changes.
     pub fn with_nickname(self, nickname: Nick) -> Self {
-        Self { nickname, ..self }
+        let color_seed = self.color_seed.unwrap_or(self.nickname);
+
+        Self {
+            color_seed: (color_seed != nickname).then_some(color_seed),
+            nickname,
+            ..self
+        }
+    }
+
+    pub fn with_color_seed(self, color_seed: Nick) -> Self {
+        Self {
+            color_seed: (color_seed != self.nickname).then_some(color_seed),
+            ..self
+        }
     }
 
     pub fn with_username_and_hostname(self, username: String, hostname: String) -> Self {
@@ -244,6 +271,10 @@ impl User {
         self.away = away;
     }
 
+    pub fn update_bot(&mut self, bot: bool) {
+        self.bot = bot;
+    }
+
     pub fn formatted(&self, user_format: UsernameFormat) -> String {
         let user = self.username();
         let host = self.hostname();
@@ -261,6 +292,61 @@ impl User {
     }
 }
 
+/// How members are written out when copying a channel's member list
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ListFormat {
+    /// Nicknames only
+    #[default]
+    Nicks,
+    /// Nicknames with their access level prefix
+    Prefixed,
+    /// `nick!user@host`, falling back to the nickname when unknown
+    Hostmasks,
+}
+
+impl ListFormat {
+    pub const ALL: [ListFormat; 3] = [
+        ListFormat::Nicks,
+        ListFormat::Prefixed,
+        ListFormat::Hostmasks,
+    ];
+
+    fn format(&self, user: &User) -> String {
+        match self {
+            ListFormat::Nicks => user.nickname().to_string(),
+            ListFormat::Prefixed => user.display(true),
+            ListFormat::Hostmasks => match (user.username(), user.hostname()) {
+                (Some(username), Some(hostname)) => {
+                    format!("{}!{username}@{hostname}", user.nickname())
+                }
+                _ => user.nickname().to_string(),
+            },
+        }
+    }
+}
+
+impl std::str::FromStr for ListFormat {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "nicks" => Ok(ListFormat::Nicks),
+            "prefixes" => Ok(ListFormat::Prefixed),
+            "hostmasks" => Ok(ListFormat::Hostmasks),
+            _ => Err(()),
+        }
+    }
+}
+
+/// One member per line, in the order given
+pub fn member_list<'a>(users: impl IntoIterator<Item = &'a User>, format: ListFormat) -> String {
+    users
+        .into_iter()
+        .map(|user| format.format(user))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
 impl From<proto::User> for User {
     fn from(user: proto::User) -> Self {
         User {
@@ -270,6 +356,8 @@ impl From<proto::User> for User {
             accountname: None,
             access_levels: HashSet::default(),
             away: false,
+            bot: false,
+            color_seed: None,
         }
     }
 }
@@ -425,6 +513,8 @@ mod tests {
                         AccessLevel::Voice,
                     ]),
                     away: false,
+                    bot: false,
+                    color_seed: None,
                 },
                 "+@dan",
             ),
@@ -436,6 +526,8 @@ mod tests {
                     accountname: None,
                     access_levels: HashSet::<AccessLevel>::from([AccessLevel::Oper]),
                     away: false,
+                    bot: false,
+                    color_seed: None,
                 },
                 "@d@n!d@localhost",
             ),
@@ -447,6 +539,8 @@ mod tests {
                     accountname: None,
                     access_levels: HashSet::<AccessLevel>::new(),
                     away: false,
+                    bot: false,
+                    color_seed: None,
                 },
                 "foobar",
             ),
@@ -458,6 +552,8 @@ mod tests {
                     accountname: None,
                     access_levels: HashSet::<AccessLevel>::new(),
                     away: false,
+                    bot: false,
+                    color_seed: None,
                 },
                 "foobar!8a027a9a4a@2201:12f1:2:1162:1242:1fg:he11:abde",
             ),
@@ -472,6 +568,8 @@ mod tests {
                         AccessLevel::Voice,
                     ]),
                     away: false,
+                    bot: false,
+                    color_seed: None,
                 },
                 "+@foobar!~foobar@12.521.212.521",
             ),
@@ -495,6 +593,8 @@ mod tests {
                     accountname: None,
                     access_levels: HashSet::<AccessLevel>::new(),
                     away: false,
+                    bot: false,
+                    color_seed: None,
                 },
             ),
             (
@@ -506,6 +606,8 @@ mod tests {
                     accountname: None,
                     access_levels: HashSet::<AccessLevel>::from([AccessLevel::Oper]),
                     away: false,
+                    bot: false,
+                    color_seed: None,
                 },
             ),
             (
@@ -517,6 +619,8 @@ mod tests {
                     accountname: None,
                     access_levels: HashSet::<AccessLevel>::new(),
                     away: false,
+                    bot: false,
+                    color_seed: None,
                 },
             ),
             (
@@ -528,6 +632,8 @@ mod tests {
                     accountname: None,
                     access_levels: HashSet::<AccessLevel>::new(),
                     away: false,
+                    bot: false,
+                    color_seed: None,
                 },
             ),
             (
@@ -539,6 +645,8 @@ mod tests {
                     accountname: None,
                     access_levels: HashSet::<AccessLevel>::new(),
                     away: false,
+                    bot: false,
+                    color_seed: None,
                 },
             ),
         ];
@@ -562,4 +670,42 @@ mod tests {
             );
         }
     }
+
+    #[test]
+    fn color_seed_follows_nick_changes() {
+        let user = User::try_from("dan!d@localhost").unwrap();
+
+        let user = user.with_nickname("dan_".into());
+        assert_eq!(user.seed(), "dan");
+
+        let user = user.with_nickname("daniel".into());
+        assert_eq!(user.seed(), "dan");
+
+        let user = user.with_nickname("dan".into());
+        assert_eq!(user.seed(), "dan");
+        assert_eq!(user.color_seed, None);
+    }
+
+    #[test]
+    fn member_list_formats() {
+        let users = [
+            User::try_from("@dan!d@localhost").unwrap(),
+            User::try_from("+casper").unwrap(),
+            User::try_from("tarkah!t").unwrap(),
+        ];
+
+        assert_eq!(
+            member_list(&users, ListFormat::Nicks),
+            "dan\ncasper\ntarkah"
+        );
+        assert_eq!(
+            member_list(&users, ListFormat::Prefixed),
+            "@dan\n+casper\ntarkah"
+        );
+        assert_eq!(
+            member_list(&users, ListFormat::Hostmasks),
+            "dan!d@localhost\ncasper\ntarkah"
+        );
+        assert_eq!(member_list(&[], ListFormat::Nicks), "");
+    }
 }
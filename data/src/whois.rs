@@ -0,0 +1,355 @@
+use std::fmt;
+
+use irc::proto::{command::Numeric, Command};
+use itertools::Itertools;
+
+use crate::user::Nick;
+
+/// Condensed `WHOIS` reply of a user, shown at the top of their query as a
+/// quick check of who's messaging us
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Summary {
+    pub account: Option<String>,
+    pub realname: Option<String>,
+    /// Channels of the user which we're also in, once complete
+    pub channels: Vec<String>,
+    pub server: Option<String>,
+    pub secure: bool,
+}
+
+/// State of a `WHOIS` lookup made for a [`Summary`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Lookup {
+    Pending(Summary),
+    Done(Summary),
+    /// The user doesn't exist, e.g. they've gone offline
+    Failed,
+    /// The user has gone offline, with what the server last knew of them
+    Offline(Whowas),
+}
+
+impl Lookup {
+    pub fn is_pending(&self) -> bool {
+        matches!(self, Lookup::Pending(_))
+    }
+
+    pub fn summary(&self) -> Option<&Summary> {
+        match self {
+            Lookup::Done(summary) => Some(summary),
+            Lookup::Pending(_) | Lookup::Failed | Lookup::Offline(_) => None,
+        }
+    }
+
+    pub fn whowas(&self) -> Option<&Whowas> {
+        match self {
+            Lookup::Offline(whowas) => Some(whowas),
+            Lookup::Pending(_) | Lookup::Done(_) | Lookup::Failed => None,
+        }
+    }
+}
+
+/// User a `WHOIS` reply or error is about
+pub fn reply_nick(command: &Command) -> Option<Nick> {
+    use Numeric::*;
+
+    match command {
+        Command::Numeric(
+            RPL_WHOISUSER | RPL_WHOISSERVER | RPL_WHOISCHANNELS | RPL_WHOISACCOUNT
+            | RPL_WHOISSECURE | RPL_WHOISIDLE | RPL_WHOISACTUALLY | RPL_WHOISBOT | RPL_ENDOFWHOIS
+            | ERR_NOSUCHNICK,
+            args,
+        ) => args.get(1).map(|nick| Nick::from(nick.as_str())),
+        _ => None,
+    }
+}
+
+/// User a `WHOWAS` reply or error is about
+pub fn whowas_reply_nick(command: &Command) -> Option<Nick> {
+    use Numeric::*;
+
+    match command {
+        Command::Numeric(
+            RPL_WHOWASUSER | RPL_WHOISSERVER | RPL_ENDOFWHOWAS | ERR_WASNOSUCHNICK,
+            args,
+        ) => args.get(1).map(|nick| Nick::from(nick.as_str())),
+        _ => None,
+    }
+}
+
+impl Summary {
+    /// Record a `WHOIS` reply. Returns the completed lookup once the reply
+    /// ends, keeping only the channels for which `is_shared` holds.
+    pub fn record(
+        mut self,
+        command: &Command,
+        chantypes: &[char],
+        is_shared: impl Fn(&str) -> bool,
+    ) -> Lookup {
+        use Numeric::*;
+
+        match command {
+            Command::Numeric(RPL_WHOISUSER, args) => {
+                self.realname = args.get(5).filter(|realname| !realname.is_empty()).cloned();
+            }
+            Command::Numeric(RPL_WHOISSERVER, args) => {
+                self.server = args.get(2).cloned();
+            }
+            Command::Numeric(RPL_WHOISACCOUNT, args) => {
+                self.account = args.get(2).cloned();
+            }
+            Command::Numeric(RPL_WHOISSECURE, _) => {
+                self.secure = true;
+            }
+            Command::Numeric(RPL_WHOISCHANNELS, args) => {
+                if let Some(channels) = args.get(2) {
+                    // Channels may be prefixed with the user's membership
+                    self.channels
+                        .extend(channels.split_ascii_whitespace().map(|channel| {
+                            channel
+                                .trim_start_matches(|c: char| !chantypes.contains(&c))
+                                .to_string()
+                        }));
+                }
+            }
+            Command::Numeric(ERR_NOSUCHNICK, _) => return Lookup::Failed,
+            Command::Numeric(RPL_ENDOFWHOIS, _) => {
+                self.channels.retain(|channel| is_shared(channel));
+
+                return Lookup::Done(self);
+            }
+            _ => {}
+        }
+
+        Lookup::Pending(self)
+    }
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let account = match &self.account {
+            Some(account) => format!("logged in as {account}"),
+            None => "not logged in".to_string(),
+        };
+        let shared = if self.channels.is_empty() {
+            "no shared channels".to_string()
+        } else {
+            format!("shared: {}", self.channels.iter().join(", "))
+        };
+        let secure = if self.secure { "TLS" } else { "no TLS" };
+
+        let parts = [
+            Some(account),
+            self.realname.clone(),
+            Some(shared),
+            self.server.clone(),
+            Some(secure.to_string()),
+        ]
+        .into_iter()
+        .flatten();
+
+        write!(f, "{}", parts.format(" · "))
+    }
+}
+
+/// What the server last knew of a user who's gone offline, from `WHOWAS`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Whowas {
+    pub userhost: Option<String>,
+    pub realname: Option<String>,
+    pub server: Option<String>,
+    /// When the user signed off, as given by the server
+    pub signed_off: Option<String>,
+}
+
+impl Whowas {
+    /// Record a `WHOWAS` reply, keeping only the most recent entry. Returns
+    /// whether the reply has ended.
+    pub fn record(&mut self, command: &Command) -> bool {
+        use Numeric::*;
+
+        match command {
+            Command::Numeric(RPL_WHOWASUSER, args) if self.userhost.is_none() => {
+                if let (Some(user), Some(host)) = (args.get(2), args.get(3)) {
+                    self.userhost = Some(format!("{user}@{host}"));
+                }
+                self.realname = args.get(5).filter(|realname| !realname.is_empty()).cloned();
+            }
+            Command::Numeric(RPL_WHOISSERVER, args) if self.server.is_none() => {
+                self.server = args.get(2).cloned();
+                self.signed_off = args.get(3).filter(|time| !time.is_empty()).cloned();
+            }
+            Command::Numeric(RPL_ENDOFWHOWAS, _) => return true,
+            _ => {}
+        }
+
+        false
+    }
+
+    /// Whether the server had no history of the user
+    pub fn is_empty(&self) -> bool {
+        self.userhost.is_none() && self.server.is_none()
+    }
+}
+
+impl fmt::Display for Whowas {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let userhost = self
+            .userhost
+            .as_ref()
+            .map(|userhost| format!("last seen as {userhost}"))
+            .unwrap_or_else(|| "last seen".to_string());
+
+        let parts = [
+            Some(userhost),
+            self.realname.clone(),
+            self.server.clone(),
+            self.signed_off
+                .as_ref()
+                .map(|signed_off| format!("signed off {signed_off}")),
+        ]
+        .into_iter()
+        .flatten();
+
+        write!(f, "{}", parts.format(" · "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reply(numeric: Numeric, args: &[&str]) -> Command {
+        Command::Numeric(numeric, args.iter().map(ToString::to_string).collect())
+    }
+
+    #[test]
+    fn summary_from_replies() {
+        use Numeric::*;
+
+        let replies = [
+            reply(
+                RPL_WHOISUSER,
+                &["me", "casper", "~casper", "host", "*", "Casper Storm"],
+            ),
+            reply(
+                RPL_WHOISCHANNELS,
+                &["me", "casper", "@#halloy +#rust #other"],
+            ),
+            reply(
+                RPL_WHOISSERVER,
+                &["me", "casper", "tungsten.libera.chat", "Libera"],
+            ),
+            reply(
+                RPL_WHOISACCOUNT,
+                &["me", "casper", "casperstorm", "is logged in as"],
+            ),
+            reply(
+                RPL_WHOISSECURE,
+                &["me", "casper", "is using a secure connection"],
+            ),
+        ];
+
+        let mut lookup = Lookup::Pending(Summary::default());
+
+        for reply in &replies {
+            assert_eq!(reply_nick(reply), Some(Nick::from("casper")));
+
+            let Lookup::Pending(summary) = lookup else {
+                panic!("lookup ended early");
+            };
+            lookup = summary.record(reply, &['#'], |_| true);
+        }
+
+        let Lookup::Pending(summary) = lookup else {
+            panic!("lookup ended early");
+        };
+        let end = reply(RPL_ENDOFWHOIS, &["me", "casper", "End of /WHOIS list"]);
+        let lookup = summary.record(&end, &['#'], |channel| channel != "#other");
+
+        let summary = lookup.summary().unwrap();
+
+        assert_eq!(summary.channels, vec!["#halloy", "#rust"]);
+        assert_eq!(
+            summary.to_string(),
+            "logged in as casperstorm · Casper Storm · shared: #halloy, #rust · tungsten.libera.chat · TLS"
+        );
+    }
+
+    #[test]
+    fn missing_user_fails() {
+        let error = reply(Numeric::ERR_NOSUCHNICK, &["me", "casper", "No such nick"]);
+
+        assert_eq!(reply_nick(&error), Some(Nick::from("casper")));
+        assert_eq!(
+            Summary::default().record(&error, &['#'], |_| true),
+            Lookup::Failed
+        );
+
+        let privmsg = Command::PRIVMSG("casper".to_string(), "hi".to_string());
+        assert_eq!(reply_nick(&privmsg), None);
+    }
+
+    #[test]
+    fn whowas_from_replies() {
+        use Numeric::*;
+
+        let replies = [
+            reply(
+                RPL_WHOWASUSER,
+                &["me", "casper", "~casper", "host", "*", "Casper Storm"],
+            ),
+            reply(
+                RPL_WHOISSERVER,
+                &[
+                    "me",
+                    "casper",
+                    "tungsten.libera.chat",
+                    "Fri Oct 16 12:00:00 2026",
+                ],
+            ),
+            reply(RPL_WHOWASUSER, &["me", "casper", "old", "host", "*", "Old"]),
+            reply(
+                RPL_WHOISSERVER,
+                &[
+                    "me",
+                    "casper",
+                    "zinc.libera.chat",
+                    "Thu Oct 15 12:00:00 2026",
+                ],
+            ),
+        ];
+
+        let mut whowas = Whowas::default();
+
+        for reply in &replies {
+            assert_eq!(whowas_reply_nick(reply), Some(Nick::from("casper")));
+            assert!(!whowas.record(reply));
+        }
+
+        let end = reply(RPL_ENDOFWHOWAS, &["me", "casper", "End of WHOWAS"]);
+        assert!(whowas.record(&end));
+
+        assert_eq!(
+            whowas.to_string(),
+            "last seen as ~casper@host · Casper Storm · tungsten.libera.chat · signed off Fri Oct 16 12:00:00 2026"
+        );
+    }
+
+    #[test]
+    fn whowas_without_history() {
+        use Numeric::*;
+
+        let mut whowas = Whowas::default();
+
+        let error = reply(
+            ERR_WASNOSUCHNICK,
+            &["me", "casper", "There was no such nickname"],
+        );
+        assert_eq!(whowas_reply_nick(&error), Some(Nick::from("casper")));
+        assert!(!whowas.record(&error));
+
+        let end = reply(RPL_ENDOFWHOWAS, &["me", "casper", "End of WHOWAS"]);
+        assert!(whowas.record(&end));
+        assert!(whowas.is_empty());
+    }
+}
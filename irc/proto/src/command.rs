@@ -396,6 +396,7 @@ pub enum Numeric {
     RPL_NOTOPIC = 331,
     RPL_TOPIC = 332,
     RPL_TOPICWHOTIME = 333,
+    RPL_WHOISBOT = 335,
     RPL_INVITELIST = 336,
     RPL_ENDOFINVITELIST = 337,
     RPL_WHOISACTUALLY = 338,
@@ -542,6 +543,7 @@ impl TryFrom<u16> for Numeric {
             331 => RPL_NOTOPIC,
             332 => RPL_TOPIC,
             333 => RPL_TOPICWHOTIME,
+            335 => RPL_WHOISBOT,
             336 => RPL_INVITELIST,
             337 => RPL_ENDOFINVITELIST,
             338 => RPL_WHOISACTUALLY,
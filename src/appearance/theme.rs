@@ -51,6 +51,13 @@ impl Theme {
         }
     }
 
+    /// Name of the selected theme, ignoring any preview
+    pub fn name(&self) -> &str {
+        match self {
+            Theme::Selected(selected) | Theme::Preview { selected, .. } => &selected.name,
+        }
+    }
+
     pub fn colors(&self) -> &Colors {
         match self {
             Theme::Selected(selected) => &selected.colors,
@@ -109,6 +109,24 @@ pub fn secondary(theme: &Theme, status: Status, selected: bool) -> Style {
     button(foreground, background, background_hover, status)
 }
 
+/// Marker in the strip of highlights beside a channel's scrollbar
+pub fn mention(theme: &Theme, status: Status, own: bool) -> Style {
+    let color = match status {
+        Status::Hovered => theme.colors().text.primary,
+        _ if own => theme.colors().text.tertiary,
+        _ => theme.colors().general.unread_indicator,
+    };
+
+    Style {
+        background: Some(Background::Color(color)),
+        border: Border {
+            radius: 1.0.into(),
+            ..Default::default()
+        },
+        ..Default::default()
+    }
+}
+
 pub fn bare(_theme: &Theme, status: Status) -> Style {
     match status {
         Status::Active | Status::Pressed | Status::Hovered => Style {
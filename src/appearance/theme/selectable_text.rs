@@ -87,7 +87,8 @@ pub fn nicklist_nickname(theme: &Theme, config: &Config, user: &User) -> Style {
         theme,
         config.buffer.channel.nicklist.color,
         user,
-        config.buffer.away.should_dim_nickname(user.is_away()),
+        config.buffer.away.should_dim_nickname(user.is_away())
+            || config.buffer.bot.should_dim(user.is_bot()),
     )
 }
 
@@ -96,7 +97,8 @@ pub fn nickname(theme: &Theme, config: &Config, user: &User) -> Style {
         theme,
         config.buffer.channel.message.nickname_color,
         user,
-        config.buffer.away.should_dim_nickname(user.is_away()),
+        config.buffer.away.should_dim_nickname(user.is_away())
+            || config.buffer.bot.should_dim(user.is_bot()),
     )
 }
 
@@ -1,6 +1,7 @@
 pub use data::buffer::{Internal, Settings, Upstream};
+use data::note::Notes;
 use data::user::Nick;
-use data::{buffer, file_transfer, history, message, Config};
+use data::{buffer, command, file_transfer, history, message, Config};
 use iced::Task;
 
 pub use self::channel::Channel;
@@ -8,6 +9,8 @@ pub use self::file_transfers::FileTransfers;
 pub use self::highlights::Highlights;
 pub use self::logs::Logs;
 pub use self::query::Query;
+pub use self::raw_log::RawLog;
+pub use self::scroll_view::Direction;
 pub use self::server::Server;
 use crate::screen::dashboard::sidebar;
 use crate::widget::Element;
@@ -19,7 +22,9 @@ pub mod file_transfers;
 pub mod highlights;
 mod input_view;
 pub mod logs;
+mod note;
 pub mod query;
+pub mod raw_log;
 mod scroll_view;
 pub mod server;
 pub mod user_context;
@@ -33,6 +38,7 @@ pub enum Buffer {
     FileTransfers(FileTransfers),
     Logs(Logs),
     Highlights(Highlights),
+    RawLog(RawLog),
 }
 
 #[derive(Debug, Clone)]
@@ -50,7 +56,16 @@ pub enum Event {
     OpenChannel(String),
     GoToMessage(data::Server, String, message::Hash),
     History(Task<history::manager::Message>),
+    Clear(command::Clear),
+    RawLog(bool),
+    MarkRead(bool),
+    AutoTranslate(bool),
+    Profile(Option<String>),
+    Theme(command::Theme),
+    Connection(command::Connection),
+    SetNote(String),
     RequestOlderChatHistory,
+    LoadEvictedHistory,
 }
 
 impl Buffer {
@@ -63,9 +78,11 @@ impl Buffer {
             Buffer::Channel(state) => Some(&state.buffer),
             Buffer::Server(state) => Some(&state.buffer),
             Buffer::Query(state) => Some(&state.buffer),
-            Buffer::Empty | Buffer::FileTransfers(_) | Buffer::Logs(_) | Buffer::Highlights(_) => {
-                None
-            }
+            Buffer::Empty
+            | Buffer::FileTransfers(_)
+            | Buffer::Logs(_)
+            | Buffer::Highlights(_)
+            | Buffer::RawLog(_) => None,
         }
     }
 
@@ -75,6 +92,7 @@ impl Buffer {
             Buffer::FileTransfers(_) => Some(buffer::Internal::FileTransfers),
             Buffer::Logs(_) => Some(buffer::Internal::Logs),
             Buffer::Highlights(_) => Some(buffer::Internal::Highlights),
+            Buffer::RawLog(_) => Some(buffer::Internal::RawLog),
         }
     }
 
@@ -89,6 +107,7 @@ impl Buffer {
             }
             Buffer::Logs(_) => Some(data::Buffer::Internal(buffer::Internal::Logs)),
             Buffer::Highlights(_) => Some(data::Buffer::Internal(buffer::Internal::Highlights)),
+            Buffer::RawLog(_) => Some(data::Buffer::Internal(buffer::Internal::RawLog)),
         }
     }
 
@@ -98,16 +117,26 @@ impl Buffer {
         clients: &mut data::client::Map,
         history: &mut history::Manager,
         file_transfers: &mut file_transfer::Manager,
+        notes: &Notes,
         config: &Config,
     ) -> (Task<Message>, Option<Event>) {
         match (self, message) {
             (Buffer::Channel(state), Message::Channel(message)) => {
-                let (command, event) = state.update(message, clients, history, config);
+                let (command, event) = state.update(message, clients, history, notes, config);
 
                 let event = event.map(|event| match event {
                     channel::Event::UserContext(event) => Event::UserContext(event),
                     channel::Event::OpenChannel(channel) => Event::OpenChannel(channel),
                     channel::Event::History(task) => Event::History(task),
+                    channel::Event::Clear(clear) => Event::Clear(clear),
+                    channel::Event::RawLog(enabled) => Event::RawLog(enabled),
+                    channel::Event::MarkRead(force) => Event::MarkRead(force),
+                    channel::Event::AutoTranslate(enabled) => Event::AutoTranslate(enabled),
+                    channel::Event::Profile(profile) => Event::Profile(profile),
+                    channel::Event::Theme(theme) => Event::Theme(theme),
+                    channel::Event::Connection(connection) => Event::Connection(connection),
+                    channel::Event::SetNote(note) => Event::SetNote(note),
+                    channel::Event::LoadEvictedHistory => Event::LoadEvictedHistory,
                     channel::Event::RequestOlderChatHistory => Event::RequestOlderChatHistory,
                 });
 
@@ -120,17 +149,33 @@ impl Buffer {
                     server::Event::UserContext(event) => Event::UserContext(event),
                     server::Event::OpenChannel(channel) => Event::OpenChannel(channel),
                     server::Event::History(task) => Event::History(task),
+                    server::Event::Clear(clear) => Event::Clear(clear),
+                    server::Event::RawLog(enabled) => Event::RawLog(enabled),
+                    server::Event::MarkRead(force) => Event::MarkRead(force),
+                    server::Event::Profile(profile) => Event::Profile(profile),
+                    server::Event::Theme(theme) => Event::Theme(theme),
+                    server::Event::Connection(connection) => Event::Connection(connection),
+                    server::Event::LoadEvictedHistory => Event::LoadEvictedHistory,
                 });
 
                 (command.map(Message::Server), event)
             }
             (Buffer::Query(state), Message::Query(message)) => {
-                let (command, event) = state.update(message, clients, history, config);
+                let (command, event) = state.update(message, clients, history, notes, config);
 
                 let event = event.map(|event| match event {
                     query::Event::UserContext(event) => Event::UserContext(event),
                     query::Event::OpenChannel(channel) => Event::OpenChannel(channel),
                     query::Event::History(task) => Event::History(task),
+                    query::Event::Clear(clear) => Event::Clear(clear),
+                    query::Event::RawLog(enabled) => Event::RawLog(enabled),
+                    query::Event::MarkRead(force) => Event::MarkRead(force),
+                    query::Event::AutoTranslate(enabled) => Event::AutoTranslate(enabled),
+                    query::Event::Profile(profile) => Event::Profile(profile),
+                    query::Event::Theme(theme) => Event::Theme(theme),
+                    query::Event::Connection(connection) => Event::Connection(connection),
+                    query::Event::SetNote(note) => Event::SetNote(note),
+                    query::Event::LoadEvictedHistory => Event::LoadEvictedHistory,
                     query::Event::RequestOlderChatHistory => Event::RequestOlderChatHistory,
                 });
 
@@ -148,6 +193,7 @@ impl Buffer {
                     logs::Event::UserContext(event) => Event::UserContext(event),
                     logs::Event::OpenChannel(channel) => Event::OpenChannel(channel),
                     logs::Event::History(task) => Event::History(task),
+                    logs::Event::LoadEvictedHistory => Event::LoadEvictedHistory,
                 });
 
                 (command.map(Message::Logs), event)
@@ -162,6 +208,7 @@ impl Buffer {
                         Event::GoToMessage(server, channel, message)
                     }
                     highlights::Event::History(task) => Event::History(task),
+                    highlights::Event::LoadEvictedHistory => Event::LoadEvictedHistory,
                 });
 
                 (command.map(Message::Highlights), event)
@@ -174,6 +221,9 @@ impl Buffer {
         &'a self,
         clients: &'a data::client::Map,
         file_transfers: &'a file_transfer::Manager,
+        raw_log: &'a data::raw_log::Log,
+        translations: &'a data::translation::Translations,
+        notes: &'a Notes,
         history: &'a history::Manager,
         settings: &'a buffer::Settings,
         config: &'a Config,
@@ -187,6 +237,8 @@ impl Buffer {
                 state,
                 clients,
                 history,
+                translations,
+                notes,
                 &settings.channel,
                 config,
                 theme,
@@ -197,9 +249,17 @@ impl Buffer {
                 server::view(state, clients, history, config, theme, is_focused)
                     .map(Message::Server)
             }
-            Buffer::Query(state) => {
-                query::view(state, clients, history, config, theme, is_focused).map(Message::Query)
-            }
+            Buffer::Query(state) => query::view(
+                state,
+                clients,
+                history,
+                translations,
+                notes,
+                config,
+                theme,
+                is_focused,
+            )
+            .map(Message::Query),
             Buffer::FileTransfers(state) => {
                 file_transfers::view(state, file_transfers).map(Message::FileTransfers)
             }
@@ -207,6 +267,7 @@ impl Buffer {
             Buffer::Highlights(state) => {
                 highlights::view(state, clients, history, config, theme).map(Message::Highlights)
             }
+            Buffer::RawLog(state) => raw_log::view(state, raw_log, config),
         }
     }
 
@@ -232,9 +293,11 @@ impl Buffer {
 
     pub fn focus(&self) -> Task<Message> {
         match self {
-            Buffer::Empty | Buffer::FileTransfers(_) | Buffer::Logs(_) | Buffer::Highlights(_) => {
-                Task::none()
-            }
+            Buffer::Empty
+            | Buffer::FileTransfers(_)
+            | Buffer::Logs(_)
+            | Buffer::Highlights(_)
+            | Buffer::RawLog(_) => Task::none(),
             Buffer::Channel(channel) => channel.focus().map(Message::Channel),
             Buffer::Server(server) => server.focus().map(Message::Server),
             Buffer::Query(query) => query.focus().map(Message::Query),
@@ -243,7 +306,11 @@ impl Buffer {
 
     pub fn reset(&mut self) {
         match self {
-            Buffer::Empty | Buffer::FileTransfers(_) | Buffer::Logs(_) | Buffer::Highlights(_) => {}
+            Buffer::Empty
+            | Buffer::FileTransfers(_)
+            | Buffer::Logs(_)
+            | Buffer::Highlights(_)
+            | Buffer::RawLog(_) => {}
             Buffer::Channel(channel) => channel.reset(),
             Buffer::Server(server) => server.reset(),
             Buffer::Query(query) => query.reset(),
@@ -260,7 +327,8 @@ impl Buffer {
             | Buffer::Server(_)
             | Buffer::FileTransfers(_)
             | Buffer::Logs(_)
-            | Buffer::Highlights(_) => Task::none(),
+            | Buffer::Highlights(_)
+            | Buffer::RawLog(_) => Task::none(),
             Buffer::Channel(state) => state
                 .input_view
                 .insert_user(nick, state.buffer.clone(), history)
@@ -272,9 +340,32 @@ impl Buffer {
         }
     }
 
+    /// Ask for the key of `channel` in the text input
+    pub fn prompt_channel_key(&mut self, channel: String) -> Task<Message> {
+        match self {
+            Buffer::Empty
+            | Buffer::FileTransfers(_)
+            | Buffer::Logs(_)
+            | Buffer::Highlights(_)
+            | Buffer::RawLog(_) => Task::none(),
+            Buffer::Channel(state) => state
+                .input_view
+                .prompt_channel_key(channel)
+                .map(|message| Message::Channel(channel::Message::InputView(message))),
+            Buffer::Server(state) => state
+                .input_view
+                .prompt_channel_key(channel)
+                .map(|message| Message::Server(server::Message::InputView(message))),
+            Buffer::Query(state) => state
+                .input_view
+                .prompt_channel_key(channel)
+                .map(|message| Message::Query(query::Message::InputView(message))),
+        }
+    }
+
     pub fn scroll_to_start(&mut self) -> Task<Message> {
         match self {
-            Buffer::Empty | Buffer::FileTransfers(_) => Task::none(),
+            Buffer::Empty | Buffer::FileTransfers(_) | Buffer::RawLog(_) => Task::none(),
             Buffer::Channel(channel) => channel
                 .scroll_view
                 .scroll_to_start()
@@ -300,7 +391,7 @@ impl Buffer {
 
     pub fn scroll_to_end(&mut self) -> Task<Message> {
         match self {
-            Buffer::Empty | Buffer::FileTransfers(_) => Task::none(),
+            Buffer::Empty | Buffer::FileTransfers(_) | Buffer::RawLog(_) => Task::none(),
             Buffer::Channel(channel) => channel
                 .scroll_view
                 .scroll_to_end()
@@ -331,7 +422,7 @@ impl Buffer {
         config: &Config,
     ) -> Task<Message> {
         match self {
-            Buffer::Empty | Buffer::FileTransfers(_) => Task::none(),
+            Buffer::Empty | Buffer::FileTransfers(_) | Buffer::RawLog(_) => Task::none(),
             Buffer::Channel(state) => state
                 .scroll_view
                 .scroll_to_message(
@@ -376,7 +467,7 @@ impl Buffer {
         config: &Config,
     ) -> Task<Message> {
         match self {
-            Buffer::Empty | Buffer::FileTransfers(_) => Task::none(),
+            Buffer::Empty | Buffer::FileTransfers(_) | Buffer::RawLog(_) => Task::none(),
             Buffer::Channel(state) => state
                 .scroll_view
                 .scroll_to_backlog(
@@ -410,7 +501,7 @@ impl Buffer {
 
     pub fn is_scrolled_to_bottom(&self) -> Option<bool> {
         match self {
-            Buffer::Empty | Buffer::FileTransfers(_) => None,
+            Buffer::Empty | Buffer::FileTransfers(_) | Buffer::RawLog(_) => None,
             Buffer::Channel(channel) => Some(channel.scroll_view.is_scrolled_to_bottom()),
             Buffer::Server(server) => Some(server.scroll_view.is_scrolled_to_bottom()),
             Buffer::Query(query) => Some(query.scroll_view.is_scrolled_to_bottom()),
@@ -418,6 +509,53 @@ impl Buffer {
             Buffer::Highlights(highlights) => Some(highlights.scroll_view.is_scrolled_to_bottom()),
         }
     }
+
+    pub fn pending_scroll_to(&self) -> Option<message::Hash> {
+        match self {
+            Buffer::Empty | Buffer::FileTransfers(_) | Buffer::RawLog(_) => None,
+            Buffer::Channel(channel) => channel.scroll_view.pending_scroll_to(),
+            Buffer::Server(server) => server.scroll_view.pending_scroll_to(),
+            Buffer::Query(query) => query.scroll_view.pending_scroll_to(),
+            Buffer::Logs(log) => log.scroll_view.pending_scroll_to(),
+            Buffer::Highlights(highlights) => highlights.scroll_view.pending_scroll_to(),
+        }
+    }
+
+    /// Scroll to the previous or next highlight of a channel
+    pub fn scroll_to_highlight(
+        &mut self,
+        direction: Direction,
+        history: &history::Manager,
+        config: &Config,
+    ) -> Task<Message> {
+        match self {
+            Buffer::Channel(state) => state
+                .scroll_view
+                .scroll_to_highlight(
+                    direction,
+                    scroll_view::Kind::Channel(&state.server, &state.channel),
+                    history,
+                    config,
+                )
+                .map(|message| Message::Channel(channel::Message::ScrollView(message))),
+            Buffer::Empty
+            | Buffer::Server(_)
+            | Buffer::Query(_)
+            | Buffer::FileTransfers(_)
+            | Buffer::Logs(_)
+            | Buffer::Highlights(_)
+            | Buffer::RawLog(_) => Task::none(),
+        }
+    }
+
+    /// Whether a channel is waiting on older history to continue to its
+    /// previous highlight
+    pub fn pending_highlight(&self) -> bool {
+        match self {
+            Buffer::Channel(channel) => channel.scroll_view.pending_highlight(),
+            _ => false,
+        }
+    }
 }
 
 impl From<data::Buffer> for Buffer {
@@ -434,6 +572,7 @@ impl From<data::Buffer> for Buffer {
                 buffer::Internal::FileTransfers => Self::FileTransfers(FileTransfers::new()),
                 buffer::Internal::Logs => Self::Logs(Logs::new()),
                 buffer::Internal::Highlights => Self::Highlights(Highlights::new()),
+                buffer::Internal::RawLog => Self::RawLog(RawLog::new()),
             },
         }
     }
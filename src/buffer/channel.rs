@@ -1,12 +1,15 @@
+use std::slice;
+
+use data::note::Notes;
 use data::server::Server;
 use data::user::Nick;
 use data::{buffer, User};
-use data::{channel, history, message, Config};
-use iced::widget::{column, container, row};
+use data::{channel, command, history, message, translation, Config};
+use iced::widget::{button, column, container, row, text};
 use iced::{alignment, padding, Length, Task};
 
-use super::{input_view, scroll_view, user_context};
-use crate::widget::{message_content, message_marker, selectable_text, Element};
+use super::{input_view, note, scroll_view, user_context};
+use crate::widget::{bot_badge, message_content, message_marker, selectable_text, Element};
 use crate::{theme, Theme};
 
 mod topic;
@@ -17,19 +20,32 @@ pub enum Message {
     InputView(input_view::Message),
     UserContext(user_context::Message),
     Topic(topic::Message),
+    Note(note::Message),
+    Rejoin,
 }
 
 pub enum Event {
     UserContext(user_context::Event),
     OpenChannel(String),
     History(Task<history::manager::Message>),
+    Clear(command::Clear),
+    RawLog(bool),
+    MarkRead(bool),
+    AutoTranslate(bool),
+    Profile(Option<String>),
+    Theme(command::Theme),
+    Connection(command::Connection),
+    SetNote(String),
     RequestOlderChatHistory,
+    LoadEvictedHistory,
 }
 
 pub fn view<'a>(
     state: &'a Channel,
     clients: &'a data::client::Map,
     history: &'a history::Manager,
+    translations: &'a translation::Translations,
+    notes: &'a Notes,
     settings: &'a channel::Settings,
     config: &'a Config,
     theme: &'a Theme,
@@ -49,6 +65,11 @@ pub fn view<'a>(
 
     let chathistory_state = clients.get_chathistory_state(server, channel);
 
+    let corrections = history.corrections(
+        &history::Kind::Channel(server.clone(), channel.clone()),
+        &config.buffer,
+    );
+
     let messages = container(
         scroll_view::view(
             &state.scroll_view,
@@ -56,7 +77,7 @@ pub fn view<'a>(
             history,
             chathistory_state,
             config,
-            move |message, max_nick_width, max_prefix_width| {
+            move |message, max_nick_width, max_prefix_width, grouped| {
                 let timestamp =
                     config
                         .buffer
@@ -101,6 +122,7 @@ pub fn view<'a>(
                 match message.target.source() {
                     message::Source::User(user) => {
                         let current_user = users.iter().find(|current_user| *current_user == user);
+                        let is_bot = user.is_bot() || current_user.is_some_and(User::is_bot);
 
                         let mut text = selectable_text(
                             config
@@ -124,26 +146,54 @@ pub fn view<'a>(
                             user,
                             current_user,
                             our_user,
+                            message.raw.as_ref(),
                         )
                         .map(scroll_view::Message::UserContext);
 
-                        let message_content = message_content::with_context(
-                            &message.content,
-                            theme,
-                            scroll_view::Message::Link,
-                            theme::selectable_text::default,
-                            move |link| match link {
-                                message::Link::User(_) => user_context::Entry::list(true, our_user),
-                                _ => vec![],
-                            },
-                            move |link, entry, length| match link {
-                                message::Link::User(user) => entry
-                                    .view(server, Some(channel), user, current_user, length)
-                                    .map(scroll_view::Message::UserContext),
-                                _ => row![].into(),
-                            },
-                            config,
-                        );
+                        let content_style: fn(&Theme) -> selectable_text::Style =
+                            if config.buffer.bot.should_dim(is_bot) {
+                                theme::selectable_text::tertiary
+                            } else {
+                                theme::selectable_text::default
+                            };
+
+                        let message_content = match corrections
+                            .and_then(|corrections| corrections.get(&message.hash))
+                        {
+                            Some(correction) => {
+                                message_content::correction(correction, content_style)
+                            }
+                            None => message_content::with_context(
+                                &message.content,
+                                theme,
+                                scroll_view::Message::Link,
+                                content_style,
+                                move |link| match link {
+                                    message::Link::User(_) => {
+                                        user_context::Entry::list(true, our_user)
+                                    }
+                                    _ => vec![],
+                                },
+                                move |link, entry, length| match link {
+                                    message::Link::User(user) => entry
+                                        .view(server, Some(channel), user, current_user, length)
+                                        .map(scroll_view::Message::UserContext),
+                                    _ => row![].into(),
+                                },
+                                config,
+                            ),
+                        };
+                        let message_content = row![]
+                            .push_maybe(config.buffer.bot.should_show_badge(is_bot).then(bot_badge))
+                            .push(message_content);
+                        let message_content = column![message_content]
+                            .push_maybe(message.previous_nickname.as_ref().map(|previous| {
+                                selectable_text(format!("(previously {previous})"))
+                                    .style(theme::selectable_text::tertiary)
+                            }))
+                            .push_maybe(translations.get(&message.hash).map(|translation| {
+                                selectable_text(translation).style(theme::selectable_text::tertiary)
+                            }));
 
                         let timestamp_nickname_row = row![]
                             .push_maybe(timestamp)
@@ -154,13 +204,63 @@ pub fn view<'a>(
                         let text_container =
                             container(message_content).style(move |theme| match our_nick {
                                 Some(nick)
-                                    if message::references_user(user.nickname(), nick, message) =>
+                                    if message::references_user(user.nickname(), nick, message)
+                                        && config.buffer.bot.should_highlight(is_bot) =>
                                 {
                                     theme::container::highlight(theme)
                                 }
                                 _ => Default::default(),
                             });
 
+                        if grouped {
+                            let mut header_chars = 1;
+                            let mut header_width = 0.0;
+
+                            header_chars += config
+                                .buffer
+                                .format_timestamp(&message.server_time)
+                                .map_or(0, |timestamp| timestamp.chars().count());
+
+                            match (message.target.prefixes(), max_prefix_width) {
+                                (Some(_), Some(width)) => header_width += width,
+                                (None, Some(width)) if max_nick_width.is_some() => {
+                                    header_width += width;
+                                }
+                                (Some(prefixes), None) => {
+                                    header_chars += config
+                                        .buffer
+                                        .status_message_prefix
+                                        .brackets
+                                        .format(String::from_iter(prefixes))
+                                        .chars()
+                                        .count()
+                                        + 1;
+                                }
+                                _ => {}
+                            }
+
+                            match max_nick_width {
+                                Some(width) => header_width += width,
+                                None => {
+                                    header_chars += config
+                                        .buffer
+                                        .nickname
+                                        .brackets
+                                        .format(user.display(with_access_levels))
+                                        .chars()
+                                        .count();
+                                }
+                            }
+
+                            return Some(scroll_view::grouped(
+                                text_container,
+                                header_chars,
+                                header_width,
+                                &message.server_time,
+                                config,
+                            ));
+                        }
+
                         match &config.buffer.nickname.alignment {
                             data::buffer::Alignment::Left | data::buffer::Alignment::Right => Some(
                                 row![]
@@ -253,7 +353,9 @@ pub fn view<'a>(
                             .into(),
                         )
                     }
-                    message::Source::Internal(message::source::Internal::Logs) => None,
+                    message::Source::Internal(
+                        message::source::Internal::Logs | message::source::Internal::Session(_),
+                    ) => None,
                 }
             },
         )
@@ -278,17 +380,27 @@ pub fn view<'a>(
     let channels = clients.get_channels(&state.server);
     let is_connected_to_channel = channels.iter().any(|c| c == &state.channel);
 
+    let kick = clients.get_channel_kick(&state.server, &state.channel);
+
+    // Sending is disabled until rejoined, through the kick banner
     let text_input = show_text_input.then(move || {
         input_view::view(
             &state.input_view,
             input,
             is_focused,
-            !is_connected_to_channel,
+            !is_connected_to_channel || kick.is_some(),
         )
         .map(Message::InputView)
     });
 
-    let content = column![topic, messages].spacing(4);
+    // Zero-height placeholder for the same reason as topic
+    let kick_banner = kick.map_or_else(|| column![].into(), kick_banner);
+
+    // Zero-height placeholder for the same reason as topic
+    let note = note::view(&state.note, notes.get(buffer))
+        .map_or_else(|| column![].into(), |note| note.map(Message::Note));
+
+    let content = column![topic, note, kick_banner, messages].spacing(4);
 
     let content = match (
         settings.nicklist.enabled,
@@ -313,6 +425,32 @@ pub fn view<'a>(
         .into()
 }
 
+fn kick_banner(kick: &data::client::Kick) -> Element<'_, Message> {
+    let description = match (&kick.by, &kick.reason) {
+        (Some(by), Some(reason)) => format!("Kicked by {by} ({reason})"),
+        (Some(by), None) => format!("Kicked by {by}"),
+        (None, Some(reason)) => format!("Kicked ({reason})"),
+        (None, None) => "Kicked".to_string(),
+    };
+
+    container(
+        row![
+            text(description)
+                .style(theme::text::error)
+                .width(Length::Fill),
+            button(text("Rejoin"))
+                .padding([4, 8])
+                .style(|theme, status| theme::button::secondary(theme, status, false))
+                .on_press(Message::Rejoin),
+        ]
+        .spacing(8)
+        .align_y(iced::Alignment::Center),
+    )
+    .padding(8)
+    .style(theme::container::tooltip)
+    .into()
+}
+
 #[derive(Debug, Clone)]
 pub struct Channel {
     pub buffer: buffer::Upstream,
@@ -321,6 +459,7 @@ pub struct Channel {
 
     pub scroll_view: scroll_view::State,
     pub input_view: input_view::State,
+    pub note: note::State,
 }
 
 impl Channel {
@@ -331,6 +470,7 @@ impl Channel {
             channel,
             scroll_view: scroll_view::State::new(),
             input_view: input_view::State::new(),
+            note: note::State::default(),
         }
     }
 
@@ -339,6 +479,7 @@ impl Channel {
         message: Message,
         clients: &mut data::client::Map,
         history: &mut history::Manager,
+        notes: &Notes,
         config: &Config,
     ) -> (Task<Message>, Option<Event>) {
         match message {
@@ -347,13 +488,29 @@ impl Channel {
                     .scroll_view
                     .update(message, config.buffer.chathistory.infinite_scroll);
 
+                if let Some(scroll_view::Event::ScrollToMessage(message)) = event {
+                    let scroll_to = self.scroll_view.scroll_to_message(
+                        message,
+                        scroll_view::Kind::Channel(&self.server, &self.channel),
+                        history,
+                        config,
+                    );
+
+                    return (
+                        Task::batch(vec![command, scroll_to]).map(Message::ScrollView),
+                        None,
+                    );
+                }
+
                 let event = event.and_then(|event| match event {
                     scroll_view::Event::UserContext(event) => Some(Event::UserContext(event)),
                     scroll_view::Event::OpenChannel(channel) => Some(Event::OpenChannel(channel)),
-                    scroll_view::Event::GoToMessage(..) => None,
+                    scroll_view::Event::GoToMessage(..)
+                    | scroll_view::Event::ScrollToMessage(_) => None,
                     scroll_view::Event::RequestOlderChatHistory => {
                         Some(Event::RequestOlderChatHistory)
                     }
+                    scroll_view::Event::LoadEvictedHistory => Some(Event::LoadEvictedHistory),
                 });
 
                 (command.map(Message::ScrollView), event)
@@ -373,6 +530,34 @@ impl Channel {
 
                         (command, Some(Event::History(history_task)))
                     }
+                    Some(input_view::Event::Clear(clear)) => (command, Some(Event::Clear(clear))),
+                    Some(input_view::Event::RawLog(enabled)) => {
+                        (command, Some(Event::RawLog(enabled)))
+                    }
+                    Some(input_view::Event::MarkRead(force)) => {
+                        (command, Some(Event::MarkRead(force)))
+                    }
+                    Some(input_view::Event::AutoTranslate(enabled)) => {
+                        (command, Some(Event::AutoTranslate(enabled)))
+                    }
+                    Some(input_view::Event::Profile(profile)) => {
+                        (command, Some(Event::Profile(profile)))
+                    }
+                    Some(input_view::Event::Theme(theme)) => (command, Some(Event::Theme(theme))),
+                    Some(input_view::Event::Connection(connection)) => {
+                        (command, Some(Event::Connection(connection)))
+                    }
+                    Some(input_view::Event::Note(Some(note))) => {
+                        (command, Some(Event::SetNote(note)))
+                    }
+                    Some(input_view::Event::Note(None)) => {
+                        let command = Task::batch(vec![
+                            command,
+                            self.note.edit(notes.get(&self.buffer)).map(Message::Note),
+                        ]);
+
+                        (command, None)
+                    }
                     None => (command, None),
                 }
             }
@@ -387,6 +572,21 @@ impl Channel {
                     topic::Event::OpenChannel(channel) => Event::OpenChannel(channel),
                 }),
             ),
+            Message::Note(message) => {
+                let (command, event) = self.note.update(message, notes.get(&self.buffer));
+
+                (
+                    command.map(Message::Note),
+                    event.map(|event| match event {
+                        note::Event::Save(note) => Event::SetNote(note),
+                    }),
+                )
+            }
+            Message::Rejoin => {
+                clients.join(&self.server, slice::from_ref(&self.channel));
+
+                (Task::none(), None)
+            }
         }
     }
 
@@ -433,7 +633,7 @@ fn topic<'a>(
 
 mod nick_list {
     use data::{config, Config, Server, User};
-    use iced::widget::{column, scrollable, Scrollable};
+    use iced::widget::{column, row, scrollable, Scrollable};
     use iced::{alignment, Length};
     use user_context::Message;
 
@@ -476,7 +676,15 @@ mod nick_list {
                 })
                 .width(Length::Fixed(width));
 
-            user_context::view(content, server, Some(channel), user, Some(user), our_user)
+            let content = row![].push(content).push_maybe(
+                config
+                    .buffer
+                    .bot
+                    .should_show_badge(user.is_bot())
+                    .then(|| selectable_text(" bot").style(theme::selectable_text::tertiary)),
+            );
+
+            user_context::nicklist_view(content, server, channel, user, our_user)
         }));
 
         Scrollable::new(content)
@@ -59,6 +59,7 @@ pub fn view<'a>(
                 user,
                 Some(user),
                 our_user,
+                None,
             )
         } else {
             selectable_text(who)
@@ -1,3 +1,5 @@
+use std::collections::HashSet;
+
 use data::{history, message, Config, Server};
 use iced::widget::{container, row, span};
 use iced::{Length, Task};
@@ -16,6 +18,7 @@ pub enum Event {
     OpenChannel(String),
     GoToMessage(Server, String, message::Hash),
     History(Task<history::manager::Message>),
+    LoadEvictedHistory,
 }
 
 pub fn view<'a>(
@@ -32,7 +35,7 @@ pub fn view<'a>(
             history,
             None,
             config,
-            move |message, _, _| match &message.target {
+            move |message, _, _, _| match &message.target {
                 message::Target::Highlights {
                     server,
                     channel,
@@ -48,17 +51,27 @@ pub fn view<'a>(
                                 selectable_text(timestamp).style(theme::selectable_text::timestamp)
                             });
 
-                    let channel_text = selectable_rich_text::<_, _, (), _, _>(vec![
-                        span(channel).color(theme.colors().buffer.url).link(
-                            message::Link::GoToMessage(
-                                server.clone(),
-                                channel.to_string(),
-                                message.hash,
+                    let channel_spans = if state.unavailable.contains(&message.hash) {
+                        vec![
+                            span(channel).color(theme.colors().text.secondary),
+                            span(" (history no longer available) ")
+                                .color(theme.colors().text.secondary),
+                        ]
+                    } else {
+                        vec![
+                            span(channel).color(theme.colors().buffer.url).link(
+                                message::Link::GoToMessage(
+                                    server.clone(),
+                                    channel.to_string(),
+                                    message.hash,
+                                ),
                             ),
-                        ),
-                        span(" "),
-                    ])
-                    .on_link(scroll_view::Message::Link);
+                            span(" "),
+                        ]
+                    };
+
+                    let channel_text = selectable_rich_text::<_, _, (), _, _>(channel_spans)
+                        .on_link(scroll_view::Message::Link);
 
                     let with_access_levels = config.buffer.nickname.show_access_levels;
 
@@ -73,9 +86,16 @@ pub fn view<'a>(
                     )
                     .style(|theme| theme::selectable_text::nickname(theme, config, user));
 
-                    let nick =
-                        user_context::view(text, server, Some(channel), user, current_user, None)
-                            .map(scroll_view::Message::UserContext);
+                    let nick = user_context::view(
+                        text,
+                        server,
+                        Some(channel),
+                        user,
+                        current_user,
+                        None,
+                        message.raw.as_ref(),
+                    )
+                    .map(scroll_view::Message::UserContext);
 
                     let text = message_content::with_context(
                         &message.content,
@@ -124,6 +144,8 @@ pub fn view<'a>(
 #[derive(Debug, Clone, Default)]
 pub struct Highlights {
     pub scroll_view: scroll_view::State,
+    /// Highlights whose message was pruned from the channel's history
+    unavailable: HashSet<message::Hash>,
 }
 
 impl Highlights {
@@ -131,6 +153,10 @@ impl Highlights {
         Self::default()
     }
 
+    pub fn mark_unavailable(&mut self, message: message::Hash) {
+        self.unavailable.insert(message);
+    }
+
     pub fn update(&mut self, message: Message) -> (Task<Message>, Option<Event>) {
         match message {
             Message::ScrollView(message) => {
@@ -143,6 +169,8 @@ impl Highlights {
                         Some(Event::GoToMessage(server, channel, message))
                     }
                     scroll_view::Event::RequestOlderChatHistory => None,
+                    scroll_view::Event::LoadEvictedHistory => Some(Event::LoadEvictedHistory),
+                    scroll_view::Event::ScrollToMessage(_) => None,
                 });
 
                 (command.map(Message::ScrollView), event)
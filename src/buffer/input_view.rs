@@ -1,10 +1,13 @@
+use data::buffer::EmptyMessages;
 use data::input::{self, Cache, Draft};
 use data::user::Nick;
-use data::{buffer, client, history, Config};
-use iced::widget::{container, row, text, text_input};
+use data::{buffer, client, command, history, isupport, translation, Config};
+use iced::widget::{column, container, text, text_input};
 use iced::Task;
+use itertools::Itertools;
 
 use self::completion::Completion;
+use super::user_context;
 use crate::theme;
 use crate::widget::{anchored_overlay, key_press, Element};
 
@@ -14,6 +17,14 @@ pub enum Event {
     InputSent {
         history_task: Task<history::manager::Message>,
     },
+    Clear(command::Clear),
+    RawLog(bool),
+    AutoTranslate(bool),
+    Profile(Option<String>),
+    Note(Option<String>),
+    Theme(command::Theme),
+    Connection(command::Connection),
+    MarkRead(bool),
 }
 
 #[derive(Debug, Clone)]
@@ -23,6 +34,10 @@ pub enum Message {
     Tab(bool),
     Up,
     Down,
+    Translated(Result<String, translation::Error>),
+    KeyInput(String),
+    SubmitKey,
+    CancelKey,
 }
 
 pub fn view<'a>(
@@ -31,6 +46,10 @@ pub fn view<'a>(
     buffer_focused: bool,
     disabled: bool,
 ) -> Element<'a, Message> {
+    if let Some(prompt) = &state.key_prompt {
+        return key_prompt(state, prompt);
+    }
+
     let style = if state.error.is_some() {
         theme::text_input::error
     } else {
@@ -75,16 +94,36 @@ pub fn view<'a>(
         );
     }
 
-    let overlay = state
-        .error
-        .as_deref()
-        .map(error)
-        .or_else(|| state.completion.view(cache.draft))
-        .unwrap_or_else(|| row![].into());
+    let overlay = state.error.as_deref().map(error).unwrap_or_else(|| {
+        column![]
+            .push_maybe(state.completion.view(cache.draft))
+            .push_maybe(state.text_limit.map(text_limit))
+            .spacing(4)
+            .into()
+    });
 
     anchored_overlay(input, overlay, anchored_overlay::Anchor::AboveTop, 4.0)
 }
 
+/// Masked input for the key of a channel we failed to join. The key is
+/// never stored as a draft or in the input history.
+fn key_prompt<'a>(state: &'a State, prompt: &'a KeyPrompt) -> Element<'a, Message> {
+    let input = text_input(&format!("Key for {}...", prompt.channel), &prompt.key)
+        .on_input(Message::KeyInput)
+        .on_submit(Message::SubmitKey)
+        .id(state.input_id.clone())
+        .secure(true)
+        .padding(8)
+        .style(theme::text_input::primary);
+
+    key_press(
+        input,
+        key_press::Key::Named(key_press::Named::Escape),
+        key_press::Modifiers::default(),
+        Message::CancelKey,
+    )
+}
+
 fn error<'a, 'b, Message: 'a>(error: &'b str) -> Element<'a, Message> {
     container(text(error.to_string()).style(theme::text::error))
         .padding(8)
@@ -92,10 +131,34 @@ fn error<'a, 'b, Message: 'a>(error: &'b str) -> Element<'a, Message> {
         .into()
 }
 
+/// Bytes used of the server's limit on the text being typed
+fn text_limit<'a, Message: 'a>((used, max_len): (usize, u16)) -> Element<'a, Message> {
+    let style = if used > max_len as usize {
+        theme::text::error
+    } else {
+        theme::text::secondary
+    };
+
+    container(text(format!("{used}/{max_len} bytes")).style(style))
+        .padding(8)
+        .style(theme::container::tooltip)
+        .into()
+}
+
+#[derive(Debug, Clone)]
+struct KeyPrompt {
+    channel: String,
+    key: String,
+}
+
 #[derive(Debug, Clone)]
 pub struct State {
     input_id: text_input::Id,
+    key_prompt: Option<KeyPrompt>,
     error: Option<String>,
+    /// Input sent once which must be sent again to confirm it
+    confirm: Option<String>,
+    text_limit: Option<(usize, u16)>,
     completion: Completion,
     selected_history: Option<usize>,
 }
@@ -110,7 +173,10 @@ impl State {
     pub fn new() -> Self {
         Self {
             input_id: text_input::Id::unique(),
+            key_prompt: None,
             error: None,
+            confirm: None,
+            text_limit: None,
             completion: Completion::default(),
             selected_history: None,
         }
@@ -128,6 +194,7 @@ impl State {
             Message::Input(input) => {
                 // Reset error state
                 self.error = None;
+                self.confirm = None;
                 // Reset selected history
                 self.selected_history = None;
 
@@ -138,7 +205,11 @@ impl State {
                 let channels = clients.get_channels(buffer.server());
                 let isupport = clients.get_isupport(buffer.server());
 
-                self.completion.process(&input, users, channels, &isupport);
+                self.completion
+                    .process(&input, users, channels, &config.appearance.all, &isupport);
+
+                self.text_limit = command::limited_text(&input)
+                    .map(|(limit, text)| (text.len(), isupport::text_limit(&isupport, limit)));
 
                 history.record_draft(Draft {
                     buffer: buffer.clone(),
@@ -149,9 +220,11 @@ impl State {
             }
             Message::Send => {
                 let input = history.input(buffer).draft;
+                let confirmed = self.confirm.take().is_some_and(|confirm| confirm == input);
 
                 // Reset error
                 self.error = None;
+                self.text_limit = None;
                 // Reset selected history
                 self.selected_history = None;
 
@@ -162,11 +235,21 @@ impl State {
                 } else if !input.is_empty() {
                     self.completion.reset();
 
+                    if command::is_stray_slash(input) && !confirmed {
+                        self.confirm = Some(input.to_string());
+                        self.error =
+                            Some("not a command, press enter again to send it as text".into());
+                        return (Task::none(), None);
+                    }
+
+                    let draft = input.to_string();
+
                     // Parse input
-                    let input = match input::parse(
+                    let mut input = match input::parse(
                         buffer.clone(),
                         config.buffer.text_input.auto_format,
                         input,
+                        clients.nickname(buffer.server()),
                     ) {
                         Ok(input) => input,
                         Err(error) => {
@@ -175,6 +258,208 @@ impl State {
                         }
                     };
 
+                    if input.is_blank() {
+                        match config.buffer.text_input.empty_messages {
+                            EmptyMessages::Drop => {
+                                history.record_draft(Draft {
+                                    buffer: buffer.clone(),
+                                    text: String::new(),
+                                });
+
+                                return (Task::none(), None);
+                            }
+                            EmptyMessages::Confirm if !confirmed => {
+                                self.confirm = Some(draft);
+                                self.error = Some(
+                                    "message will appear empty, press enter again to send it"
+                                        .into(),
+                                );
+                                return (Task::none(), None);
+                            }
+                            EmptyMessages::Confirm => {}
+                        }
+                    }
+
+                    if let Some(clear) = input.clear() {
+                        history.record_draft(Draft {
+                            buffer: buffer.clone(),
+                            text: String::new(),
+                        });
+
+                        return (Task::none(), Some(Event::Clear(clear)));
+                    }
+
+                    if let Some(enabled) = input.raw_log() {
+                        history.record_draft(Draft {
+                            buffer: buffer.clone(),
+                            text: String::new(),
+                        });
+
+                        return (Task::none(), Some(Event::RawLog(enabled)));
+                    }
+
+                    if let Some(force) = input.mark_read() {
+                        history.record_draft(Draft {
+                            buffer: buffer.clone(),
+                            text: String::new(),
+                        });
+
+                        return (Task::none(), Some(Event::MarkRead(force)));
+                    }
+
+                    if let Some(profile) = input.profile() {
+                        if let Some(name) =
+                            profile.filter(|name| !config.profiles.contains_key(*name))
+                        {
+                            self.error = Some(format!("unknown profile: {name}"));
+                            return (Task::none(), None);
+                        }
+
+                        history.record_draft(Draft {
+                            buffer: buffer.clone(),
+                            text: String::new(),
+                        });
+
+                        return (
+                            Task::none(),
+                            Some(Event::Profile(profile.map(String::from))),
+                        );
+                    }
+
+                    if let Some(theme) = input.theme() {
+                        if let command::Theme::Switch(name) = theme {
+                            if config.appearance.find(name).is_none() {
+                                self.error = Some(format!(
+                                    "unknown theme: {name}, available themes: {}",
+                                    config
+                                        .appearance
+                                        .all
+                                        .iter()
+                                        .map(|theme| theme.name.as_str())
+                                        .join(", ")
+                                ));
+                                return (Task::none(), None);
+                            }
+                        }
+
+                        history.record_draft(Draft {
+                            buffer: buffer.clone(),
+                            text: String::new(),
+                        });
+
+                        return (Task::none(), Some(Event::Theme(theme.clone())));
+                    }
+
+                    if let Some(connection) = input.connection() {
+                        match connection {
+                            command::Connection::Connect(server) => {
+                                if !config.servers.contains(server) {
+                                    self.error = Some(format!("unknown server: {server}"));
+                                    return (Task::none(), None);
+                                }
+
+                                if clients.connected_servers().any(|other| other == server) {
+                                    self.error = Some(format!("already connected to {server}"));
+                                    return (Task::none(), None);
+                                }
+                            }
+                            command::Connection::Disconnect(server) => {
+                                if !config.servers.contains(server)
+                                    && !clients.iter().any(|(other, _)| other == server)
+                                {
+                                    self.error = Some(format!("unknown server: {server}"));
+                                    return (Task::none(), None);
+                                }
+                            }
+                        }
+
+                        history.record_draft(Draft {
+                            buffer: buffer.clone(),
+                            text: String::new(),
+                        });
+
+                        return (Task::none(), Some(Event::Connection(connection.clone())));
+                    }
+
+                    if let Some(note) = input.note() {
+                        if matches!(buffer, buffer::Upstream::Server(_)) {
+                            self.error =
+                                Some("notes are only available in channels and queries".into());
+                            return (Task::none(), None);
+                        }
+
+                        history.record_draft(Draft {
+                            buffer: buffer.clone(),
+                            text: String::new(),
+                        });
+
+                        return (Task::none(), Some(Event::Note(note.map(String::from))));
+                    }
+
+                    if let Some(format) = input.copy_names() {
+                        let buffer::Upstream::Channel(server, channel) = buffer else {
+                            self.error = Some("member lists can only be copied in channels".into());
+                            return (Task::none(), None);
+                        };
+
+                        history.record_draft(Draft {
+                            buffer: buffer.clone(),
+                            text: String::new(),
+                        });
+
+                        return (
+                            user_context::copy_member_list(
+                                clients.get_channel_users(server, channel),
+                                format,
+                            ),
+                            None,
+                        );
+                    }
+
+                    if let Some(translate) = input.translate() {
+                        match translate {
+                            command::Translate::Auto(enabled) => {
+                                if *enabled && config.buffer.translation.command.is_none() {
+                                    self.error =
+                                        Some(translation::Error::NotConfigured.to_string());
+                                    return (Task::none(), None);
+                                }
+
+                                history.record_draft(Draft {
+                                    buffer: buffer.clone(),
+                                    text: String::new(),
+                                });
+
+                                return (Task::none(), Some(Event::AutoTranslate(*enabled)));
+                            }
+                            command::Translate::Text(text) => {
+                                let Some(command) = config.buffer.translation.command.clone()
+                                else {
+                                    self.error =
+                                        Some(translation::Error::NotConfigured.to_string());
+                                    return (Task::none(), None);
+                                };
+
+                                return (
+                                    Task::perform(
+                                        translation::translate(command, text.clone()),
+                                        Message::Translated,
+                                    ),
+                                    None,
+                                );
+                            }
+                        }
+                    }
+
+                    let away_limit = isupport::text_limit(
+                        &clients.get_isupport(buffer.server()),
+                        isupport::TextLimit::Away,
+                    );
+
+                    if input.truncate_away(away_limit) {
+                        self.error = Some(format!("away message truncated to {away_limit} bytes"));
+                    }
+
                     if let Some(encoded) = input.encoded() {
                         clients.send(buffer, encoded);
                     }
@@ -214,6 +499,11 @@ impl State {
             Message::Tab(reverse) => {
                 let input = history.input(buffer).draft;
 
+                if let Some(channel) = buffer.channel() {
+                    self.completion
+                        .expire_users(clients.get_channel_users(buffer.server(), channel));
+                }
+
                 if let Some(entry) = self.completion.tab(reverse) {
                     let new_input = entry.complete_input(input);
 
@@ -247,8 +537,13 @@ impl State {
                     let channels = clients.get_channels(buffer.server());
                     let isupport = clients.get_isupport(buffer.server());
 
-                    self.completion
-                        .process(&new_input, users, channels, &isupport);
+                    self.completion.process(
+                        &new_input,
+                        users,
+                        channels,
+                        &config.appearance.all,
+                        &isupport,
+                    );
 
                     return self.on_completion(buffer, history, new_input);
                 }
@@ -275,14 +570,49 @@ impl State {
                         let channels = clients.get_channels(buffer.server());
                         let isupport = clients.get_isupport(buffer.server());
 
-                        self.completion
-                            .process(&new_input, users, channels, &isupport);
+                        self.completion.process(
+                            &new_input,
+                            users,
+                            channels,
+                            &config.appearance.all,
+                            &isupport,
+                        );
                         new_input
                     };
 
                     return self.on_completion(buffer, history, new_input);
                 }
 
+                (Task::none(), None)
+            }
+            Message::Translated(Ok(translation)) => {
+                // Leave the translation in the input, to be reviewed before sending
+                self.on_completion(buffer, history, translation)
+            }
+            Message::Translated(Err(error)) => {
+                self.error = Some(error.to_string());
+
+                (Task::none(), None)
+            }
+            Message::KeyInput(key) => {
+                if let Some(prompt) = &mut self.key_prompt {
+                    prompt.key = key;
+                }
+
+                (Task::none(), None)
+            }
+            Message::SubmitKey => {
+                if let Some(prompt) = self.key_prompt.take() {
+                    if !prompt.key.is_empty() {
+                        clients.join_with_key(buffer.server(), &prompt.channel, &prompt.key);
+                    }
+                }
+
+                (Task::none(), None)
+            }
+            Message::CancelKey => {
+                self.key_prompt = None;
+
                 (Task::none(), None)
             }
         }
@@ -302,11 +632,22 @@ impl State {
         (text_input::move_cursor_to_end(self.input_id.clone()), None)
     }
 
+    /// Ask for the key of `channel` in place of the input
+    pub fn prompt_channel_key(&mut self, channel: String) -> Task<Message> {
+        self.key_prompt = Some(KeyPrompt {
+            channel,
+            key: String::new(),
+        });
+
+        self.focus()
+    }
+
     pub fn focus(&self) -> Task<Message> {
         text_input::focus(self.input_id.clone())
     }
 
     pub fn reset(&mut self) {
+        self.key_prompt = None;
         self.error = None;
         self.completion = Completion::default();
         self.selected_history = None;
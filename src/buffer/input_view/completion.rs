@@ -29,6 +29,7 @@ impl Completion {
         input: &str,
         users: &[User],
         channels: &[String],
+        themes: &[data::Theme],
         isupport: &HashMap<isupport::Kind, isupport::Parameter>,
     ) {
         let is_command = input.starts_with('/');
@@ -39,7 +40,7 @@ impl Completion {
             // Disallow user completions when selecting a command
             if matches!(self.commands, Commands::Selecting { .. }) {
                 self.text = Text::default();
-            } else {
+            } else if !self.text.process_themes(input, themes) {
                 self.text.process(input, users, channels);
             }
         } else {
@@ -52,6 +53,12 @@ impl Completion {
         self.commands.select().map(Entry::Command)
     }
 
+    /// Drop nickname completions for users no longer in the channel,
+    /// e.g. after a nick change
+    pub fn expire_users(&mut self, users: &[User]) {
+        self.text.expire_users(users);
+    }
+
     pub fn tab(&mut self, reverse: bool) -> Option<Entry> {
         if !self.commands.tab(reverse) {
             self.text.tab(reverse).map(Entry::Text)
@@ -184,6 +191,13 @@ impl Commands {
                             return join_command(channel_len, channel_limits, key_len);
                         }
                     }
+                    "KICK" => {
+                        if let Some(isupport::Parameter::KICKLEN(max_len)) =
+                            isupport.get(&isupport::Kind::KICKLEN)
+                        {
+                            return kick_command(max_len);
+                        }
+                    }
                     "MSG" => {
                         let channel_membership_prefixes = 
                         if let Some(
@@ -622,6 +636,21 @@ impl Text {
             .collect();
     }
 
+    fn expire_users(&mut self, users: &[User]) {
+        if self.prompt.starts_with('#') {
+            return;
+        }
+
+        let selected = self
+            .selected
+            .and_then(|index| self.filtered.get(index).cloned());
+
+        self.filtered
+            .retain(|nick| users.iter().any(|user| user.nickname().as_ref() == nick));
+        self.selected =
+            selected.and_then(|selected| self.filtered.iter().position(|nick| *nick == selected));
+    }
+
     fn process_channels(&mut self, input: &str, channels: &[String]) -> bool {
         let (_, last) = input.rsplit_once(' ').unwrap_or(("", input));
         let Some((_, rest)) = last.split_once('#') else {
@@ -644,6 +673,29 @@ impl Text {
         true
     }
 
+    /// Complete the theme name of `/theme`
+    fn process_themes(&mut self, input: &str, themes: &[data::Theme]) -> bool {
+        let Some((command, name)) = input.split_once(' ') else {
+            return false;
+        };
+
+        if !command.eq_ignore_ascii_case("/theme") || name.contains(' ') {
+            return false;
+        }
+
+        let lower_name = name.to_lowercase();
+
+        self.selected = None;
+        self.prompt = name.to_string();
+        self.filtered = themes
+            .iter()
+            .filter(|theme| theme.name.to_lowercase().starts_with(&lower_name))
+            .map(|theme| theme.name.clone())
+            .collect();
+
+        true
+    }
+
     fn tab(&mut self, reverse: bool) -> Option<String> {
         if !self.filtered.is_empty() {
             if let Some(index) = &mut self.selected {
@@ -782,6 +834,15 @@ static COMMAND_LIST: Lazy<Vec<Command>> = Lazy::new(|| {
             ],
             subcommands: None,
         },
+        Command {
+            title: "UMODE",
+            args: vec![Arg {
+                text: "modestring",
+                optional: true,
+                tooltip: Some(String::from("e.g. +x-w")),
+            }],
+            subcommands: None,
+        },
         Command {
             title: "PART",
             args: vec![
@@ -829,7 +890,9 @@ static COMMAND_LIST: Lazy<Vec<Command>> = Lazy::new(|| {
                 Arg {
                     text: "channels",
                     optional: false,
-                    tooltip: Some(String::from("comma-separated")),
+                    tooltip: Some(String::from(
+                        "comma-separated\nor -copy [nicks|prefixes|hostmasks] to copy this channel's members",
+                    )),
                 },
             ],
             subcommands: None,
@@ -882,6 +945,110 @@ static COMMAND_LIST: Lazy<Vec<Command>> = Lazy::new(|| {
             ],
             subcommands: None,
         },
+        Command {
+            title: "CLEAR",
+            args: vec![Arg {
+                text: "-history",
+                optional: true,
+                tooltip: Some(String::from("also delete the history stored on disk")),
+            }],
+            subcommands: None,
+        },
+        Command {
+            title: "CLEARALL",
+            args: vec![Arg {
+                text: "-history",
+                optional: true,
+                tooltip: Some(String::from("also delete the history stored on disk")),
+            }],
+            subcommands: None,
+        },
+        Command {
+            title: "MARKREAD",
+            args: vec![Arg {
+                text: "--force",
+                optional: true,
+                tooltip: Some(String::from(
+                    "overwrite the read marker, even if it's ahead of the latest message",
+                )),
+            }],
+            subcommands: None,
+        },
+        Command {
+            title: "RAW-LOG",
+            args: vec![Arg {
+                text: "on|off",
+                optional: false,
+                tooltip: Some(String::from("log raw protocol lines for this server")),
+            }],
+            subcommands: None,
+        },
+        Command {
+            title: "TRANSLATE",
+            args: vec![Arg {
+                text: "text|on|off",
+                optional: false,
+                tooltip: Some(String::from(
+                    "text to translate, or on|off to translate incoming messages",
+                )),
+            }],
+            subcommands: None,
+        },
+        Command {
+            title: "THEME",
+            args: vec![Arg {
+                text: "name",
+                optional: true,
+                tooltip: Some(String::from(
+                    "theme to switch to, \"random\" for any other theme or \"save\" to keep the current theme in the config\nomit to preview the installed themes",
+                )),
+            }],
+            subcommands: None,
+        },
+        Command {
+            title: "PROFILE",
+            args: vec![Arg {
+                text: "name",
+                optional: true,
+                tooltip: Some(String::from(
+                    "notification profile to switch to, omit to use the default settings",
+                )),
+            }],
+            subcommands: None,
+        },
+        Command {
+            title: "NOTE",
+            args: vec![Arg {
+                text: "note",
+                optional: true,
+                tooltip: Some(String::from(
+                    "private note on this buffer, omit to edit the current one",
+                )),
+            }],
+            subcommands: None,
+        },
+        Command {
+            title: "CONNECT",
+            args: vec![Arg {
+                text: "server",
+                optional: true,
+                tooltip: Some(String::from(
+                    "configured server to connect to, omit for this buffer's server",
+                )),
+            }],
+            subcommands: None,
+        },
+        Command {
+            title: "DISCONNECT",
+            args: vec![Arg {
+                text: "server",
+                optional: true,
+                tooltip: Some(String::from(
+                    "server to disconnect from, omit for this buffer's server",
+                )),
+            }],
+            subcommands: None,
+        },
     ]
 });
 
@@ -1046,6 +1213,30 @@ static LIST_COMMAND: Lazy<Command> = Lazy::new(|| Command {
     subcommands: None,
 });
 
+fn kick_command(max_len: &u16) -> Command {
+    Command {
+        title: "KICK",
+        args: vec![
+            Arg {
+                text: "channel",
+                optional: false,
+                tooltip: None,
+            },
+            Arg {
+                text: "user",
+                optional: false,
+                tooltip: None,
+            },
+            Arg {
+                text: "comment",
+                optional: true,
+                tooltip: Some(format!("maximum length: {}", max_len)),
+            },
+        ],
+        subcommands: None,
+    }
+}
+
 fn list_command(
     search_extensions: Option<&String>,
     target_limit: Option<&isupport::CommandTargetLimit>,
@@ -1238,6 +1429,9 @@ fn names_command(target_limit: &isupport::CommandTargetLimit) -> Command {
         }
     }
 
+    channels_tooltip
+        .push_str("\nor -copy [nicks|prefixes|hostmasks] to copy this channel's members");
+
     Command {
         title: "NAMES",
         args: vec![Arg {
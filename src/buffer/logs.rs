@@ -15,6 +15,7 @@ pub enum Event {
     UserContext(user_context::Event),
     OpenChannel(String),
     History(Task<history::manager::Message>),
+    LoadEvictedHistory,
 }
 
 pub fn view<'a>(
@@ -30,7 +31,7 @@ pub fn view<'a>(
             history,
             None,
             config,
-            move |message, _, _| match message.target.source() {
+            move |message, _, _, _| match message.target.source() {
                 message::Source::Internal(message::source::Internal::Logs) => Some(
                     container(message_content(
                         &message.content,
@@ -75,6 +76,8 @@ impl Logs {
                     scroll_view::Event::OpenChannel(channel) => Some(Event::OpenChannel(channel)),
                     scroll_view::Event::GoToMessage(_, _, _) => None,
                     scroll_view::Event::RequestOlderChatHistory => None,
+                    scroll_view::Event::LoadEvictedHistory => Some(Event::LoadEvictedHistory),
+                    scroll_view::Event::ScrollToMessage(_) => None,
                 });
 
                 (command.map(Message::ScrollView), event)
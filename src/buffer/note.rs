@@ -0,0 +1,140 @@
+use data::note;
+use iced::widget::{button, column, container, horizontal_rule, row, text, text_input};
+use iced::{Length, Task};
+
+use crate::theme;
+use crate::widget::Element;
+
+/// Max # characters of a note shown while collapsed
+const COLLAPSED_LENGTH: usize = 80;
+
+#[derive(Debug, Clone)]
+pub enum Message {
+    Toggle,
+    Edit,
+    Input(String),
+    Save,
+    Cancel,
+}
+
+pub enum Event {
+    Save(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct State {
+    expanded: bool,
+    editing: Option<String>,
+    input_id: text_input::Id,
+}
+
+impl Default for State {
+    fn default() -> Self {
+        Self {
+            expanded: false,
+            editing: None,
+            input_id: text_input::Id::unique(),
+        }
+    }
+}
+
+impl State {
+    /// Open the editor with the current `note`
+    pub fn edit(&mut self, note: Option<&str>) -> Task<Message> {
+        self.editing = Some(note.unwrap_or_default().to_string());
+
+        text_input::focus(self.input_id.clone())
+    }
+
+    pub fn update(
+        &mut self,
+        message: Message,
+        note: Option<&str>,
+    ) -> (Task<Message>, Option<Event>) {
+        match message {
+            Message::Toggle => {
+                self.expanded = !self.expanded;
+            }
+            Message::Edit => return (self.edit(note), None),
+            Message::Input(input) => {
+                if let Some(editing) = &mut self.editing {
+                    *editing = note::truncate(&input).to_string();
+                }
+            }
+            Message::Save => {
+                if let Some(editing) = self.editing.take() {
+                    return (Task::none(), Some(Event::Save(editing)));
+                }
+            }
+            Message::Cancel => {
+                self.editing = None;
+            }
+        }
+
+        (Task::none(), None)
+    }
+}
+
+/// Header with the buffer's note, `None` when there's no note to show
+pub fn view<'a>(state: &'a State, note: Option<&'a str>) -> Option<Element<'a, Message>> {
+    let content: Element<'a, Message> = if let Some(editing) = &state.editing {
+        row![
+            text_input("Note, only visible to you", editing)
+                .id(state.input_id.clone())
+                .on_input(Message::Input)
+                .on_submit(Message::Save)
+                .padding([4, 8])
+                .style(|theme, status| theme::text_input::primary(theme, status)),
+            text(format!("{}/{}", editing.chars().count(), note::MAX_LENGTH))
+                .style(theme::text::secondary),
+            action_button("Save", Message::Save),
+            action_button("Cancel", Message::Cancel),
+        ]
+        .spacing(8)
+        .align_y(iced::Alignment::Center)
+        .into()
+    } else {
+        let note = note?;
+
+        // Long notes are collapsed, click to show all of it
+        let summary = match note.char_indices().nth(COLLAPSED_LENGTH) {
+            Some((index, _)) if !state.expanded => format!("{}…", &note[..index]),
+            _ => note.to_string(),
+        };
+
+        row![
+            button(
+                text(summary)
+                    .style(theme::text::secondary)
+                    .shaping(text::Shaping::Advanced)
+            )
+            .padding(0)
+            .width(Length::Fill)
+            .style(|theme, status| theme::button::bare(theme, status))
+            .on_press(Message::Toggle),
+            action_button("Edit", Message::Edit),
+        ]
+        .spacing(8)
+        .align_y(iced::Alignment::Center)
+        .into()
+    };
+
+    Some(
+        column![
+            container(content).width(Length::Fill).padding([0, 8]),
+            container(horizontal_rule(1))
+                .width(Length::Fill)
+                .padding([0, 11])
+        ]
+        .spacing(8)
+        .into(),
+    )
+}
+
+fn action_button(label: &str, message: Message) -> Element<'_, Message> {
+    button(text(label))
+        .padding([4, 8])
+        .style(|theme, status| theme::button::secondary(theme, status, false))
+        .on_press(message)
+        .into()
+}
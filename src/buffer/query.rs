@@ -1,29 +1,42 @@
+use data::note::Notes;
 use data::user::Nick;
-use data::{buffer, history, message, Config, Server};
-use iced::widget::{column, container, row, vertical_space};
+use data::{buffer, command, history, message, translation, Config, Server};
+use iced::widget::{column, container, row, text, vertical_space};
 use iced::{alignment, Length, Task};
 
-use super::{input_view, scroll_view, user_context};
-use crate::widget::{message_content, message_marker, selectable_text, Element};
+use super::{input_view, note, scroll_view, user_context};
+use crate::widget::{bot_badge, message_content, message_marker, selectable_text, Element};
 use crate::{theme, Theme};
 
 #[derive(Debug, Clone)]
 pub enum Message {
     ScrollView(scroll_view::Message),
     InputView(input_view::Message),
+    Note(note::Message),
 }
 
 pub enum Event {
     UserContext(user_context::Event),
     OpenChannel(String),
     History(Task<history::manager::Message>),
+    Clear(command::Clear),
+    RawLog(bool),
+    MarkRead(bool),
+    AutoTranslate(bool),
+    Profile(Option<String>),
+    Theme(command::Theme),
+    Connection(command::Connection),
+    SetNote(String),
     RequestOlderChatHistory,
+    LoadEvictedHistory,
 }
 
 pub fn view<'a>(
     state: &'a Query,
     clients: &'a data::client::Map,
     history: &'a history::Manager,
+    translations: &'a translation::Translations,
+    notes: &'a Notes,
     config: &'a Config,
     theme: &'a Theme,
     is_focused: bool,
@@ -35,6 +48,15 @@ pub fn view<'a>(
 
     let chathistory_state = clients.get_chathistory_state(server, state.nick.as_ref());
 
+    let kind = history::Kind::Query(server.clone(), state.nick.clone());
+
+    // Our latest message covered by a read marker relayed by the server
+    let read_up_to = clients
+        .get_peer_read_marker(server, &state.nick)
+        .and_then(|read_marker| history.latest_sent_read(&kind, read_marker));
+
+    let corrections = history.corrections(&kind, &config.buffer);
+
     let messages = container(
         scroll_view::view(
             &state.scroll_view,
@@ -42,7 +64,7 @@ pub fn view<'a>(
             history,
             chathistory_state,
             config,
-            move |message, max_nick_width, _| {
+            move |message, max_nick_width, _, grouped| {
                 let timestamp =
                     config
                         .buffer
@@ -71,36 +93,114 @@ pub fn view<'a>(
                                 .horizontal_alignment(alignment::Horizontal::Right);
                         }
 
-                        let nick = user_context::view(text, server, None, user, None, None)
-                            .map(scroll_view::Message::UserContext);
-
-                        let message = message_content::with_context(
-                            &message.content,
-                            theme,
-                            scroll_view::Message::Link,
-                            theme::selectable_text::default,
-                            move |link| match link {
-                                message::Link::User(_) => user_context::Entry::list(false, None),
-                                _ => vec![],
-                            },
-                            move |link, entry, length| match link {
-                                message::Link::User(user) => entry
-                                    .view(server, None, user, None, length)
-                                    .map(scroll_view::Message::UserContext),
-                                _ => row![].into(),
-                            },
-                            config,
-                        );
+                        let nick = user_context::view(
+                            text,
+                            server,
+                            None,
+                            user,
+                            None,
+                            None,
+                            message.raw.as_ref(),
+                        )
+                        .map(scroll_view::Message::UserContext);
+
+                        let content_style: fn(&Theme) -> selectable_text::Style =
+                            if config.buffer.bot.should_dim(user.is_bot()) {
+                                theme::selectable_text::tertiary
+                            } else {
+                                theme::selectable_text::default
+                            };
+
+                        let content = match corrections
+                            .and_then(|corrections| corrections.get(&message.hash))
+                        {
+                            Some(correction) => {
+                                message_content::correction(correction, content_style)
+                            }
+                            None => message_content::with_context(
+                                &message.content,
+                                theme,
+                                scroll_view::Message::Link,
+                                content_style,
+                                move |link| match link {
+                                    message::Link::User(_) => {
+                                        user_context::Entry::list(false, None)
+                                    }
+                                    _ => vec![],
+                                },
+                                move |link, entry, length| match link {
+                                    message::Link::User(user) => entry
+                                        .view(server, None, user, None, length)
+                                        .map(scroll_view::Message::UserContext),
+                                    _ => row![].into(),
+                                },
+                                config,
+                            ),
+                        };
+                        let content = row![]
+                            .push_maybe(
+                                config
+                                    .buffer
+                                    .bot
+                                    .should_show_badge(user.is_bot())
+                                    .then(bot_badge),
+                            )
+                            .push(content);
+                        let content = column![content]
+                            .push_maybe(message.previous_nickname.as_ref().map(|previous| {
+                                selectable_text(format!("(previously {previous})"))
+                                    .style(theme::selectable_text::tertiary)
+                            }))
+                            .push_maybe(translations.get(&message.hash).map(|translation| {
+                                selectable_text(translation).style(theme::selectable_text::tertiary)
+                            }))
+                            .push_maybe(
+                                (matches!(message.direction, message::Direction::Sent)
+                                    && read_up_to == Some(message.server_time))
+                                .then(|| {
+                                    selectable_text("✓ read")
+                                        .style(theme::selectable_text::tertiary)
+                                }),
+                            );
+
+                        if grouped {
+                            let timestamp_chars = config
+                                .buffer
+                                .format_timestamp(&message.server_time)
+                                .map_or(0, |timestamp| timestamp.chars().count());
+
+                            let (nick_chars, nick_width) = match max_nick_width {
+                                Some(width) => (0, width),
+                                None => (
+                                    config
+                                        .buffer
+                                        .nickname
+                                        .brackets
+                                        .format(user.display(with_access_levels))
+                                        .chars()
+                                        .count(),
+                                    0.0,
+                                ),
+                            };
+
+                            return Some(scroll_view::grouped(
+                                content,
+                                timestamp_chars + nick_chars + 1,
+                                nick_width,
+                                &message.server_time,
+                                config,
+                            ));
+                        }
 
                         let timestamp_nickname_row =
                             row![].push_maybe(timestamp).push(nick).push(space);
 
                         match &config.buffer.nickname.alignment {
                             data::buffer::Alignment::Left | data::buffer::Alignment::Right => {
-                                Some(row![].push(timestamp_nickname_row).push(message).into())
+                                Some(row![].push(timestamp_nickname_row).push(content).into())
                             }
                             data::buffer::Alignment::Top => {
-                                Some(column![].push(timestamp_nickname_row).push(message).into())
+                                Some(column![].push(timestamp_nickname_row).push(content).into())
                             }
                         }
                     }
@@ -178,7 +278,9 @@ pub fn view<'a>(
                             .into(),
                         )
                     }
-                    message::Source::Internal(message::source::Internal::Logs) => None,
+                    message::Source::Internal(
+                        message::source::Internal::Logs | message::source::Internal::Session(_),
+                    ) => None,
                 }
             },
         )
@@ -200,10 +302,44 @@ pub fn view<'a>(
         .width(Length::Fill)
     });
 
-    let scrollable = column![messages]
-        .push_maybe(text_input)
+    // Zero-height placeholder so toggling the note keeps the scroll state
+    let note = note::view(&state.note, notes.get(buffer))
+        .map_or_else(|| column![].into(), |note| note.map(Message::Note));
+
+    let whois = config
+        .buffer
+        .query
+        .whois_summary
+        .then(|| {
+            clients
+                .get_whois_summary(server, &state.nick)
+                .map(ToString::to_string)
+                .or_else(|| {
+                    clients
+                        .get_whowas_summary(server, &state.nick)
+                        .filter(|_| config.buffer.query.whowas_summary)
+                        .map(|whowas| format!("offline, {whowas}"))
+                })
+        })
+        .flatten()
+        .map(|summary| {
+            container(
+                text(summary)
+                    .style(theme::text::secondary)
+                    .shaping(text::Shaping::Advanced),
+            )
+            .padding([0, 8])
+        });
+
+    let content = column![]
+        .push_maybe(whois)
+        .push(note)
+        .push(messages)
+        .spacing(4)
         .height(Length::Fill);
 
+    let scrollable = column![content].push_maybe(text_input).height(Length::Fill);
+
     container(scrollable)
         .width(Length::Fill)
         .height(Length::Fill)
@@ -218,6 +354,7 @@ pub struct Query {
     pub nick: Nick,
     pub scroll_view: scroll_view::State,
     pub input_view: input_view::State,
+    pub note: note::State,
 }
 
 impl Query {
@@ -228,6 +365,7 @@ impl Query {
             nick,
             scroll_view: scroll_view::State::new(),
             input_view: input_view::State::new(),
+            note: note::State::default(),
         }
     }
 
@@ -236,6 +374,7 @@ impl Query {
         message: Message,
         clients: &mut data::client::Map,
         history: &mut history::Manager,
+        notes: &Notes,
         config: &Config,
     ) -> (Task<Message>, Option<Event>) {
         match message {
@@ -251,6 +390,8 @@ impl Query {
                     scroll_view::Event::RequestOlderChatHistory => {
                         Some(Event::RequestOlderChatHistory)
                     }
+                    scroll_view::Event::LoadEvictedHistory => Some(Event::LoadEvictedHistory),
+                    scroll_view::Event::ScrollToMessage(_) => None,
                 });
 
                 (command.map(Message::ScrollView), event)
@@ -270,9 +411,47 @@ impl Query {
 
                         (command, Some(Event::History(history_task)))
                     }
+                    Some(input_view::Event::Clear(clear)) => (command, Some(Event::Clear(clear))),
+                    Some(input_view::Event::RawLog(enabled)) => {
+                        (command, Some(Event::RawLog(enabled)))
+                    }
+                    Some(input_view::Event::MarkRead(force)) => {
+                        (command, Some(Event::MarkRead(force)))
+                    }
+                    Some(input_view::Event::AutoTranslate(enabled)) => {
+                        (command, Some(Event::AutoTranslate(enabled)))
+                    }
+                    Some(input_view::Event::Profile(profile)) => {
+                        (command, Some(Event::Profile(profile)))
+                    }
+                    Some(input_view::Event::Theme(theme)) => (command, Some(Event::Theme(theme))),
+                    Some(input_view::Event::Connection(connection)) => {
+                        (command, Some(Event::Connection(connection)))
+                    }
+                    Some(input_view::Event::Note(Some(note))) => {
+                        (command, Some(Event::SetNote(note)))
+                    }
+                    Some(input_view::Event::Note(None)) => {
+                        let command = Task::batch(vec![
+                            command,
+                            self.note.edit(notes.get(&self.buffer)).map(Message::Note),
+                        ]);
+
+                        (command, None)
+                    }
                     None => (command, None),
                 }
             }
+            Message::Note(message) => {
+                let (command, event) = self.note.update(message, notes.get(&self.buffer));
+
+                (
+                    command.map(Message::Note),
+                    event.map(|event| match event {
+                        note::Event::Save(note) => Event::SetNote(note),
+                    }),
+                )
+            }
         }
     }
 
@@ -0,0 +1,62 @@
+use data::{raw_log, Config};
+use iced::widget::{center, column, container, row, scrollable, text, Scrollable};
+use iced::Length;
+
+use crate::theme;
+use crate::widget::{selectable_text, Element};
+
+pub fn view<'a, Message: 'a>(
+    _state: &RawLog,
+    raw_log: &'a raw_log::Log,
+    config: &'a Config,
+) -> Element<'a, Message> {
+    if raw_log.is_empty() {
+        return center(
+            text("No lines logged, use /raw-log on in a server's buffers to start")
+                .style(theme::text::secondary),
+        )
+        .into();
+    }
+
+    let lines = column(raw_log.lines().map(|line| {
+        let timestamp = config
+            .buffer
+            .format_timestamp(&line.timestamp)
+            .map(|timestamp| selectable_text(timestamp).style(theme::selectable_text::timestamp));
+        let server =
+            selectable_text(format!("{} ", line.server)).style(theme::selectable_text::tertiary);
+        let marker = selectable_text(format!("{} ", line.direction.marker()))
+            .style(theme::selectable_text::tertiary);
+
+        row![]
+            .push_maybe(timestamp)
+            .push(server)
+            .push(marker)
+            .push(selectable_text(line.text.as_str()))
+            .into()
+    }))
+    .spacing(1)
+    .padding([0, 8]);
+
+    container(
+        Scrollable::new(lines)
+            .direction(scrollable::Direction::Vertical(
+                scrollable::Scrollbar::new().width(1).scroller_width(1),
+            ))
+            .anchor_bottom()
+            .style(theme::scrollable::hidden),
+    )
+    .width(Length::Fill)
+    .height(Length::Fill)
+    .padding(8)
+    .into()
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct RawLog;
+
+impl RawLog {
+    pub fn new() -> Self {
+        RawLog
+    }
+}
@@ -1,13 +1,16 @@
-use chrono::{DateTime, Utc};
+use std::collections::HashSet;
+
+use chrono::{DateTime, Local, Utc};
 use data::isupport::ChatHistoryState;
 use data::message::{self, Limit};
 use data::server::Server;
 use data::user::Nick;
 use data::{history, Config};
 use iced::widget::{
-    button, column, container, horizontal_rule, horizontal_space, row, scrollable, text, Scrollable,
+    button, column, container, horizontal_rule, horizontal_space, row, scrollable, text, tooltip,
+    vertical_space, Scrollable, Space,
 };
-use iced::{padding, Length, Task};
+use iced::{padding, Length, Rectangle, Size, Task};
 
 use self::keyed::keyed;
 use super::user_context;
@@ -19,6 +22,7 @@ pub enum Message {
     Scrolled {
         count: usize,
         remaining: bool,
+        has_evicted: bool,
         oldest: DateTime<Utc>,
         status: Status,
         viewport: scrollable::Viewport,
@@ -26,7 +30,11 @@ pub enum Message {
     UserContext(user_context::Message),
     Link(message::Link),
     ScrollTo(keyed::Bounds),
+    Anchored(Option<ScrollAnchor>),
+    RestoreAnchor(ScrollAnchor, keyed::Bounds),
     RequestOlderChatHistory,
+    Mention(message::Hash),
+    LoadOlderHighlights,
 }
 
 #[derive(Debug, Clone)]
@@ -35,6 +43,15 @@ pub enum Event {
     OpenChannel(String),
     GoToMessage(Server, String, message::Hash),
     RequestOlderChatHistory,
+    LoadEvictedHistory,
+    ScrollToMessage(message::Hash),
+}
+
+/// Direction to look for the next highlight in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Previous,
+    Next,
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -66,7 +83,8 @@ pub fn view<'a>(
     history: &'a history::Manager,
     chathistory_state: Option<ChatHistoryState>,
     config: &'a Config,
-    format: impl Fn(&'a data::Message, Option<f32>, Option<f32>) -> Option<Element<'a, Message>> + 'a,
+    format: impl Fn(&'a data::Message, Option<f32>, Option<f32>, bool) -> Option<Element<'a, Message>>
+        + 'a,
 ) -> Element<'a, Message> {
     let Some(history::View {
         total,
@@ -74,11 +92,32 @@ pub fn view<'a>(
         new_messages,
         max_nick_chars,
         max_prefix_chars,
+        has_evicted,
     }) = history.get_messages(&kind.into(), Some(state.limit), &config.buffer)
     else {
         return column![].into();
     };
 
+    let highlights_row = state.offer_older_highlights.then(|| {
+        let font_size = config.font.size.map(f32::from).unwrap_or(theme::TEXT_SIZE) - 1.0;
+
+        row![
+            horizontal_space(),
+            text("No older highlights loaded")
+                .size(font_size)
+                .style(theme::text::secondary),
+            button(text("Load Older History").size(font_size))
+                .padding([3, 5])
+                .style(|theme, status| theme::button::primary(theme, status, false))
+                .on_press(Message::LoadOlderHighlights),
+            horizontal_space(),
+        ]
+        .spacing(8)
+        .padding(padding::top(2).bottom(6))
+        .width(Length::Fill)
+        .align_y(iced::Alignment::Center)
+    });
+
     let top_row = if let Some(chathistory_state) = chathistory_state {
         let (content, message) = match chathistory_state {
             ChatHistoryState::Exhausted => ("No Older Chat History Messages Available", None),
@@ -125,20 +164,48 @@ pub fn view<'a>(
 
     let max_prefix_width = max_prefix_chars.map(|len| font::width_from_chars(len, &config.font));
 
-    let old = old_messages
-        .into_iter()
-        .filter_map(|message| {
-            format(message, max_nick_width, max_prefix_width)
-                .map(|element| keyed(keyed::Key::message(message), element))
-        })
-        .collect::<Vec<_>>();
-    let new = new_messages
-        .into_iter()
-        .filter_map(|message| {
-            format(message, max_nick_width, max_prefix_width)
-                .map(|element| keyed(keyed::Key::message(message), element))
-        })
-        .collect::<Vec<_>>();
+    let mentions = config.buffer.channel.mentions;
+    let strip = (mentions.enabled && matches!(kind, Kind::Channel(..))).then(|| {
+        let highlights = history
+            .highlights(&kind.into())
+            .into_iter()
+            .collect::<HashSet<_>>();
+
+        let markers = old_messages
+            .iter()
+            .chain(&new_messages)
+            .enumerate()
+            .filter_map(|(index, message)| {
+                let own = matches!(message.direction, message::Direction::Sent);
+
+                (highlights.contains(&message.hash) || (own && mentions.show_own_messages))
+                    .then_some((index, message.hash, own))
+            })
+            .collect::<Vec<_>>();
+
+        mention_strip(markers, count)
+    });
+
+    // Groups don't span the divider, so the first unread message
+    // always shows who it's from
+    let format_all = |messages: Vec<&'a data::Message>| {
+        let mut previous = None;
+
+        messages
+            .into_iter()
+            .filter_map(|message| {
+                let grouped = previous
+                    .is_some_and(|previous| config.buffer.grouping.continues(previous, message));
+                previous = Some(message);
+
+                format(message, max_nick_width, max_prefix_width, grouped)
+                    .map(|element| keyed(keyed::Key::message(message), element))
+            })
+            .collect::<Vec<_>>()
+    };
+
+    let old = format_all(old_messages);
+    let new = format_all(new_messages);
 
     let show_divider =
         !new.is_empty() || matches!(status, Status::Idle(Anchor::Bottom) | Status::ScrollTo);
@@ -164,12 +231,13 @@ pub fn view<'a>(
     };
 
     let content = column![]
+        .push_maybe(highlights_row)
         .push_maybe(top_row)
         .push(column(old))
         .push(keyed(keyed::Key::Divider, divider))
         .push(column(new));
 
-    Scrollable::new(container(content).width(Length::Fill).padding([0, 8]))
+    let scrollable = Scrollable::new(container(content).width(Length::Fill).padding([0, 8]))
         .direction(scrollable::Direction::Vertical(
             scrollable::Scrollbar::default()
                 .anchor(status.alignment())
@@ -179,11 +247,56 @@ pub fn view<'a>(
         .on_scroll(move |viewport| Message::Scrolled {
             count,
             remaining,
+            has_evicted,
             oldest,
             status,
             viewport,
         })
         .id(state.scrollable.clone())
+        .width(Length::Fill)
+        .height(Length::Fill);
+
+    row![scrollable].push_maybe(strip).into()
+}
+
+/// Strip of `markers` at their position among `count` messages, which
+/// scroll to their message when clicked. Markers of our own messages are
+/// shown subdued.
+fn mention_strip<'a>(
+    markers: Vec<(usize, message::Hash, bool)>,
+    count: usize,
+) -> Element<'a, Message> {
+    // Space between markers, in proportion to the # messages it spans
+    let gap = |messages: usize| {
+        vertical_space().height(Length::FillPortion(messages.min(u16::MAX as usize) as u16))
+    };
+
+    let mut strip = column![];
+    let mut next = 0;
+
+    for (index, hash, own) in markers {
+        if index > next {
+            strip = strip.push(gap(index - next));
+        }
+
+        strip = strip.push(
+            button(Space::new(Length::Fill, 3))
+                .padding(0)
+                .style(move |theme, status| theme::button::mention(theme, status, own))
+                .on_press(Message::Mention(hash)),
+        );
+
+        next = index + 1;
+    }
+
+    if count > next {
+        strip = strip.push(gap(count - next));
+    }
+
+    container(strip)
+        .width(4)
+        .height(Length::Fill)
+        .padding(padding::top(2).bottom(2))
         .into()
 }
 
@@ -193,6 +306,26 @@ pub struct State {
     limit: Limit,
     status: Status,
     pending_scroll_to: Option<message::Hash>,
+    /// Highlight last navigated to with [`State::scroll_to_highlight`]
+    highlight: Option<message::Hash>,
+    /// Navigated past the oldest highlight while older history is on disk
+    offer_older_highlights: bool,
+    /// Continue to the previous highlight once older history is loaded
+    pending_highlight: bool,
+    /// Message at the top of the viewport while scrolled away from the
+    /// start, scrolled back to once the viewport is resized
+    anchor: Option<ScrollAnchor>,
+}
+
+/// Message at the top of the viewport, kept in view when messages wrap
+/// differently after a resize instead of the offset in pixels
+#[derive(Debug, Clone, Copy)]
+pub struct ScrollAnchor {
+    hash: message::Hash,
+    /// Fraction of the message's height above the viewport
+    progress: f32,
+    /// Size of the viewport the anchor was found in
+    viewport: Size,
 }
 
 impl Default for State {
@@ -202,6 +335,10 @@ impl Default for State {
             limit: Limit::bottom(),
             status: Status::default(),
             pending_scroll_to: None,
+            highlight: None,
+            offer_older_highlights: false,
+            pending_highlight: false,
+            anchor: None,
         }
     }
 }
@@ -220,12 +357,35 @@ impl State {
             Message::Scrolled {
                 count,
                 remaining,
+                has_evicted,
                 oldest,
                 status: old_status,
                 viewport,
             } => {
                 let relative_offset = viewport.relative_offset().y;
 
+                // Messages wrap differently once resized, keep the same one
+                // at the top instead of the same offset
+                let unlocked = matches!(self.status, Status::Unlocked(_));
+
+                if let Some(anchor) = self
+                    .anchor
+                    .as_mut()
+                    .filter(|anchor| unlocked && anchor.viewport != viewport.bounds().size())
+                {
+                    anchor.viewport = viewport.bounds().size();
+                    let anchor = *anchor;
+
+                    return (
+                        keyed::find_bounds(
+                            self.scrollable.clone(),
+                            keyed::Key::Message(anchor.hash),
+                        )
+                        .map(move |bounds| Message::RestoreAnchor(anchor, bounds)),
+                        None,
+                    );
+                }
+
                 match old_status {
                     Status::ScrollTo => {
                         return (Task::none(), None);
@@ -283,9 +443,42 @@ impl State {
                         scrollable::scroll_to(self.scrollable.clone(), new_offset),
                         None,
                     );
+                } else if has_evicted && !remaining && self.status.is_top(relative_offset) {
+                    // Everything in memory is shown, pull older messages back in from disk
+                    return (Task::none(), Some(Event::LoadEvictedHistory));
                 } else if infinite_scroll && self.status.is_top(relative_offset) {
                     return (Task::none(), Some(Event::RequestOlderChatHistory));
                 }
+
+                if matches!(self.status, Status::Unlocked(_)) {
+                    return (
+                        keyed::find_anchor(self.scrollable.clone()).map(Message::Anchored),
+                        None,
+                    );
+                }
+
+                self.anchor = None;
+            }
+            Message::Anchored(anchor) => {
+                if matches!(self.status, Status::Unlocked(_)) {
+                    self.anchor = anchor;
+                }
+            }
+            Message::RestoreAnchor(anchor, bounds) => {
+                let offset = anchored_offset(
+                    bounds.scrollable_bounds,
+                    bounds.hit_bounds,
+                    anchor.progress,
+                    self.status.alignment(),
+                );
+
+                return (
+                    scrollable::scroll_to(
+                        self.scrollable.clone(),
+                        scrollable::AbsoluteOffset { x: 0.0, y: offset },
+                    ),
+                    None,
+                );
             }
             Message::UserContext(message) => {
                 return (
@@ -355,6 +548,17 @@ impl State {
             Message::RequestOlderChatHistory => {
                 return (Task::none(), Some(Event::RequestOlderChatHistory))
             }
+            Message::Mention(message) => {
+                self.highlight = Some(message);
+
+                return (Task::none(), Some(Event::ScrollToMessage(message)));
+            }
+            Message::LoadOlderHighlights => {
+                self.offer_older_highlights = false;
+                self.pending_highlight = true;
+
+                return (Task::none(), Some(Event::LoadEvictedHistory));
+            }
         }
 
         (Task::none(), None)
@@ -409,9 +613,15 @@ impl State {
             .chain(&new_messages)
             .position(|m| m.hash == message)
         else {
+            // Message may have been evicted from memory, retry once
+            // more is loaded back from disk
+            self.pending_scroll_to = history.has_evicted(&kind.into()).then_some(message);
+
             return Task::none();
         };
 
+        self.pending_scroll_to = None;
+
         // Get all messages from bottom until 1 before message
         let offset = total - pos + 1;
 
@@ -422,6 +632,84 @@ impl State {
             .map(Message::ScrollTo)
     }
 
+    /// Message waiting on history to be loaded before it can be scrolled to
+    pub fn pending_scroll_to(&self) -> Option<message::Hash> {
+        self.pending_scroll_to
+    }
+
+    /// Whether we're waiting on older history to continue to the previous
+    /// highlight
+    pub fn pending_highlight(&self) -> bool {
+        self.pending_highlight
+    }
+
+    /// Scroll to the highlight before or after the one last navigated to.
+    /// Going before the oldest highlight in memory offers to load older
+    /// history, going past the latest scrolls to the end.
+    pub fn scroll_to_highlight(
+        &mut self,
+        direction: Direction,
+        kind: Kind,
+        history: &history::Manager,
+        config: &Config,
+    ) -> Task<Message> {
+        self.pending_highlight = false;
+        self.offer_older_highlights = false;
+
+        let Some(history::View {
+            old_messages,
+            new_messages,
+            ..
+        }) = history.get_messages(&kind.into(), None, &config.buffer)
+        else {
+            return Task::none();
+        };
+
+        // Only highlights whose message is loaded can be scrolled to
+        let loaded = old_messages
+            .iter()
+            .chain(&new_messages)
+            .map(|message| message.hash)
+            .collect::<HashSet<_>>();
+        let highlights = history
+            .highlights(&kind.into())
+            .into_iter()
+            .filter(|highlight| loaded.contains(highlight))
+            .collect::<Vec<_>>();
+
+        let current = self.highlight.and_then(|current| {
+            highlights
+                .iter()
+                .position(|highlight| *highlight == current)
+        });
+
+        let target = match (direction, current) {
+            (Direction::Previous, Some(index)) => index.checked_sub(1),
+            (Direction::Previous, None) => highlights.len().checked_sub(1),
+            (Direction::Next, Some(index)) => Some(index + 1),
+            (Direction::Next, None) => None,
+        }
+        .and_then(|index| highlights.get(index).copied());
+
+        match target {
+            Some(highlight) => {
+                self.highlight = Some(highlight);
+
+                self.scroll_to_message(highlight, kind, history, config)
+            }
+            None if direction == Direction::Previous => {
+                self.offer_older_highlights = history.has_evicted(&kind.into());
+
+                Task::none()
+            }
+            None => {
+                self.highlight = None;
+
+                self.scroll_to_end()
+            }
+        }
+    }
+
     pub fn scroll_to_backlog(
         &mut self,
         kind: Kind,
@@ -455,6 +743,76 @@ impl State {
     }
 }
 
+/// Offset from the start of `alignment` scrolling the message laid out in
+/// `hit_bounds` back to the top of the viewport, with `progress` of its
+/// height above it
+fn anchored_offset(
+    scrollable_bounds: keyed::ScrollableBounds,
+    hit_bounds: Rectangle,
+    progress: f32,
+    alignment: scrollable::Anchor,
+) -> f32 {
+    let total_offset =
+        (scrollable_bounds.content.height - scrollable_bounds.viewport.height).max(0.0);
+
+    let absolute = (hit_bounds.y - scrollable_bounds.content.y + progress * hit_bounds.height)
+        .clamp(0.0, total_offset);
+
+    match alignment {
+        scrollable::Anchor::Start => absolute,
+        scrollable::Anchor::End => total_offset - absolute,
+    }
+}
+
+/// First of `messages` laid out below `top`, and the fraction of its height
+/// above it
+fn anchor_at<T>(top: f32, messages: impl IntoIterator<Item = (T, Rectangle)>) -> Option<(T, f32)> {
+    messages.into_iter().find_map(|(key, bounds)| {
+        (bounds.y + bounds.height > top).then(|| {
+            let progress = if bounds.height > 0.0 {
+                ((top - bounds.y) / bounds.height).clamp(0.0, 1.0)
+            } else {
+                0.0
+            };
+
+            (key, progress)
+        })
+    })
+}
+
+/// Lay out a message which continues a group, indented by the width of
+/// the timestamp and nickname shown for the group (`header_chars` plus
+/// `header_width` for any fixed width columns). Its timestamp is shown
+/// when hovered.
+pub fn grouped<'a>(
+    content: impl Into<Element<'a, Message>>,
+    header_chars: usize,
+    header_width: f32,
+    server_time: &DateTime<Utc>,
+    config: &Config,
+) -> Element<'a, Message> {
+    let indent = match config.buffer.nickname.alignment {
+        data::buffer::Alignment::Left | data::buffer::Alignment::Right => {
+            font::width_from_chars(header_chars, &config.font) + header_width
+        }
+        data::buffer::Alignment::Top => 0.0,
+    };
+
+    let timestamp = server_time
+        .with_timezone(&Local)
+        .format("%Y-%m-%d %H:%M:%S")
+        .to_string();
+
+    tooltip(
+        row![Space::with_width(indent), content.into()],
+        container(text(timestamp).style(theme::text::secondary))
+            .style(theme::container::tooltip)
+            .padding(8),
+        tooltip::Position::FollowCursor,
+    )
+    .into()
+}
+
 #[derive(Debug, Clone, Copy)]
 pub enum Status {
     Idle(Anchor),
@@ -683,4 +1041,156 @@ mod keyed {
         })
         .and_then(Task::done)
     }
+
+    /// Find the message at the top of the viewport of `scrollable`
+    pub fn find_anchor(scrollable: scrollable::Id) -> Task<Option<super::ScrollAnchor>> {
+        #[derive(Debug, Clone)]
+        struct State {
+            active: bool,
+            scrollable: scrollable::Id,
+            viewport: Option<(Rectangle, Vector)>,
+            messages: Vec<(message::Hash, Rectangle)>,
+        }
+
+        impl Operation<State> for State {
+            fn scrollable(
+                &mut self,
+                _state: &mut dyn widget::operation::Scrollable,
+                id: Option<&widget::Id>,
+                bounds: Rectangle,
+                _content_bounds: Rectangle,
+                translation: Vector,
+            ) {
+                if id == Some(&self.scrollable.clone().into()) {
+                    self.viewport = Some((bounds, translation));
+                    self.active = true;
+                } else {
+                    self.active = false;
+                }
+            }
+
+            fn container(
+                &mut self,
+                _id: Option<&widget::Id>,
+                _bounds: Rectangle,
+                operate_on_children: &mut dyn FnMut(&mut dyn Operation<State>),
+            ) {
+                operate_on_children(self)
+            }
+
+            fn custom(&mut self, state: &mut dyn std::any::Any, _id: Option<&widget::Id>) {
+                if self.active {
+                    if let Some((Key::Message(hash), bounds)) =
+                        state.downcast_ref::<(Key, Rectangle)>()
+                    {
+                        self.messages.push((*hash, *bounds));
+                    }
+                }
+            }
+
+            fn finish(&self) -> widget::operation::Outcome<State> {
+                widget::operation::Outcome::Some(self.clone())
+            }
+        }
+
+        widget::operate(State {
+            active: false,
+            scrollable,
+            viewport: None,
+            messages: vec![],
+        })
+        .map(|state| {
+            let (viewport, translation) = state.viewport?;
+
+            super::anchor_at(viewport.y + translation.y, state.messages).map(|(hash, progress)| {
+                super::ScrollAnchor {
+                    hash,
+                    progress,
+                    viewport: viewport.size(),
+                }
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use iced::Point;
+
+    use super::*;
+
+    fn bounds(viewport_height: f32, content_height: f32) -> keyed::ScrollableBounds {
+        keyed::ScrollableBounds {
+            viewport: Rectangle::new(Point::new(0.0, 10.0), Size::new(400.0, viewport_height)),
+            content: Rectangle::new(Point::new(0.0, 10.0), Size::new(400.0, content_height)),
+        }
+    }
+
+    /// Messages of `heights` laid out one after another from `y`
+    fn layout(y: f32, heights: &[f32]) -> Vec<(usize, Rectangle)> {
+        heights
+            .iter()
+            .scan(y, |y, height| {
+                let bounds = Rectangle::new(Point::new(0.0, *y), Size::new(400.0, *height));
+                *y += height;
+                Some(bounds)
+            })
+            .enumerate()
+            .collect()
+    }
+
+    #[test]
+    fn anchor_is_first_message_below_top() {
+        let messages = layout(10.0, &[20.0, 40.0, 20.0]);
+
+        assert_eq!(anchor_at(10.0, messages.clone()), Some((0, 0.0)));
+        // Viewport starts a quarter of the way into the second message
+        assert_eq!(anchor_at(40.0, messages.clone()), Some((1, 0.25)));
+        assert_eq!(anchor_at(70.0, messages.clone()), Some((2, 0.0)));
+        assert_eq!(anchor_at(100.0, messages), None);
+    }
+
+    #[test]
+    fn anchor_survives_rewrapping() {
+        // Wide window, messages take a line each except the second
+        let wide = layout(10.0, &[20.0, 40.0, 20.0, 20.0]);
+        let (anchor, progress) = anchor_at(40.0, wide).unwrap();
+
+        // Narrow window, every message wraps onto twice as many lines
+        let narrow = layout(10.0, &[40.0, 80.0, 40.0, 40.0]);
+        let (_, hit) = narrow[anchor];
+
+        let offset = anchored_offset(
+            bounds(100.0, 200.0),
+            hit,
+            progress,
+            scrollable::Anchor::Start,
+        );
+        assert_eq!(offset, 60.0);
+
+        // Scrolled from the bottom, as when unlocked from the end of a buffer
+        let offset = anchored_offset(bounds(100.0, 200.0), hit, progress, scrollable::Anchor::End);
+        assert_eq!(offset, 40.0);
+    }
+
+    #[test]
+    fn anchored_offset_stays_in_content() {
+        let messages = layout(10.0, &[20.0, 20.0, 20.0]);
+        let (_, last) = messages[2];
+
+        // Content shorter than the viewport can't scroll
+        assert_eq!(
+            anchored_offset(bounds(100.0, 60.0), last, 0.5, scrollable::Anchor::Start),
+            0.0
+        );
+        // Can't scroll the last message past the end of the content
+        assert_eq!(
+            anchored_offset(bounds(40.0, 60.0), last, 0.5, scrollable::Anchor::Start),
+            20.0
+        );
+        assert_eq!(
+            anchored_offset(bounds(40.0, 60.0), last, 0.5, scrollable::Anchor::End),
+            0.0
+        );
+    }
 }
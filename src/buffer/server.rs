@@ -1,6 +1,6 @@
-use data::{buffer, history, message, Config};
-use iced::widget::{column, container, row, vertical_space};
-use iced::{Length, Task};
+use data::{buffer, command, history, message, Config};
+use iced::widget::{column, container, horizontal_rule, row, text, vertical_space};
+use iced::{padding, Length, Task};
 
 use super::{input_view, scroll_view, user_context};
 use crate::widget::{message_content, selectable_text, Element};
@@ -16,6 +16,13 @@ pub enum Event {
     UserContext(user_context::Event),
     OpenChannel(String),
     History(Task<history::manager::Message>),
+    Clear(command::Clear),
+    RawLog(bool),
+    MarkRead(bool),
+    Profile(Option<String>),
+    Theme(command::Theme),
+    Connection(command::Connection),
+    LoadEvictedHistory,
 }
 
 pub fn view<'a>(
@@ -37,7 +44,7 @@ pub fn view<'a>(
             history,
             None,
             config,
-            move |message, _, _| {
+            move |message, _, _, _| {
                 let timestamp =
                     config
                         .buffer
@@ -69,6 +76,36 @@ pub fn view<'a>(
 
                         Some(container(row![].push_maybe(timestamp).push(message)).into())
                     }
+                    message::Source::Internal(message::source::Internal::Session(_)) => {
+                        let font_size =
+                            config.font.size.map(f32::from).unwrap_or(theme::TEXT_SIZE) - 1.0;
+
+                        let label = text(format!(
+                            "{}{}",
+                            config
+                                .buffer
+                                .format_timestamp(&message.server_time)
+                                .unwrap_or_default(),
+                            message.plain().unwrap_or_default()
+                        ))
+                        .size(font_size)
+                        .style(theme::text::secondary);
+
+                        Some(
+                            row![
+                                container(horizontal_rule(1))
+                                    .width(Length::Fill)
+                                    .padding(padding::right(6)),
+                                label,
+                                container(horizontal_rule(1))
+                                    .width(Length::Fill)
+                                    .padding(padding::left(6))
+                            ]
+                            .padding(2)
+                            .align_y(iced::Alignment::Center)
+                            .into(),
+                        )
+                    }
                     _ => None,
                 }
             },
@@ -136,6 +173,8 @@ impl Server {
                     scroll_view::Event::OpenChannel(channel) => Some(Event::OpenChannel(channel)),
                     scroll_view::Event::GoToMessage(_, _, _) => None,
                     scroll_view::Event::RequestOlderChatHistory => None,
+                    scroll_view::Event::LoadEvictedHistory => Some(Event::LoadEvictedHistory),
+                    scroll_view::Event::ScrollToMessage(_) => None,
                 });
 
                 (command.map(Message::ScrollView), event)
@@ -154,6 +193,24 @@ impl Server {
                         ]),
                         Some(Event::History(history_task)),
                     ),
+                    Some(input_view::Event::Clear(clear)) => (command, Some(Event::Clear(clear))),
+                    Some(input_view::Event::RawLog(enabled)) => {
+                        (command, Some(Event::RawLog(enabled)))
+                    }
+                    Some(input_view::Event::MarkRead(force)) => {
+                        (command, Some(Event::MarkRead(force)))
+                    }
+                    // Only messages from users are translated
+                    Some(input_view::Event::AutoTranslate(_)) => (command, None),
+                    Some(input_view::Event::Profile(profile)) => {
+                        (command, Some(Event::Profile(profile)))
+                    }
+                    Some(input_view::Event::Theme(theme)) => (command, Some(Event::Theme(theme))),
+                    Some(input_view::Event::Connection(connection)) => {
+                        (command, Some(Event::Connection(connection)))
+                    }
+                    // Rejected by the input for server buffers
+                    Some(input_view::Event::Note(_)) => (command, None),
                     None => (command, None),
                 }
             }
@@ -1,7 +1,7 @@
-use data::user::Nick;
-use data::{Server, User};
+use data::user::{self, ListFormat, Nick};
+use data::{message, Server, User};
 use iced::widget::{button, container, horizontal_rule, row, text, Space};
-use iced::{padding, Length, Padding};
+use iced::{clipboard, padding, Length, Padding, Task};
 
 use crate::widget::{context_menu, double_pass, Element};
 use crate::{icon, theme};
@@ -15,6 +15,8 @@ pub enum Entry {
     SendFile,
     UserInfo,
     HorizontalRule,
+    ViewRaw,
+    CopyMemberList(ListFormat),
 }
 
 impl Entry {
@@ -127,6 +129,23 @@ impl Entry {
                 Length::Fill => container(horizontal_rule(1)).padding([0, 6]).into(),
                 _ => Space::new(length, 1).into(),
             },
+            // Needs the message, see `view`
+            Entry::ViewRaw => row![].into(),
+            Entry::CopyMemberList(format) => {
+                if let Some(channel) = channel {
+                    menu_button(
+                        match format {
+                            ListFormat::Nicks => "Copy Member List",
+                            ListFormat::Prefixed => "Copy With Prefixes",
+                            ListFormat::Hostmasks => "Copy Hostmasks",
+                        },
+                        Message::CopyMemberList(server.clone(), channel.to_string(), format),
+                        length,
+                    )
+                } else {
+                    row![].into()
+                }
+            }
         }
     }
 }
@@ -138,6 +157,8 @@ pub enum Message {
     ToggleAccessLevel(Server, String, Nick, String),
     SendFile(Server, Nick),
     SingleClick(Nick),
+    ViewRaw(message::Raw),
+    CopyMemberList(Server, String, ListFormat),
 }
 
 #[derive(Debug, Clone)]
@@ -147,6 +168,8 @@ pub enum Event {
     ToggleAccessLevel(Server, String, Nick, String),
     SendFile(Server, Nick),
     SingleClick(Nick),
+    ViewRaw(message::Raw),
+    CopyMemberList(Server, String, ListFormat),
 }
 
 pub fn update(message: Message) -> Option<Event> {
@@ -158,6 +181,10 @@ pub fn update(message: Message) -> Option<Event> {
         }
         Message::SendFile(server, nick) => Some(Event::SendFile(server, nick)),
         Message::SingleClick(nick) => Some(Event::SingleClick(nick)),
+        Message::ViewRaw(raw) => Some(Event::ViewRaw(raw)),
+        Message::CopyMemberList(server, channel, format) => {
+            Some(Event::CopyMemberList(server, channel, format))
+        }
     }
 }
 
@@ -168,9 +195,51 @@ pub fn view<'a>(
     user: &'a User,
     current_user: Option<&'a User>,
     our_user: Option<&'a User>,
+    raw: Option<&'a message::Raw>,
+) -> Element<'a, Message> {
+    let mut entries = Entry::list(channel.is_some(), our_user);
+
+    if raw.is_some() {
+        entries.extend([Entry::HorizontalRule, Entry::ViewRaw]);
+    }
+
+    menu(content, server, channel, user, current_user, entries, raw)
+}
+
+/// The user context menu of a nicklist entry, which can also copy the
+/// channel's member list
+pub fn nicklist_view<'a>(
+    content: impl Into<Element<'a, Message>>,
+    server: &'a Server,
+    channel: &'a str,
+    user: &'a User,
+    our_user: Option<&'a User>,
 ) -> Element<'a, Message> {
-    let entries = Entry::list(channel.is_some(), our_user);
+    let mut entries = Entry::list(true, our_user);
+
+    entries.push(Entry::HorizontalRule);
+    entries.extend(ListFormat::ALL.map(Entry::CopyMemberList));
+
+    menu(
+        content,
+        server,
+        Some(channel),
+        user,
+        Some(user),
+        entries,
+        None,
+    )
+}
 
+fn menu<'a>(
+    content: impl Into<Element<'a, Message>>,
+    server: &'a Server,
+    channel: Option<&'a str>,
+    user: &'a User,
+    current_user: Option<&'a User>,
+    entries: Vec<Entry>,
+    raw: Option<&'a message::Raw>,
+) -> Element<'a, Message> {
     let content = button(content)
         .padding(0)
         .style(theme::button::bare)
@@ -180,7 +249,12 @@ pub fn view<'a>(
         Default::default(),
         content,
         entries,
-        move |entry, length| entry.view(server, channel, user, current_user, length),
+        move |entry, length| match (entry, raw) {
+            (Entry::ViewRaw, Some(raw)) => {
+                menu_button("Message Details", Message::ViewRaw(raw.clone()), length)
+            }
+            _ => entry.view(server, channel, user, current_user, length),
+        },
     )
     .into()
 }
@@ -199,9 +273,21 @@ fn right_justified_padding() -> Padding {
 
 fn user_info<'a>(current_user: Option<&User>, length: Length) -> Element<'a, Message> {
     if let Some(current_user) = current_user {
+        let status = |status: &str| {
+            if current_user.is_bot() {
+                format!("{status} (bot)")
+            } else {
+                status.to_string()
+            }
+        };
+
         if current_user.is_away() {
             row![]
-                .push(text("Away").style(theme::text::secondary).width(length))
+                .push(
+                    text(status("Away"))
+                        .style(theme::text::secondary)
+                        .width(length),
+                )
                 .push(
                     icon::dot()
                         .size(6)
@@ -213,7 +299,11 @@ fn user_info<'a>(current_user: Option<&User>, length: Length) -> Element<'a, Mes
                 .into()
         } else {
             row![]
-                .push(text("Online").style(theme::text::secondary).width(length))
+                .push(
+                    text(status("Online"))
+                        .style(theme::text::secondary)
+                        .width(length),
+                )
                 .push(
                     icon::dot()
                         .size(6)
@@ -238,3 +328,15 @@ fn user_info<'a>(current_user: Option<&User>, length: Length) -> Element<'a, Mes
             .into()
     }
 }
+
+/// Copy `users` to the clipboard one per line, in the order given. The list
+/// is built off the UI thread, as channels can have thousands of members.
+pub fn copy_member_list<T: Send + 'static>(users: &[User], format: ListFormat) -> Task<T> {
+    let users = users.to_vec();
+
+    Task::perform(
+        async move { user::member_list(&users, format) },
+        std::convert::identity,
+    )
+    .then(clipboard::write)
+}
@@ -6,6 +6,7 @@ pub enum Event {
     Escape,
     Home,
     End,
+    Modifiers(keyboard::Modifiers),
 }
 
 pub fn events() -> Subscription<(window::Id, Event)> {
@@ -37,6 +38,9 @@ fn filtered_events(
             key: keyboard::Key::Named(keyboard::key::Named::End),
             ..
         }) if ignored(status) => Some(Event::End),
+        iced::Event::Keyboard(keyboard::Event::ModifiersChanged(modifiers)) => {
+            Some(Event::Modifiers(*modifiers))
+        }
         _ => None,
     };
 
@@ -128,9 +128,13 @@ struct Halloy {
     config: Config,
     clients: data::client::Map,
     servers: server::Map,
+    streams: stream::Settings,
+    /// Servers being disconnected from, kept listed once they've quit
+    disconnecting: HashSet<Server>,
     modal: Option<Modal>,
     main_window: Window,
     pending_logs: Vec<data::log::Record>,
+    notification_limiter: data::notification::Limiter,
 }
 
 impl Halloy {
@@ -183,17 +187,29 @@ impl Halloy {
             ),
         };
 
+        // Servers which aren't connected to on startup are listed as
+        // disconnected until connected to
+        let mut clients = data::client::Map::default();
+        for entry in config.servers.entries() {
+            if !entry.config.autoconnect {
+                clients.disconnected(entry.server);
+            }
+        }
+
         (
             Halloy {
                 version: Version::new(),
                 screen,
                 theme: appearance::theme(&config.appearance.selected).into(),
-                clients: Default::default(),
-                servers: config.servers.clone(),
+                clients,
+                servers: config.servers.autoconnect(),
+                streams: stream::Settings::new(&config),
+                disconnecting: HashSet::new(),
                 config,
                 modal: None,
                 main_window,
                 pending_logs: vec![],
+                notification_limiter: data::notification::Limiter::default(),
             },
             command,
         )
@@ -212,6 +228,7 @@ pub enum Screen {
 pub enum Message {
     AppearanceReloaded(data::appearance::Appearance),
     ScreenConfigReloaded(Result<Config, config::Error>),
+    NetworkSaved(Result<(), config::Error>),
     Dashboard(dashboard::Message),
     Stream(stream::Update),
     Help(help::Message),
@@ -299,6 +316,25 @@ impl Halloy {
                 *self = halloy;
                 command
             }
+            Message::NetworkSaved(result) => match result {
+                // Servers are added, changed and removed by the reload
+                Ok(()) => {
+                    if matches!(self.modal, Some(Modal::Network(_))) {
+                        self.modal = None;
+                    }
+
+                    Task::perform(Config::load(), |config| {
+                        Message::Dashboard(dashboard::Message::ConfigReloaded(config))
+                    })
+                }
+                Err(error) => {
+                    if let Some(Modal::Network(state)) = &mut self.modal {
+                        state.error = Some(error.to_string());
+                    }
+
+                    Task::none()
+                }
+            },
             Message::Dashboard(message) => {
                 let Screen::Dashboard(dashboard) = &mut self.screen else {
                     return Task::none();
@@ -314,7 +350,7 @@ impl Halloy {
                 );
 
                 // Retrack after dashboard state changes
-                let track = dashboard.track();
+                let track = dashboard.track(&self.clients);
 
                 let event_task = match event {
                     Some(dashboard::Event::ConfigReloaded(config)) => {
@@ -327,7 +363,54 @@ impl Halloy {
                                     .cloned()
                                     .collect::<Vec<_>>();
 
-                                self.servers = updated.servers.clone();
+                                // Servers listed as disconnected are no longer listed once
+                                // removed from the config
+                                let unlisted_servers = self
+                                    .clients
+                                    .iter()
+                                    .map(|(server, _)| server)
+                                    .filter(|server| {
+                                        !self.servers.contains(server)
+                                            && !updated.servers.contains(server)
+                                    })
+                                    .cloned()
+                                    .collect::<Vec<_>>();
+
+                                for server in unlisted_servers {
+                                    self.clients.remove(&server);
+                                }
+
+                                // Apply changed identities to connected servers
+                                for entry in updated.servers.entries() {
+                                    let identity = entry.config.identity();
+
+                                    if self.servers.entries().any(|current| {
+                                        current.server == entry.server
+                                            && current.config.identity() != identity
+                                    }) {
+                                        self.clients.update_identity(&entry.server, &identity);
+                                    }
+                                }
+
+                                // Connected servers and those listed as disconnected stay as
+                                // they are, other servers connect unless configured not to
+                                let mut servers = server::Map::default();
+
+                                for entry in updated.servers.entries() {
+                                    let is_listed = self.clients.contains(&entry.server);
+
+                                    if self.servers.contains(&entry.server)
+                                        || (!is_listed && entry.config.autoconnect)
+                                    {
+                                        servers.insert(entry.server, entry.config);
+                                    } else if !is_listed {
+                                        self.clients.disconnected(entry.server);
+                                    }
+                                }
+
+                                self.servers = servers;
+                                self.streams.update(&updated);
+                                dashboard.config_reloaded(&updated);
                                 self.theme = appearance::theme(&updated.appearance.selected).into();
                                 self.config = updated;
 
@@ -348,10 +431,73 @@ impl Halloy {
                         self.clients.quit(&server, None);
                         Task::none()
                     }
+                    Some(dashboard::Event::ConnectServer(server)) => {
+                        if !self.servers.contains(&server) {
+                            if let Some(entry) = self
+                                .config
+                                .servers
+                                .entries()
+                                .find(|entry| entry.server == server)
+                            {
+                                self.servers.insert(entry.server, entry.config);
+                            }
+                        }
+                        Task::none()
+                    }
+                    Some(dashboard::Event::DisconnectServer(server)) => {
+                        if self
+                            .clients
+                            .connected_servers()
+                            .any(|other| *other == server)
+                        {
+                            self.clients.quit(&server, None);
+                            self.disconnecting.insert(server);
+                        } else {
+                            // Stop reconnecting, the server stays listed as disconnected
+                            self.servers.remove(&server);
+                        }
+                        Task::none()
+                    }
+                    Some(dashboard::Event::AddNetwork) => {
+                        self.modal = Some(Modal::Network(modal::network::State::default()));
+                        Task::none()
+                    }
+                    Some(dashboard::Event::EditNetwork(server)) => {
+                        if let Some(entry) = self
+                            .config
+                            .servers
+                            .entries()
+                            .find(|entry| entry.server == server)
+                        {
+                            self.modal = Some(Modal::Network(modal::network::State::edit(
+                                entry.server,
+                                &entry.config,
+                            )));
+                        }
+                        Task::none()
+                    }
                     Some(dashboard::Event::IrcError(e)) => {
                         handle_irc_error(e);
                         Task::none()
                     }
+                    Some(dashboard::Event::ConfirmDeleteHistory(kinds)) => {
+                        if !kinds.is_empty() {
+                            self.modal = Some(Modal::DeleteHistory(kinds));
+                        }
+                        Task::none()
+                    }
+                    Some(dashboard::Event::ConfirmLeaveBuffer(buffer, reason)) => {
+                        self.modal = Some(Modal::LeaveBuffer(buffer, reason));
+                        Task::none()
+                    }
+                    Some(dashboard::Event::ViewRawMessage(raw)) => {
+                        self.modal = Some(Modal::RawMessage(raw));
+                        Task::none()
+                    }
+                    Some(dashboard::Event::RawLog(server, enabled)) => {
+                        self.streams.set_raw_logged(&server, enabled);
+                        Task::none()
+                    }
                     Some(dashboard::Event::Exit) => {
                         let pending_exit = self.clients.exit();
 
@@ -428,11 +574,32 @@ impl Halloy {
 
                     if is_initial {
                         // Intial is sent when first trying to connect
-                        dashboard
-                            .broadcast(&server, &self.config, sent_time, Broadcast::Connecting)
-                            .map(Message::Dashboard)
+                        Task::batch(vec![
+                            dashboard.broadcast(
+                                &server,
+                                &self.config,
+                                sent_time,
+                                Broadcast::SessionStarted,
+                            ),
+                            dashboard.broadcast(
+                                &server,
+                                &self.config,
+                                sent_time,
+                                Broadcast::Connecting,
+                            ),
+                        ])
+                        .map(Message::Dashboard)
                     } else {
-                        notification::disconnected(&self.config.notifications, &server);
+                        let notifications = self.config.notifications(dashboard.profile());
+
+                        if self.notification_limiter.allow_connection(
+                            Instant::now(),
+                            &notifications.rate_limit,
+                            &server,
+                            data::notification::Connection::Disconnected,
+                        ) {
+                            notification::disconnected(notifications, &server);
+                        }
 
                         dashboard
                             .broadcast(
@@ -457,13 +624,25 @@ impl Halloy {
                     };
 
                     if is_initial {
-                        notification::connected(&self.config.notifications, &server);
+                        notification::connected(
+                            self.config.notifications(dashboard.profile()),
+                            &server,
+                        );
 
                         dashboard
                             .broadcast(&server, &self.config, sent_time, Broadcast::Connected)
                             .map(Message::Dashboard)
                     } else {
-                        notification::reconnected(&self.config.notifications, &server);
+                        let notifications = self.config.notifications(dashboard.profile());
+
+                        if self.notification_limiter.allow_connection(
+                            Instant::now(),
+                            &notifications.rate_limit,
+                            &server,
+                            data::notification::Connection::Reconnected,
+                        ) {
+                            notification::reconnected(notifications, &server);
+                        }
 
                         dashboard
                             .broadcast(&server, &self.config, sent_time, Broadcast::Reconnected)
@@ -496,6 +675,11 @@ impl Halloy {
                     let commands = messages
                         .into_iter()
                         .flat_map(|message| {
+                            let previous_nickname = self
+                                .clients
+                                .take_previous_nickname(&server, &message)
+                                .filter(|_| self.config.buffer.nickname.show_previous_nickname);
+
                             let events = match self.clients.receive(&server, message) {
                                 Ok(events) => events,
                                 Err(e) => {
@@ -532,6 +716,18 @@ impl Halloy {
                                             chantypes,
                                             statusmsg,
                                         ) {
+                                            let message = message
+                                                .with_previous_nickname(previous_nickname.clone());
+
+                                            commands.push(
+                                                dashboard
+                                                    .translate_message(
+                                                        &server,
+                                                        &message,
+                                                        &self.config,
+                                                    )
+                                                    .map(Message::Dashboard),
+                                            );
                                             commands.push(
                                                 dashboard
                                                     .record_message(&server, message)
@@ -549,14 +745,24 @@ impl Halloy {
                                             chantypes,
                                             statusmsg,
                                         ) {
+                                            let message = message
+                                                .with_target(target)
+                                                .with_previous_nickname(previous_nickname.clone());
+
                                             commands.push(
                                                 dashboard
-                                                    .record_message(
+                                                    .translate_message(
                                                         &server,
-                                                        message.with_target(target),
+                                                        &message,
+                                                        &self.config,
                                                     )
                                                     .map(Message::Dashboard),
                                             );
+                                            commands.push(
+                                                dashboard
+                                                    .record_message(&server, message)
+                                                    .map(Message::Dashboard),
+                                            );
                                         }
                                     }
                                     data::client::Event::Broadcast(broadcast) => match broadcast {
@@ -652,6 +858,75 @@ impl Halloy {
                                                     .map(Message::Dashboard),
                                             );
                                         }
+                                        data::client::Broadcast::Back { user, sent_time } => {
+                                            commands.push(
+                                                dashboard
+                                                    .broadcast(
+                                                        &server,
+                                                        &self.config,
+                                                        sent_time,
+                                                        Broadcast::Back { user },
+                                                    )
+                                                    .map(Message::Dashboard),
+                                            );
+                                        }
+                                        data::client::Broadcast::Joined {
+                                            channel,
+                                            users,
+                                            topic,
+                                            topic_who,
+                                            topic_time,
+                                            sent_time,
+                                        } => {
+                                            commands.push(
+                                                dashboard
+                                                    .broadcast(
+                                                        &server,
+                                                        &self.config,
+                                                        sent_time,
+                                                        Broadcast::Joined {
+                                                            channel,
+                                                            users,
+                                                            topic,
+                                                            topic_who,
+                                                            topic_time,
+                                                        },
+                                                    )
+                                                    .map(Message::Dashboard),
+                                            );
+                                        }
+                                        data::client::Broadcast::Netsplit {
+                                            servers,
+                                            users,
+                                            sent_time,
+                                        } => {
+                                            commands.push(
+                                                dashboard
+                                                    .broadcast(
+                                                        &server,
+                                                        &self.config,
+                                                        sent_time,
+                                                        Broadcast::Netsplit { servers, users },
+                                                    )
+                                                    .map(Message::Dashboard),
+                                            );
+                                        }
+                                        data::client::Broadcast::Netjoin {
+                                            servers,
+                                            users,
+                                            sent_time,
+                                        } => {
+                                            commands.push(
+                                                dashboard
+                                                    .broadcast(
+                                                        &server,
+                                                        &self.config,
+                                                        sent_time,
+                                                        Broadcast::Netjoin { servers, users },
+                                                    )
+                                                    .map(Message::Dashboard),
+                                            );
+                                        }
                                     },
                                     data::client::Event::Notification(
                                         encoded,
@@ -667,6 +942,18 @@ impl Halloy {
                                             chantypes,
                                             statusmsg,
                                         ) {
+                                            let message = message
+                                                .with_previous_nickname(previous_nickname.clone());
+
+                                            commands.push(
+                                                dashboard
+                                                    .translate_message(
+                                                        &server,
+                                                        &message,
+                                                        &self.config,
+                                                    )
+                                                    .map(Message::Dashboard),
+                                            );
                                             commands.push(
                                                 dashboard
                                                     .record_message(&server, message.clone())
@@ -674,8 +961,13 @@ impl Halloy {
                                             );
 
                                             if matches!(
-                                                notification,
-                                                data::client::Notification::Highlight { .. }
+                                                &notification,
+                                                data::client::Notification::Highlight { user, channel, .. }
+                                                    if self.config.buffer.bot.should_highlight(user.is_bot())
+                                                        && self
+                                                            .config
+                                                            .highlights(dashboard.profile())
+                                                            .should_highlight(channel)
                                             ) {
                                                 commands.extend(
                                                     message.into_highlight(server.clone()).map(
@@ -700,10 +992,23 @@ impl Halloy {
                                                     ),
                                                 ) || !self.main_window.focused
                                                 {
-                                                    notification::direct_message(
-                                                        &self.config.notifications,
-                                                        user.nickname(),
-                                                    );
+                                                    let notifications = self
+                                                        .config
+                                                        .notifications(dashboard.profile());
+
+                                                    if self.notification_limiter.allow(
+                                                        Instant::now(),
+                                                        &notifications.rate_limit,
+                                                        data::notification::Kind::DirectMessage,
+                                                        &server,
+                                                        &user.nickname().to_owned(),
+                                                        None,
+                                                    ) {
+                                                        notification::direct_message(
+                                                            notifications,
+                                                            user.nickname(),
+                                                        );
+                                                    }
                                                 }
                                             }
                                             data::client::Notification::Highlight {
@@ -711,12 +1016,35 @@ impl Halloy {
                                                 user,
                                                 channel,
                                             } => {
-                                                if enabled {
-                                                    notification::highlight(
-                                                        &self.config.notifications,
-                                                        user.nickname(),
-                                                        channel,
-                                                    );
+                                                if enabled
+                                                    && self
+                                                        .config
+                                                        .buffer
+                                                        .bot
+                                                        .should_highlight(user.is_bot())
+                                                    && self
+                                                        .config
+                                                        .highlights(dashboard.profile())
+                                                        .should_highlight(&channel)
+                                                {
+                                                    let notifications = self
+                                                        .config
+                                                        .notifications(dashboard.profile());
+
+                                                    if self.notification_limiter.allow(
+                                                        Instant::now(),
+                                                        &notifications.rate_limit,
+                                                        data::notification::Kind::Highlight,
+                                                        &server,
+                                                        &user.nickname().to_owned(),
+                                                        Some(channel.as_str()),
+                                                    ) {
+                                                        notification::highlight(
+                                                            notifications,
+                                                            user.nickname(),
+                                                            channel,
+                                                        );
+                                                    }
                                                 }
                                             }
                                             data::client::Notification::MonitoredOnline(
@@ -724,7 +1052,7 @@ impl Halloy {
                                             ) => {
                                                 targets.into_iter().for_each(|target| {
                                                     notification::monitored_online(
-                                                        &self.config.notifications,
+                                                        self.config.notifications(dashboard.profile()),
                                                         target.nickname().to_owned(),
                                                         server.clone(),
                                                     );
@@ -735,7 +1063,7 @@ impl Halloy {
                                             ) => {
                                                 targets.into_iter().for_each(|target| {
                                                     notification::monitored_offline(
-                                                        &self.config.notifications,
+                                                        self.config.notifications(dashboard.profile()),
                                                         target,
                                                         server.clone(),
                                                     );
@@ -766,6 +1094,17 @@ impl Halloy {
                                                 .map(Message::Dashboard),
                                         );
                                     }
+                                    data::client::Event::ChannelKeyRequired(channel) => {
+                                        commands.push(
+                                            dashboard
+                                                .prompt_channel_key(
+                                                    &server,
+                                                    channel,
+                                                    &self.main_window,
+                                                )
+                                                .map(Message::Dashboard),
+                                        );
+                                    }
                                     data::client::Event::JoinedChannel(channel, server_time) => {
                                         let command = dashboard
                                             .load_metadata(
@@ -830,13 +1169,32 @@ impl Halloy {
                     // user & channel lists are in sync
                     self.clients.sync(&server);
 
-                    Task::batch(commands)
+                    // Messages arriving in the buffer being read are seen right away
+                    let mark_seen = dashboard
+                        .mark_seen(Instant::now(), &self.config, &self.main_window)
+                        .map(Message::Dashboard);
+
+                    Task::batch(commands.into_iter().chain(Some(mark_seen)))
+                }
+                stream::Update::RawLog(line) => {
+                    if let Screen::Dashboard(dashboard) = &mut self.screen {
+                        dashboard.record_raw_line(line);
+                    }
+
+                    Task::none()
                 }
                 stream::Update::Quit(server, reason) => match &mut self.screen {
                     Screen::Dashboard(dashboard) => {
                         self.servers.remove(&server);
 
-                        if let Some(client) = self.clients.remove(&server) {
+                        let client = self.clients.remove(&server);
+
+                        // Disconnected from rather than closed, so keep it listed
+                        if self.disconnecting.remove(&server) {
+                            self.clients.disconnected(server.clone());
+                        }
+
+                        if let Some(client) = client {
                             let user = client.nickname().to_owned().into();
 
                             let channels = client.channels().to_vec();
@@ -887,11 +1245,24 @@ impl Halloy {
                 Task::none()
             }
             Message::Tick(now) => {
+                if let Screen::Dashboard(dashboard) = &self.screen {
+                    let notifications = self.config.notifications(dashboard.profile());
+
+                    for coalesced in self
+                        .notification_limiter
+                        .flush(now, &notifications.rate_limit)
+                    {
+                        notification::coalesced(notifications, &coalesced);
+                    }
+                }
+
                 if let Err(e) = self.clients.tick(now) {
                     handle_irc_error(e);
                     Task::none()
                 } else if let Screen::Dashboard(dashboard) = &mut self.screen {
-                    dashboard.tick(now).map(Message::Dashboard)
+                    dashboard
+                        .tick(now, &mut self.clients, &self.config, &self.main_window)
+                        .map(Message::Dashboard)
                 } else {
                     Task::none()
                 }
@@ -922,6 +1293,69 @@ impl Halloy {
                                 }
                             }
                         }
+                        modal::Event::AcceptDeleteHistory => {
+                            if let (
+                                Some(Modal::DeleteHistory(kinds)),
+                                Screen::Dashboard(dashboard),
+                            ) = (self.modal.take(), &mut self.screen)
+                            {
+                                return dashboard.delete_history(kinds).map(Message::Dashboard);
+                            }
+                        }
+                        modal::Event::AcceptLeaveBuffer => {
+                            if let (
+                                Some(Modal::LeaveBuffer(buffer, _)),
+                                Screen::Dashboard(dashboard),
+                            ) = (self.modal.take(), &mut self.screen)
+                            {
+                                // Only channels and queries are confirmed, which
+                                // leave without an event
+                                let (task, _) = dashboard.leave_buffer(
+                                    &self.main_window,
+                                    &mut self.clients,
+                                    buffer,
+                                    &self.config,
+                                );
+
+                                return task.map(Message::Dashboard);
+                            }
+                        }
+                        modal::Event::Copy(contents) => {
+                            return iced::clipboard::write(contents);
+                        }
+                        modal::Event::SaveNetwork => {
+                            if let Some(Modal::Network(state)) = &mut self.modal {
+                                match state
+                                    .network
+                                    .validate(&self.config.servers, state.editing.as_ref())
+                                {
+                                    Ok(()) => {
+                                        state.error = None;
+
+                                        return Task::perform(
+                                            Config::save_server(
+                                                state.network.clone(),
+                                                state.editing.clone(),
+                                            ),
+                                            Message::NetworkSaved,
+                                        );
+                                    }
+                                    Err(error) => state.error = Some(error.to_string()),
+                                }
+                            }
+                        }
+                        modal::Event::RemoveNetwork => {
+                            if let Some(Modal::Network(modal::network::State {
+                                editing: Some(server),
+                                ..
+                            })) = &self.modal
+                            {
+                                return Task::perform(
+                                    Config::remove_server(server.clone()),
+                                    Message::NetworkSaved,
+                                );
+                            }
+                        }
                     }
                 }
 
@@ -938,6 +1372,13 @@ impl Halloy {
             }
             Message::Window(id, event) => {
                 if id == self.main_window.id {
+                    let focus_changed = matches!(
+                        event,
+                        window::Event::Focused
+                            | window::Event::Unfocused
+                            | window::Event::Opened { .. }
+                    );
+
                     match event {
                         window::Event::Moved(position) => {
                             self.main_window.position = Some(position)
@@ -957,14 +1398,38 @@ impl Halloy {
                         }
                     }
 
-                    Task::perform(
-                        data::Window::from(self.main_window).save(),
-                        Message::WindowSettingsSaved,
-                    )
+                    if focus_changed {
+                        if let Screen::Dashboard(dashboard) = &mut self.screen {
+                            dashboard.set_window_focused(id, self.main_window.focused);
+                        }
+                    }
+
+                    let refocus = match (&self.screen, event) {
+                        (Screen::Dashboard(dashboard), window::Event::Resized(_)) => dashboard
+                            .window_resized(&self.main_window, id)
+                            .map(Message::Dashboard),
+                        _ => Task::none(),
+                    };
+
+                    Task::batch(vec![
+                        refocus,
+                        Task::perform(
+                            data::Window::from(self.main_window).save(),
+                            Message::WindowSettingsSaved,
+                        ),
+                    ])
                 } else if let Screen::Dashboard(dashboard) = &mut self.screen {
-                    dashboard
-                        .handle_window_event(id, event, &mut self.theme)
-                        .map(Message::Dashboard)
+                    let refocus = if matches!(event, window::Event::Resized(_)) {
+                        dashboard.window_resized(&self.main_window, id)
+                    } else {
+                        Task::none()
+                    };
+
+                    Task::batch(vec![
+                        refocus,
+                        dashboard.handle_window_event(id, event, &mut self.theme),
+                    ])
+                    .map(Message::Dashboard)
                 } else {
                     Task::none()
                 }
@@ -1084,7 +1549,7 @@ impl Halloy {
         let streams = Subscription::batch(
             self.servers
                 .entries()
-                .map(|entry| stream::run(entry, self.config.proxy.clone())),
+                .map(|entry| stream::run(entry, self.config.proxy.clone(), self.streams.clone())),
         )
         .map(Message::Stream);
 
@@ -1,7 +1,11 @@
 use crate::widget::Element;
-use data::{config, Server};
+use data::{buffer, config, history, message, Server};
 
 pub mod connect_to_server;
+pub mod delete_history;
+pub mod leave_buffer;
+pub mod network;
+pub mod raw_message;
 pub mod reload_configuration_error;
 
 #[derive(Debug)]
@@ -12,18 +16,33 @@ pub enum Modal {
         server: Server,
         config: config::Server,
     },
+    DeleteHistory(Vec<history::Kind>),
+    RawMessage(message::Raw),
+    /// Leaving a channel or closing a query, and why it needs confirming
+    LeaveBuffer(buffer::Upstream, String),
+    /// Adding a network, or editing or removing one
+    Network(network::State),
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone)]
 pub enum Message {
     Cancel,
     AcceptNewServer,
     DangerouslyAcceptInvalidCerts(bool),
+    AcceptDeleteHistory,
+    AcceptLeaveBuffer,
+    Copy(String),
+    Network(network::Message),
 }
 
 pub enum Event {
     CloseModal,
     AcceptNewServer,
+    AcceptDeleteHistory,
+    AcceptLeaveBuffer,
+    Copy(String),
+    SaveNetwork,
+    RemoveNetwork,
 }
 
 impl Modal {
@@ -31,6 +50,9 @@ impl Modal {
         match message {
             Message::Cancel => Some(Event::CloseModal),
             Message::AcceptNewServer => Some(Event::AcceptNewServer),
+            Message::AcceptDeleteHistory => Some(Event::AcceptDeleteHistory),
+            Message::AcceptLeaveBuffer => Some(Event::AcceptLeaveBuffer),
+            Message::Copy(contents) => Some(Event::Copy(contents)),
             Message::DangerouslyAcceptInvalidCerts(toggle) => {
                 if let Modal::ServerConnect { config, .. } = self {
                     config.dangerously_accept_invalid_certs = toggle;
@@ -38,6 +60,17 @@ impl Modal {
 
                 None
             }
+            Message::Network(message) => {
+                let Modal::Network(state) = self else {
+                    return None;
+                };
+
+                match state.update(message)? {
+                    network::Event::Save => Some(Event::SaveNetwork),
+                    network::Event::Remove => Some(Event::RemoveNetwork),
+                    network::Event::Close => Some(Event::CloseModal),
+                }
+            }
         }
     }
 
@@ -47,6 +80,10 @@ impl Modal {
             Modal::ServerConnect {
                 url: raw, config, ..
             } => connect_to_server::view(raw, config),
+            Modal::DeleteHistory(kinds) => delete_history::view(kinds),
+            Modal::RawMessage(raw) => raw_message::view(raw),
+            Modal::LeaveBuffer(buffer, reason) => leave_buffer::view(buffer, reason),
+            Modal::Network(state) => network::view(state).map(Message::Network),
         }
     }
 }
@@ -0,0 +1,52 @@
+use data::history;
+use iced::{
+    alignment,
+    widget::{button, column, container, text},
+    Length,
+};
+
+use super::Message;
+use crate::{theme, widget::Element};
+
+pub fn view<'a>(kinds: &[history::Kind]) -> Element<'a, Message> {
+    let description = match kinds {
+        [kind] => kind.to_string(),
+        kinds => format!("{} buffers", kinds.len()),
+    };
+
+    container(
+        column![
+            text("Delete history?"),
+            text(description).style(theme::text::tertiary),
+        ]
+        .push(
+            column![
+                button(
+                    container(text("Delete"))
+                        .align_x(alignment::Horizontal::Center)
+                        .width(Length::Fill),
+                )
+                .padding(5)
+                .width(Length::Fixed(250.0))
+                .style(|theme, status| theme::button::secondary(theme, status, false))
+                .on_press(Message::AcceptDeleteHistory),
+                button(
+                    container(text("Cancel"))
+                        .align_x(alignment::Horizontal::Center)
+                        .width(Length::Fill),
+                )
+                .padding(5)
+                .width(Length::Fixed(250.0))
+                .style(|theme, status| theme::button::secondary(theme, status, false))
+                .on_press(Message::Cancel),
+            ]
+            .spacing(4),
+        )
+        .spacing(20)
+        .align_x(iced::Alignment::Center),
+    )
+    .width(Length::Shrink)
+    .style(theme::container::tooltip)
+    .padding(25)
+    .into()
+}
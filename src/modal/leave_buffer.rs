@@ -0,0 +1,55 @@
+use data::buffer;
+use iced::{
+    alignment,
+    widget::{button, column, container, text},
+    Length,
+};
+
+use super::Message;
+use crate::{theme, widget::Element};
+
+pub fn view<'a>(buffer: &buffer::Upstream, reason: &str) -> Element<'a, Message> {
+    let (title, accept) = match buffer {
+        buffer::Upstream::Query(_, nick) => (format!("Close {nick}?"), "Close"),
+        _ => (
+            format!("Leave {}?", buffer.target().unwrap_or_default()),
+            "Leave",
+        ),
+    };
+
+    container(
+        column![
+            text(title),
+            text(reason.to_string()).style(theme::text::tertiary),
+        ]
+        .push(
+            column![
+                button(
+                    container(text(accept))
+                        .align_x(alignment::Horizontal::Center)
+                        .width(Length::Fill),
+                )
+                .padding(5)
+                .width(Length::Fixed(250.0))
+                .style(|theme, status| theme::button::secondary(theme, status, false))
+                .on_press(Message::AcceptLeaveBuffer),
+                button(
+                    container(text("Cancel"))
+                        .align_x(alignment::Horizontal::Center)
+                        .width(Length::Fill),
+                )
+                .padding(5)
+                .width(Length::Fixed(250.0))
+                .style(|theme, status| theme::button::secondary(theme, status, false))
+                .on_press(Message::Cancel),
+            ]
+            .spacing(4),
+        )
+        .spacing(20)
+        .align_x(iced::Alignment::Center),
+    )
+    .width(Length::Shrink)
+    .style(theme::container::tooltip)
+    .padding(25)
+    .into()
+}
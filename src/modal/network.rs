@@ -0,0 +1,233 @@
+use data::config::{self, network::Network};
+use data::Server;
+use iced::{
+    alignment,
+    widget::{button, checkbox, column, container, text, text_input},
+    Length,
+};
+
+use crate::{theme, widget::Element};
+
+const INPUT_WIDTH: f32 = 250.0;
+
+#[derive(Debug, Default)]
+pub struct State {
+    /// Server being edited, `None` when adding a network
+    pub editing: Option<Server>,
+    pub network: Network,
+    /// Why the network couldn't be saved
+    pub error: Option<String>,
+    confirm_remove: bool,
+}
+
+impl State {
+    pub fn edit(server: Server, config: &config::Server) -> Self {
+        Self {
+            network: Network::from_config(&server, config),
+            editing: Some(server),
+            error: None,
+            confirm_remove: false,
+        }
+    }
+
+    pub fn update(&mut self, message: Message) -> Option<Event> {
+        let network = &mut self.network;
+
+        match message {
+            Message::Name(name) => network.name = name,
+            Message::Server(server) => network.server = server,
+            Message::Port(port) => network.port = port,
+            Message::UseTls(use_tls) => {
+                // Follow the usual port for TLS unless it was changed
+                if network.port.trim() == default_port(network.use_tls) {
+                    network.port = default_port(use_tls).to_string();
+                }
+                network.use_tls = use_tls;
+            }
+            Message::Nickname(nickname) => network.nickname = nickname,
+            Message::SaslUsername(username) => network.sasl_username = username,
+            Message::SaslPassword(password) => network.sasl_password = password,
+            Message::SaslPasswordIsCommand(is_command) => {
+                network.sasl_password_is_command = is_command
+            }
+            Message::Channels(channels) => network.channels = channels,
+            Message::Save => return Some(Event::Save),
+            Message::Remove => {
+                if self.confirm_remove {
+                    return Some(Event::Remove);
+                }
+
+                self.confirm_remove = true;
+                return None;
+            }
+            Message::Cancel => return Some(Event::Close),
+        }
+
+        self.confirm_remove = false;
+
+        None
+    }
+}
+
+fn default_port(use_tls: bool) -> &'static str {
+    if use_tls {
+        "6697"
+    } else {
+        "6667"
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Message {
+    Name(String),
+    Server(String),
+    Port(String),
+    UseTls(bool),
+    Nickname(String),
+    SaslUsername(String),
+    SaslPassword(String),
+    SaslPasswordIsCommand(bool),
+    Channels(String),
+    Save,
+    Remove,
+    Cancel,
+}
+
+pub enum Event {
+    Save,
+    Remove,
+    Close,
+}
+
+pub fn view(state: &State) -> Element<Message> {
+    let network = &state.network;
+
+    let sasl: Element<Message> = if network.keeps_sasl {
+        text("SASL EXTERNAL is set in the config file and is kept as is")
+            .style(theme::text::secondary)
+            .into()
+    } else {
+        column![
+            field(
+                "SASL username (optional)",
+                input("", &network.sasl_username, Message::SaslUsername),
+            ),
+            field(
+                if network.sasl_password_is_command {
+                    "SASL password command"
+                } else {
+                    "SASL password"
+                },
+                text_input(
+                    if network.keeps_sasl_password() {
+                        "Unchanged"
+                    } else {
+                        ""
+                    },
+                    &network.sasl_password,
+                )
+                .secure(!network.sasl_password_is_command)
+                .on_input(Message::SaslPassword)
+                .on_submit(Message::Save)
+                .padding([4, 8])
+                .width(Length::Fixed(INPUT_WIDTH))
+                .style(|theme, status| theme::text_input::primary(theme, status)),
+            ),
+            checkbox(
+                "Run a command for the password, e.g. a keyring lookup",
+                network.sasl_password_is_command,
+            )
+            .on_toggle(Message::SaslPasswordIsCommand),
+        ]
+        .spacing(12)
+        .into()
+    };
+
+    let remove = state.editing.as_ref().map(|_| {
+        action(
+            if state.confirm_remove {
+                "Confirm removal"
+            } else {
+                "Remove network"
+            },
+            Message::Remove,
+        )
+    });
+
+    container(
+        column![
+            text(if state.editing.is_some() {
+                "Edit network"
+            } else {
+                "Add network"
+            }),
+            column![
+                field("Name", input("libera", &network.name, Message::Name)),
+                field(
+                    "Server",
+                    input("irc.libera.chat", &network.server, Message::Server),
+                ),
+                field("Port", input("", &network.port, Message::Port)),
+                checkbox("Use TLS", network.use_tls).on_toggle(Message::UseTls),
+                field("Nickname", input("", &network.nickname, Message::Nickname)),
+                sasl,
+                field(
+                    "Channels",
+                    input("#halloy, #rust", &network.channels, Message::Channels),
+                ),
+            ]
+            .spacing(12),
+        ]
+        .push_maybe(
+            state
+                .error
+                .as_ref()
+                .map(|error| text(error.clone()).style(theme::text::error)),
+        )
+        .push(
+            column![action("Save", Message::Save)]
+                .push_maybe(remove)
+                .push(action("Close", Message::Cancel))
+                .spacing(4),
+        )
+        .spacing(20)
+        .align_x(iced::Alignment::Center),
+    )
+    .width(Length::Shrink)
+    .style(theme::container::tooltip)
+    .padding(25)
+    .into()
+}
+
+fn field<'a>(label: &'a str, input: impl Into<Element<'a, Message>>) -> Element<'a, Message> {
+    column![text(label).style(theme::text::secondary), input.into()]
+        .spacing(4)
+        .into()
+}
+
+fn input<'a>(
+    placeholder: &str,
+    value: &'a str,
+    on_input: fn(String) -> Message,
+) -> Element<'a, Message> {
+    text_input(placeholder, value)
+        .on_input(on_input)
+        .on_submit(Message::Save)
+        .padding([4, 8])
+        .width(Length::Fixed(INPUT_WIDTH))
+        .style(|theme, status| theme::text_input::primary(theme, status))
+        .into()
+}
+
+fn action(label: &str, message: Message) -> Element<Message> {
+    button(
+        container(text(label))
+            .align_x(alignment::Horizontal::Center)
+            .width(Length::Fill),
+    )
+    .padding(5)
+    .width(Length::Fixed(INPUT_WIDTH))
+    .style(|theme, status| theme::button::secondary(theme, status, false))
+    .on_press(message)
+    .into()
+}
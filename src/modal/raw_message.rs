@@ -0,0 +1,87 @@
+use data::message;
+use iced::{
+    alignment,
+    widget::{button, column, container, row, scrollable, text},
+    Length,
+};
+
+use super::Message;
+use crate::{icon, theme, widget::Element};
+
+pub fn view<'a>(raw: &message::Raw) -> Element<'a, Message> {
+    let client_tags = raw
+        .client_tags()
+        .map(|tag| match &tag.value {
+            Some(value) => format!("{}={value}", tag.key),
+            None => tag.key.clone(),
+        })
+        .collect::<Vec<_>>()
+        .join(" ");
+    let capabilities = raw.capabilities().join(", ");
+
+    let fields = [
+        ("Raw line", Some(raw.line.clone())),
+        ("Message ID", raw.tag("msgid").map(String::from)),
+        ("Account", raw.tag("account").map(String::from)),
+        ("Server time", raw.tag("time").map(String::from)),
+        (
+            "Client tags",
+            Some(client_tags).filter(|tags| !tags.is_empty()),
+        ),
+        ("Sender", raw.prefix.clone()),
+        (
+            "Capabilities",
+            Some(capabilities).filter(|capabilities| !capabilities.is_empty()),
+        ),
+        ("Received", Some(raw.origin.to_string())),
+    ];
+
+    let fields = column(fields.into_iter().map(|(label, value)| field(label, value))).spacing(8);
+
+    container(
+        column![
+            text("Message details"),
+            scrollable(fields).height(Length::Shrink),
+            button(
+                container(text("Close"))
+                    .align_x(alignment::Horizontal::Center)
+                    .width(Length::Fill),
+            )
+            .style(|theme, status| theme::button::secondary(theme, status, false))
+            .padding(5)
+            .width(Length::Fixed(250.0))
+            .on_press(Message::Cancel)
+        ]
+        .spacing(20)
+        .align_x(iced::Alignment::Center),
+    )
+    .max_width(600)
+    .style(theme::container::tooltip)
+    .padding(25)
+    .into()
+}
+
+fn field<'a>(label: &'a str, value: Option<String>) -> Element<'a, Message> {
+    let Some(value) = value else {
+        return row![
+            text(label).style(theme::text::secondary).width(100),
+            text("None").style(theme::text::tertiary),
+        ]
+        .spacing(8)
+        .into();
+    };
+
+    let copy = button(icon::copy())
+        .padding([2, 4])
+        .style(|theme, status| theme::button::secondary(theme, status, false))
+        .on_press(Message::Copy(value.clone()));
+
+    row![
+        text(label).style(theme::text::secondary).width(100),
+        text(value).width(Length::Fill),
+        copy,
+    ]
+    .spacing(8)
+    .align_y(iced::Alignment::Center)
+    .into()
+}
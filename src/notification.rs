@@ -1,6 +1,11 @@
+use std::path::PathBuf;
+
+use bytesize::ByteSize;
 use data::{
     audio::Sound,
     config::{self, notification},
+    file_transfer::{self, FileTransfer},
+    notification::Coalesced,
     user::{Nick, NickRef},
 };
 
@@ -38,6 +43,35 @@ pub fn highlight(config: &config::Notifications<Sound>, nick: NickRef, channel:
     );
 }
 
+/// Notify of highlights or direct messages held back by the rate limit,
+/// without a sound
+pub fn coalesced(config: &config::Notifications<Sound>, coalesced: &Coalesced) {
+    let (notification, title, body) = match coalesced.kind {
+        data::notification::Kind::Highlight => (
+            &config.highlight,
+            "Highlight",
+            format!(
+                "{} more highlights from {} in {}",
+                coalesced.count,
+                coalesced.sender,
+                coalesced.channel.as_deref().unwrap_or_default()
+            ),
+        ),
+        data::notification::Kind::DirectMessage => (
+            &config.direct_message,
+            "Direct message",
+            format!(
+                "{} more direct messages from {}",
+                coalesced.count, coalesced.sender
+            ),
+        ),
+    };
+
+    if notification.show_toast {
+        toast::show(title, body);
+    }
+}
+
 pub fn file_transfer_request(
     config: &config::Notifications<Sound>,
     nick: Nick,
@@ -50,6 +84,108 @@ pub fn file_transfer_request(
     );
 }
 
+/// Notify of transfers that completed, alongside where downloads were
+/// saved to, collapsing several into a summary
+pub fn file_transfers_completed(
+    config: &config::Notifications<Sound>,
+    transfers: &[(FileTransfer, Option<PathBuf>)],
+) {
+    let folders = transfers
+        .iter()
+        .map(|(_, path)| path.as_deref().and_then(|path| path.parent()))
+        .collect::<Option<Vec<_>>>();
+    // Only open a folder when every download ended up in it
+    let folder = folders
+        .filter(|folders| folders.windows(2).all(|pair| pair[0] == pair[1]))
+        .and_then(|folders| folders.first().map(|folder| folder.to_path_buf()));
+
+    let (title, body) = match transfers {
+        [] => return,
+        [(transfer, _)] => {
+            let elapsed = match &transfer.status {
+                file_transfer::Status::Completed { elapsed, .. } => {
+                    format!(" in {}s", elapsed.as_secs())
+                }
+                _ => String::new(),
+            };
+
+            (
+                "File transfer completed".to_string(),
+                format!(
+                    "{} ({}) {}{elapsed}",
+                    transfer.filename,
+                    ByteSize::b(transfer.size),
+                    remote_user(transfer),
+                ),
+            )
+        }
+        _ => (
+            format!("{} file transfers completed", transfers.len()),
+            filenames(transfers.iter().map(|(transfer, _)| transfer)),
+        ),
+    };
+
+    if let Some(folder) = folder {
+        show_notification_opening(&config.file_transfer_completed, &title, body, folder);
+    } else {
+        show_notification(&config.file_transfer_completed, &title, body);
+    }
+}
+
+/// Notify of transfers that failed, collapsing several into a summary
+pub fn file_transfers_failed(config: &config::Notifications<Sound>, transfers: &[FileTransfer]) {
+    let (title, body) = match transfers {
+        [] => return,
+        [transfer] => {
+            let error = match &transfer.status {
+                file_transfer::Status::Failed { error } => format!(": {error}"),
+                _ => String::new(),
+            };
+
+            (
+                "File transfer failed".to_string(),
+                format!(
+                    "{} ({}) {}{error}",
+                    transfer.filename,
+                    ByteSize::b(transfer.size),
+                    remote_user(transfer),
+                ),
+            )
+        }
+        _ => (
+            format!("{} file transfers failed", transfers.len()),
+            filenames(transfers.iter()),
+        ),
+    };
+
+    show_notification(&config.file_transfer_failed, &title, body);
+}
+
+fn remote_user(transfer: &FileTransfer) -> String {
+    match transfer.direction {
+        file_transfer::Direction::Sent => format!("to {}", transfer.remote_user),
+        file_transfer::Direction::Received => format!("from {}", transfer.remote_user),
+    }
+}
+
+fn filenames<'a>(transfers: impl Iterator<Item = &'a FileTransfer>) -> String {
+    const LISTED: usize = 3;
+
+    let filenames = transfers
+        .map(|transfer| transfer.filename.as_str())
+        .collect::<Vec<_>>();
+
+    if filenames.len() > LISTED {
+        format!(
+            "{} and {} more",
+            filenames[..LISTED].join(", "),
+            filenames.len() - LISTED
+        )
+    } else {
+        filenames.join(", ")
+    }
+}
+
 pub fn monitored_online(config: &config::Notifications<Sound>, nick: Nick, server: impl ToString) {
     show_notification(
         &config.monitored_online,
@@ -75,3 +211,18 @@ fn show_notification(notification: &notification::Loaded, title: &str, body: imp
         audio::play(sound.clone());
     }
 }
+
+fn show_notification_opening(
+    notification: &notification::Loaded,
+    title: &str,
+    body: impl ToString,
+    path: PathBuf,
+) {
+    if notification.show_toast {
+        toast::show_opening(title, body, path);
+    }
+
+    if let Some(sound) = &notification.sound {
+        audio::play(sound.clone());
+    }
+}
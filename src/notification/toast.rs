@@ -1,3 +1,5 @@
+use std::path::PathBuf;
+
 #[cfg(target_os = "macos")]
 pub fn prepare() {
     match notify_rust::set_application(data::environment::APPLICATION_ID) {
@@ -12,6 +14,33 @@ pub fn prepare() {
 pub fn prepare() {}
 
 pub fn show(title: &str, body: impl ToString) {
+    let _ = notification(title, body).show();
+}
+
+/// Show a toast which opens `path` when clicked, where supported
+#[cfg(target_os = "linux")]
+pub fn show_opening(title: &str, body: impl ToString, path: PathBuf) {
+    let mut notification = notification(title, body);
+    notification.action("default", "Open");
+
+    if let Ok(handle) = notification.show() {
+        // Blocks until the notification is clicked or closed
+        std::thread::spawn(move || {
+            handle.wait_for_action(|action| {
+                if action == "default" {
+                    let _ = open::that_detached(path);
+                }
+            });
+        });
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn show_opening(title: &str, body: impl ToString, _path: PathBuf) {
+    show(title, body);
+}
+
+fn notification(title: &str, body: impl ToString) -> notify_rust::Notification {
     let mut notification = notify_rust::Notification::new();
 
     notification.summary(title);
@@ -27,5 +56,5 @@ pub fn show(title: &str, body: impl ToString) {
         notification.app_id(data::environment::APPLICATION_ID);
     }
 
-    let _ = notification.show();
+    notification
 }
@@ -2,6 +2,7 @@ use chrono::{DateTime, Utc};
 use data::dashboard::BufferAction;
 use data::environment::{RELEASE_WEBSITE, WIKI_WEBSITE};
 use data::history::ReadMarker;
+use data::note::Notes;
 use std::collections::HashMap;
 use std::path::PathBuf;
 use std::time::{Duration, Instant};
@@ -12,10 +13,10 @@ use data::file_transfer;
 use data::history::manager::Broadcast;
 use data::isupport::{self, ChatHistorySubcommand, MessageReference};
 use data::user::Nick;
-use data::{client, environment, history, Config, Server, Version};
+use data::{client, environment, history, message, raw_log, translation, Config, Server, Version};
 use iced::widget::pane_grid::{self, PaneGrid};
-use iced::widget::{column, container, row, Space};
-use iced::{clipboard, Length, Task, Vector};
+use iced::widget::{button, column, container, row, text, Space};
+use iced::{clipboard, keyboard, Length, Task, Vector};
 
 use self::command_bar::CommandBar;
 use self::pane::Pane;
@@ -34,6 +35,8 @@ pub mod sidebar;
 mod theme_editor;
 
 const SAVE_AFTER: Duration = Duration::from_secs(3);
+/// File transfers finishing within this long of the first are notified together
+const FILE_TRANSFER_NOTIFICATION_WINDOW: Duration = Duration::from_secs(3);
 
 pub struct Dashboard {
     panes: Panes,
@@ -43,7 +46,20 @@ pub struct Dashboard {
     last_changed: Option<Instant>,
     command_bar: Option<CommandBar>,
     file_transfers: file_transfer::Manager,
+    raw_log: raw_log::Log,
+    translations: translation::Translations,
     theme_editor: Option<ThemeEditor>,
+    modifiers: keyboard::Modifiers,
+    profile: Option<String>,
+    notes: Notes,
+    /// Window with OS focus
+    focused_window: Option<window::Id>,
+    /// Buffer being read and since when, marked as seen after a delay
+    reading_since: Option<(history::Kind, Instant)>,
+    /// File transfers finished since the first one's time, yet to be notified
+    finished_file_transfers: Option<(Instant, Vec<file_transfer::FileTransfer>)>,
+    /// Latest buffer closed, which can be reopened until its undo expires
+    closed_buffer: Option<ClosedBuffer>,
 }
 
 #[derive(Debug)]
@@ -53,6 +69,10 @@ pub enum Message {
     SelectedText(Vec<(f32, String)>),
     History(history::manager::Message),
     DashboardSaved(Result<(), data::dashboard::Error>),
+    NotesLoaded(Notes),
+    NotesSaved(Result<(), data::note::Error>),
+    ThemesLoaded(Result<Vec<data::Theme>, config::Error>),
+    ThemeSaved(Result<(), config::Error>),
     Task(command_bar::Message),
     Shortcut(shortcut::Command),
     FileTransfer(file_transfer::task::Update),
@@ -61,6 +81,8 @@ pub enum Message {
     ThemeEditor(theme_editor::Message),
     ConfigReloaded(Result<Config, config::Error>),
     Client(client::Message),
+    Translated(message::Hash, Result<String, translation::Error>),
+    UndoCloseBuffer,
 }
 
 #[derive(Debug)]
@@ -68,7 +90,15 @@ pub enum Event {
     ConfigReloaded(Result<Config, config::Error>),
     ReloadThemes,
     QuitServer(Server),
+    ConnectServer(Server),
+    DisconnectServer(Server),
+    AddNetwork,
+    EditNetwork(Server),
     IrcError(anyhow::Error),
+    ConfirmDeleteHistory(Vec<history::Kind>),
+    ConfirmLeaveBuffer(buffer::Upstream, String),
+    ViewRawMessage(message::Raw),
+    RawLog(Server, bool),
     Exit,
 }
 
@@ -83,16 +113,26 @@ impl Dashboard {
             },
             focus: None,
             side_menu: Sidebar::new(),
-            history: history::Manager::default(),
+            history: history::Manager::new(config),
             last_changed: None,
             command_bar: None,
             file_transfers: file_transfer::Manager::new(config.file_transfer.clone()),
+            raw_log: raw_log::Log::default(),
+            translations: translation::Translations::default(),
             theme_editor: None,
+            modifiers: keyboard::Modifiers::default(),
+            profile: None,
+            notes: Notes::default(),
+            focused_window: None,
+            reading_since: None,
+            finished_file_transfers: None,
+            closed_buffer: None,
         };
 
-        let command = dashboard.track();
+        // Not connected to any server yet
+        let command = dashboard.track(&data::client::Map::default());
 
-        (dashboard, command)
+        (dashboard, Task::batch(vec![command, load_notes(config)]))
     }
 
     pub fn restore(
@@ -102,13 +142,16 @@ impl Dashboard {
     ) -> (Self, Task<Message>) {
         let (mut dashboard, task) = Dashboard::from_data(dashboard, config, main_window);
 
+        // Not connected to any server yet
+        let clients = data::client::Map::default();
+
         let command = if let Some((pane, _)) = dashboard.panes.main.panes.iter().next() {
             Task::batch(vec![
                 dashboard.focus_pane(main_window, main_window.id, *pane),
-                dashboard.track(),
+                dashboard.track(&clients),
             ])
         } else {
-            dashboard.track()
+            dashboard.track(&clients)
         };
 
         (dashboard, Task::batch(vec![task, command]))
@@ -142,7 +185,10 @@ impl Dashboard {
                     pane::Message::PaneDragged(_) => {}
                     pane::Message::ClosePane => {
                         if let Some((window, pane)) = self.focus.take() {
-                            return (self.close_pane(main_window, window, pane), None);
+                            return (
+                                self.close_buffer_pane(main_window, window, pane, config),
+                                None,
+                            );
                         }
                     }
                     pane::Message::SplitPane(axis) => {
@@ -155,6 +201,7 @@ impl Dashboard {
                                 clients,
                                 &mut self.history,
                                 &mut self.file_transfers,
+                                &self.notes,
                                 config,
                             );
 
@@ -257,9 +304,28 @@ impl Dashboard {
                                         }
                                         buffer::user_context::Event::OpenQuery(server, nick) => {
                                             let buffer = buffer::Upstream::Query(server, nick);
+
+                                            // Avoid showing the whole conversation as unread
+                                            // when opening a query from a related buffer
+                                            let seed_read_marker = pane
+                                                .buffer
+                                                .upstream()
+                                                .cloned()
+                                                .and_then(|source| {
+                                                    self.history.seed_read_marker(
+                                                        history::Kind::from_input_buffer(
+                                                            buffer.clone(),
+                                                        ),
+                                                        history::Kind::from_input_buffer(source),
+                                                    )
+                                                })
+                                                .map(|task| Task::perform(task, Message::History))
+                                                .unwrap_or_else(Task::none);
+
                                             return (
                                                 Task::batch(vec![
                                                     task,
+                                                    seed_read_marker,
                                                     self.open_buffer(
                                                         main_window,
                                                         data::Buffer::Upstream(buffer),
@@ -320,6 +386,26 @@ impl Dashboard {
                                                 None,
                                             );
                                         }
+                                        buffer::user_context::Event::ViewRaw(raw) => {
+                                            return (task, Some(Event::ViewRawMessage(raw)));
+                                        }
+                                        buffer::user_context::Event::CopyMemberList(
+                                            server,
+                                            channel,
+                                            format,
+                                        ) => {
+                                            return (
+                                                Task::batch(vec![
+                                                    task,
+                                                    buffer::user_context::copy_member_list(
+                                                        clients
+                                                            .get_channel_users(&server, &channel),
+                                                        format,
+                                                    ),
+                                                ]),
+                                                None,
+                                            );
+                                        }
                                     }
                                 }
                                 buffer::Event::OpenChannel(channel) => {
@@ -350,6 +436,156 @@ impl Dashboard {
                                         None,
                                     )
                                 }
+                                buffer::Event::Clear(clear) => {
+                                    let kinds = if clear.all {
+                                        self.panes
+                                            .resources()
+                                            .map(|resource| resource.kind)
+                                            .collect()
+                                    } else {
+                                        pane.resource()
+                                            .map(|resource| resource.kind)
+                                            .into_iter()
+                                            .collect()
+                                    };
+
+                                    if clear.history {
+                                        return (task, Some(Event::ConfirmDeleteHistory(kinds)));
+                                    } else {
+                                        return (
+                                            Task::batch(vec![task, self.clear_buffers(kinds)]),
+                                            None,
+                                        );
+                                    }
+                                }
+                                buffer::Event::RawLog(enabled) => {
+                                    let Some(server) = pane
+                                        .buffer
+                                        .upstream()
+                                        .map(|buffer| buffer.server().clone())
+                                    else {
+                                        return (task, None);
+                                    };
+
+                                    let event = Some(Event::RawLog(server, enabled));
+
+                                    if enabled {
+                                        return (
+                                            Task::batch(vec![
+                                                task,
+                                                self.open_buffer(
+                                                    main_window,
+                                                    data::Buffer::Internal(
+                                                        buffer::Internal::RawLog,
+                                                    ),
+                                                    config.buffer.clone().into(),
+                                                ),
+                                            ]),
+                                            event,
+                                        );
+                                    }
+
+                                    return (task, event);
+                                }
+                                buffer::Event::MarkRead(force) => {
+                                    let Some(kind) = pane.resource().map(|resource| resource.kind)
+                                    else {
+                                        return (task, None);
+                                    };
+                                    let Some((read_marker, history_task)) =
+                                        self.history.mark_read(kind.clone(), force)
+                                    else {
+                                        return (task, None);
+                                    };
+
+                                    if let Some((server, target)) = kind.server().zip(kind.target())
+                                    {
+                                        if let Err(e) =
+                                            clients.send_markread(server, target, read_marker)
+                                        {
+                                            return (task, Some(Event::IrcError(e)));
+                                        }
+                                    }
+
+                                    return (
+                                        Task::batch(vec![
+                                            task,
+                                            Task::perform(history_task, Message::History),
+                                        ]),
+                                        None,
+                                    );
+                                }
+                                buffer::Event::AutoTranslate(enabled) => {
+                                    if let Some(kind) = pane
+                                        .buffer
+                                        .upstream()
+                                        .cloned()
+                                        .map(history::Kind::from_input_buffer)
+                                    {
+                                        self.translations.set_enabled(kind, enabled);
+                                    }
+                                }
+                                buffer::Event::Profile(profile) => {
+                                    self.set_profile(profile);
+                                }
+                                buffer::Event::Theme(command) => match command {
+                                    data::command::Theme::Preview => {
+                                        return (
+                                            Task::batch(vec![
+                                                task,
+                                                Task::perform(
+                                                    Config::load_themes(),
+                                                    Message::ThemesLoaded,
+                                                ),
+                                            ]),
+                                            None,
+                                        );
+                                    }
+                                    data::command::Theme::Switch(name) => {
+                                        if let Some(new) = config.appearance.find(&name) {
+                                            *theme = Theme::from(new.clone());
+                                        }
+                                    }
+                                    data::command::Theme::Random => {
+                                        if let Some(new) = config.appearance.random(theme.name()) {
+                                            *theme = Theme::from(new.clone());
+                                        }
+                                    }
+                                    data::command::Theme::Save => {
+                                        return (
+                                            Task::batch(vec![
+                                                task,
+                                                Task::perform(
+                                                    Config::save_theme(theme.name().to_string()),
+                                                    Message::ThemeSaved,
+                                                ),
+                                            ]),
+                                            None,
+                                        );
+                                    }
+                                },
+                                buffer::Event::Connection(connection) => {
+                                    let event = match connection {
+                                        data::command::Connection::Connect(server) => {
+                                            Event::ConnectServer(server)
+                                        }
+                                        data::command::Connection::Disconnect(server) => {
+                                            Event::DisconnectServer(server)
+                                        }
+                                    };
+
+                                    return (task, Some(event));
+                                }
+                                buffer::Event::SetNote(note) => {
+                                    if let Some(buffer) = pane.buffer.upstream().cloned() {
+                                        if self.notes.set(buffer, &note) {
+                                            return (
+                                                Task::batch(vec![task, self.save_notes()]),
+                                                None,
+                                            );
+                                        }
+                                    }
+                                }
                                 buffer::Event::GoToMessage(server, channel, message) => {
                                     let buffer = data::Buffer::Upstream(buffer::Upstream::Channel(
                                         server, channel,
@@ -385,13 +621,45 @@ impl Dashboard {
                                         );
                                     }
 
+                                    tasks.push(self.follow_scroll_to(
+                                        main_window,
+                                        &buffer,
+                                        message,
+                                        config,
+                                    ));
+
                                     return (Task::batch(tasks), None);
                                 }
                                 buffer::Event::RequestOlderChatHistory => {
+                                    if let Some(kind) =
+                                        pane.resource().map(|resource| resource.kind)
+                                    {
+                                        if self.history.has_evicted(&kind) {
+                                            return (
+                                                Task::batch(vec![
+                                                    task,
+                                                    self.load_evicted(kind),
+                                                ]),
+                                                None,
+                                            );
+                                        }
+                                    }
+
                                     if let Some(buffer) = pane.buffer.data() {
                                         self.request_older_chathistory(clients, &buffer);
                                     }
                                 }
+                                buffer::Event::LoadEvictedHistory => {
+                                    if let Some(resource) = pane.resource() {
+                                        return (
+                                            Task::batch(vec![
+                                                task,
+                                                self.load_evicted(resource.kind),
+                                            ]),
+                                            None,
+                                        );
+                                    }
+                                }
                             }
 
                             return (task, None);
@@ -413,7 +681,9 @@ impl Dashboard {
                             self.last_changed = Some(Instant::now());
                         }
                     }
-                    pane::Message::MaximizePane => self.maximize_pane(),
+                    pane::Message::MaximizePane => {
+                        return (self.maximize_pane(main_window), None);
+                    }
                     pane::Message::Popout => return (self.popout_pane(main_window), None),
                     pane::Message::Merge => return (self.merge_pane(config, main_window), None),
                     pane::Message::ScrollToBottom => {
@@ -439,11 +709,19 @@ impl Dashboard {
 
                 let (event_task, event) = match event {
                     sidebar::Event::Open(buffer) => (
-                        self.open_buffer(
-                            main_window,
-                            data::Buffer::Upstream(buffer),
-                            config.buffer.clone().into(),
-                        ),
+                        if config.sidebar.allow_duplicate_buffers {
+                            self.new_buffer_pane(
+                                main_window,
+                                data::Buffer::Upstream(buffer),
+                                config.buffer.clone().into(),
+                            )
+                        } else {
+                            self.open_buffer(
+                                main_window,
+                                data::Buffer::Upstream(buffer),
+                                config.buffer.clone().into(),
+                            )
+                        },
                         None,
                     ),
                     sidebar::Event::Popout(buffer) => (
@@ -457,27 +735,20 @@ impl Dashboard {
                         (self.focus_pane(main_window, window, pane), None)
                     }
                     sidebar::Event::Replace(window, buffer, pane) => {
-                        if let Some(state) = self.panes.get_mut(main_window.id, window, pane) {
-                            state.buffer = Buffer::from(data::Buffer::Upstream(buffer));
-                            self.last_changed = Some(Instant::now());
-                            self.focus = None;
-                            (
-                                Task::batch(vec![
-                                    self.reset_pane(main_window, window, pane),
-                                    self.focus_pane(main_window, window, pane),
-                                ]),
-                                None,
-                            )
-                        } else {
-                            (Task::none(), None)
-                        }
+                        (self.replace_pane(main_window, window, pane, buffer), None)
+                    }
+                    sidebar::Event::ReplaceLargest(buffer) => {
+                        (self.replace_largest_pane(main_window, buffer, config), None)
                     }
                     sidebar::Event::Close(window, pane) => {
                         if self.focus == Some((window, pane)) {
                             self.focus = None;
                         }
 
-                        (self.close_pane(main_window, window, pane), None)
+                        (
+                            self.close_buffer_pane(main_window, window, pane, config),
+                            None,
+                        )
                     }
                     sidebar::Event::Swap(from_window, from_pane, to_window, to_pane) => {
                         self.last_changed = Some(Instant::now());
@@ -507,7 +778,17 @@ impl Dashboard {
                         }
                     }
                     sidebar::Event::Leave(buffer) => {
-                        self.leave_buffer(main_window, clients, buffer)
+                        self.request_leave_buffer(main_window, clients, buffer, config)
+                    }
+                    sidebar::Event::Connect(server) => {
+                        (Task::none(), Some(Event::ConnectServer(server)))
+                    }
+                    sidebar::Event::Disconnect(server) => {
+                        (Task::none(), Some(Event::DisconnectServer(server)))
+                    }
+                    sidebar::Event::AddNetwork => (Task::none(), Some(Event::AddNetwork)),
+                    sidebar::Event::EditNetwork(server) => {
+                        (Task::none(), Some(Event::EditNetwork(server)))
                     }
                     sidebar::Event::ToggleInternalBuffer(buffer) => (
                         self.toggle_internal_buffer(config, main_window, buffer),
@@ -537,6 +818,10 @@ impl Dashboard {
                         let _ = open::that_detached(WIKI_WEBSITE);
                         (Task::none(), None)
                     }
+                    sidebar::Event::CycleProfile => {
+                        self.set_profile(config.next_profile(self.profile()));
+                        (Task::none(), None)
+                    }
                 };
 
                 return (
@@ -568,21 +853,101 @@ impl Dashboard {
             Message::History(message) => {
                 if let Some(event) = self.history.update(message) {
                     match event {
-                        history::manager::Event::Loaded(kind) => {
+                        history::manager::Event::Loaded(kind, chathistory) => {
+                            // Catch up on what was missed while the buffer was closed
+                            if let Some((server, subcommand)) = kind.server().zip(chathistory) {
+                                let backfill_disabled = subcommand.target().is_some_and(|target| {
+                                    clients.get_backfill_disabled(server, target)
+                                });
+
+                                if !backfill_disabled {
+                                    clients.send_chathistory_request(server, subcommand);
+                                }
+                            }
+
                             let buffer = kind.into();
 
                             if let Some((window, pane, state)) =
                                 self.panes.get_mut_by_buffer(main_window.id, &buffer)
+                            {
+                                let pending_scroll_to = state.buffer.pending_scroll_to();
+
+                                let scroll_to_backlog = state
+                                    .buffer
+                                    .scroll_to_backlog(&self.history, config)
+                                    .map(move |message| {
+                                        Message::Pane(window, pane::Message::Buffer(pane, message))
+                                    });
+
+                                let follow_scroll_to =
+                                    pending_scroll_to.map_or(Task::none(), |message| {
+                                        self.follow_scroll_to(main_window, &buffer, message, config)
+                                    });
+
+                                return (
+                                    Task::batch(vec![scroll_to_backlog, follow_scroll_to]),
+                                    None,
+                                );
+                            }
+                        }
+                        history::manager::Event::LoadedEvicted(kind) => {
+                            let buffer: data::Buffer = kind.into();
+
+                            // With a buffer open in several panes, only the
+                            // one waiting on the messages is scrolled
+                            if let Some((window, pane, state)) =
+                                self.panes.iter_mut(main_window.id).find(|(_, _, state)| {
+                                    state.buffer.data().as_ref() == Some(&buffer)
+                                        && state.buffer.pending_scroll_to().is_some()
+                                })
+                            {
+                                if let Some(message) = state.buffer.pending_scroll_to() {
+                                    let scroll_to_message = state
+                                        .buffer
+                                        .scroll_to_message(message, &self.history, config)
+                                        .map(move |message| {
+                                            Message::Pane(
+                                                window,
+                                                pane::Message::Buffer(pane, message),
+                                            )
+                                        });
+
+                                    let follow_scroll_to = self.follow_scroll_to(
+                                        main_window,
+                                        &buffer,
+                                        message,
+                                        config,
+                                    );
+
+                                    return (
+                                        Task::batch(vec![scroll_to_message, follow_scroll_to]),
+                                        None,
+                                    );
+                                }
+                            }
+
+                            // Continue to the previous highlight with the
+                            // older history loaded
+                            if let Some((window, pane, state)) =
+                                self.panes.iter_mut(main_window.id).find(|(_, _, state)| {
+                                    state.buffer.data().as_ref() == Some(&buffer)
+                                        && state.buffer.pending_highlight()
+                                })
                             {
                                 return (
-                                    state.buffer.scroll_to_backlog(&self.history, config).map(
-                                        move |message| {
+                                    state
+                                        .buffer
+                                        .scroll_to_highlight(
+                                            buffer::Direction::Previous,
+                                            &self.history,
+                                            config,
+                                        )
+                                        .map(move |message| {
                                             Message::Pane(
                                                 window,
                                                 pane::Message::Buffer(pane, message),
                                             )
-                                        },
-                                    ),
+                                        }),
                                     None,
                                 );
                             }
@@ -620,6 +985,30 @@ impl Dashboard {
             Message::DashboardSaved(Err(error)) => {
                 log::warn!("error saving dashboard: {error}");
             }
+            Message::NotesLoaded(notes) => {
+                // Notes set while loading are saved along with the rest
+                if self.notes.merge_loaded(notes) {
+                    return (self.save_notes(), None);
+                }
+            }
+            Message::NotesSaved(Ok(_)) => {
+                log::debug!("notes saved");
+            }
+            Message::NotesSaved(Err(error)) => {
+                log::warn!("error saving notes: {error}");
+            }
+            Message::ThemesLoaded(Ok(themes)) => {
+                self.command_bar = Some(CommandBar::themes(themes));
+            }
+            Message::ThemesLoaded(Err(error)) => {
+                log::warn!("error loading themes: {error}");
+            }
+            Message::ThemeSaved(Ok(_)) => {
+                log::info!("theme saved to config");
+            }
+            Message::ThemeSaved(Err(error)) => {
+                log::warn!("error saving theme: {error}");
+            }
             Message::Task(message) => {
                 let Some(command_bar) = &mut self.command_bar else {
                     return (Task::none(), None);
@@ -640,8 +1029,7 @@ impl Dashboard {
                             },
                             command_bar::Command::Buffer(command) => match command {
                                 command_bar::Buffer::Maximize(_) => {
-                                    self.maximize_pane();
-                                    (Task::none(), None)
+                                    (self.maximize_pane(main_window), None)
                                 }
                                 command_bar::Buffer::New => (
                                     self.new_pane(pane_grid::Axis::Horizontal, config, main_window),
@@ -649,30 +1037,55 @@ impl Dashboard {
                                 ),
                                 command_bar::Buffer::Close => {
                                     if let Some((window, pane)) = self.focus {
-                                        (self.close_pane(main_window, window, pane), None)
+                                        (
+                                            self.close_buffer_pane(
+                                                main_window,
+                                                window,
+                                                pane,
+                                                config,
+                                            ),
+                                            None,
+                                        )
                                     } else {
                                         (Task::none(), None)
                                     }
                                 }
-                                command_bar::Buffer::Replace(buffer) => {
-                                    let mut commands = vec![];
-
-                                    if let Some((window, pane)) = self.focus.take() {
-                                        if let Some(state) =
-                                            self.panes.get_mut(main_window.id, window, pane)
-                                        {
-                                            state.buffer =
-                                                Buffer::from(data::Buffer::Upstream(buffer));
-                                            self.last_changed = Some(Instant::now());
-
-                                            commands.extend(vec![
-                                                self.reset_pane(main_window, window, pane),
-                                                self.focus_pane(main_window, window, pane),
-                                            ]);
+                                command_bar::Buffer::Replace(buffer, _) => {
+                                    if let Some(action) =
+                                        config.sidebar.modified_buffer_action(self.modifiers)
+                                    {
+                                        (
+                                            self.open_with_action(
+                                                action,
+                                                buffer,
+                                                config,
+                                                main_window,
+                                            ),
+                                            None,
+                                        )
+                                    } else {
+                                        let mut commands = vec![];
+
+                                        if let Some((window, pane)) = self.focus.take() {
+                                            if let Some(state) =
+                                                self.panes.get_mut(main_window.id, window, pane)
+                                            {
+                                                state.buffer =
+                                                    Buffer::from(data::Buffer::Upstream(buffer));
+                                                self.last_changed = Some(Instant::now());
+
+                                                commands.extend(vec![
+                                                    self.reset_pane(main_window, window, pane),
+                                                    self.focus_pane(main_window, window, pane),
+                                                ]);
+                                            }
                                         }
-                                    }
 
-                                    (Task::batch(commands), None)
+                                        (Task::batch(commands), None)
+                                    }
+                                }
+                                command_bar::Buffer::SplitScrollback => {
+                                    (self.split_scrollback(main_window), None)
                                 }
                                 command_bar::Buffer::Popout => {
                                     (self.popout_pane(main_window), None)
@@ -680,6 +1093,29 @@ impl Dashboard {
                                 command_bar::Buffer::Merge => {
                                     (self.merge_pane(config, main_window), None)
                                 }
+                                command_bar::Buffer::Clear { history } => {
+                                    let kinds = self
+                                        .get_focused(main_window)
+                                        .and_then(|(_, _, pane)| pane.resource())
+                                        .map(|resource| resource.kind)
+                                        .into_iter()
+                                        .collect();
+
+                                    if history {
+                                        (Task::none(), Some(Event::ConfirmDeleteHistory(kinds)))
+                                    } else {
+                                        (self.clear_buffers(kinds), None)
+                                    }
+                                }
+                                command_bar::Buffer::ClearAll => {
+                                    let kinds = self
+                                        .panes
+                                        .resources()
+                                        .map(|resource| resource.kind)
+                                        .collect();
+
+                                    (self.clear_buffers(kinds), None)
+                                }
                                 command_bar::Buffer::ToggleInternal(buffer) => (
                                     self.toggle_internal_buffer(config, main_window, buffer),
                                     None,
@@ -777,7 +1213,10 @@ impl Dashboard {
                     MoveRight => return (move_focus(pane_grid::Direction::Right), None),
                     CloseBuffer => {
                         if let Some((window, pane)) = self.focus {
-                            return (self.close_pane(main_window, window, pane), None);
+                            return (
+                                self.close_buffer_pane(main_window, window, pane, config),
+                                None,
+                            );
                         }
                     }
                     MaximizeBuffer => {
@@ -785,11 +1224,15 @@ impl Dashboard {
                             // Only main window has >1 pane to maximize
                             if *window == main_window.id {
                                 self.panes.main.maximize(*pane);
+
+                                return (self.refocus_pane(main_window), None);
                             }
                         }
                     }
                     RestoreBuffer => {
                         self.panes.main.restore();
+
+                        return (self.refocus_pane(main_window), None);
                     }
                     CycleNextBuffer => {
                         let all_buffers = all_buffers(clients, &self.history);
@@ -826,7 +1269,12 @@ impl Dashboard {
                     LeaveBuffer => {
                         if let Some((_, _, state)) = self.get_focused_mut(main_window) {
                             if let Some(buffer) = state.buffer.upstream().cloned() {
-                                return self.leave_buffer(main_window, clients, buffer);
+                                return self.request_leave_buffer(
+                                    main_window,
+                                    clients,
+                                    buffer,
+                                    config,
+                                );
                             }
                         }
                     }
@@ -896,10 +1344,42 @@ impl Dashboard {
                             None,
                         );
                     }
+                    PreviousHighlight | NextHighlight => {
+                        let direction = if matches!(shortcut, PreviousHighlight) {
+                            buffer::Direction::Previous
+                        } else {
+                            buffer::Direction::Next
+                        };
+
+                        if let Some((window, pane)) = self.focus {
+                            if let Some(state) = self.panes.get_mut(main_window.id, window, pane) {
+                                return (
+                                    state
+                                        .buffer
+                                        .scroll_to_highlight(direction, &self.history, config)
+                                        .map(move |message| {
+                                            Message::Pane(
+                                                window,
+                                                pane::Message::Buffer(pane, message),
+                                            )
+                                        }),
+                                    None,
+                                );
+                            }
+                        }
+                    }
+                    UndoCloseBuffer => {
+                        return (self.undo_close_buffer(main_window, clients, config), None);
+                    }
                 }
             }
             Message::FileTransfer(update) => {
-                self.file_transfers.update(update);
+                if let Some(finished) = self.file_transfers.update(update) {
+                    self.finished_file_transfers
+                        .get_or_insert_with(|| (Instant::now(), vec![]))
+                        .1
+                        .push(finished);
+                }
             }
             Message::SendFileSelected(server, to, path) => {
                 if let Some(server_handle) = clients.get_server_handle(&server) {
@@ -922,6 +1402,8 @@ impl Dashboard {
                 if !any_closed {
                     if self.is_pane_maximized() && window == main_window.id {
                         self.panes.main.restore();
+
+                        return (self.refocus_pane(main_window), None);
                     } else {
                         self.focus = None;
                     }
@@ -957,6 +1439,14 @@ impl Dashboard {
             Message::ConfigReloaded(config) => {
                 return (Task::none(), Some(Event::ConfigReloaded(config)));
             }
+            Message::Translated(hash, result) => {
+                let next = self.translations.finish(hash, result);
+
+                return (self.translate(next, config), None);
+            }
+            Message::UndoCloseBuffer => {
+                return (self.undo_close_buffer(main_window, clients, config), None);
+            }
             Message::Client(message) => match message {
                 client::Message::ChatHistoryRequest(server, subcommand) => {
                     clients.send_chathistory_request(&server, subcommand);
@@ -974,27 +1464,30 @@ impl Dashboard {
                     );
                 }
                 client::Message::RequestNewerChatHistory(server, target, server_time) => {
-                    let message_reference_types =
-                        clients.get_server_chathistory_message_reference_types(&server);
-
-                    let message_reference = self
-                        .history
-                        .last_can_reference_before(
-                            server.clone(),
-                            clients.get_chantypes(&server),
-                            target.clone(),
-                            server_time,
-                        )
-                        .map_or(MessageReference::None, |message_references| {
-                            message_references.message_reference(&message_reference_types)
-                        });
-
-                    let limit = clients.get_server_chathistory_limit(&server);
+                    if clients.get_backfill_disabled(&server, &target) {
+                        return (Task::none(), None);
+                    }
 
-                    clients.send_chathistory_request(
-                        &server,
-                        ChatHistorySubcommand::Latest(target.clone(), message_reference, limit),
+                    let chathistory_references = self.history.last_can_reference_before(
+                        server.clone(),
+                        clients.get_chantypes(&server),
+                        target.clone(),
+                        server_time,
                     );
+
+                    let metadata = history::Metadata {
+                        chathistory_references,
+                        ..Default::default()
+                    };
+
+                    let Some(subcommand) =
+                        metadata.backfill_request(&target, &clients.get_server_caps(&server))
+                    else {
+                        log::debug!("[{server}] no usable reference to backfill {target}");
+                        return (Task::none(), None);
+                    };
+
+                    clients.send_chathistory_request(&server, subcommand);
                 }
                 client::Message::RequestChatHistoryTargets(server, timestamp, server_time) => {
                     let start_message_reference = timestamp
@@ -1041,6 +1534,9 @@ impl Dashboard {
                         false,
                         clients,
                         &self.file_transfers,
+                        &self.raw_log,
+                        &self.translations,
+                        &self.notes,
                         &self.history,
                         &self.side_menu,
                         config,
@@ -1086,6 +1582,9 @@ impl Dashboard {
                 maximized,
                 clients,
                 &self.file_transfers,
+                &self.raw_log,
+                &self.translations,
+                &self.notes,
                 &self.history,
                 &self.side_menu,
                 config,
@@ -1111,11 +1610,15 @@ impl Dashboard {
                 clients,
                 &self.history,
                 &self.panes,
+                &self.notes,
                 self.focus,
                 config.sidebar,
+                self.modifiers,
                 &config.keyboard,
                 &self.file_transfers,
                 version,
+                self.profile(),
+                !config.profiles.is_empty(),
                 main_window.id,
             )
             .map(|e| e.map(Message::Sidebar));
@@ -1141,6 +1644,22 @@ impl Dashboard {
                 .into()
         };
 
+        let base = if let Some(closed) = self
+            .closed_buffer
+            .as_ref()
+            .filter(|_| self.command_bar.is_none())
+        {
+            anchored_overlay(
+                base,
+                closed_buffer_toast(closed),
+                anchored_overlay::Anchor::BelowTopCentered,
+                10.0,
+            )
+        } else {
+            // Align `base` into same view tree shape as `anchored_overlay`
+            column![base].into()
+        };
+
         let base = if let Some(command_bar) = self.command_bar.as_ref() {
             let background = anchored_overlay(
                 base,
@@ -1212,22 +1731,36 @@ impl Dashboard {
                 }
             }
             Copy => selectable_text::selected(Message::SelectedText),
+            Modifiers(modifiers) => {
+                self.modifiers = modifiers;
+
+                Task::none()
+            }
             Home => {
-                if config.buffer.chathistory.infinite_scroll {
-                    if let Some((_, _, state)) = self.get_focused(main_window) {
+                let mut load_evicted = Task::none();
+
+                if let Some((_, _, state)) = self.get_focused(main_window) {
+                    let kind = state.resource().map(|resource| resource.kind);
+
+                    if let Some(kind) = kind.filter(|kind| self.history.has_evicted(kind)) {
+                        load_evicted = self.load_evicted(kind);
+                    } else if config.buffer.chathistory.infinite_scroll {
                         if let Some(buffer) = state.buffer.data() {
                             self.request_older_chathistory(clients, &buffer);
                         }
                     }
                 }
 
-                self.get_focused_mut(main_window)
+                let scroll_to_start = self
+                    .get_focused_mut(main_window)
                     .map(|(window, id, pane)| {
                         pane.buffer.scroll_to_start().map(move |message| {
                             Message::Pane(window, pane::Message::Buffer(id, message))
                         })
                     })
-                    .unwrap_or_else(Task::none)
+                    .unwrap_or_else(Task::none);
+
+                Task::batch(vec![load_evicted, scroll_to_start])
             }
             End => self
                 .get_focused_mut(main_window)
@@ -1272,7 +1805,9 @@ impl Dashboard {
         } else {
             match config.sidebar.buffer_action {
                 // Don't replace for internal buffers
-                BufferAction::NewPane | BufferAction::ReplacePane => self.open_buffer(
+                BufferAction::NewPane
+                | BufferAction::ReplacePane
+                | BufferAction::ReplaceLargestPane => self.open_buffer(
                     main_window,
                     data::Buffer::Internal(buffer),
                     config.buffer.clone().into(),
@@ -1302,14 +1837,26 @@ impl Dashboard {
             }
         }
 
-        // If we only have one pane, and its empty, we replace it.
-        if self.panes.len() == 1 {
-            for (id, pane) in panes.main.iter() {
-                if matches!(pane.buffer, Buffer::Empty) {
-                    self.panes
-                        .main
-                        .panes
-                        .entry(*id)
+        self.new_buffer_pane(main_window, buffer, settings)
+    }
+
+    /// Open `buffer` in a new pane, even if it's already open elsewhere
+    fn new_buffer_pane(
+        &mut self,
+        main_window: &Window,
+        buffer: data::Buffer,
+        settings: buffer::Settings,
+    ) -> Task<Message> {
+        let panes = self.panes.clone();
+
+        // If we only have one pane, and its empty, we replace it.
+        if self.panes.len() == 1 {
+            for (id, pane) in panes.main.iter() {
+                if matches!(pane.buffer, Buffer::Empty) {
+                    self.panes
+                        .main
+                        .panes
+                        .entry(*id)
                         .and_modify(|p| *p = Pane::with_settings(Buffer::from(buffer), settings));
                     self.last_changed = Some(Instant::now());
 
@@ -1345,32 +1892,163 @@ impl Dashboard {
         Task::none()
     }
 
+    fn replace_pane(
+        &mut self,
+        main_window: &Window,
+        window: window::Id,
+        pane: pane_grid::Pane,
+        buffer: data::buffer::Upstream,
+    ) -> Task<Message> {
+        let Some(state) = self.panes.get_mut(main_window.id, window, pane) else {
+            return Task::none();
+        };
+
+        state.buffer = Buffer::from(data::Buffer::Upstream(buffer));
+        self.last_changed = Some(Instant::now());
+        self.focus = None;
+
+        Task::batch(vec![
+            self.reset_pane(main_window, window, pane),
+            self.focus_pane(main_window, window, pane),
+        ])
+    }
+
+    /// Replace the buffer of the largest pane in the main window
+    fn replace_largest_pane(
+        &mut self,
+        main_window: &Window,
+        buffer: data::buffer::Upstream,
+        config: &Config,
+    ) -> Task<Message> {
+        let largest = self
+            .panes
+            .main
+            .layout()
+            .pane_regions(4.0, main_window.size)
+            .into_iter()
+            .max_by(|(_, a), (_, b)| (a.width * a.height).total_cmp(&(b.width * b.height)))
+            .map(|(pane, _)| pane);
+
+        match largest {
+            Some(pane) => self.replace_pane(main_window, main_window.id, pane, buffer),
+            None => self.open_buffer(
+                main_window,
+                data::Buffer::Upstream(buffer),
+                config.buffer.clone().into(),
+            ),
+        }
+    }
+
+    /// Open `buffer` as configured for sidebar buffer `action`s
+    fn open_with_action(
+        &mut self,
+        action: BufferAction,
+        buffer: data::buffer::Upstream,
+        config: &Config,
+        main_window: &Window,
+    ) -> Task<Message> {
+        match action {
+            BufferAction::NewPane => self.open_buffer(
+                main_window,
+                data::Buffer::Upstream(buffer),
+                config.buffer.clone().into(),
+            ),
+            BufferAction::ReplacePane => match self.focus {
+                Some((window, pane)) => self.replace_pane(main_window, window, pane, buffer),
+                None => self.open_buffer(
+                    main_window,
+                    data::Buffer::Upstream(buffer),
+                    config.buffer.clone().into(),
+                ),
+            },
+            BufferAction::ReplaceLargestPane => {
+                self.replace_largest_pane(main_window, buffer, config)
+            }
+            BufferAction::NewWindow => self.open_popout_window(
+                main_window,
+                Pane::new(Buffer::from(data::Buffer::Upstream(buffer)), config),
+            ),
+        }
+    }
+
+    /// Leave `buffer`, first asking to confirm if configured to and it has
+    /// unread highlights or an unsent draft
+    fn request_leave_buffer(
+        &mut self,
+        main_window: &Window,
+        clients: &mut data::client::Map,
+        buffer: buffer::Upstream,
+        config: &Config,
+    ) -> (Task<Message>, Option<Event>) {
+        if config.buffer.close.confirm {
+            let unread_highlights = self
+                .history
+                .has_unread_highlights(&history::Kind::from_input_buffer(buffer.clone()));
+            let draft = !self.history.input(&buffer).draft.trim().is_empty();
+
+            let reason = match (unread_highlights, draft) {
+                (true, true) => Some("It has unread highlights and an unsent draft"),
+                (true, false) => Some("It has unread highlights"),
+                (false, true) => Some("It has an unsent draft"),
+                (false, false) => None,
+            };
+
+            if let Some(reason) = reason {
+                if !matches!(buffer, buffer::Upstream::Server(_)) {
+                    return (
+                        Task::none(),
+                        Some(Event::ConfirmLeaveBuffer(buffer, reason.to_string())),
+                    );
+                }
+            }
+        }
+
+        self.leave_buffer(main_window, clients, buffer, config)
+    }
+
     pub fn leave_buffer(
         &mut self,
         main_window: &Window,
         clients: &mut data::client::Map,
         buffer: buffer::Upstream,
+        config: &Config,
     ) -> (Task<Message>, Option<Event>) {
         let open = self
             .panes
             .iter(main_window.id)
-            .find_map(|(window, pane, state)| {
+            .filter_map(|(window, pane, state)| {
                 (state.buffer.upstream() == Some(&buffer)).then_some((window, pane))
-            });
+            })
+            .collect::<Vec<_>>();
 
         let mut tasks = vec![];
+        let mut closed_panes = vec![];
 
-        // Close pane
-        if let Some((window, pane)) = open {
+        // Close panes, including split views of the buffer
+        for (window, pane) in open {
             if self.focus == Some((window, pane)) {
                 self.focus = None;
             }
 
+            closed_panes.extend(self.closed_pane(main_window, window, pane));
             tasks.push(self.close_pane(main_window, window, pane));
 
             self.last_changed = Some(Instant::now());
         }
 
+        if !matches!(buffer, buffer::Upstream::Server(_)) {
+            self.record_closed_buffer(
+                ClosedBuffer {
+                    draft: self.history.input(&buffer).draft.to_string(),
+                    parted: matches!(buffer, buffer::Upstream::Channel(..)),
+                    buffer: buffer.clone(),
+                    panes: closed_panes,
+                    closed_at: Instant::now(),
+                },
+                config,
+            );
+        }
+
         match buffer.clone() {
             buffer::Upstream::Server(server) => {
                 (Task::batch(tasks), Some(Event::QuitServer(server)))
@@ -1415,6 +2093,39 @@ impl Dashboard {
         }
     }
 
+    /// Translate `message` if it was received in a buffer with automatic
+    /// translation turned on
+    pub fn translate_message(
+        &mut self,
+        server: &Server,
+        message: &data::Message,
+        config: &Config,
+    ) -> Task<Message> {
+        let Some(kind) = history::Kind::from_server_message(server.clone(), message) else {
+            return Task::none();
+        };
+
+        let next = self.translations.queue(&kind, message);
+
+        self.translate(next, config)
+    }
+
+    fn translate(&self, job: Option<(message::Hash, String)>, config: &Config) -> Task<Message> {
+        let Some((hash, text)) = job else {
+            return Task::none();
+        };
+
+        match config.buffer.translation.command.clone() {
+            Some(command) => Task::perform(translation::translate(command, text), move |result| {
+                Message::Translated(hash, result)
+            }),
+            None => Task::done(Message::Translated(
+                hash,
+                Err(translation::Error::NotConfigured),
+            )),
+        }
+    }
+
     pub fn record_log(&mut self, record: data::log::Record) -> Task<Message> {
         if let Some(task) = self.history.record_log(record) {
             Task::perform(task, Message::History)
@@ -1423,6 +2134,10 @@ impl Dashboard {
         }
     }
 
+    pub fn record_raw_line(&mut self, line: raw_log::Line) {
+        self.raw_log.push(line);
+    }
+
     pub fn record_highlight(&mut self, message: data::Message) -> Task<Message> {
         if let Some(task) = self.history.record_highlight(message) {
             Task::perform(task, Message::History)
@@ -1477,7 +2192,10 @@ impl Dashboard {
         let server = upstream.server();
 
         if clients.get_server_supports_chathistory(server) {
-            if let Some(target) = upstream.target() {
+            if let Some(target) = upstream
+                .target()
+                .filter(|target| !clients.get_backfill_disabled(server, target))
+            {
                 let message_reference_types =
                     clients.get_server_chathistory_message_reference_types(server);
 
@@ -1507,6 +2225,93 @@ impl Dashboard {
         }
     }
 
+    /// Clear the given buffers for the rest of the session, their history
+    /// stays on disk
+    fn clear_buffers(&mut self, kinds: Vec<history::Kind>) -> Task<Message> {
+        Task::batch(
+            kinds
+                .into_iter()
+                .filter_map(|kind| self.history.clear(kind))
+                .map(|task| Task::perform(task, Message::History)),
+        )
+    }
+
+    /// Clear the given buffers and delete their history from disk
+    pub fn delete_history(&mut self, kinds: Vec<history::Kind>) -> Task<Message> {
+        Task::batch(
+            kinds
+                .into_iter()
+                .map(|kind| Task::perform(self.history.delete(kind), Message::History)),
+        )
+    }
+
+    /// Keep loading evicted history into `buffer` until the message it's
+    /// scrolling to is in memory. If all history is loaded and the message
+    /// still isn't found, it was pruned and highlights pointing to it are
+    /// marked unavailable.
+    fn follow_scroll_to(
+        &mut self,
+        main_window: &Window,
+        buffer: &data::Buffer,
+        message: message::Hash,
+        config: &Config,
+    ) -> Task<Message> {
+        let Some(kind) = buffer
+            .upstream()
+            .cloned()
+            .map(history::Kind::from_input_buffer)
+        else {
+            return Task::none();
+        };
+
+        let Some((_, _, state)) = self.panes.get_mut_by_buffer(main_window.id, buffer) else {
+            return Task::none();
+        };
+
+        if state.buffer.pending_scroll_to() == Some(message) {
+            // Otherwise history is still loading, we'll be back on `Loaded`
+            if self.history.has_evicted(&kind) {
+                return self.load_evicted(kind);
+            }
+
+            return Task::none();
+        }
+
+        let Some(history::View {
+            old_messages,
+            new_messages,
+            ..
+        }) = self.history.get_messages(&kind, None, &config.buffer)
+        else {
+            return Task::none();
+        };
+
+        if old_messages
+            .iter()
+            .chain(&new_messages)
+            .any(|m| m.hash == message)
+        {
+            return Task::none();
+        }
+
+        if let Some((_, _, state)) = self.panes.get_mut_by_buffer(
+            main_window.id,
+            &data::Buffer::Internal(buffer::Internal::Highlights),
+        ) {
+            if let Buffer::Highlights(highlights) = &mut state.buffer {
+                highlights.mark_unavailable(message);
+            }
+        }
+
+        Task::none()
+    }
+
+    fn load_evicted(&mut self, kind: history::Kind) -> Task<Message> {
+        self.history
+            .load_evicted(kind)
+            .map_or(Task::none(), |task| Task::perform(task, Message::History))
+    }
+
     pub fn broadcast(
         &mut self,
         server: &Server,
@@ -1631,12 +2436,41 @@ impl Dashboard {
         Task::none()
     }
 
-    fn maximize_pane(&mut self) {
+    fn maximize_pane(&mut self, main_window: &Window) -> Task<Message> {
         if self.is_pane_maximized() {
             self.panes.main.restore();
         } else if let Some((_, pane)) = self.focus {
             self.panes.main.maximize(pane);
         }
+
+        self.refocus_pane(main_window)
+    }
+
+    /// Focus the input of the focused pane again, which can be lost when the
+    /// layout of panes changes
+    fn refocus_pane(&self, main_window: &Window) -> Task<Message> {
+        if self.command_bar.is_some() {
+            return Task::none();
+        }
+
+        let Some((window, pane, state)) = self.get_focused(main_window) else {
+            return Task::none();
+        };
+
+        state
+            .buffer
+            .focus()
+            .map(move |message| Message::Pane(window, pane::Message::Buffer(pane, message)))
+    }
+
+    /// Keep the focused pane focused when `window` is resized, e.g. when
+    /// it's maximized or moved to a different monitor
+    pub fn window_resized(&self, main_window: &Window, window: window::Id) -> Task<Message> {
+        if self.focus.is_some_and(|(focused, _)| focused == window) {
+            self.refocus_pane(main_window)
+        } else {
+            Task::none()
+        }
     }
 
     fn is_pane_maximized(&self) -> bool {
@@ -1703,6 +2537,39 @@ impl Dashboard {
         Task::none()
     }
 
+    /// Open the focused buffer again below itself, so backlog can be read in
+    /// one view while the other follows new messages. Views share history,
+    /// read marker and input.
+    fn split_scrollback(&mut self, main_window: &Window) -> Task<Message> {
+        let Some((window, pane)) = self.focus.filter(|(window, _)| *window == main_window.id)
+        else {
+            return Task::none();
+        };
+
+        let Some(state) = self.panes.get(main_window.id, window, pane) else {
+            return Task::none();
+        };
+
+        let Some(buffer) = state.buffer.upstream().cloned() else {
+            return Task::none();
+        };
+
+        let settings = state.settings.clone();
+
+        let result = self.panes.main.split(
+            pane_grid::Axis::Horizontal,
+            pane,
+            Pane::with_settings(Buffer::from(data::Buffer::Upstream(buffer)), settings),
+        );
+        self.last_changed = Some(Instant::now());
+
+        if let Some((pane, _)) = result {
+            return self.focus_pane(main_window, main_window.id, pane);
+        }
+
+        Task::none()
+    }
+
     fn reset_pane(
         &mut self,
         main_window: &Window,
@@ -1716,6 +2583,207 @@ impl Dashboard {
         Task::none()
     }
 
+    /// Close the pane of a buffer, which can be reopened in its place with undo
+    fn close_buffer_pane(
+        &mut self,
+        main_window: &Window,
+        window: window::Id,
+        pane: pane_grid::Pane,
+        config: &Config,
+    ) -> Task<Message> {
+        let buffer = self
+            .panes
+            .get(main_window.id, window, pane)
+            .and_then(|state| state.buffer.upstream().cloned());
+
+        if let Some((buffer, closed_pane)) = buffer.zip(self.closed_pane(main_window, window, pane))
+        {
+            self.record_closed_buffer(
+                ClosedBuffer {
+                    draft: self.history.input(&buffer).draft.to_string(),
+                    parted: false,
+                    buffer,
+                    panes: vec![closed_pane],
+                    closed_at: Instant::now(),
+                },
+                config,
+            );
+        }
+
+        self.close_pane(main_window, window, pane)
+    }
+
+    /// Where `pane` is and its settings, to be able to reopen it once closed
+    fn closed_pane(
+        &self,
+        main_window: &Window,
+        window: window::Id,
+        pane: pane_grid::Pane,
+    ) -> Option<ClosedPane> {
+        let state = self.panes.get(main_window.id, window, pane)?;
+
+        let position = if window != main_window.id {
+            PanePosition::Popout
+        } else if let Some((sibling, axis, ratio, first)) =
+            pane_position(self.panes.main.layout(), pane)
+        {
+            PanePosition::Split {
+                pane,
+                sibling,
+                axis,
+                ratio,
+                first,
+            }
+        } else {
+            PanePosition::Only(pane)
+        };
+
+        Some(ClosedPane {
+            position,
+            settings: state.settings.clone(),
+        })
+    }
+
+    fn record_closed_buffer(&mut self, mut closed: ClosedBuffer, config: &Config) {
+        if config.buffer.close.undo_duration().is_none() {
+            return;
+        }
+
+        // Closing the same buffer again, e.g. leaving a channel after closing
+        // its pane, extends the undo rather than replacing it
+        if let Some(previous) = self
+            .closed_buffer
+            .take()
+            .filter(|previous| previous.buffer == closed.buffer)
+        {
+            if closed.panes.is_empty() {
+                closed.panes = previous.panes;
+            }
+            if closed.draft.is_empty() {
+                closed.draft = previous.draft;
+            }
+            closed.parted |= previous.parted;
+        }
+
+        self.closed_buffer = Some(closed);
+    }
+
+    /// Reopen the latest closed buffer where it was, rejoining the channel
+    /// and restoring the draft
+    fn undo_close_buffer(
+        &mut self,
+        main_window: &Window,
+        clients: &mut data::client::Map,
+        config: &Config,
+    ) -> Task<Message> {
+        let Some(closed) = self.closed_buffer.take() else {
+            return Task::none();
+        };
+
+        if closed.parted {
+            if let buffer::Upstream::Channel(server, channel) = &closed.buffer {
+                clients.join(server, slice::from_ref(channel));
+            }
+        }
+
+        if !closed.draft.is_empty() {
+            self.history.record_draft(data::input::Draft {
+                buffer: closed.buffer.clone(),
+                text: closed.draft,
+            });
+        }
+
+        // Reopened in the meantime, nothing to restore
+        if let Some((window, pane)) =
+            self.panes
+                .iter(main_window.id)
+                .find_map(|(window, pane, state)| {
+                    (state.buffer.upstream() == Some(&closed.buffer)).then_some((window, pane))
+                })
+        {
+            return self.focus_pane(main_window, window, pane);
+        }
+
+        if closed.panes.is_empty() {
+            return self.open_buffer(
+                main_window,
+                data::Buffer::Upstream(closed.buffer),
+                config.buffer.clone().into(),
+            );
+        }
+
+        let mut tasks = vec![];
+        // Panes restored so far, for panes which were split from them
+        let mut restored = HashMap::new();
+
+        // Restore in reverse, as each pane's position was taken after
+        // closing the ones before it
+        for closed_pane in closed.panes.into_iter().rev() {
+            let state = Pane::with_settings(
+                Buffer::from(data::Buffer::Upstream(closed.buffer.clone())),
+                closed_pane.settings,
+            );
+
+            match closed_pane.position {
+                PanePosition::Split {
+                    pane,
+                    sibling,
+                    axis,
+                    ratio,
+                    first,
+                } => {
+                    let sibling = restored.get(&sibling).copied().unwrap_or(sibling);
+
+                    let split = if self.panes.main.get(sibling).is_some() {
+                        self.panes.main.split(axis, sibling, state.clone())
+                    } else {
+                        None
+                    };
+
+                    if let Some((new, split)) = split {
+                        if first {
+                            self.panes.main.swap(new, sibling);
+                        }
+                        self.panes.main.resize(split, ratio);
+
+                        restored.insert(pane, new);
+                        tasks.push(self.focus_pane(main_window, main_window.id, new));
+                    } else {
+                        tasks.push(self.new_buffer_pane(
+                            main_window,
+                            data::Buffer::Upstream(closed.buffer.clone()),
+                            state.settings,
+                        ));
+                    }
+                }
+                PanePosition::Only(pane) => {
+                    if let Some(empty) = self
+                        .panes
+                        .main
+                        .get_mut(pane)
+                        .filter(|empty| matches!(empty.buffer, Buffer::Empty))
+                    {
+                        *empty = state;
+                        tasks.push(self.focus_pane(main_window, main_window.id, pane));
+                    } else {
+                        tasks.push(self.new_buffer_pane(
+                            main_window,
+                            data::Buffer::Upstream(closed.buffer.clone()),
+                            state.settings,
+                        ));
+                    }
+                }
+                PanePosition::Popout => {
+                    tasks.push(self.open_popout_window(main_window, state));
+                }
+            }
+        }
+
+        self.last_changed = Some(Instant::now());
+
+        Task::batch(tasks)
+    }
+
     fn close_pane(
         &mut self,
         main_window: &Window,
@@ -1767,24 +2835,63 @@ impl Dashboard {
         Task::none()
     }
 
-    pub fn track(&mut self) -> Task<Message> {
+    pub fn track(&mut self, clients: &data::client::Map) -> Task<Message> {
         let resources = self.panes.resources().collect();
 
         Task::batch(
             self.history
-                .track(resources)
+                .track(resources, |server| clients.get_server_caps(server))
                 .into_iter()
                 .map(|fut| Task::perform(fut, Message::History))
                 .collect::<Vec<_>>(),
         )
     }
 
-    pub fn tick(&mut self, now: Instant) -> Task<Message> {
+    pub fn tick(
+        &mut self,
+        now: Instant,
+        clients: &mut data::client::Map,
+        config: &Config,
+        main_window: &Window,
+    ) -> Task<Message> {
+        let mark_seen = self.mark_seen(now, config, main_window);
+        self.notify_finished_file_transfers(now, config);
+
+        if self.closed_buffer.as_ref().is_some_and(|closed| {
+            config
+                .buffer
+                .close
+                .undo_duration()
+                .map_or(true, |duration| {
+                    now.duration_since(closed.closed_at) >= duration
+                })
+        }) {
+            self.closed_buffer = None;
+        }
+
+        if config.buffer.query.whois_summary {
+            self.request_whois_summaries(clients, config.buffer.query.whowas_summary);
+        }
+
+        let member_counts = clients
+            .connected_servers()
+            .flat_map(|server| {
+                clients.get_channels(server).iter().filter_map(|channel| {
+                    let count = clients.get_channel_users(server, channel).len();
+
+                    // Not yet received the channel's names
+                    (count > 0).then(|| (server.clone(), channel.clone(), count))
+                })
+            })
+            .collect::<Vec<_>>();
+
         let history = Task::batch(
             self.history
                 .tick(now.into())
                 .into_iter()
+                .chain(self.history.record_member_counts(Utc::now(), member_counts))
                 .map(|task| Task::perform(task, Message::History))
+                .chain(Some(mark_seen))
                 .collect::<Vec<_>>(),
         );
 
@@ -1804,6 +2911,90 @@ impl Dashboard {
         history
     }
 
+    pub fn set_window_focused(&mut self, window: window::Id, focused: bool) {
+        if focused {
+            self.focused_window = Some(window);
+        } else if self.focused_window == Some(window) {
+            self.focused_window = None;
+        }
+
+        // Regaining focus waits out the delay again
+        self.reading_since = None;
+    }
+
+    /// History of the buffer being read: in the focused pane of the focused
+    /// window, and scrolled to the bottom
+    fn reading(&self, main_window: &Window) -> Option<history::Kind> {
+        let (window, pane) = self.focus?;
+
+        if self.focused_window != Some(window) {
+            return None;
+        }
+
+        let state = self.panes.get(main_window.id, window, pane)?;
+
+        if state.buffer.is_scrolled_to_bottom() != Some(true) {
+            return None;
+        }
+
+        state.resource().map(|resource| resource.kind)
+    }
+
+    /// Mark the buffer being read as seen, once it's been read for the
+    /// configured delay. Seeing the highlights buffer also marks the
+    /// highlights of every channel as read
+    pub fn mark_seen(
+        &mut self,
+        now: Instant,
+        config: &Config,
+        main_window: &Window,
+    ) -> Task<Message> {
+        if !config.buffer.history.mark_read_when_focused {
+            return Task::none();
+        }
+
+        let Some(reading) = self.reading(main_window) else {
+            self.reading_since = None;
+            return Task::none();
+        };
+
+        match &self.reading_since {
+            Some((kind, since)) if *kind == reading => {
+                if now.duration_since(*since) < config.buffer.history.mark_read_delay() {
+                    return Task::none();
+                }
+
+                self.history.mark_seen(&reading);
+
+                if reading == history::Kind::Highlights {
+                    return Task::batch(
+                        self.history
+                            .mark_all_highlights_read()
+                            .into_iter()
+                            .map(|task| Task::perform(task, Message::History))
+                            .collect::<Vec<_>>(),
+                    );
+                }
+            }
+            _ => {
+                self.reading_since = Some((reading, now));
+            }
+        }
+
+        Task::none()
+    }
+
+    /// Look up the users of open queries, lookups are cached per connection
+    fn request_whois_summaries(&self, clients: &mut data::client::Map, whowas: bool) {
+        let servers = clients.connected_servers().cloned().collect::<Vec<_>>();
+
+        for server in servers {
+            for nick in self.history.get_unique_queries(&server) {
+                clients.request_whois_summary(&server, nick, whowas);
+            }
+        }
+    }
+
     pub fn toggle_command_bar(
         &mut self,
         buffers: &[buffer::Upstream],
@@ -1837,6 +3028,7 @@ impl Dashboard {
     ) {
         self.command_bar = Some(CommandBar::new(
             buffers,
+            &self.notes,
             version,
             config,
             self.focus,
@@ -1854,6 +3046,45 @@ impl Dashboard {
         data::buffer::Resize::action(can_resize_buffer, self.is_pane_maximized())
     }
 
+    /// Ask for the key of `channel` after joining it failed, in the focused
+    /// pane if it's of `server`, otherwise in a pane of `server`
+    pub fn prompt_channel_key(
+        &mut self,
+        server: &Server,
+        channel: String,
+        main_window: &Window,
+    ) -> Task<Message> {
+        let of_server = |state: &Pane| {
+            state
+                .buffer
+                .upstream()
+                .is_some_and(|buffer| buffer.server() == server)
+        };
+
+        let target = self
+            .get_focused(main_window)
+            .filter(|(_, _, state)| of_server(state))
+            .or_else(|| {
+                self.panes
+                    .iter(main_window.id)
+                    .find(|(_, _, state)| of_server(state))
+            })
+            .map(|(window, pane, _)| (window, pane));
+
+        let Some((window, pane)) = target else {
+            return Task::none();
+        };
+
+        let Some(state) = self.panes.get_mut(main_window.id, window, pane) else {
+            return Task::none();
+        };
+
+        state
+            .buffer
+            .prompt_channel_key(channel)
+            .map(move |message| Message::Pane(window, pane::Message::Buffer(pane, message)))
+    }
+
     pub fn receive_file_transfer(
         &mut self,
         server: &Server,
@@ -1864,7 +3095,11 @@ impl Dashboard {
             .file_transfers
             .receive(request.clone(), config.proxy.as_ref())
         {
-            notification::file_transfer_request(&config.notifications, request.from, server);
+            notification::file_transfer_request(
+                config.notifications(self.profile()),
+                request.from,
+                server,
+            );
 
             return Some(self.handle_file_transfer_event(server, event));
         }
@@ -1872,6 +3107,41 @@ impl Dashboard {
         None
     }
 
+    fn notify_finished_file_transfers(&mut self, now: Instant, config: &Config) {
+        let Some((since, _)) = &self.finished_file_transfers else {
+            return;
+        };
+
+        if now.duration_since(*since) < FILE_TRANSFER_NOTIFICATION_WINDOW {
+            return;
+        }
+
+        let Some((_, finished)) = self.finished_file_transfers.take() else {
+            return;
+        };
+
+        let (completed, failed): (Vec<_>, Vec<_>) = finished.into_iter().partition(|transfer| {
+            matches!(transfer.status, file_transfer::Status::Completed { .. })
+        });
+
+        let completed = completed
+            .into_iter()
+            .map(|transfer| {
+                let save_path = self
+                    .file_transfers
+                    .save_path(&transfer.id)
+                    .map(PathBuf::from);
+
+                (transfer, save_path)
+            })
+            .collect::<Vec<_>>();
+
+        let notifications = config.notifications(self.profile());
+
+        notification::file_transfers_completed(notifications, &completed);
+        notification::file_transfers_failed(notifications, &failed);
+    }
+
     pub fn handle_file_transfer_event(
         &mut self,
         server: &Server,
@@ -1944,14 +3214,23 @@ impl Dashboard {
             },
             focus: None,
             side_menu: Sidebar::new(),
-            history: history::Manager::default(),
+            history: history::Manager::new(config),
             last_changed: None,
             command_bar: None,
             file_transfers: file_transfer::Manager::new(config.file_transfer.clone()),
+            raw_log: raw_log::Log::default(),
+            translations: translation::Translations::default(),
             theme_editor: None,
+            modifiers: keyboard::Modifiers::default(),
+            profile: data.profile,
+            notes: Notes::default(),
+            focused_window: None,
+            reading_since: None,
+            finished_file_transfers: None,
+            closed_buffer: None,
         };
 
-        let mut tasks = vec![];
+        let mut tasks = vec![load_notes(config)];
 
         for pane in data.popout_panes {
             // Popouts are only a single pane
@@ -1969,6 +3248,29 @@ impl Dashboard {
         &self.history
     }
 
+    /// Apply a reloaded `config` to state which keeps its own copy of it
+    pub fn config_reloaded(&mut self, config: &Config) {
+        self.history.set_config(config);
+    }
+
+    /// Name of the active notification profile, `None` for the top level
+    /// settings
+    pub fn profile(&self) -> Option<&str> {
+        self.profile.as_deref()
+    }
+
+    fn save_notes(&self) -> Task<Message> {
+        Task::perform(
+            self.notes.clone().save(self.history.config().clone()),
+            Message::NotesSaved,
+        )
+    }
+
+    fn set_profile(&mut self, profile: Option<String>) {
+        self.profile = profile;
+        self.last_changed = Some(Instant::now());
+    }
+
     pub fn handle_window_event(
         &mut self,
         id: window::Id,
@@ -1981,10 +3283,10 @@ impl Dashboard {
                     self.panes.popout.remove(&id);
                     return window::close(id);
                 }
+                window::Event::Focused => self.set_window_focused(id, true),
+                window::Event::Unfocused => self.set_window_focused(id, false),
                 window::Event::Moved(_)
                 | window::Event::Resized(_)
-                | window::Event::Focused
-                | window::Event::Unfocused
                 | window::Event::Opened { .. } => {}
             }
         } else if self
@@ -2150,7 +3452,98 @@ impl<'a> From<&'a Dashboard> for data::Dashboard {
                 .values()
                 .map(|state| from_layout(state, state.layout().clone()))
                 .collect(),
+            profile: dashboard.profile.clone(),
+        }
+    }
+}
+
+fn load_notes(config: &Config) -> Task<Message> {
+    Task::perform(Notes::load(history::Config::new(config)), Message::NotesLoaded)
+}
+
+/// A closed channel or query, reopened as it was with undo
+struct ClosedBuffer {
+    buffer: buffer::Upstream,
+    panes: Vec<ClosedPane>,
+    draft: String,
+    /// Closing it parted the channel
+    parted: bool,
+    closed_at: Instant,
+}
+
+struct ClosedPane {
+    position: PanePosition,
+    settings: buffer::Settings,
+}
+
+enum PanePosition {
+    /// Split from `sibling` in the main window, placed first (left or top)
+    /// of the split if `first`
+    Split {
+        pane: pane_grid::Pane,
+        sibling: pane_grid::Pane,
+        axis: pane_grid::Axis,
+        ratio: f32,
+        first: bool,
+    },
+    /// Only pane of the main window, left in place as an empty pane
+    Only(pane_grid::Pane),
+    Popout,
+}
+
+fn closed_buffer_toast<'a>(closed: &ClosedBuffer) -> Element<'a, Message> {
+    let target = closed.buffer.target().unwrap_or_default();
+    let description = if closed.parted {
+        format!("Left {target}")
+    } else {
+        format!("Closed {target}")
+    };
+
+    container(
+        row![
+            text(description),
+            button(text("Undo"))
+                .padding([2, 8])
+                .style(|theme, status| theme::button::secondary(theme, status, false))
+                .on_press(Message::UndoCloseBuffer),
+        ]
+        .spacing(12)
+        .align_y(iced::Alignment::Center),
+    )
+    .padding([6, 12])
+    .style(theme::container::tooltip)
+    .into()
+}
+
+/// The pane next to `pane` in `node`, with the axis and ratio of the split
+/// between them and whether `pane` comes first
+fn pane_position(
+    node: &pane_grid::Node,
+    pane: pane_grid::Pane,
+) -> Option<(pane_grid::Pane, pane_grid::Axis, f32, bool)> {
+    use pane_grid::Node;
+
+    // Nearest pane to the split on either side of it
+    fn edge_pane(node: &Node, first: bool) -> pane_grid::Pane {
+        match node {
+            Node::Split { a, b, .. } => edge_pane(if first { a } else { b }, first),
+            Node::Pane(pane) => *pane,
+        }
+    }
+
+    match node {
+        Node::Split {
+            axis, ratio, a, b, ..
+        } => {
+            if matches!(**a, Node::Pane(a) if a == pane) {
+                Some((edge_pane(b, true), *axis, *ratio, true))
+            } else if matches!(**b, Node::Pane(b) if b == pane) {
+                Some((edge_pane(a, false), *axis, *ratio, false))
+            } else {
+                pane_position(a, pane).or_else(|| pane_position(b, pane))
+            }
         }
+        Node::Pane(_) => None,
     }
 }
 
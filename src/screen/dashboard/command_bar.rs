@@ -1,3 +1,4 @@
+use data::note::Notes;
 use data::{buffer, Config};
 use iced::widget::{column, container, pane_grid, text};
 use iced::Length;
@@ -21,6 +22,7 @@ pub enum Message {
 impl CommandBar {
     pub fn new(
         buffers: &[buffer::Upstream],
+        notes: &Notes,
         version: &data::Version,
         config: &Config,
         focus: Option<(window::Id, pane_grid::Pane)>,
@@ -29,6 +31,7 @@ impl CommandBar {
     ) -> Self {
         let state = combo_box::State::new(Command::list(
             buffers,
+            notes,
             config,
             focus,
             resize_buffer,
@@ -40,6 +43,19 @@ impl CommandBar {
         Self { state }
     }
 
+    /// Command bar listing only `themes`, hovering one previews it
+    pub fn themes(themes: Vec<data::Theme>) -> Self {
+        let state = combo_box::State::new(
+            themes
+                .into_iter()
+                .map(|theme| Command::Theme(Theme::Switch(theme)))
+                .collect(),
+        );
+        state.focus();
+
+        Self { state }
+    }
+
     pub fn update(&mut self, message: Message) -> Option<Event> {
         match message {
             Message::Command(command) => Some(Event::Command(command)),
@@ -80,13 +96,22 @@ impl CommandBar {
         );
 
         double_pass(
-            // Layout should be based on the Shrink text size width of largest option
+            // Layout should be based on the Shrink text size width of largest option,
+            // leaving out notes so a long one doesn't stretch the bar
             column(
                 std::iter::once(text("Type a command...").size(font_size))
                     .chain(
-                        Command::list(buffers, config, focus, resize_buffer, version, main_window)
-                            .iter()
-                            .map(|command| text(command.to_string()).size(font_size)),
+                        Command::list(
+                            buffers,
+                            &Notes::default(),
+                            config,
+                            focus,
+                            resize_buffer,
+                            version,
+                            main_window,
+                        )
+                        .iter()
+                        .map(|command| text(command.to_string()).size(font_size)),
                     )
                     .map(Element::from),
             )
@@ -125,9 +150,12 @@ pub enum Buffer {
     Maximize(bool),
     New,
     Close,
-    Replace(buffer::Upstream),
+    Replace(buffer::Upstream, Option<String>),
+    SplitScrollback,
     Popout,
     Merge,
+    Clear { history: bool },
+    ClearAll,
     ToggleInternal(buffer::Internal),
 }
 
@@ -152,13 +180,14 @@ pub enum Theme {
 impl Command {
     pub fn list(
         buffers: &[buffer::Upstream],
+        notes: &Notes,
         config: &Config,
         focus: Option<(window::Id, pane_grid::Pane)>,
         resize_buffer: data::buffer::Resize,
         version: &data::Version,
         main_window: window::Id,
     ) -> Vec<Self> {
-        let buffers = Buffer::list(buffers, focus, resize_buffer, main_window)
+        let buffers = Buffer::list(buffers, notes, focus, resize_buffer, main_window)
             .into_iter()
             .map(Command::Buffer);
 
@@ -196,6 +225,7 @@ impl std::fmt::Display for Command {
 impl Buffer {
     fn list(
         buffers: &[buffer::Upstream],
+        notes: &Notes,
         focus: Option<(window::Id, pane_grid::Pane)>,
         resize_buffer: data::buffer::Resize,
         main_window: window::Id,
@@ -218,14 +248,22 @@ impl Buffer {
             }
 
             if window == main_window {
+                list.push(Buffer::SplitScrollback);
                 list.push(Buffer::Popout);
             } else {
                 list.push(Buffer::Merge);
             }
 
-            list.extend(buffers.iter().cloned().map(Buffer::Replace));
+            list.push(Buffer::Clear { history: false });
+            list.push(Buffer::Clear { history: true });
+
+            list.extend(buffers.iter().map(|buffer| {
+                Buffer::Replace(buffer.clone(), notes.get(buffer).map(String::from))
+            }));
         }
 
+        list.push(Buffer::ClearAll);
+
         list
     }
 }
@@ -294,15 +332,26 @@ impl std::fmt::Display for Buffer {
             }
             Buffer::New => write!(f, "New buffer"),
             Buffer::Close => write!(f, "Close buffer"),
-            Buffer::Replace(buffer) => match buffer {
-                buffer::Upstream::Server(server) => write!(f, "Change to {}", server),
-                buffer::Upstream::Channel(server, channel) => {
-                    write!(f, "Change to {} ({})", channel, server)
+            Buffer::Replace(buffer, note) => {
+                match buffer {
+                    buffer::Upstream::Server(server) => write!(f, "Change to {}", server),
+                    buffer::Upstream::Channel(server, channel) => {
+                        write!(f, "Change to {} ({})", channel, server)
+                    }
+                    buffer::Upstream::Query(_, nick) => write!(f, "Change to {}", nick),
+                }?;
+
+                match note {
+                    Some(note) => write!(f, " - {note}"),
+                    None => Ok(()),
                 }
-                buffer::Upstream::Query(_, nick) => write!(f, "Change to {}", nick),
-            },
+            }
+            Buffer::SplitScrollback => write!(f, "Split scrollback"),
             Buffer::Popout => write!(f, "Pop out buffer"),
             Buffer::Merge => write!(f, "Merge buffer"),
+            Buffer::Clear { history: false } => write!(f, "Clear buffer"),
+            Buffer::Clear { history: true } => write!(f, "Clear buffer and delete history"),
+            Buffer::ClearAll => write!(f, "Clear all buffers"),
             Buffer::ToggleInternal(internal) => write!(f, "Toggle {internal}"),
         }
     }
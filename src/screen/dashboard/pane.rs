@@ -1,4 +1,7 @@
-use data::{file_transfer, history, Config};
+use chrono::Utc;
+use data::history::metadata;
+use data::note::Notes;
+use data::{file_transfer, history, raw_log, translation, Config};
 use iced::widget::{button, center, container, pane_grid, row, text};
 
 use crate::buffer::{self, Buffer};
@@ -8,6 +11,9 @@ use crate::{icon, theme, widget, Theme};
 
 use super::sidebar;
 
+/// Bars of the member count sparkline in the title bar
+const SPARKLINE_WIDTH: usize = 12;
+
 #[derive(Debug, Clone)]
 pub enum Message {
     PaneClicked(pane_grid::Pane),
@@ -56,6 +62,9 @@ impl Pane {
         maximized: bool,
         clients: &'a data::client::Map,
         file_transfers: &'a file_transfer::Manager,
+        raw_log: &'a raw_log::Log,
+        translations: &'a translation::Translations,
+        notes: &'a Notes,
         history: &'a history::Manager,
         sidebar: &'a sidebar::Sidebar,
         config: &'a Config,
@@ -73,18 +82,62 @@ impl Pane {
                     .get_channel_users(&state.server, &state.channel)
                     .len();
 
-                format!("{channel} @ {server} - {users} users")
+                let mut title = format!("{channel} @ {server} - {users} users");
+
+                let member_count = &config.buffer.channel.member_count;
+
+                if users >= member_count.min_members {
+                    let kind = history::Kind::Channel(server.clone(), channel.clone());
+                    let counts = history.member_counts(&kind);
+                    let today = metadata::start_of_day(Utc::now());
+
+                    if let Some(delta) = member_count
+                        .show_delta
+                        .then(|| metadata::member_count_delta(counts, today, users))
+                        .flatten()
+                        .filter(|delta| *delta != 0)
+                    {
+                        title.push_str(&format!(" ({delta:+} today)"));
+                    }
+
+                    if let Some(sparkline) = member_count
+                        .show_sparkline
+                        .then(|| {
+                            metadata::member_count_sparkline(counts, today, users, SPARKLINE_WIDTH)
+                        })
+                        .flatten()
+                    {
+                        title.push_str(&format!(" {sparkline}"));
+                    }
+                }
+
+                title
+            }
+            Buffer::Server(state) => {
+                let server = &state.server;
+
+                if let Some(modes) = clients.get_user_modes(server) {
+                    format!("{server} - {modes}")
+                } else {
+                    server.to_string()
+                }
             }
-            Buffer::Server(state) => state.server.to_string(),
             Buffer::Query(state) => {
                 let nick = &state.nick;
                 let server = &state.server;
 
-                format!("{nick} @ {server}")
+                match clients.get_away_message(server, nick) {
+                    Some(away_message) if !away_message.is_empty() => {
+                        format!("{nick} @ {server} - away: {away_message}")
+                    }
+                    Some(_) => format!("{nick} @ {server} - away"),
+                    None => format!("{nick} @ {server}"),
+                }
             }
             Buffer::FileTransfers(_) => "File Transfers".to_string(),
             Buffer::Logs(_) => "Logs".to_string(),
             Buffer::Highlights(_) => "Highlights".to_string(),
+            Buffer::RawLog(_) => "Raw Log".to_string(),
         };
 
         let title_bar = self.title_bar.view(
@@ -105,6 +158,9 @@ impl Pane {
             .view(
                 clients,
                 file_transfers,
+                raw_log,
+                translations,
+                notes,
                 history,
                 &self.settings,
                 config,
@@ -134,6 +190,7 @@ impl Pane {
             Buffer::FileTransfers(_) => None,
             Buffer::Logs(_) => Some(history::Resource::logs()),
             Buffer::Highlights(_) => Some(history::Resource::highlights()),
+            Buffer::RawLog(_) => None,
         }
     }
 
@@ -328,6 +385,7 @@ impl From<Pane> for data::Pane {
             Buffer::FileTransfers(_) => data::Buffer::Internal(buffer::Internal::FileTransfers),
             Buffer::Logs(_) => data::Buffer::Internal(buffer::Internal::Logs),
             Buffer::Highlights(_) => data::Buffer::Internal(buffer::Internal::Highlights),
+            Buffer::RawLog(_) => data::Buffer::Internal(buffer::Internal::RawLog),
         };
 
         data::Pane::Buffer {
@@ -1,17 +1,19 @@
 use data::config::{self, sidebar, Config};
 use data::dashboard::{BufferAction, BufferFocusedAction};
-use data::{buffer, file_transfer, history, Version};
+use data::note::Notes;
+use data::user::NickRef;
+use data::{buffer, file_transfer, history, Server, Version};
 use iced::widget::{
     button, column, container, horizontal_rule, horizontal_space, pane_grid, row, scrollable, text,
     vertical_rule, vertical_space, Column, Row, Scrollable, Space,
 };
-use iced::{padding, Alignment, Length, Task};
+use iced::{keyboard, padding, Alignment, Length, Task};
 use std::time::Duration;
 
 use tokio::time;
 
 use super::Panes;
-use crate::widget::{context_menu, Element, Text};
+use crate::widget::{context_menu, tooltip, Element, Text};
 use crate::{icon, theme, window};
 
 const CONFIG_RELOAD_DELAY: Duration = Duration::from_secs(1);
@@ -22,9 +24,14 @@ pub enum Message {
     Popout(buffer::Upstream),
     Focus(window::Id, pane_grid::Pane),
     Replace(window::Id, buffer::Upstream, pane_grid::Pane),
+    ReplaceLargest(buffer::Upstream),
     Close(window::Id, pane_grid::Pane),
     Swap(window::Id, pane_grid::Pane, window::Id, pane_grid::Pane),
     Leave(buffer::Upstream),
+    Connect(Server),
+    Disconnect(Server),
+    AddNetwork,
+    EditNetwork(Server),
     ToggleInternalBuffer(buffer::Internal),
     ToggleCommandBar,
     ToggleThemeEditor,
@@ -32,6 +39,7 @@ pub enum Message {
     ConfigReloaded(Result<Config, config::Error>),
     OpenReleaseWebsite,
     OpenDocumentation,
+    CycleProfile,
     ReloadComplete,
     Noop,
 }
@@ -42,14 +50,20 @@ pub enum Event {
     Popout(buffer::Upstream),
     Focus(window::Id, pane_grid::Pane),
     Replace(window::Id, buffer::Upstream, pane_grid::Pane),
+    ReplaceLargest(buffer::Upstream),
     Close(window::Id, pane_grid::Pane),
     Swap(window::Id, pane_grid::Pane, window::Id, pane_grid::Pane),
     Leave(buffer::Upstream),
+    Connect(Server),
+    Disconnect(Server),
+    AddNetwork,
+    EditNetwork(Server),
     ToggleInternalBuffer(buffer::Internal),
     ToggleCommandBar,
     ToggleThemeEditor,
     OpenReleaseWebsite,
     OpenDocumentation,
+    CycleProfile,
     ConfigReloaded(Result<Config, config::Error>),
 }
 
@@ -85,12 +99,17 @@ impl Sidebar {
             Message::Replace(window, source, pane) => {
                 (Task::none(), Some(Event::Replace(window, source, pane)))
             }
+            Message::ReplaceLargest(source) => (Task::none(), Some(Event::ReplaceLargest(source))),
             Message::Close(window, pane) => (Task::none(), Some(Event::Close(window, pane))),
             Message::Swap(from_window, from_pane, to_window, to_pane) => (
                 Task::none(),
                 Some(Event::Swap(from_window, from_pane, to_window, to_pane)),
             ),
             Message::Leave(buffer) => (Task::none(), Some(Event::Leave(buffer))),
+            Message::Connect(server) => (Task::none(), Some(Event::Connect(server))),
+            Message::Disconnect(server) => (Task::none(), Some(Event::Disconnect(server))),
+            Message::AddNetwork => (Task::none(), Some(Event::AddNetwork)),
+            Message::EditNetwork(server) => (Task::none(), Some(Event::EditNetwork(server))),
             Message::ToggleInternalBuffer(buffer) => {
                 (Task::none(), Some(Event::ToggleInternalBuffer(buffer)))
             }
@@ -113,6 +132,7 @@ impl Sidebar {
             }
             Message::Noop => (Task::none(), None),
             Message::OpenDocumentation => (Task::none(), Some(Event::OpenDocumentation)),
+            Message::CycleProfile => (Task::none(), Some(Event::CycleProfile)),
         }
     }
 
@@ -121,13 +141,15 @@ impl Sidebar {
         keyboard: &'a data::config::Keyboard,
         file_transfers: &'a file_transfer::Manager,
         version: &'a Version,
+        profile: Option<&'a str>,
+        has_profiles: bool,
     ) -> Element<'a, Message> {
         let base = button(icon::menu())
             .padding(5)
             .width(Length::Shrink)
             .on_press(Message::Noop);
 
-        let menu = Menu::list();
+        let menu = Menu::list(has_profiles);
 
         if menu.is_empty() {
             base.into()
@@ -166,6 +188,12 @@ impl Sidebar {
                             icon::refresh(),
                             Message::ReloadingConfigFile,
                         ),
+                        Menu::AddNetwork => context_button(
+                            text("Add network…"),
+                            None,
+                            icon::connected(),
+                            Message::AddNetwork,
+                        ),
                         Menu::CommandBar => context_button(
                             text("Command Bar"),
                             Some(&keyboard.command_bar),
@@ -228,6 +256,12 @@ impl Sidebar {
                             icon::documentation(),
                             Message::OpenDocumentation,
                         ),
+                        Menu::Profile => context_button(
+                            text(format!("Profile: {}", profile.unwrap_or("default"))),
+                            None,
+                            icon::megaphone(),
+                            Message::CycleProfile,
+                        ),
                     }
                 },
             )
@@ -240,20 +274,35 @@ impl Sidebar {
         clients: &data::client::Map,
         history: &'a history::Manager,
         panes: &'a Panes,
+        notes: &'a Notes,
         focus: Option<(window::Id, pane_grid::Pane)>,
         config: data::config::Sidebar,
+        modifiers: keyboard::Modifiers,
         keyboard: &'a data::config::Keyboard,
         file_transfers: &'a file_transfer::Manager,
         version: &'a Version,
+        profile: Option<&'a str>,
+        has_profiles: bool,
         main_window: window::Id,
     ) -> Option<Element<'a, Message>> {
         if self.hidden {
             return None;
         }
 
-        let user_menu_button = config
-            .show_user_menu
-            .then(|| self.user_menu_button(keyboard, file_transfers, version));
+        let user_menu_button = config.show_user_menu.then(|| {
+            self.user_menu_button(keyboard, file_transfers, version, profile, has_profiles)
+        });
+
+        // Modifier clicks focus an open buffer instead of duplicating it,
+        // unless duplicates are allowed
+        let modified_action = config.modified_buffer_action(modifiers);
+        let buffer_action = modified_action.unwrap_or(config.buffer_action);
+        let click = Click {
+            action: buffer_action,
+            focused_action: config.buffer_focused_action,
+            modified: modified_action.is_some(),
+            duplicate: modified_action.is_some() && config.allow_duplicate_buffers,
+        };
 
         let mut buffers = vec![];
 
@@ -263,11 +312,12 @@ impl Sidebar {
                     buffers.push(upstream_buffer_button(
                         main_window,
                         panes,
+                        notes,
                         focus,
                         buffer::Upstream::Server(server.clone()),
                         false,
-                        config.buffer_action,
-                        config.buffer_focused_action,
+                        None,
+                        click,
                         config.position,
                         config.unread_indicator,
                         history.has_unread(&history::Kind::Server(server.clone())),
@@ -277,11 +327,12 @@ impl Sidebar {
                     buffers.push(upstream_buffer_button(
                         main_window,
                         panes,
+                        notes,
                         focus,
                         buffer::Upstream::Server(server.clone()),
                         true,
-                        config.buffer_action,
-                        config.buffer_focused_action,
+                        connection.fallback_nickname(),
+                        click,
                         config.position,
                         config.unread_indicator,
                         history.has_unread(&history::Kind::Server(server.clone())),
@@ -291,11 +342,12 @@ impl Sidebar {
                         buffers.push(upstream_buffer_button(
                             main_window,
                             panes,
+                            notes,
                             focus,
                             buffer::Upstream::Channel(server.clone(), channel.clone()),
                             true,
-                            config.buffer_action,
-                            config.buffer_focused_action,
+                            None,
+                            click,
                             config.position,
                             config.unread_indicator,
                             history.has_unread(&history::Kind::Channel(
@@ -310,11 +362,12 @@ impl Sidebar {
                         buffers.push(upstream_buffer_button(
                             main_window,
                             panes,
+                            notes,
                             focus,
                             buffer::Upstream::Query(server.clone(), user.clone()),
                             true,
-                            config.buffer_action,
-                            config.buffer_focused_action,
+                            None,
+                            click,
                             config.position,
                             config.unread_indicator,
                             history.has_unread(&history::Kind::Query(server.clone(), user.clone())),
@@ -396,6 +449,7 @@ impl Sidebar {
 
 #[derive(Debug, Clone, Copy)]
 enum Menu {
+    AddNetwork,
     RefreshConfig,
     CommandBar,
     ThemeEditor,
@@ -405,21 +459,30 @@ enum Menu {
     Version,
     HorizontalRule,
     Documentation,
+    Profile,
 }
 
 impl Menu {
-    fn list() -> Vec<Self> {
-        vec![
+    fn list(has_profiles: bool) -> Vec<Self> {
+        let mut list = vec![
             Menu::Version,
             Menu::HorizontalRule,
             Menu::CommandBar,
             Menu::FileTransfers,
             Menu::Highlights,
             Menu::Logs,
+            Menu::AddNetwork,
             Menu::RefreshConfig,
             Menu::ThemeEditor,
             Menu::Documentation,
-        ]
+        ];
+
+        // Only worth showing once there's something to switch to
+        if has_profiles {
+            list.push(Menu::Profile);
+        }
+
+        list
     }
 }
 
@@ -430,6 +493,9 @@ enum Entry {
     Replace(window::Id, pane_grid::Pane),
     Close(window::Id, pane_grid::Pane),
     Swap(window::Id, pane_grid::Pane, window::Id, pane_grid::Pane),
+    Connect,
+    Disconnect,
+    EditNetwork,
     Leave,
 }
 
@@ -464,18 +530,32 @@ impl Entry {
     }
 }
 
-fn upstream_buffer_button(
+/// What pressing a buffer in the sidebar does
+#[derive(Debug, Clone, Copy)]
+struct Click {
+    action: BufferAction,
+    focused_action: Option<BufferFocusedAction>,
+    /// Whether a modifier is held
+    modified: bool,
+    /// Whether to open the buffer again when it's already open
+    duplicate: bool,
+}
+
+fn upstream_buffer_button<'a>(
     main_window: window::Id,
-    panes: &Panes,
+    panes: &'a Panes,
+    notes: &'a Notes,
     focus: Option<(window::Id, pane_grid::Pane)>,
     buffer: buffer::Upstream,
     connected: bool,
-    buffer_action: BufferAction,
-    focused_buffer_action: Option<BufferFocusedAction>,
+    fallback_nickname: Option<NickRef<'_>>,
+    click: Click,
     position: sidebar::Position,
     unread_indicator: sidebar::UnreadIndicator,
     has_unread: bool,
-) -> Element<Message> {
+) -> Element<'a, Message> {
+    let note = notes.get(&buffer);
+
     let open = panes
         .iter(main_window)
         .find_map(|(window_id, pane, state)| {
@@ -530,6 +610,12 @@ fn upstream_buffer_button(
                 .style(buffer_title_style)
                 .shaping(text::Shaping::Advanced)
         ]
+        // Connected with a fallback since the configured nickname is in use
+        .push_maybe(fallback_nickname.map(|nickname| {
+            text(format!("as {nickname}"))
+                .style(theme::text::secondary)
+                .shaping(text::Shaping::Advanced)
+        }))
         .spacing(8)
         .align_y(iced::Alignment::Center),
         buffer::Upstream::Channel(_, channel) => row![]
@@ -576,8 +662,8 @@ fn upstream_buffer_button(
         })
         .on_press_maybe({
             match is_focused {
-                Some((window, pane)) => {
-                    if let Some(focus_action) = focused_buffer_action {
+                Some((window, pane)) if !click.modified => {
+                    if let Some(focus_action) = click.focused_action {
                         match focus_action {
                             BufferFocusedAction::ClosePane => Some(Message::Close(window, pane)),
                         }
@@ -585,29 +671,50 @@ fn upstream_buffer_button(
                         None
                     }
                 }
-                None => {
-                    if let Some((window, pane)) = open {
-                        Some(Message::Focus(window, pane))
-                    } else {
-                        match buffer_action {
-                            BufferAction::NewPane => Some(Message::Open(buffer.clone())),
-                            BufferAction::ReplacePane => match focus {
-                                Some((window, pane)) => {
-                                    Some(Message::Replace(window, buffer.clone(), pane))
-                                }
-                                None => Some(Message::Open(buffer.clone())),
-                            },
-                            BufferAction::NewWindow => Some(Message::Popout(buffer.clone())),
+                _ => match open {
+                    Some((window, pane)) if !click.duplicate => Some(Message::Focus(window, pane)),
+                    _ => match click.action {
+                        BufferAction::NewPane => Some(Message::Open(buffer.clone())),
+                        BufferAction::ReplacePane => match focus {
+                            Some((window, pane)) => {
+                                Some(Message::Replace(window, buffer.clone(), pane))
+                            }
+                            None => Some(Message::Open(buffer.clone())),
+                        },
+                        BufferAction::ReplaceLargestPane => {
+                            Some(Message::ReplaceLargest(buffer.clone()))
                         }
-                    }
-                }
+                        BufferAction::NewWindow => Some(Message::Popout(buffer.clone())),
+                    },
+                },
             }
         });
 
-    let entries = Entry::list(panes.len(), open, focus);
+    let entries = match &buffer {
+        // A disconnected server can only be connected to or edited
+        buffer::Upstream::Server(_) if !connected => vec![Entry::Connect, Entry::EditNetwork],
+        buffer::Upstream::Server(_) => {
+            let mut entries = Entry::list(panes.len(), open, focus);
+            entries.insert(entries.len() - 1, Entry::Disconnect);
+            entries.insert(entries.len() - 1, Entry::EditNetwork);
+            entries
+        }
+        _ if !connected => vec![],
+        _ => Entry::list(panes.len(), open, focus),
+    };
 
-    if entries.is_empty() || !connected {
-        base.into()
+    // Notes show on hover, away from the panes
+    let tooltip_position = match position {
+        sidebar::Position::Left => tooltip::Position::Right,
+        sidebar::Position::Right => tooltip::Position::Left,
+        sidebar::Position::Top => tooltip::Position::Bottom,
+        sidebar::Position::Bottom => tooltip::Position::Top,
+    };
+
+    let base = tooltip(base, note, tooltip_position);
+
+    if entries.is_empty() {
+        base
     } else {
         context_menu(Default::default(), base, entries, move |entry, length| {
             let (content, message) = match entry {
@@ -622,6 +729,12 @@ fn upstream_buffer_button(
                     "Swap with current pane",
                     Message::Swap(from_window, from_pane, to_window, to_pane),
                 ),
+                Entry::Connect => ("Connect", Message::Connect(buffer.server().clone())),
+                Entry::Disconnect => ("Disconnect", Message::Disconnect(buffer.server().clone())),
+                Entry::EditNetwork => (
+                    "Edit network",
+                    Message::EditNetwork(buffer.server().clone()),
+                ),
                 Entry::Leave => (
                     match &buffer {
                         buffer::Upstream::Server(_) => "Leave server",
@@ -61,3 +61,12 @@ pub fn message_marker<'a, M: 'a>(
 }
 
 pub const MESSAGE_MARKER_TEXT: &str = " ∙";
+
+/// Shown in front of messages from bots
+pub fn bot_badge<'a, M: 'a>() -> Element<'a, M> {
+    selectable_text(BOT_BADGE_TEXT)
+        .style(crate::theme::selectable_text::tertiary)
+        .into()
+}
+
+pub const BOT_BADGE_TEXT: &str = "bot ";
@@ -44,6 +44,26 @@ pub fn with_context<'a, T: Copy + 'a, M: 'a>(
     )
 }
 
+/// `correction` of the sender's previous message, with the replaced text
+/// emphasized
+pub fn correction<'a, M: 'a>(
+    correction: &message::correction::Correction,
+    style: impl Fn(&Theme) -> selectable_text::Style + 'a,
+) -> Element<'a, M> {
+    let spans = std::iter::once(span("meant: ".to_string()).font(font::MONO_ITALICS.clone()))
+        .chain(correction.parts().iter().map(|part| match part {
+            message::correction::Part::Unchanged(text) => span(text.clone()),
+            message::correction::Part::Replaced(text) => {
+                span(text.clone()).font(font::MONO_BOLD.clone())
+            }
+        }))
+        .collect::<Vec<Span<'a, message::Link, _>>>();
+
+    selectable_rich_text::<M, message::Link, (), Theme, Renderer>(spans)
+        .style(style)
+        .into()
+}
+
 #[allow(clippy::type_complexity)]
 fn message_content_impl<'a, T: Copy + 'a, M: 'a>(
     content: &'a message::Content,